@@ -30,6 +30,8 @@ javac -sourcepath src/main/java/ src/main/java/ai/djl/engine/rust/RustLibrary.ja
 RUST_MANIFEST=rust/Cargo.toml
 if [ -x "$(command -v nvcc)" ]; then
   cargo build --manifest-path $RUST_MANIFEST --release --features cuda,flash-attn
+elif [[ $PLATFORM == 'darwin' && $ARCH == 'aarch64' ]]; then
+  cargo build --manifest-path $RUST_MANIFEST --release --features metal,accelerate
 else
   cargo build --manifest-path $RUST_MANIFEST --release
 fi
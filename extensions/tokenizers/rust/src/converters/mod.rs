@@ -0,0 +1,26 @@
+use std::collections::HashMap;
+
+mod sentencepiece;
+mod tiktoken;
+
+pub(crate) use sentencepiece::from_sentencepiece;
+pub(crate) use tiktoken::from_tiktoken;
+
+// Recovers BPE merges from a vocabulary whose ids are merge ranks: every split of a token into
+// two tokens that are also in the vocabulary is a merge, ordered by the id of the merged token.
+fn merges_from_vocab(vocab: &HashMap<String, u32>) -> Vec<(String, String)> {
+    let mut merges = Vec::new();
+    for (token, &id) in vocab.iter() {
+        for (i, _) in token.char_indices().skip(1) {
+            let (left, right) = token.split_at(i);
+            if let (Some(&left_id), Some(&right_id)) = (vocab.get(left), vocab.get(right)) {
+                merges.push((id, left_id, right_id, left.to_string(), right.to_string()));
+            }
+        }
+    }
+    merges.sort();
+    merges
+        .into_iter()
+        .map(|(_, _, _, left, right)| (left, right))
+        .collect()
+}
@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use tk::decoders::byte_fallback::ByteFallback;
+use tk::decoders::fuse::Fuse;
+use tk::decoders::sequence::Sequence as DecoderSequence;
+use tk::decoders::strip::Strip;
+use tk::decoders::DecoderWrapper;
+use tk::models::bpe::BPE;
+use tk::models::unigram::Unigram;
+use tk::normalizers::replace::ReplacePattern;
+use tk::normalizers::{NormalizerWrapper, Precompiled, Prepend, Replace, Sequence};
+use tk::pre_tokenizers::metaspace::{Metaspace, PrependScheme};
+use tk::{AddedToken, Result, Tokenizer};
+
+use crate::converters::merges_from_vocab;
+
+// TrainerSpec.ModelType
+const MODEL_TYPE_UNIGRAM: u64 = 1;
+const MODEL_TYPE_BPE: u64 = 2;
+
+// SentencePiece.Type
+const PIECE_TYPE_NORMAL: u64 = 1;
+const PIECE_TYPE_UNKNOWN: u64 = 2;
+const PIECE_TYPE_CONTROL: u64 = 3;
+const PIECE_TYPE_USER_DEFINED: u64 = 4;
+const PIECE_TYPE_BYTE: u64 = 6;
+
+const SPIECE_UNDERLINE: char = '▁';
+
+struct Piece {
+    piece: String,
+    score: f32,
+    piece_type: u64,
+}
+
+// The subset of sentencepiece_model.proto ModelProto needed to rebuild the tokenizer
+struct SentencePieceModel {
+    pieces: Vec<Piece>,
+    model_type: u64,
+    byte_fallback: bool,
+    unk_id: Option<usize>,
+    precompiled_charsmap: Vec<u8>,
+    add_dummy_prefix: bool,
+    remove_extra_whitespaces: bool,
+}
+
+impl SentencePieceModel {
+    fn parse(buf: &[u8]) -> Result<Self> {
+        let mut model = Self {
+            pieces: Vec::new(),
+            model_type: MODEL_TYPE_UNIGRAM,
+            byte_fallback: false,
+            unk_id: Some(0),
+            precompiled_charsmap: Vec::new(),
+            add_dummy_prefix: true,
+            remove_extra_whitespaces: true,
+        };
+
+        let mut reader = ProtoReader::new(buf);
+        while let Some((field, wire_type)) = reader.next_field()? {
+            match (field, wire_type) {
+                (1, 2) => {
+                    let piece = Self::parse_piece(reader.read_bytes()?)?;
+                    model.pieces.push(piece);
+                }
+                (2, 2) => model.parse_trainer_spec(reader.read_bytes()?)?,
+                (3, 2) => model.parse_normalizer_spec(reader.read_bytes()?)?,
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        if model.pieces.is_empty() {
+            return Err("Invalid SentencePiece model: no pieces found".into());
+        }
+        Ok(model)
+    }
+
+    fn parse_piece(buf: &[u8]) -> Result<Piece> {
+        let mut piece = Piece {
+            piece: String::new(),
+            score: 0f32,
+            piece_type: PIECE_TYPE_NORMAL,
+        };
+        let mut reader = ProtoReader::new(buf);
+        while let Some((field, wire_type)) = reader.next_field()? {
+            match (field, wire_type) {
+                (1, 2) => piece.piece = String::from_utf8(reader.read_bytes()?.to_vec())?,
+                (2, 5) => piece.score = f32::from_bits(reader.read_fixed32()?),
+                (3, 0) => piece.piece_type = reader.read_varint()?,
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(piece)
+    }
+
+    fn parse_trainer_spec(&mut self, buf: &[u8]) -> Result<()> {
+        let mut reader = ProtoReader::new(buf);
+        while let Some((field, wire_type)) = reader.next_field()? {
+            match (field, wire_type) {
+                (3, 0) => self.model_type = reader.read_varint()?,
+                (35, 0) => self.byte_fallback = reader.read_varint()? != 0,
+                (40, 0) => {
+                    // int32 fields are sign extended to 64 bits on the wire
+                    let unk_id = reader.read_varint()? as i64;
+                    self.unk_id = usize::try_from(unk_id).ok();
+                }
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_normalizer_spec(&mut self, buf: &[u8]) -> Result<()> {
+        let mut reader = ProtoReader::new(buf);
+        while let Some((field, wire_type)) = reader.next_field()? {
+            match (field, wire_type) {
+                (2, 2) => self.precompiled_charsmap = reader.read_bytes()?.to_vec(),
+                (3, 0) => self.add_dummy_prefix = reader.read_varint()? != 0,
+                (4, 0) => self.remove_extra_whitespaces = reader.read_varint()? != 0,
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+struct ProtoReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ProtoReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn next_field(&mut self) -> Result<Option<(u64, u8)>> {
+        if self.pos >= self.buf.len() {
+            return Ok(None);
+        }
+        let key = self.read_varint()?;
+        Ok(Some((key >> 3, (key & 0x7) as u8)))
+    }
+
+    fn read_varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = *self
+                .buf
+                .get(self.pos)
+                .ok_or("Invalid SentencePiece model: truncated varint")?;
+            self.pos += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+            if shift >= 64 {
+                return Err("Invalid SentencePiece model: malformed varint".into());
+            }
+        }
+    }
+
+    fn read_bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.read_varint()? as usize;
+        let bytes = self.advance(len)?;
+        Ok(bytes)
+    }
+
+    fn read_fixed32(&mut self) -> Result<u32> {
+        let bytes = self.advance(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn skip(&mut self, wire_type: u8) -> Result<()> {
+        match wire_type {
+            0 => {
+                self.read_varint()?;
+            }
+            1 => {
+                self.advance(8)?;
+            }
+            2 => {
+                self.read_bytes()?;
+            }
+            5 => {
+                self.advance(4)?;
+            }
+            _ => {
+                return Err(format!("Unsupported protobuf wire type: {wire_type}").into());
+            }
+        }
+        Ok(())
+    }
+
+    fn advance(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.buf.len())
+            .ok_or("Invalid SentencePiece model: truncated message")?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+}
+
+// Converts a SentencePiece model the same way as transformers' `convert_slow_tokenizer`:
+// byte-fallback BPE models (LLaMA style) use Prepend/Replace normalization, all others use
+// the precompiled normalizer with a Metaspace pre-tokenizer.
+pub(crate) fn from_sentencepiece<P: AsRef<Path>>(path: P) -> Result<Tokenizer> {
+    let buf = std::fs::read(path)?;
+    let spm = SentencePieceModel::parse(&buf)?;
+
+    let is_llama = spm.model_type == MODEL_TYPE_BPE && spm.byte_fallback;
+    let mut tokenizer = match spm.model_type {
+        MODEL_TYPE_UNIGRAM => {
+            let vocab = spm
+                .pieces
+                .iter()
+                .map(|p| (p.piece.clone(), p.score as f64))
+                .collect::<Vec<_>>();
+            Tokenizer::new(Unigram::from(vocab, spm.unk_id, spm.byte_fallback)?)
+        }
+        MODEL_TYPE_BPE => {
+            let vocab = spm
+                .pieces
+                .iter()
+                .enumerate()
+                .map(|(id, p)| (p.piece.clone(), id as u32))
+                .collect::<HashMap<_, _>>();
+            // user defined, byte and special pieces are never the result of a merge
+            let normal = spm
+                .pieces
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| p.piece_type == PIECE_TYPE_NORMAL)
+                .map(|(id, p)| (p.piece.clone(), id as u32))
+                .collect::<HashMap<_, _>>();
+            let merges = merges_from_vocab(&normal);
+            let mut builder = BPE::builder()
+                .vocab_and_merges(vocab, merges)
+                .byte_fallback(spm.byte_fallback)
+                .fuse_unk(true);
+            if let Some(unk) = spm.unk_id.and_then(|id| spm.pieces.get(id)) {
+                builder = builder.unk_token(unk.piece.clone());
+            }
+            Tokenizer::new(builder.build()?)
+        }
+        model_type => {
+            return Err(format!("Unsupported SentencePiece model type: {model_type}").into());
+        }
+    };
+
+    if is_llama {
+        let mut normalizers: Vec<NormalizerWrapper> = Vec::new();
+        if spm.add_dummy_prefix {
+            normalizers.push(Prepend::new(SPIECE_UNDERLINE.to_string()).into());
+        }
+        normalizers.push(Replace::new(" ", SPIECE_UNDERLINE.to_string())?.into());
+        tokenizer.with_normalizer(Sequence::new(normalizers));
+
+        let mut decoders: Vec<DecoderWrapper> = vec![
+            DecoderWrapper::Replace(Replace::new(SPIECE_UNDERLINE.to_string(), " ")?),
+            ByteFallback::new().into(),
+            Fuse::new().into(),
+        ];
+        if spm.add_dummy_prefix {
+            decoders.push(Strip::new(' ', 1, 0).into());
+        }
+        tokenizer.with_decoder(DecoderSequence::new(decoders));
+    } else {
+        let mut normalizers: Vec<NormalizerWrapper> = Vec::new();
+        if !spm.precompiled_charsmap.is_empty() {
+            let precompiled = Precompiled::from(&spm.precompiled_charsmap)
+                .map_err(|err| format!("Invalid precompiled charsmap: {err:?}"))?;
+            normalizers.push(precompiled.into());
+        }
+        if spm.remove_extra_whitespaces {
+            let pattern = ReplacePattern::Regex(" {2,}".to_string());
+            normalizers.push(Replace::new(pattern, " ")?.into());
+        }
+        if !normalizers.is_empty() {
+            tokenizer.with_normalizer(Sequence::new(normalizers));
+        }
+
+        let prepend_scheme = if spm.add_dummy_prefix {
+            PrependScheme::Always
+        } else {
+            PrependScheme::Never
+        };
+        let metaspace = Metaspace::new(SPIECE_UNDERLINE, prepend_scheme, true);
+        tokenizer.with_pre_tokenizer(metaspace.clone());
+        if spm.byte_fallback {
+            // the byte pieces of characters missing from the vocab decode back to the characters
+            let decoders: Vec<DecoderWrapper> = vec![
+                ByteFallback::new().into(),
+                Fuse::new().into(),
+                metaspace.into(),
+            ];
+            tokenizer.with_decoder(DecoderSequence::new(decoders));
+        } else {
+            tokenizer.with_decoder(metaspace);
+        }
+    }
+
+    // user defined pieces are always kept whole, they are matched before the normalization
+    let user_defined = spm
+        .pieces
+        .iter()
+        .filter(|p| p.piece_type == PIECE_TYPE_USER_DEFINED)
+        .map(|p| AddedToken::from(p.piece.clone(), false).normalized(false))
+        .collect::<Vec<_>>();
+    tokenizer.add_tokens(&user_defined);
+
+    let special_tokens = spm
+        .pieces
+        .iter()
+        .filter(|p| p.piece_type == PIECE_TYPE_CONTROL || p.piece_type == PIECE_TYPE_UNKNOWN)
+        .map(|p| AddedToken::from(p.piece.clone(), true))
+        .collect::<Vec<_>>();
+    tokenizer.add_special_tokens(&special_tokens);
+
+    Ok(tokenizer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn varint(buf: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        buf.push(value as u8);
+    }
+
+    fn bytes_field(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+        varint(buf, field << 3 | 2);
+        varint(buf, bytes.len() as u64);
+        buf.extend_from_slice(bytes);
+    }
+
+    fn varint_field(buf: &mut Vec<u8>, field: u64, value: u64) {
+        varint(buf, field << 3);
+        varint(buf, value);
+    }
+
+    /// Writes a byte fallback ModelProto with the `(piece, score, type)` pieces.
+    fn write_model(name: &str, pieces: &[(&str, f32, u64)], model_type: u64) -> Result<PathBuf> {
+        let mut model = Vec::new();
+        for (piece, score, piece_type) in pieces {
+            let mut buf = Vec::new();
+            bytes_field(&mut buf, 1, piece.as_bytes());
+            varint(&mut buf, 2 << 3 | 5);
+            buf.extend_from_slice(&score.to_bits().to_le_bytes());
+            varint_field(&mut buf, 3, *piece_type);
+            bytes_field(&mut model, 1, &buf);
+        }
+        let mut trainer_spec = Vec::new();
+        varint_field(&mut trainer_spec, 3, model_type);
+        varint_field(&mut trainer_spec, 35, 1);
+        bytes_field(&mut model, 2, &trainer_spec);
+        let path = std::env::temp_dir().join(format!("djl-{name}-{}.model", std::process::id()));
+        std::fs::write(&path, model)?;
+        Ok(path)
+    }
+
+    #[test]
+    fn maps_user_defined_and_byte_pieces_of_bpe_models() -> Result<()> {
+        let pieces = [
+            ("<unk>", 0., PIECE_TYPE_UNKNOWN),
+            ("<s>", 0., PIECE_TYPE_CONTROL),
+            ("<0x41>", 0., PIECE_TYPE_BYTE),
+            ("\u{2581}", 0., PIECE_TYPE_NORMAL),
+            ("a", 0., PIECE_TYPE_NORMAL),
+            ("\u{2581}a", 0., PIECE_TYPE_NORMAL),
+            ("<user>", 0., PIECE_TYPE_USER_DEFINED),
+        ];
+        let path = write_model("spm-bpe", &pieces, MODEL_TYPE_BPE)?;
+        let tokenizer = from_sentencepiece(&path)?;
+        let encoding = tokenizer.encode("a A<user>", false)?;
+        assert_eq!(encoding.get_ids(), [5, 3, 2, 6]);
+        assert_eq!(tokenizer.decode(encoding.get_ids(), false)?, "a A<user>");
+        Ok(())
+    }
+
+    #[test]
+    fn decodes_the_byte_pieces_of_unigram_models() -> Result<()> {
+        let pieces = [
+            ("<unk>", 0., PIECE_TYPE_UNKNOWN),
+            ("\u{2581}", -1., PIECE_TYPE_NORMAL),
+            ("a", -2., PIECE_TYPE_NORMAL),
+            ("<0x41>", 0., PIECE_TYPE_BYTE),
+        ];
+        let path = write_model("spm-unigram", &pieces, MODEL_TYPE_UNIGRAM)?;
+        let tokenizer = from_sentencepiece(&path)?;
+        let encoding = tokenizer.encode("A", false)?;
+        assert_eq!(encoding.get_ids(), [1, 3]);
+        assert_eq!(tokenizer.decode(encoding.get_ids(), false)?, "A");
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use tk::decoders::byte_level::ByteLevel;
+use tk::models::bpe::BPE;
+use tk::pre_tokenizers::sequence::Sequence;
+use tk::pre_tokenizers::split::{Split, SplitPattern};
+use tk::pre_tokenizers::PreTokenizerWrapper;
+use tk::{AddedToken, Result, SplitDelimiterBehavior, Tokenizer};
+
+use crate::converters::merges_from_vocab;
+
+// The cl100k_base pattern used by GPT-3.5/GPT-4 tokenizers
+const CL100K_PATTERN: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+";
+
+// GPT-2 byte to unicode mapping used by the ByteLevel pre-tokenizer
+fn bytes_char() -> HashMap<u8, char> {
+    let mut bs: Vec<u8> = Vec::new();
+    bs.extend(b'!'..=b'~');
+    bs.extend(b'\xA1'..=b'\xAC');
+    bs.extend(b'\xAE'..=b'\xFF');
+
+    let mut cs: Vec<u32> = bs.iter().map(|i| *i as u32).collect();
+    let mut n = 0;
+    for b in 0..=255u8 {
+        if !bs.contains(&b) {
+            bs.push(b);
+            cs.push(u32::pow(2, 8) + n);
+            n += 1;
+        }
+    }
+
+    bs.into_iter()
+        .zip(cs)
+        .map(|(b, c)| (b, char::from_u32(c).unwrap()))
+        .collect()
+}
+
+// Reads a tiktoken mergeable ranks file: one `<base64 token> <rank>` pair per line. The ranks
+// double as BPE merge priorities, so the merges can be recovered from the vocabulary alone.
+pub(crate) fn from_tiktoken<P: AsRef<Path>>(
+    path: P,
+    pattern: Option<String>,
+    special_tokens: Vec<(String, u32)>,
+) -> Result<Tokenizer> {
+    let content = std::fs::read_to_string(path)?;
+    let byte_map = bytes_char();
+
+    let mut vocab: HashMap<String, u32> = HashMap::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (token, rank) = line
+            .split_once(' ')
+            .ok_or_else(|| format!("Invalid tiktoken file at line {}", i + 1))?;
+        let bytes = STANDARD
+            .decode(token)
+            .map_err(|err| format!("Invalid tiktoken file at line {}: {err}", i + 1))?;
+        let rank = rank.trim().parse::<u32>()?;
+        let token = bytes.iter().map(|b| byte_map[b]).collect::<String>();
+        vocab.insert(token, rank);
+    }
+    if vocab.is_empty() {
+        return Err("Invalid tiktoken file: no tokens found".into());
+    }
+
+    let merges = merges_from_vocab(&vocab);
+    // Special tokens keep the ids assigned by the model rather than being appended to the vocab
+    for (token, id) in special_tokens.iter() {
+        vocab.insert(token.clone(), *id);
+    }
+    let bpe = BPE::builder()
+        .vocab_and_merges(vocab, merges)
+        .ignore_merges(true)
+        .build()?;
+
+    let pattern = pattern.unwrap_or_else(|| CL100K_PATTERN.to_string());
+    let split = Split::new(
+        SplitPattern::Regex(pattern),
+        SplitDelimiterBehavior::Isolated,
+        false,
+    )?;
+    let pre_tokenizers: Vec<PreTokenizerWrapper> =
+        vec![split.into(), ByteLevel::new(false, true, false).into()];
+
+    let mut tokenizer = Tokenizer::new(bpe);
+    tokenizer.with_pre_tokenizer(Sequence::new(pre_tokenizers));
+    tokenizer.with_decoder(ByteLevel::default());
+
+    let special_tokens = special_tokens
+        .into_iter()
+        .map(|(token, _)| AddedToken::from(token, true))
+        .collect::<Vec<_>>();
+    tokenizer.add_special_tokens(&special_tokens);
+
+    Ok(tokenizer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_mergeable_ranks() -> Result<()> {
+        let tokens: [&[u8]; 7] = [b"a", b"b", b"c", b" ", b"ab", b"abc", b" ab"];
+        let ranks: String = tokens
+            .iter()
+            .enumerate()
+            .map(|(rank, token)| format!("{} {rank}\n", STANDARD.encode(token)))
+            .collect();
+        let path = std::env::temp_dir().join(format!("djl-tiktoken-{}", std::process::id()));
+        std::fs::write(&path, ranks)?;
+
+        let special_tokens = vec![("<|endoftext|>".to_string(), 100)];
+        let tokenizer = from_tiktoken(&path, None, special_tokens)?;
+        let encoding = tokenizer.encode("abc ab", false)?;
+        assert_eq!(encoding.get_ids(), [5, 6]);
+        assert_eq!(tokenizer.decode(encoding.get_ids(), false)?, "abc ab");
+        assert_eq!(tokenizer.encode("ba", false)?.get_ids(), [1, 0]);
+        let encoding = tokenizer.encode("ab<|endoftext|>", false)?;
+        assert_eq!(encoding.get_ids(), [4, 100]);
+
+        std::fs::write(&path, "not base64\n")?;
+        assert!(from_tiktoken(&path, None, Vec::new()).is_err());
+        Ok(())
+    }
+}
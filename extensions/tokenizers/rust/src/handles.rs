@@ -0,0 +1,237 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use jni::objects::{JByteBuffer, JIntArray, JLongArray, JObject, JObjectArray, JString};
+use jni::sys::{jboolean, jint, jlong, jobjectArray};
+use jni::JNIEnv;
+
+use crate::to_json_string;
+
+/// Unwraps `$result` in a JNI export, or throws its error as an `EngineException` and returns
+/// from the export. Java ignores the value returned along with an exception.
+macro_rules! or_throw {
+    ($env:expr, $result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(err) => {
+                $crate::throw_error(&mut $env, err);
+                return $crate::handles::Thrown::thrown();
+            }
+        }
+    };
+}
+
+/// The value a JNI export returns after throwing.
+pub(crate) trait Thrown {
+    fn thrown() -> Self;
+}
+
+macro_rules! thrown_value {
+    ($($ty:ty => $value:expr),* $(,)?) => {
+        $(impl Thrown for $ty {
+            fn thrown() -> Self {
+                $value
+            }
+        })*
+    };
+}
+
+thrown_value! {
+    () => (),
+    jlong => 0,
+    jint => 0,
+    jboolean => 0,
+    jobjectArray => std::ptr::null_mut(),
+}
+
+macro_rules! thrown_object {
+    ($($ty:ident),*) => {
+        $(impl<'local> Thrown for $ty<'local> {
+            fn thrown() -> Self {
+                $ty::from(JObject::null())
+            }
+        })*
+    };
+}
+
+thrown_object!(JString, JLongArray, JIntArray, JObjectArray, JByteBuffer);
+
+impl<'local> Thrown for JObject<'local> {
+    fn thrown() -> Self {
+        JObject::null()
+    }
+}
+
+/// A handle that's null, freed, e.g. by `freeAllHandles` while Java still holds it, or of another
+/// type.
+#[derive(Debug)]
+pub(crate) struct HandleError(String);
+
+impl fmt::Display for HandleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for HandleError {}
+
+impl From<HandleError> for candle_core::Error {
+    fn from(err: HandleError) -> Self {
+        candle_core::Error::Msg(err.0)
+    }
+}
+
+struct Entry {
+    kind: &'static str,
+    value: Arc<dyn Any + Send + Sync>,
+}
+
+// every value handed to Java is registered until it's dropped, which makes leaks, double frees and
+// uses of freed handles visible. Java only holds ids, the values are shared out as `Arc`s so one
+// freed while a call still uses it lives until that call returns.
+static REGISTRY: RwLock<Option<HashMap<jlong, Entry>>> = RwLock::new(None);
+static NEXT_HANDLE: AtomicI64 = AtomicI64::new(1);
+
+/// Returns the name of the handle type without its module path, e.g. `Tensor` or `Model`.
+fn kind_of<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    let name = name.trim_end_matches('>');
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+pub(crate) fn to_handle<T: Send + Sync + 'static>(val: T) -> jlong {
+    let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+    let entry = Entry {
+        kind: kind_of::<T>(),
+        value: Arc::new(val),
+    };
+    REGISTRY
+        .write()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(handle, entry);
+    handle
+}
+
+fn lookup<T: Send + Sync + 'static>(
+    entries: Option<&HashMap<jlong, Entry>>,
+    handle: jlong,
+) -> Result<Arc<T>, HandleError> {
+    if handle == 0 {
+        return Err(HandleError(format!("Invalid {} handle 0", kind_of::<T>())));
+    }
+    match entries.and_then(|entries| entries.get(&handle)) {
+        Some(entry) => entry.value.clone().downcast::<T>().map_err(|_| {
+            HandleError(format!(
+                "Handle {handle} is a {}, not a {}",
+                entry.kind,
+                kind_of::<T>()
+            ))
+        }),
+        None => Err(HandleError(format!(
+            "{} handle {handle} was freed",
+            kind_of::<T>()
+        ))),
+    }
+}
+
+/// Returns the value behind `handle`, it stays alive while the `Arc` is held.
+pub(crate) fn borrow_handle<T: Send + Sync + 'static>(
+    handle: jlong,
+) -> Result<Arc<T>, HandleError> {
+    lookup(REGISTRY.read().unwrap().as_ref(), handle)
+}
+
+/// Updates the value behind `handle` with `op`. Calls holding the current value keep seeing it
+/// unchanged, `op` runs on a copy unless no one else holds it.
+pub(crate) fn update_handle<T: Clone + Send + Sync + 'static, R>(
+    handle: jlong,
+    op: impl FnOnce(&mut T) -> R,
+) -> Result<R, HandleError> {
+    let mut registry = REGISTRY.write().unwrap();
+    let mut value = lookup::<T>(registry.as_ref(), handle)?;
+    let entry = registry
+        .as_mut()
+        .and_then(|entries| entries.get_mut(&handle))
+        .unwrap();
+    // the registry's reference is dropped first, so the value is only copied if a call holds it
+    entry.value = Arc::new(());
+    let ret = op(Arc::make_mut(&mut value));
+    entry.value = value;
+    Ok(ret)
+}
+
+pub(crate) fn drop_handle<T: 'static>(handle: jlong) {
+    let entry = REGISTRY
+        .write()
+        .unwrap()
+        .as_mut()
+        .and_then(|entries| entries.remove(&handle));
+    // the value is dropped outside the lock, dropping a model may free its own handles
+    match entry {
+        Some(entry) => debug_assert_eq!(entry.kind, kind_of::<T>(), "Handle type mismatch"),
+        None => tracing::warn!("{} handle {handle} was already freed", kind_of::<T>()),
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_getLiveHandles<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+) -> JString<'local> {
+    let mut counts: BTreeMap<&str, u64> = BTreeMap::new();
+    if let Some(entries) = REGISTRY.read().unwrap().as_ref() {
+        for entry in entries.values() {
+            *counts.entry(entry.kind).or_default() += 1;
+        }
+    }
+    to_json_string(&mut env, &counts)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_freeAllHandles(
+    mut env: JNIEnv,
+    _: JObject,
+    kind: JString,
+) -> jlong {
+    let kind: String = env
+        .get_string(&kind)
+        .expect("Couldn't get java string!")
+        .into();
+    let freed: Vec<Entry> = match REGISTRY.write().unwrap().as_mut() {
+        Some(entries) => {
+            let handles: Vec<jlong> = entries
+                .iter()
+                .filter(|(_, entry)| entry.kind == kind)
+                .map(|(&handle, _)| handle)
+                .collect();
+            handles
+                .into_iter()
+                .filter_map(|handle| entries.remove(&handle))
+                .collect()
+        }
+        None => Vec::new(),
+    };
+    // the values are dropped outside the lock, dropping a model may free its own handles. Later
+    // uses of the freed handles throw.
+    let count = freed.len() as jlong;
+    drop(freed);
+    count
+}
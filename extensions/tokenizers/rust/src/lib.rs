@@ -12,32 +12,53 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+#[macro_use]
+mod handles;
 mod ndarray;
 
 #[cfg(feature = "cuda")]
 mod compute_cap;
+mod converters;
 mod models;
+mod threads;
+mod trainers;
 
+#[cfg(feature = "accelerate")]
+extern crate accelerate_src;
+#[cfg(feature = "mkl")]
+extern crate intel_mkl_src;
 extern crate tokenizers as tk;
 
 #[cfg(feature = "cuda")]
 use crate::compute_cap::get_runtime_compute_cap;
+use crate::converters::{from_sentencepiece, from_tiktoken};
+use crate::handles::{borrow_handle, drop_handle, to_handle, update_handle};
+use crate::ndarray::as_device;
+use crate::trainers::{train_bpe, train_unigram, train_wordpiece};
 
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+use candle_core::Tensor;
+use hf_hub::api::sync::{ApiBuilder, ApiError};
 use jni::errors::Error;
 use jni::objects::{
-    JClass, JLongArray, JMethodID, JObject, JObjectArray, JString, JValue, ReleaseMode,
+    JByteArray, JClass, JLongArray, JMethodID, JObject, JObjectArray, JString, JValue, ReleaseMode,
 };
 #[cfg(feature = "cuda")]
 use jni::sys::JNI_FALSE;
 
-use jni::sys::{jboolean, jint, jlong, jobjectArray, jsize, jvalue, JNI_TRUE};
+use jni::sys::{jboolean, jfloat, jint, jlong, jobjectArray, jsize, jvalue, JNI_TRUE};
 use jni::JNIEnv;
 use tk::models::bpe::BPE;
+use tk::normalizers::NormalizerWrapper;
+use tk::pre_tokenizers::PreTokenizerWrapper;
+use tk::processors::template::TemplateProcessing;
+use tk::processors::PostProcessorWrapper;
 use tk::tokenizer::{EncodeInput, Encoding};
-use tk::utils::padding::{PaddingParams, PaddingStrategy};
-use tk::utils::truncation::{TruncationParams, TruncationStrategy};
+use tk::utils::padding::{PaddingDirection, PaddingParams, PaddingStrategy};
+use tk::utils::truncation::{TruncationDirection, TruncationParams, TruncationStrategy};
 use tk::Tokenizer;
 use tk::{FromPretrainedParameters, Offsets};
 
@@ -65,12 +86,55 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     match tokenizer {
         Ok(output) => to_handle(output),
         Err(err) => {
-            env.throw(err.to_string()).unwrap();
+            throw_error(&mut env, err);
             0
         }
     }
 }
 
+/// Downloads the `tokenizer_config.json` of a Hugging Face Hub repo, returns the path of the
+/// cached file or null if the repo doesn't have one.
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_downloadTokenizerConfig<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    input: JString,
+    hf_token: JString,
+) -> JString<'local> {
+    let identifier: String = env
+        .get_string(&input)
+        .expect("Couldn't get java string!")
+        .into();
+
+    let mut builder = ApiBuilder::new().with_progress(false);
+    if !hf_token.is_null() {
+        let hf_token: String = env.get_string(&hf_token).unwrap().into();
+        builder = builder.with_token(Some(hf_token));
+    }
+    let path = builder
+        .build()
+        .and_then(|api| api.model(identifier).get("tokenizer_config.json"));
+
+    match path {
+        Ok(path) => env
+            .new_string(path.to_string_lossy())
+            .expect("Couldn't create java string!"),
+        // the file is optional, the tokenizer itself has already been downloaded
+        Err(ApiError::RequestError(err)) if matches!(*err, ureq::Error::Status(404, _)) => {
+            JObject::null().into()
+        }
+        Err(err) => {
+            throw_error(
+                &mut env,
+                format!("Couldn't download the tokenizer config: {err}"),
+            );
+            JObject::null().into()
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_createTokenizerFromString<
     'local,
@@ -88,7 +152,102 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     match tokenizer {
         Ok(output) => to_handle(output),
         Err(err) => {
-            env.throw(err.to_string()).unwrap();
+            throw_error(&mut env, err);
+            0
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_createTokenizerFromBytes<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    json: JByteArray<'local>,
+) -> jlong {
+    let data = env
+        .convert_byte_array(&json)
+        .expect("Couldn't get java byte array!");
+
+    let tokenizer = Tokenizer::from_bytes(data);
+    match tokenizer {
+        Ok(output) => to_handle(output),
+        Err(err) => {
+            throw_error(&mut env, err);
+            0
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_createSentencePieceTokenizer<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    model_file: JString,
+) -> jlong {
+    let model_file: String = env
+        .get_string(&model_file)
+        .expect("Couldn't get java string!")
+        .into();
+
+    match from_sentencepiece(model_file) {
+        Ok(output) => to_handle(output),
+        Err(err) => {
+            throw_error(&mut env, err);
+            0
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_createTiktokenTokenizer<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    ranks_file: JString,
+    pattern: JString,
+    special_tokens: JObjectArray<'local>,
+    special_token_ids: JLongArray<'local>,
+) -> jlong {
+    let ranks_file: String = env
+        .get_string(&ranks_file)
+        .expect("Couldn't get java string!")
+        .into();
+    let pattern: Option<String> = if pattern.is_null() {
+        None
+    } else {
+        Some(
+            env.get_string(&pattern)
+                .expect("Couldn't get java string!")
+                .into(),
+        )
+    };
+
+    let len = env.get_array_length(&special_tokens).unwrap();
+    let mut ids: Vec<jlong> = vec![0; len as usize];
+    env.get_long_array_region(&special_token_ids, 0, &mut ids)
+        .unwrap();
+    let mut tokens: Vec<(String, u32)> = Vec::with_capacity(len as usize);
+    for i in 0..len {
+        let item = env
+            .get_object_array_element(&special_tokens, i)
+            .unwrap()
+            .into();
+        let token: String = env
+            .get_string(&item)
+            .expect("Couldn't get java string!")
+            .into();
+        tokens.push((token, ids[i as usize] as u32));
+    }
+
+    match from_tiktoken(ranks_file, pattern, tokens) {
+        Ok(output) => to_handle(output),
+        Err(err) => {
+            throw_error(&mut env, err);
             0
         }
     }
@@ -117,7 +276,7 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     match BPE::from_file(&vocabulary, &merges).build() {
         Ok(model) => to_handle(Tokenizer::new(model)),
         Err(err) => {
-            env.throw(err.to_string()).unwrap();
+            throw_error(&mut env, err);
             0
         }
     }
@@ -132,6 +291,134 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     drop_handle::<Tokenizer>(handle);
 }
 
+/// The named tokens of `special_tokens_map.json`, with the names the added special tokens are
+/// matched by once the brackets are removed, e.g. `</s>` is the eos token.
+const SPECIAL_TOKEN_NAMES: [(&str, &[&str]); 7] = [
+    ("bos_token", &["bos", "s", "startoftext"]),
+    ("eos_token", &["eos", "/s", "endoftext"]),
+    ("unk_token", &["unk"]),
+    ("sep_token", &["sep"]),
+    ("pad_token", &["pad"]),
+    ("cls_token", &["cls"]),
+    ("mask_token", &["mask"]),
+];
+
+/// Builds the `special_tokens_map.json` of `tokenizer`. The padding, the model and the
+/// post-processor name the pad, unk, cls and sep tokens, the other names are matched against the
+/// added special tokens. The special tokens without a name are the additional special tokens.
+fn special_tokens_map(tokenizer: &Tokenizer) -> serde_json::Value {
+    let mut special_tokens = tokenizer
+        .get_added_tokens_decoder()
+        .into_iter()
+        .filter(|(_, token)| token.special)
+        .collect::<Vec<_>>();
+    special_tokens.sort_by_key(|(id, _)| *id);
+    let special_tokens = special_tokens
+        .into_iter()
+        .map(|(_, token)| token.content)
+        .collect::<Vec<_>>();
+
+    let mut map = serde_json::Map::new();
+    if let Some(padding) = tokenizer.get_padding() {
+        map.insert("pad_token".to_string(), padding.pad_token.clone().into());
+    }
+    // BPE, WordPiece and WordLevel name their unk token, Unigram has its id
+    if let Ok(model) = serde_json::to_value(tokenizer.get_model()) {
+        let unk_token = match (&model["unk_token"], model["unk_id"].as_u64()) {
+            (serde_json::Value::String(token), _) => Some(token.clone()),
+            (_, Some(id)) => tokenizer.id_to_token(id as u32),
+            _ => None,
+        };
+        if let Some(unk_token) = unk_token {
+            map.insert("unk_token".to_string(), unk_token.into());
+        }
+    }
+    let post_processor = tokenizer
+        .get_post_processor()
+        .and_then(|post_processor| serde_json::to_value(post_processor).ok());
+    if let Some((cls, sep)) = post_processor.as_ref().and_then(cls_sep_tokens) {
+        map.insert("cls_token".to_string(), cls.into());
+        map.insert("sep_token".to_string(), sep.into());
+    }
+    for token in &special_tokens {
+        let name = token
+            .trim_matches(|c| matches!(c, '[' | ']' | '<' | '>' | '|'))
+            .to_lowercase();
+        for (key, names) in SPECIAL_TOKEN_NAMES {
+            if names.contains(&name.as_str()) && !map.contains_key(key) {
+                map.insert(key.to_string(), token.clone().into());
+            }
+        }
+    }
+
+    let additional_special_tokens = special_tokens
+        .into_iter()
+        .filter(|token| !map.values().any(|named| named == token))
+        .collect::<Vec<_>>();
+    map.insert(
+        "additional_special_tokens".to_string(),
+        additional_special_tokens.into(),
+    );
+    serde_json::Value::Object(map)
+}
+
+/// Returns the cls and sep tokens the serialized `post_processor` adds around a single sequence.
+fn cls_sep_tokens(post_processor: &serde_json::Value) -> Option<(String, String)> {
+    match post_processor["type"].as_str()? {
+        "BertProcessing" | "RobertaProcessing" => {
+            let cls = post_processor["cls"][0].as_str()?;
+            let sep = post_processor["sep"][0].as_str()?;
+            Some((cls.to_string(), sep.to_string()))
+        }
+        "TemplateProcessing" => {
+            // e.g. `[CLS] $A [SEP]`, a template without tokens on both sides has none
+            let single = post_processor["single"].as_array()?;
+            let special_token = |piece: &serde_json::Value| {
+                piece["SpecialToken"]["id"].as_str().map(str::to_string)
+            };
+            Some((
+                special_token(single.first()?)?,
+                special_token(single.last()?)?,
+            ))
+        }
+        "Sequence" => post_processor["processors"]
+            .as_array()?
+            .iter()
+            .find_map(cls_sep_tokens),
+        _ => None,
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_saveTokenizer<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+    path: JString,
+) {
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
+    let path: String = env
+        .get_string(&path)
+        .expect("Couldn't get java string!")
+        .into();
+
+    let dir = PathBuf::from(path);
+    let save = || -> tk::Result<()> {
+        std::fs::create_dir_all(&dir)?;
+        tokenizer.save(dir.join("tokenizer.json"), true)?;
+
+        let special_tokens_map = serde_json::to_string_pretty(&special_tokens_map(&tokenizer))?;
+        std::fs::write(dir.join("special_tokens_map.json"), special_tokens_map)?;
+        Ok(())
+    };
+
+    if let Err(err) = save() {
+        throw_error(&mut env, err);
+    }
+}
+
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_encode<'local>(
     mut env: JNIEnv<'local>,
@@ -140,7 +427,7 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     input: JString,
     add_special_tokens: jboolean,
 ) -> jlong {
-    let tokenizer = cast_handle::<Tokenizer>(handle);
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
     let sequence: String = env
         .get_string(&input)
         .expect("Couldn't get java string!")
@@ -153,7 +440,7 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     match encoding {
         Ok(output) => to_handle(output),
         Err(err) => {
-            env.throw(err.to_string()).unwrap();
+            throw_error(&mut env, err);
             0
         }
     }
@@ -170,7 +457,7 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     text_pair: JString,
     add_special_tokens: jboolean,
 ) -> jlong {
-    let tokenizer = cast_handle::<Tokenizer>(handle);
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
     let sequence1: String = env
         .get_string(&text)
         .expect("Couldn't get text string!")
@@ -188,7 +475,7 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     match encoding {
         Ok(output) => to_handle(output),
         Err(err) => {
-            env.throw(err.to_string()).unwrap();
+            throw_error(&mut env, err);
             0
         }
     }
@@ -204,7 +491,7 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     inputs: JObjectArray<'local>,
     add_special_tokens: jboolean,
 ) -> jlong {
-    let tokenizer = cast_handle::<Tokenizer>(handle);
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
     let len = env.get_array_length(&inputs).unwrap();
     let mut array: Vec<String> = Vec::new();
     for i in 0..len {
@@ -223,7 +510,7 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     match encoding {
         Ok(output) => to_handle(output),
         Err(err) => {
-            env.throw(err.to_string()).unwrap();
+            throw_error(&mut env, err);
             0
         }
     }
@@ -239,7 +526,7 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     inputs: JObjectArray<'local>,
     add_special_tokens: jboolean,
 ) -> JLongArray<'local> {
-    let tokenizer = cast_handle::<Tokenizer>(handle);
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
     let len = env.get_array_length(&inputs).unwrap();
     let mut array: Vec<String> = Vec::new();
     for i in 0..len {
@@ -265,6 +552,60 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     ret
 }
 
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_batchEncodeToTensors<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+    inputs: JObjectArray<'local>,
+    add_special_tokens: jboolean,
+    device_type: JString,
+    device_id: jint,
+) -> JLongArray<'local> {
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
+    let inputs = from_string_array(&mut env, &inputs);
+
+    let encode = || -> tk::Result<Vec<jlong>> {
+        let device = as_device(&mut env, device_type, device_id as usize)?;
+        let encodings = tokenizer.encode_batch(inputs, add_special_tokens == JNI_TRUE)?;
+        let batch_size = encodings.len();
+        let seq_len = encodings.first().map_or(0, |e| e.len());
+        if encodings.iter().any(|e| e.len() != seq_len) {
+            return Err("Encodings must have the same length, padding is required".into());
+        }
+
+        let mut ids: Vec<i64> = Vec::with_capacity(batch_size * seq_len);
+        let mut attention_mask: Vec<i64> = Vec::with_capacity(batch_size * seq_len);
+        let mut type_ids: Vec<i64> = Vec::with_capacity(batch_size * seq_len);
+        for encoding in encodings.iter() {
+            ids.extend(encoding.get_ids().iter().map(|i| *i as i64));
+            attention_mask.extend(encoding.get_attention_mask().iter().map(|i| *i as i64));
+            type_ids.extend(encoding.get_type_ids().iter().map(|i| *i as i64));
+        }
+
+        let shape = (batch_size, seq_len);
+        let tensors = [
+            Tensor::from_vec(ids, shape, &device)?,
+            Tensor::from_vec(attention_mask, shape, &device)?,
+            Tensor::from_vec(type_ids, shape, &device)?,
+        ];
+        Ok(tensors.into_iter().map(to_handle).collect())
+    };
+
+    let handles = match encode() {
+        Ok(handles) => handles,
+        Err(err) => {
+            throw_error(&mut env, err);
+            Vec::new()
+        }
+    };
+    let ret = env.new_long_array(handles.len() as jsize).unwrap();
+    env.set_long_array_region(&ret, 0, &handles).unwrap();
+    ret
+}
+
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_batchEncodePair<
     'local,
@@ -276,7 +617,7 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     text_pair: JObjectArray<'local>,
     add_special_tokens: jboolean,
 ) -> JLongArray<'local> {
-    let tokenizer = cast_handle::<Tokenizer>(handle);
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
     let len = env.get_array_length(&text).unwrap();
     let mut array: Vec<EncodeInput> = Vec::new();
     for i in 0..len {
@@ -324,11 +665,11 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
 pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_getTokenIds<
     'local,
 >(
-    env: JNIEnv<'local>,
+    mut env: JNIEnv<'local>,
     _: JObject,
     handle: jlong,
 ) -> JLongArray<'local> {
-    let encoding = cast_handle::<Encoding>(handle);
+    let encoding = or_throw!(env, borrow_handle::<Encoding>(handle));
     let ids = encoding.get_ids();
     let len = ids.len() as jsize;
     let mut long_ids: Vec<jlong> = Vec::new();
@@ -346,11 +687,11 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
 pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_getTypeIds<
     'local,
 >(
-    env: JNIEnv<'local>,
+    mut env: JNIEnv<'local>,
     _: JObject,
     handle: jlong,
 ) -> JLongArray<'local> {
-    let encoding = cast_handle::<Encoding>(handle);
+    let encoding = or_throw!(env, borrow_handle::<Encoding>(handle));
     let type_ids = encoding.get_type_ids();
     let len = type_ids.len() as jsize;
     let mut long_ids: Vec<jlong> = Vec::new();
@@ -367,11 +708,11 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
 pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_getWordIds<
     'local,
 >(
-    env: JNIEnv<'local>,
+    mut env: JNIEnv<'local>,
     _: JObject,
     handle: jlong,
 ) -> JLongArray<'local> {
-    let encoding = cast_handle::<Encoding>(handle);
+    let encoding = or_throw!(env, borrow_handle::<Encoding>(handle));
     let word_ids = encoding.get_word_ids();
     let len = word_ids.len() as jsize;
     let mut long_ids: Vec<jlong> = Vec::new();
@@ -396,7 +737,7 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     _: JObject,
     handle: jlong,
 ) -> JObjectArray<'local> {
-    let encoding = cast_handle::<Encoding>(handle);
+    let encoding = or_throw!(env, borrow_handle::<Encoding>(handle));
     let tokens = encoding.get_tokens();
     let len = tokens.len() as jsize;
 
@@ -415,11 +756,11 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
 pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_getAttentionMask<
     'local,
 >(
-    env: JNIEnv<'local>,
+    mut env: JNIEnv<'local>,
     _: JObject,
     handle: jlong,
 ) -> JLongArray<'local> {
-    let encoding = cast_handle::<Encoding>(handle);
+    let encoding = or_throw!(env, borrow_handle::<Encoding>(handle));
     let attention_masks = encoding.get_attention_mask();
     let len = attention_masks.len() as jsize;
     let mut long_ids: Vec<jlong> = Vec::new();
@@ -436,11 +777,11 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
 pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_getSpecialTokenMask<
     'local,
 >(
-    env: JNIEnv<'local>,
+    mut env: JNIEnv<'local>,
     _: JObject,
     handle: jlong,
 ) -> JLongArray<'local> {
-    let encoding = cast_handle::<Encoding>(handle);
+    let encoding = or_throw!(env, borrow_handle::<Encoding>(handle));
     let special_token_masks = encoding.get_special_tokens_mask();
     let len = special_token_masks.len() as jsize;
     let mut long_ids: Vec<jlong> = Vec::new();
@@ -461,7 +802,7 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     _: JObject,
     handle: jlong,
 ) -> JObjectArray<'local> {
-    let encoding = cast_handle::<Encoding>(handle);
+    let encoding = or_throw!(env, borrow_handle::<Encoding>(handle));
     let tokens = encoding.get_tokens();
     let len = tokens.len() as jsize;
 
@@ -501,11 +842,11 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
 pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_getOverflowing<
     'local,
 >(
-    env: JNIEnv<'local>,
+    mut env: JNIEnv<'local>,
     _: JObject,
     handle: jlong,
 ) -> JLongArray<'local> {
-    let encoding = cast_handle::<Encoding>(handle);
+    let encoding = or_throw!(env, borrow_handle::<Encoding>(handle));
     let handles = encoding
         .get_overflowing()
         .clone()
@@ -526,7 +867,7 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     ids: JLongArray<'local>,
     skip_special_tokens: jboolean,
 ) -> JString<'local> {
-    let tokenizer = cast_handle::<Tokenizer>(handle);
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
     let long_ids = unsafe { env.get_array_elements(&ids, ReleaseMode::NoCopyBack) }.unwrap();
     let long_ids_ptr = long_ids.as_ptr();
     let len = long_ids.len();
@@ -557,7 +898,7 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     batch_ids: JObjectArray<'local>,
     skip_special_tokens: jboolean,
 ) -> JObjectArray<'local> {
-    let tokenizer = cast_handle::<Tokenizer>(handle);
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
     let batch_len = env.get_array_length(&batch_ids).unwrap();
     let mut batch_decode_input: Vec<Vec<u32>> = Vec::new();
     unsafe {
@@ -595,15 +936,153 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     ret
 }
 
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_tokenToId<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+    token: JString,
+) -> jlong {
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
+    let token: String = env
+        .get_string(&token)
+        .expect("Couldn't get java string!")
+        .into();
+
+    match tokenizer.token_to_id(&token) {
+        Some(id) => id as jlong,
+        None => -1,
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_idToToken<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+    id: jlong,
+) -> JString<'local> {
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
+    match tokenizer.id_to_token(id as u32) {
+        Some(token) => env.new_string(token).expect("Couldn't create java string!"),
+        None => JString::from(JObject::null()),
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_batchTokenToId<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+    tokens: JObjectArray<'local>,
+) -> JLongArray<'local> {
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
+    let len = env.get_array_length(&tokens).unwrap();
+    let mut ids: Vec<jlong> = Vec::with_capacity(len as usize);
+    for i in 0..len {
+        let item = env.get_object_array_element(&tokens, i).unwrap().into();
+        let token: String = env
+            .get_string(&item)
+            .expect("Couldn't get java string!")
+            .into();
+        match tokenizer.token_to_id(&token) {
+            Some(id) => ids.push(id as jlong),
+            None => ids.push(-1),
+        }
+    }
+
+    let array = env.new_long_array(len).unwrap();
+    env.set_long_array_region(&array, 0, &ids).unwrap();
+    array
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_batchIdToToken<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+    ids: JLongArray<'local>,
+) -> JObjectArray<'local> {
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
+    let ids = unsafe { env.get_array_elements(&ids, ReleaseMode::NoCopyBack) }.unwrap();
+    let tokens = ids
+        .iter()
+        .map(|id| tokenizer.id_to_token(*id as u32))
+        .collect::<Vec<_>>();
+
+    let array = env
+        .new_object_array(tokens.len() as jsize, "java/lang/String", JObject::null())
+        .unwrap();
+    for (i, token) in tokens.into_iter().enumerate() {
+        if let Some(token) = token {
+            let item: JString = env.new_string(token).unwrap();
+            env.set_object_array_element(&array, i as jsize, item)
+                .unwrap();
+        }
+    }
+    array
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_getVocabTokens<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+    with_added_tokens: jboolean,
+) -> JObjectArray<'local> {
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
+    let vocab = sorted_vocab(&tokenizer, with_added_tokens == JNI_TRUE);
+
+    let array = env
+        .new_object_array(vocab.len() as jsize, "java/lang/String", JObject::null())
+        .unwrap();
+    for (i, (token, _)) in vocab.into_iter().enumerate() {
+        let item: JString = env.new_string(token).unwrap();
+        env.set_object_array_element(&array, i as jsize, item)
+            .unwrap();
+    }
+    array
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_getVocabIds<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+    with_added_tokens: jboolean,
+) -> JLongArray<'local> {
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
+    let ids = sorted_vocab(&tokenizer, with_added_tokens == JNI_TRUE)
+        .into_iter()
+        .map(|(_, id)| id as jlong)
+        .collect::<Vec<_>>();
+
+    let array = env.new_long_array(ids.len() as jsize).unwrap();
+    env.set_long_array_region(&array, 0, &ids).unwrap();
+    array
+}
+
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_getTruncationStrategy<
     'local,
 >(
-    env: JNIEnv<'local>,
+    mut env: JNIEnv<'local>,
     _: JObject,
     handle: jlong,
 ) -> JString<'local> {
-    let tokenizer = cast_handle::<Tokenizer>(handle);
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
     let truncation = tokenizer.get_truncation();
     let strategy = match truncation {
         Some(val) => val.strategy.as_ref(),
@@ -621,11 +1100,11 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
 pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_getPaddingStrategy<
     'local,
 >(
-    env: JNIEnv<'local>,
+    mut env: JNIEnv<'local>,
     _: JObject,
     handle: jlong,
 ) -> JString<'local> {
-    let tokenizer = cast_handle::<Tokenizer>(handle);
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
     let padding = tokenizer.get_padding();
     let strategy = match padding {
         Some(val) => match val.strategy {
@@ -644,11 +1123,11 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
 
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_getMaxLength(
-    _: JNIEnv,
+    mut env: JNIEnv,
     _: JObject,
     handle: jlong,
 ) -> jint {
-    let tokenizer = cast_handle::<Tokenizer>(handle);
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
     let truncation = tokenizer.get_truncation();
     let mut max_length = match truncation {
         Some(val) => val.max_length as jint,
@@ -669,11 +1148,11 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
 
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_getStride(
-    _: JNIEnv,
+    mut env: JNIEnv,
     _: JObject,
     handle: jlong,
 ) -> jint {
-    let tokenizer = cast_handle::<Tokenizer>(handle);
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
     let truncation = tokenizer.get_truncation();
     let ret = match truncation {
         Some(val) => val.stride,
@@ -684,11 +1163,11 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
 
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_getPadToMultipleOf(
-    _: JNIEnv,
+    mut env: JNIEnv,
     _: JObject,
     handle: jlong,
 ) -> jint {
-    let tokenizer = cast_handle::<Tokenizer>(handle);
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
     let padding = tokenizer.get_padding();
     let ret = match padding {
         Some(val) => val.pad_to_multiple_of.unwrap_or(0),
@@ -707,6 +1186,7 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     max_length: jint,
     padding_strategy: JString,
     pad_to_multiple_of: jint,
+    padding_side: JString,
 ) {
     let strategy: String = env
         .get_string(&padding_strategy)
@@ -718,35 +1198,49 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
         "MAX_LENGTH" => Ok(PaddingStrategy::Fixed(len)),
         _ => Err("strategy must be one of [longest, max_length]"),
     };
+    let side: String = env
+        .get_string(&padding_side)
+        .expect("Couldn't get java string!")
+        .into();
+    let res_direction = match side.as_ref() {
+        "LEFT" => Ok(PaddingDirection::Left),
+        "RIGHT" => Ok(PaddingDirection::Right),
+        _ => Err("padding side must be one of [left, right]"),
+    };
 
     let res_pad_to_multiple_of = match pad_to_multiple_of as usize {
         0 => None,
         val => Some(val),
     };
 
-    let tokenizer = cast_handle::<Tokenizer>(handle);
-
-    if let Some(padding_params) = tokenizer.get_padding_mut() {
-        padding_params.strategy = res_strategy.unwrap();
-        padding_params.pad_to_multiple_of = res_pad_to_multiple_of;
-    } else {
-        let padding_params = PaddingParams {
-            strategy: res_strategy.unwrap(),
-            pad_to_multiple_of: res_pad_to_multiple_of,
-            ..Default::default()
-        };
-        tokenizer.with_padding(Some(padding_params));
-    }
+    let op = |tokenizer: &mut Tokenizer| {
+        if let Some(padding_params) = tokenizer.get_padding_mut() {
+            padding_params.strategy = res_strategy.unwrap();
+            padding_params.pad_to_multiple_of = res_pad_to_multiple_of;
+            padding_params.direction = res_direction.unwrap();
+        } else {
+            let padding_params = PaddingParams {
+                strategy: res_strategy.unwrap(),
+                pad_to_multiple_of: res_pad_to_multiple_of,
+                direction: res_direction.unwrap(),
+                ..Default::default()
+            };
+            tokenizer.with_padding(Some(padding_params));
+        }
+    };
+    or_throw!(env, update_handle(handle, op));
 }
 
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_disablePadding(
-    _: JNIEnv,
+    mut env: JNIEnv,
     _: JObject,
     handle: jlong,
 ) {
-    let tokenizer = cast_handle::<Tokenizer>(handle);
-    tokenizer.with_padding(None);
+    let op = |tokenizer: &mut Tokenizer| {
+        tokenizer.with_padding(None);
+    };
+    or_throw!(env, update_handle(handle, op));
 }
 
 #[no_mangle]
@@ -759,6 +1253,7 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
     truncation_max_length: jint,
     truncation_strategy: JString,
     truncation_stride: jint,
+    truncation_side: JString,
 ) {
     let strategy: String = env
         .get_string(&truncation_strategy)
@@ -770,32 +1265,247 @@ pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_
         "ONLY_SECOND" => Ok(TruncationStrategy::OnlySecond),
         _ => Err("strategy must be one of [longest_first, only_first, only_second]"),
     };
+    let side: String = env
+        .get_string(&truncation_side)
+        .expect("Couldn't get java string!")
+        .into();
+    let res_direction = match side.as_ref() {
+        "LEFT" => Ok(TruncationDirection::Left),
+        "RIGHT" => Ok(TruncationDirection::Right),
+        _ => Err("truncation side must be one of [left, right]"),
+    };
 
-    let tokenizer = cast_handle::<Tokenizer>(handle);
+    let op = |tokenizer: &mut Tokenizer| {
+        if let Some(truncation_params) = tokenizer.get_truncation_mut() {
+            truncation_params.strategy = res_strategy.unwrap();
+            truncation_params.stride = truncation_stride as usize;
+            truncation_params.max_length = truncation_max_length as usize;
+            truncation_params.direction = res_direction.unwrap();
+        } else {
+            let truncation_params = TruncationParams {
+                strategy: res_strategy.unwrap(),
+                stride: truncation_stride as usize,
+                max_length: truncation_max_length as usize,
+                direction: res_direction.unwrap(),
+                ..Default::default()
+            };
+            let _ = tokenizer.with_truncation(Some(truncation_params));
+        }
+    };
+    or_throw!(env, update_handle(handle, op));
+}
 
-    if let Some(truncation_params) = tokenizer.get_truncation_mut() {
-        truncation_params.strategy = res_strategy.unwrap();
-        truncation_params.stride = truncation_stride as usize;
-        truncation_params.max_length = truncation_max_length as usize;
-    } else {
-        let truncation_params = TruncationParams {
-            strategy: res_strategy.unwrap(),
-            stride: truncation_stride as usize,
-            max_length: truncation_max_length as usize,
-            ..Default::default()
-        };
-        let _ = tokenizer.with_truncation(Some(truncation_params));
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_disableTruncation(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+) {
+    let op = |tokenizer: &mut Tokenizer| {
+        let _ = tokenizer.with_truncation(None);
+    };
+    or_throw!(env, update_handle(handle, op));
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_getNormalizer<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+) -> JString<'local> {
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
+    match tokenizer.get_normalizer() {
+        Some(normalizer) => to_json_string(&mut env, normalizer),
+        None => JString::from(JObject::null()),
     }
 }
 
 #[no_mangle]
-pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_disableTruncation(
-    _: JNIEnv,
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_setNormalizer<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
     _: JObject,
     handle: jlong,
+    json: JString,
 ) {
-    let tokenizer = cast_handle::<Tokenizer>(handle);
-    let _ = tokenizer.with_truncation(None);
+    let json: String = env
+        .get_string(&json)
+        .expect("Couldn't get java string!")
+        .into();
+    match serde_json::from_str::<NormalizerWrapper>(&json) {
+        Ok(normalizer) => {
+            let op = |tokenizer: &mut Tokenizer| {
+                tokenizer.with_normalizer(normalizer);
+            };
+            or_throw!(env, update_handle(handle, op));
+        }
+        Err(err) => {
+            throw_error(&mut env, format!("Invalid normalizer: {err}"));
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_getPreTokenizer<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+) -> JString<'local> {
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
+    match tokenizer.get_pre_tokenizer() {
+        Some(pre_tokenizer) => to_json_string(&mut env, pre_tokenizer),
+        None => JString::from(JObject::null()),
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_setPreTokenizer<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+    json: JString,
+) {
+    let json: String = env
+        .get_string(&json)
+        .expect("Couldn't get java string!")
+        .into();
+    match serde_json::from_str::<PreTokenizerWrapper>(&json) {
+        Ok(pre_tokenizer) => {
+            let op = |tokenizer: &mut Tokenizer| {
+                tokenizer.with_pre_tokenizer(pre_tokenizer);
+            };
+            or_throw!(env, update_handle(handle, op));
+        }
+        Err(err) => {
+            throw_error(&mut env, format!("Invalid pre-tokenizer: {err}"));
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_getPostProcessor<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+) -> JString<'local> {
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
+    match tokenizer.get_post_processor() {
+        Some(post_processor) => to_json_string(&mut env, post_processor),
+        None => JString::from(JObject::null()),
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_setPostProcessor<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+    json: JString,
+) {
+    let json: String = env
+        .get_string(&json)
+        .expect("Couldn't get java string!")
+        .into();
+    match serde_json::from_str::<PostProcessorWrapper>(&json) {
+        Ok(post_processor) => {
+            let op = |tokenizer: &mut Tokenizer| {
+                tokenizer.with_post_processor(post_processor);
+            };
+            or_throw!(env, update_handle(handle, op));
+        }
+        Err(err) => {
+            throw_error(&mut env, format!("Invalid post-processor: {err}"));
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_setTemplateProcessing<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+    single: JString,
+    pair: JString,
+) {
+    let single: String = env
+        .get_string(&single)
+        .expect("Couldn't get java string!")
+        .into();
+    let pair: Option<String> = if pair.is_null() {
+        None
+    } else {
+        Some(
+            env.get_string(&pair)
+                .expect("Couldn't get java string!")
+                .into(),
+        )
+    };
+
+    let tokenizer = or_throw!(env, borrow_handle::<Tokenizer>(handle));
+    let post_processor = template_processing(&tokenizer, single, pair);
+    // the setter would copy the tokenizer while it's still borrowed here
+    drop(tokenizer);
+    match post_processor {
+        Ok(post_processor) => {
+            let op = |tokenizer: &mut Tokenizer| {
+                tokenizer.with_post_processor(post_processor);
+            };
+            or_throw!(env, update_handle(handle, op));
+        }
+        Err(err) => {
+            throw_error(&mut env, err);
+        }
+    }
+}
+
+// Builds a TemplateProcessing from templates like `[CLS] $A [SEP] $B:1 [SEP]:1`, the ids of the
+// special tokens are resolved from the tokenizer vocabulary.
+fn template_processing(
+    tokenizer: &Tokenizer,
+    single: String,
+    pair: Option<String>,
+) -> tk::Result<TemplateProcessing> {
+    let mut special_tokens: Vec<(String, u32)> = Vec::new();
+    let templates = std::iter::once(&single).chain(pair.iter());
+    for piece in templates.flat_map(|template| template.split_whitespace()) {
+        if piece.starts_with('$') {
+            continue;
+        }
+        let token = match piece.rsplit_once(':') {
+            Some((token, type_id)) if type_id.parse::<u32>().is_ok() => token,
+            _ => piece,
+        };
+        if special_tokens.iter().any(|(t, _)| t == token) {
+            continue;
+        }
+        let id = tokenizer
+            .token_to_id(token)
+            .ok_or_else(|| format!("Special token {token} not found in vocabulary"))?;
+        special_tokens.push((token.to_string(), id));
+    }
+
+    let mut builder = TemplateProcessing::builder();
+    builder.try_single(single)?.special_tokens(special_tokens);
+    if let Some(pair) = pair {
+        builder.try_pair(pair)?;
+    }
+    let post_processor = builder
+        .build()
+        .map_err(|err| format!("Invalid template: {err}"))?;
+    Ok(post_processor)
 }
 
 #[no_mangle]
@@ -814,24 +1524,249 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_isCudaAvailable<'loca
     }
 }
 
-fn to_handle<T: 'static>(val: T) -> jlong {
-    let handle = Box::into_raw(Box::new(val)) as jlong;
-    handle
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_isMetalAvailable<'local>(
+    _: JNIEnv,
+    _: JObject,
+) -> jboolean {
+    if candle_core::utils::metal_is_available() {
+        JNI_TRUE
+    } else {
+        JNI_FALSE
+    }
 }
 
-fn cast_handle<T>(handle: jlong) -> &'static mut T {
-    assert_ne!(handle, 0, "Invalid handle value");
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_getBlasBackend<'local>(
+    env: JNIEnv<'local>,
+    _: JObject,
+) -> JString<'local> {
+    let backend = if candle_core::utils::has_mkl() {
+        "mkl"
+    } else if candle_core::utils::has_accelerate() {
+        "accelerate"
+    } else {
+        "gemm"
+    };
+    env.new_string(backend).unwrap()
+}
+
+static DETERMINISTIC: AtomicBool = AtomicBool::new(false);
+// the reduced precision settings of the cuBLAS matmuls before deterministic mode turned them
+// off, restored when it's disabled
+#[cfg(feature = "cuda")]
+static REDUCED_PRECISION: std::sync::Mutex<Option<[bool; 3]>> = std::sync::Mutex::new(None);
 
-    let ptr = handle as *mut T;
-    unsafe { &mut *ptr }
+#[cfg(feature = "mkl")]
+extern "C" {
+    fn mkl_cbwr_set(settings: std::os::raw::c_int) -> std::os::raw::c_int;
 }
 
-fn drop_handle<T: 'static>(handle: jlong) {
-    unsafe {
-        let _ = Box::from_raw(handle as *mut T);
+#[cfg(feature = "mkl")]
+const MKL_CBWR_OFF: std::os::raw::c_int = 0;
+#[cfg(feature = "mkl")]
+const MKL_CBWR_AUTO: std::os::raw::c_int = 2;
+#[cfg(feature = "mkl")]
+const MKL_CBWR_STRICT: std::os::raw::c_int = 0x10000;
+
+/// Returns `true` if the math libraries run with the reproducible settings.
+fn is_deterministic() -> bool {
+    DETERMINISTIC.load(Ordering::Acquire)
+}
+
+/// Makes the math libraries pick reproducible algorithms. The F32, F16 and BF16 cuBLAS matmuls
+/// stop using TF32 and reduced precision reductions, MKL's strict CNR mode keeps the reduction
+/// order independent of the thread count and the instruction set. candle's own CPU and CUDA
+/// kernels reduce each output in a fixed order and cuDNN isn't linked.
+///
+/// cuBLAS also needs a fixed workspace per stream to pick the same split-K reductions, it reads
+/// `CUBLAS_WORKSPACE_CONFIG=:4096:8` when its handle is created so the variable has to be set
+/// before the process starts.
+fn set_deterministic(enabled: bool) -> Result<(), String> {
+    if is_deterministic() == enabled {
+        return Ok(());
+    }
+    #[cfg(feature = "mkl")]
+    {
+        let settings = if enabled {
+            MKL_CBWR_AUTO | MKL_CBWR_STRICT
+        } else {
+            MKL_CBWR_OFF
+        };
+        // MKL reads the mode on its first call and refuses to change it later
+        if unsafe { mkl_cbwr_set(settings) } != 0 {
+            return Err(
+                "Deterministic mode can only change before MKL runs the first inference"
+                    .to_string(),
+            );
+        }
+    }
+    #[cfg(feature = "cuda")]
+    {
+        use candle_core::cuda_backend as cuda;
+
+        let mut saved = REDUCED_PRECISION.lock().unwrap();
+        if enabled {
+            if saved.is_none() {
+                *saved = Some([
+                    cuda::gemm_reduced_precision_f32(),
+                    cuda::gemm_reduced_precision_f16(),
+                    cuda::gemm_reduced_precision_bf16(),
+                ]);
+            }
+            cuda::set_gemm_reduced_precision_f32(false);
+            cuda::set_gemm_reduced_precision_f16(false);
+            cuda::set_gemm_reduced_precision_bf16(false);
+        } else if let Some([f32, f16, bf16]) = saved.take() {
+            cuda::set_gemm_reduced_precision_f32(f32);
+            cuda::set_gemm_reduced_precision_f16(f16);
+            cuda::set_gemm_reduced_precision_bf16(bf16);
+        }
+    }
+    DETERMINISTIC.store(enabled, Ordering::Release);
+    Ok(())
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_setDeterministic(
+    mut env: JNIEnv,
+    _: JObject,
+    enabled: jboolean,
+) {
+    if let Err(err) = set_deterministic(enabled == JNI_TRUE) {
+        throw_error(&mut env, err);
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_isDeterministic(
+    _: JNIEnv,
+    _: JObject,
+) -> jboolean {
+    if is_deterministic() {
+        JNI_TRUE
+    } else {
+        JNI_FALSE
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_trainBpeTokenizer<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    files: JObjectArray<'local>,
+    texts: JObjectArray<'local>,
+    vocab_size: jint,
+    min_frequency: jint,
+    special_tokens: JObjectArray<'local>,
+) -> jlong {
+    let files = from_string_array(&mut env, &files);
+    let texts = from_string_array(&mut env, &texts);
+    let special_tokens = from_string_array(&mut env, &special_tokens);
+
+    match train_bpe(
+        files,
+        texts,
+        vocab_size as usize,
+        min_frequency as u64,
+        special_tokens,
+    ) {
+        Ok(output) => to_handle(output),
+        Err(err) => {
+            throw_error(&mut env, err);
+            0
+        }
     }
 }
 
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_trainWordPieceTokenizer<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    files: JObjectArray<'local>,
+    texts: JObjectArray<'local>,
+    vocab_size: jint,
+    min_frequency: jint,
+    special_tokens: JObjectArray<'local>,
+    character_coverage: jfloat,
+    unk_token: JString,
+) -> jlong {
+    let files = from_string_array(&mut env, &files);
+    let texts = from_string_array(&mut env, &texts);
+    let special_tokens = from_string_array(&mut env, &special_tokens);
+    let unk_token: String = env
+        .get_string(&unk_token)
+        .expect("Couldn't get java string!")
+        .into();
+
+    match train_wordpiece(
+        files,
+        texts,
+        vocab_size as usize,
+        min_frequency as u64,
+        special_tokens,
+        character_coverage,
+        unk_token,
+    ) {
+        Ok(output) => to_handle(output),
+        Err(err) => {
+            throw_error(&mut env, err);
+            0
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_huggingface_tokenizers_jni_TokenizersLibrary_trainUnigramTokenizer<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    files: JObjectArray<'local>,
+    texts: JObjectArray<'local>,
+    vocab_size: jint,
+    special_tokens: JObjectArray<'local>,
+    character_coverage: jfloat,
+    unk_token: JString,
+) -> jlong {
+    let files = from_string_array(&mut env, &files);
+    let texts = from_string_array(&mut env, &texts);
+    let special_tokens = from_string_array(&mut env, &special_tokens);
+    let unk_token: String = env
+        .get_string(&unk_token)
+        .expect("Couldn't get java string!")
+        .into();
+
+    match train_unigram(
+        files,
+        texts,
+        vocab_size as u32,
+        special_tokens,
+        character_coverage,
+        unk_token,
+    ) {
+        Ok(output) => to_handle(output),
+        Err(err) => {
+            throw_error(&mut env, err);
+            0
+        }
+    }
+}
+
+// Vocabulary entries ordered by id, so that tokens and ids can be returned as parallel arrays
+fn sorted_vocab(tokenizer: &Tokenizer, with_added_tokens: bool) -> Vec<(String, u32)> {
+    let mut vocab = tokenizer
+        .get_vocab(with_added_tokens)
+        .into_iter()
+        .collect::<Vec<_>>();
+    vocab.sort_by_key(|(_, id)| *id);
+    vocab
+}
+
 fn to_string_array(env: &mut JNIEnv, data: Vec<String>) -> Result<jobjectArray, Error> {
     let arr = env.new_object_array(data.len() as i32, "java/lang/String", JObject::null())?;
 
@@ -842,3 +1777,36 @@ fn to_string_array(env: &mut JNIEnv, data: Vec<String>) -> Result<jobjectArray,
 
     Ok(arr.into_raw())
 }
+
+/// Throws a native error to Java as an `EngineException`.
+pub(crate) fn throw_error(env: &mut JNIEnv, err: impl std::fmt::Display) {
+    env.throw_new("ai/djl/engine/EngineException", err.to_string())
+        .unwrap();
+}
+
+fn to_json_string<'local, T: serde::Serialize>(
+    env: &mut JNIEnv<'local>,
+    value: &T,
+) -> JString<'local> {
+    match serde_json::to_string(value) {
+        Ok(json) => env.new_string(json).expect("Couldn't create java string!"),
+        Err(err) => {
+            throw_error(env, err);
+            JString::from(JObject::null())
+        }
+    }
+}
+
+fn from_string_array(env: &mut JNIEnv, array: &JObjectArray) -> Vec<String> {
+    let len = env.get_array_length(array).unwrap();
+    let mut data: Vec<String> = Vec::with_capacity(len as usize);
+    for i in 0..len {
+        let item = env.get_object_array_element(array, i).unwrap().into();
+        let value: String = env
+            .get_string(&item)
+            .expect("Couldn't get java string!")
+            .into();
+        data.push(value);
+    }
+    data
+}
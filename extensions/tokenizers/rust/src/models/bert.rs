@@ -1,7 +1,9 @@
-use crate::models::Model;
-use candle_core::{Device, Result, Tensor};
+use crate::models::packed::Packed;
+use crate::models::tensor_parallel::{ParallelLinear, Parallelism};
+use crate::models::{cancel, check_seq_len, heads, numerics, DeviceMap, Model, Outputs};
+use candle_core::{DType, Device, Result, Tensor};
 use candle_nn::{embedding, Embedding, Module, VarBuilder};
-use candle_transformers::models::with_tracing::{layer_norm, linear, LayerNorm, Linear};
+use candle_transformers::models::with_tracing::{layer_norm, LayerNorm};
 use serde::Deserialize;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
@@ -46,7 +48,7 @@ pub enum PositionEmbeddingType {
 pub struct BertConfig {
     vocab_size: usize,
     hidden_size: usize,
-    num_hidden_layers: usize,
+    pub(crate) num_hidden_layers: usize,
     num_attention_heads: usize,
     intermediate_size: usize,
     pub hidden_act: HiddenAct,
@@ -62,6 +64,7 @@ pub struct BertConfig {
     use_cache: bool,
     classifier_dropout: Option<f64>,
     pub use_flash_attn: Option<bool>,
+    pub f32_heads: Option<bool>,
     model_type: Option<String>,
 }
 
@@ -84,6 +87,7 @@ impl Default for BertConfig {
             use_cache: true,
             classifier_dropout: None,
             use_flash_attn: Some(false),
+            f32_heads: Some(false),
             model_type: Some("bert".to_string()),
         }
     }
@@ -109,6 +113,7 @@ impl BertConfig {
             use_cache: true,
             classifier_dropout: None,
             use_flash_attn: Some(false),
+            f32_heads: Some(false),
             model_type: Some("bert".to_string()),
         }
     }
@@ -174,16 +179,22 @@ impl BertEmbeddings {
         })
     }
 
-    fn forward(&self, input_ids: &Tensor, token_type_ids: &Tensor) -> Result<Tensor> {
+    fn forward(
+        &self,
+        input_ids: &Tensor,
+        token_type_ids: &Tensor,
+        position_ids: Option<&Tensor>,
+    ) -> Result<Tensor> {
         let _enter = self.span.enter();
-        let (_bsize, seq_len) = input_ids.dims2()?;
+        let seq_len = input_ids.dim(candle_core::D::Minus1)?;
         let input_embeddings = self.word_embeddings.forward(input_ids)?;
         let token_type_embeddings = self.token_type_embeddings.forward(token_type_ids)?;
         let mut embeddings = (&input_embeddings + token_type_embeddings)?;
         if let Some(position_embeddings) = &self.position_embeddings {
-            // TODO: Proper absolute positions?
-            let position_ids = (0..seq_len as u32).collect::<Vec<_>>();
-            let position_ids = Tensor::new(&position_ids[..], input_ids.device())?;
+            let position_ids = match position_ids {
+                Some(position_ids) => position_ids.to_device(input_ids.device())?,
+                None => Tensor::arange(0u32, seq_len as u32, input_ids.device())?,
+            };
             embeddings = embeddings.broadcast_add(&position_embeddings.forward(&position_ids)?)?
         }
         let embeddings = self.layer_norm.forward(&embeddings)?;
@@ -193,9 +204,9 @@ impl BertEmbeddings {
 }
 
 struct BertSelfAttention {
-    query: Linear,
-    key: Linear,
-    value: Linear,
+    query: ParallelLinear,
+    key: ParallelLinear,
+    value: ParallelLinear,
     dropout: Dropout,
     num_attention_heads: usize,
     attention_head_size: usize,
@@ -205,20 +216,21 @@ struct BertSelfAttention {
 }
 
 impl BertSelfAttention {
-    fn load(vb: VarBuilder, config: &BertConfig) -> Result<Self> {
+    fn load(vb: VarBuilder, config: &BertConfig, tp: &Parallelism) -> Result<Self> {
         let attention_head_size = config.hidden_size / config.num_attention_heads;
         let all_head_size = config.num_attention_heads * attention_head_size;
         let dropout = Dropout::new(config.hidden_dropout_prob);
         let hidden_size = config.hidden_size;
-        let query = linear(hidden_size, all_head_size, vb.pp("query"))?;
-        let value = linear(hidden_size, all_head_size, vb.pp("value"))?;
-        let key = linear(hidden_size, all_head_size, vb.pp("key"))?;
+        let query = tp.column_linear(hidden_size, all_head_size, vb.pp("query"))?;
+        let value = tp.column_linear(hidden_size, all_head_size, vb.pp("value"))?;
+        let key = tp.column_linear(hidden_size, all_head_size, vb.pp("key"))?;
         Ok(Self {
             query,
             key,
             value,
             dropout,
-            num_attention_heads: config.num_attention_heads,
+            // each tensor parallel rank computes a subset of the heads
+            num_attention_heads: tp.split(config.num_attention_heads, "num_attention_heads")?,
             attention_head_size,
             use_flash_attn: config.use_flash_attn.unwrap_or(false),
             span: tracing::span!(tracing::Level::TRACE, "self-attn"),
@@ -252,6 +264,24 @@ fn flash_attn(_: &Tensor, _: &Tensor, _: &Tensor, _: f32, _: bool) -> Result<Ten
     unimplemented!("compile with '--features flash-attn'")
 }
 
+impl BertSelfAttention {
+    fn forward_packed(&self, hidden_states: &Tensor, packed: &Packed) -> Result<Tensor> {
+        let _enter = self.span.enter();
+        // flash-attn varlen expects (total, nheads, head_dim)
+        let shape = (
+            hidden_states.dim(0)?,
+            self.num_attention_heads,
+            self.attention_head_size,
+        );
+        let q = self.query.forward(hidden_states)?.reshape(shape)?;
+        let k = self.key.forward(hidden_states)?.reshape(shape)?;
+        let v = self.value.forward(hidden_states)?.reshape(shape)?;
+        let softmax_scale = 1f32 / (self.attention_head_size as f32).sqrt();
+        let context_layer = packed.attention(&q, &k, &v, softmax_scale)?;
+        context_layer.flatten_from(candle_core::D::Minus2)
+    }
+}
+
 impl Module for BertSelfAttention {
     fn forward(&self, hidden_states: &Tensor) -> Result<Tensor> {
         let _enter = self.span.enter();
@@ -290,15 +320,15 @@ impl Module for BertSelfAttention {
 }
 
 struct BertSelfOutput {
-    dense: Linear,
+    dense: ParallelLinear,
     layer_norm: LayerNorm,
     dropout: Dropout,
     span: tracing::Span,
 }
 
 impl BertSelfOutput {
-    fn load(vb: VarBuilder, config: &BertConfig) -> Result<Self> {
-        let dense = linear(config.hidden_size, config.hidden_size, vb.pp("dense"))?;
+    fn load(vb: VarBuilder, config: &BertConfig, tp: &Parallelism) -> Result<Self> {
+        let dense = tp.row_linear(config.hidden_size, config.hidden_size, vb.pp("dense"))?;
         let layer_norm = layer_norm(
             config.hidden_size,
             config.layer_norm_eps,
@@ -329,9 +359,9 @@ struct BertAttention {
 }
 
 impl BertAttention {
-    fn load(vb: VarBuilder, config: &BertConfig) -> Result<Self> {
-        let self_attention = BertSelfAttention::load(vb.pp("self"), config)?;
-        let self_output = BertSelfOutput::load(vb.pp("output"), config)?;
+    fn load(vb: VarBuilder, config: &BertConfig, tp: &Parallelism) -> Result<Self> {
+        let self_attention = BertSelfAttention::load(vb.pp("self"), config, tp)?;
+        let self_output = BertSelfOutput::load(vb.pp("output"), config, tp)?;
         Ok(Self {
             self_attention,
             self_output,
@@ -340,10 +370,13 @@ impl BertAttention {
     }
 }
 
-impl Module for BertAttention {
-    fn forward(&self, hidden_states: &Tensor) -> Result<Tensor> {
+impl BertAttention {
+    fn forward(&self, hidden_states: &Tensor, packed: Option<&Packed>) -> Result<Tensor> {
         let _enter = self.span.enter();
-        let self_outputs = self.self_attention.forward(hidden_states)?;
+        let self_outputs = match packed {
+            Some(packed) => self.self_attention.forward_packed(hidden_states, packed)?,
+            None => self.self_attention.forward(hidden_states)?,
+        };
         let attention_output = self.self_output.forward(&self_outputs, hidden_states)?;
         Ok(attention_output)
     }
@@ -351,14 +384,15 @@ impl Module for BertAttention {
 
 // https://github.com/huggingface/transformers/blob/6eedfa6dd15dc1e22a55ae036f681914e5a0d9a1/src/transformers/models/bert/modeling_bert.py#L441
 struct BertIntermediate {
-    dense: Linear,
+    dense: ParallelLinear,
     intermediate_act: HiddenActLayer,
     span: tracing::Span,
 }
 
 impl BertIntermediate {
-    fn load(vb: VarBuilder, config: &BertConfig) -> Result<Self> {
-        let dense = linear(config.hidden_size, config.intermediate_size, vb.pp("dense"))?;
+    fn load(vb: VarBuilder, config: &BertConfig, tp: &Parallelism) -> Result<Self> {
+        let dense =
+            tp.column_linear(config.hidden_size, config.intermediate_size, vb.pp("dense"))?;
         Ok(Self {
             dense,
             intermediate_act: HiddenActLayer::new(config.hidden_act),
@@ -378,15 +412,15 @@ impl Module for BertIntermediate {
 
 // https://github.com/huggingface/transformers/blob/6eedfa6dd15dc1e22a55ae036f681914e5a0d9a1/src/transformers/models/bert/modeling_bert.py#L456
 struct BertOutput {
-    dense: Linear,
+    dense: ParallelLinear,
     layer_norm: LayerNorm,
     dropout: Dropout,
     span: tracing::Span,
 }
 
 impl BertOutput {
-    fn load(vb: VarBuilder, config: &BertConfig) -> Result<Self> {
-        let dense = linear(config.intermediate_size, config.hidden_size, vb.pp("dense"))?;
+    fn load(vb: VarBuilder, config: &BertConfig, tp: &Parallelism) -> Result<Self> {
+        let dense = tp.row_linear(config.intermediate_size, config.hidden_size, vb.pp("dense"))?;
         let layer_norm = layer_norm(
             config.hidden_size,
             config.layer_norm_eps,
@@ -418,10 +452,10 @@ struct BertLayer {
 }
 
 impl BertLayer {
-    fn load(vb: VarBuilder, config: &BertConfig) -> Result<Self> {
-        let attention = BertAttention::load(vb.pp("attention"), config)?;
-        let intermediate = BertIntermediate::load(vb.pp("intermediate"), config)?;
-        let output = BertOutput::load(vb.pp("output"), config)?;
+    fn load(vb: VarBuilder, config: &BertConfig, tp: &Parallelism) -> Result<Self> {
+        let attention = BertAttention::load(vb.pp("attention"), config, tp)?;
+        let intermediate = BertIntermediate::load(vb.pp("intermediate"), config, tp)?;
+        let output = BertOutput::load(vb.pp("output"), config, tp)?;
         Ok(Self {
             attention,
             intermediate,
@@ -431,10 +465,10 @@ impl BertLayer {
     }
 }
 
-impl Module for BertLayer {
-    fn forward(&self, hidden_states: &Tensor) -> Result<Tensor> {
+impl BertLayer {
+    fn forward(&self, hidden_states: &Tensor, packed: Option<&Packed>) -> Result<Tensor> {
         let _enter = self.span.enter();
-        let attention_output = self.attention.forward(hidden_states)?;
+        let attention_output = self.attention.forward(hidden_states, packed)?;
         // TODO: Support cross-attention?
         // https://github.com/huggingface/transformers/blob/6eedfa6dd15dc1e22a55ae036f681914e5a0d9a1/src/transformers/models/bert/modeling_bert.py#L523
         // TODO: Support something similar to `apply_chunking_to_forward`?
@@ -449,51 +483,99 @@ impl Module for BertLayer {
 // https://github.com/huggingface/transformers/blob/6eedfa6dd15dc1e22a55ae036f681914e5a0d9a1/src/transformers/models/bert/modeling_bert.py#L556
 struct BertEncoder {
     layers: Vec<BertLayer>,
+    devices: Vec<Device>,
     span: tracing::Span,
 }
 
 impl BertEncoder {
-    fn load(vb: VarBuilder, config: &BertConfig) -> Result<Self> {
+    fn load(device_map: &DeviceMap, config: &BertConfig) -> Result<Self> {
         let layers = (0..config.num_hidden_layers)
-            .map(|index| BertLayer::load(vb.pp(&format!("layer.{index}")), config))
+            .map(|index| {
+                let vb = device_map.layer(index).pp(&format!("layer.{index}"));
+                BertLayer::load(vb, config, device_map.parallelism())
+            })
             .collect::<Result<Vec<_>>>()?;
+        let devices = (0..config.num_hidden_layers)
+            .map(|index| device_map.layer(index).device().clone())
+            .collect();
         let span = tracing::span!(tracing::Level::TRACE, "encoder");
-        Ok(BertEncoder { layers, span })
+        Ok(BertEncoder {
+            layers,
+            devices,
+            span,
+        })
     }
 }
 
-impl Module for BertEncoder {
-    fn forward(&self, hidden_states: &Tensor) -> Result<Tensor> {
+impl BertEncoder {
+    fn forward(&self, hidden_states: &Tensor, packed: Option<&Packed>) -> Result<Tensor> {
         let _enter = self.span.enter();
         let mut hidden_states = hidden_states.clone();
         // Use a loop rather than a fold as it's easier to modify when adding debug/...
-        for layer in self.layers.iter() {
-            hidden_states = layer.forward(&hidden_states)?
+        for (index, (layer, device)) in self.layers.iter().zip(self.devices.iter()).enumerate() {
+            cancel::check()?;
+            // the hidden states only move when the layers are sharded across devices
+            hidden_states = layer.forward(&hidden_states.to_device(device)?, packed)?;
+            numerics::check(format_args!("encoder.layer.{index}"), &hidden_states)?;
         }
         Ok(hidden_states)
     }
 }
 
+// https://github.com/huggingface/transformers/blob/6eedfa6dd15dc1e22a55ae036f681914e5a0d9a1/src/transformers/models/bert/modeling_bert.py#L650
+struct BertPooler {
+    dense: candle_nn::Linear,
+    span: tracing::Span,
+}
+
+impl BertPooler {
+    fn load(vb: VarBuilder, config: &BertConfig) -> Result<Self> {
+        // half precision scores are unstable, e.g. for rerankers
+        let vb = if config.f32_heads.unwrap_or(false) {
+            vb.set_dtype(DType::F32)
+        } else {
+            vb
+        };
+        let dense = candle_nn::linear(config.hidden_size, config.hidden_size, vb.pp("dense"))?;
+        let span = tracing::span!(tracing::Level::TRACE, "pooler");
+        Ok(Self { dense, span })
+    }
+}
+
+impl Module for BertPooler {
+    fn forward(&self, hidden_states: &Tensor) -> Result<Tensor> {
+        let _enter = self.span.enter();
+        // the pooler only looks at the hidden state of the first token
+        let first_token = hidden_states.narrow(1, 0, 1)?.squeeze(1)?;
+        let first_token = first_token.to_dtype(self.dense.weight().dtype())?;
+        self.dense.forward(&first_token)?.tanh()
+    }
+}
+
 // https://github.com/huggingface/transformers/blob/6eedfa6dd15dc1e22a55ae036f681914e5a0d9a1/src/transformers/models/bert/modeling_bert.py#L874
 pub struct BertModel {
     embeddings: BertEmbeddings,
     encoder: BertEncoder,
+    pooler: Option<BertPooler>,
+    use_flash_attn: bool,
+    max_position_embeddings: usize,
     pub device: Device,
     span: tracing::Span,
 }
 
 impl BertModel {
-    pub fn load(vb: VarBuilder, config: &BertConfig) -> Result<Self> {
+    pub fn load(device_map: &DeviceMap, config: &BertConfig) -> Result<Self> {
+        let vb = device_map.vb();
         let (embeddings, encoder) = match (
             BertEmbeddings::load(vb.pp("embeddings"), config),
-            BertEncoder::load(vb.pp("encoder"), config),
+            BertEncoder::load(&device_map.pp("encoder"), config),
         ) {
             (Ok(embeddings), Ok(encoder)) => (embeddings, encoder),
             (Err(err), _) | (_, Err(err)) => {
                 if let Some(model_type) = &config.model_type {
                     if let (Ok(embeddings), Ok(encoder)) = (
                         BertEmbeddings::load(vb.pp(&format!("{model_type}.embeddings")), config),
-                        BertEncoder::load(vb.pp(&format!("{model_type}.encoder")), config),
+                        BertEncoder::load(&device_map.pp(&format!("{model_type}.encoder")), config),
                     ) {
                         (embeddings, encoder)
                     } else {
@@ -504,15 +586,41 @@ impl BertModel {
                 }
             }
         };
+        // sentence embedding models are often exported without the pooler
+        let pooler = std::iter::once("pooler".to_string())
+            .chain(config.model_type.as_ref().map(|t| format!("{t}.pooler")))
+            .find(|prefix| vb.contains_tensor(&format!("{prefix}.dense.weight")))
+            .map(|prefix| BertPooler::load(vb.pp(prefix), config))
+            .transpose()?;
         Ok(Self {
             embeddings,
             encoder,
+            pooler,
+            use_flash_attn: config.use_flash_attn.unwrap_or(false),
+            max_position_embeddings: config.max_position_embeddings,
             device: vb.device().clone(),
             span: tracing::span!(tracing::Level::TRACE, "model"),
         })
     }
 }
 
+impl BertModel {
+    fn outputs(&self, sequence_output: Tensor) -> Result<Outputs> {
+        let pooled_output = match &self.pooler {
+            // the pooler is placed with the embeddings when the layers are sharded
+            Some(pooler) if heads::requested() => {
+                let pooled_output = pooler.forward(&sequence_output.to_device(&self.device)?)?;
+                numerics::check("pooler", &pooled_output)?;
+                Some(pooled_output)
+            }
+            _ => None,
+        };
+        let mut outputs = vec![("last_hidden_state".to_string(), sequence_output)];
+        outputs.extend(pooled_output.map(|output| ("pooler_output".to_string(), output)));
+        Ok(outputs)
+    }
+}
+
 impl Model for BertModel {
     fn is_padded(&self) -> bool {
         true
@@ -526,17 +634,66 @@ impl Model for BertModel {
         ];
     }
 
+    fn get_output_names(&self) -> Vec<String> {
+        let mut names = vec!["last_hidden_state".to_string()];
+        if self.pooler.is_some() {
+            names.push("pooler_output".to_string());
+        }
+        names
+    }
+
+    fn device(&self) -> &Device {
+        &self.device
+    }
+
     fn forward(
         &self,
         input_ids: &Tensor,
         _attention_mask: &Tensor,
         token_type_ids: Option<&Tensor>,
-    ) -> Result<Tensor> {
+        position_ids: Option<&Tensor>,
+    ) -> Result<Outputs> {
         let _enter = self.span.enter();
+        check_seq_len(
+            input_ids.dim(1)?,
+            position_ids,
+            self.max_position_embeddings,
+        )?;
+        let token_type_ids = match token_type_ids {
+            Some(token_type_ids) => token_type_ids.clone(),
+            None => input_ids.zeros_like()?,
+        };
         let embedding_output = self
             .embeddings
-            .forward(input_ids, token_type_ids.unwrap())?;
-        let sequence_output = self.encoder.forward(&embedding_output)?;
-        Ok(sequence_output)
+            .forward(input_ids, &token_type_ids, position_ids)?;
+        numerics::check("embeddings", &embedding_output)?;
+        let sequence_output = self.encoder.forward(&embedding_output, None)?;
+        self.outputs(sequence_output)
+    }
+
+    fn supports_packed(&self) -> bool {
+        self.use_flash_attn
+    }
+
+    fn forward_packed(
+        &self,
+        input_ids: &Tensor,
+        token_type_ids: Option<&Tensor>,
+        packed: &Packed,
+    ) -> Result<Outputs> {
+        let _enter = self.span.enter();
+        // the positions of packed sequences restart at 0 for each sequence
+        check_seq_len(packed.max_seqlen(), None, self.max_position_embeddings)?;
+        let token_type_ids = match token_type_ids {
+            Some(token_type_ids) => token_type_ids.clone(),
+            None => input_ids.zeros_like()?,
+        };
+        let position_ids = packed.position_ids(input_ids.device())?;
+        let embedding_output =
+            self.embeddings
+                .forward(input_ids, &token_type_ids, Some(&position_ids))?;
+        numerics::check("embeddings", &embedding_output)?;
+        let sequence_output = self.encoder.forward(&embedding_output, Some(packed))?;
+        self.outputs(packed.unpack(&sequence_output)?)
     }
 }
@@ -0,0 +1,82 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use candle_core::Result;
+use jni::objects::JObject;
+use jni::sys::jlong;
+use jni::JNIEnv;
+
+use crate::{borrow_handle, drop_handle, to_handle};
+
+/// A flag shared by Java and the inferences it's passed to, a cancelled inference stops before
+/// its next layer.
+pub(crate) type CancellationToken = Arc<AtomicBool>;
+
+thread_local! {
+    static CURRENT: RefCell<Option<CancellationToken>> = RefCell::new(None);
+}
+
+struct Restore(Option<CancellationToken>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Runs `op` with `token` as the cancellation token of the current thread.
+pub(crate) fn with_token<R>(token: Option<CancellationToken>, op: impl FnOnce() -> R) -> R {
+    let _restore = Restore(CURRENT.with(|current| current.replace(token)));
+    op()
+}
+
+/// Fails if the inference running on the current thread has been cancelled.
+pub(crate) fn check() -> Result<()> {
+    let cancelled = CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .map_or(false, |token| token.load(Ordering::Relaxed))
+    });
+    if cancelled {
+        candle_core::bail!("inference was cancelled")
+    }
+    Ok(())
+}
+
+/// Returns a copy of the token behind the Java handle, `0` means no token.
+pub(crate) fn from_handle(handle: jlong) -> Result<Option<CancellationToken>> {
+    if handle == 0 {
+        return Ok(None);
+    }
+    Ok(Some((*borrow_handle::<CancellationToken>(handle)?).clone()))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_createCancellationToken(
+    _: JNIEnv,
+    _: JObject,
+) -> jlong {
+    to_handle(CancellationToken::default())
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_cancelInference(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+) {
+    let token = or_throw!(env, borrow_handle::<CancellationToken>(handle));
+    token.store(true, Ordering::Relaxed);
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_deleteCancellationToken(
+    _: JNIEnv,
+    _: JObject,
+    handle: jlong,
+) {
+    drop_handle::<CancellationToken>(handle);
+}
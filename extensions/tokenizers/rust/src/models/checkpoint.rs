@@ -0,0 +1,90 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use candle_core::{DType, Device, Result, Shape, Tensor};
+use candle_nn::var_builder::SimpleBackend;
+use candle_nn::{Init, VarBuilder};
+
+/// The weights a model was loaded with, so they can be saved without reading the checkpoint
+/// again.
+///
+/// The tensors are recorded as the layers read them, in the dtype and on the device the layers
+/// use, and share their storage with the layers. Weights the model doesn't read, e.g. the heads
+/// of other tasks, aren't saved.
+pub(crate) enum Checkpoint {
+    Recorded(Arc<Mutex<BTreeMap<String, Tensor>>>),
+    /// The weights can't be saved, the reason completes `The weights can't be saved, ...`.
+    Unsupported(&'static str),
+}
+
+impl Checkpoint {
+    pub(crate) fn recorded() -> Self {
+        Checkpoint::Recorded(Arc::default())
+    }
+
+    /// Records the tensors read through `vb`, unless the weights can't be saved.
+    pub(crate) fn record(&self, vb: VarBuilder<'static>) -> VarBuilder<'static> {
+        match self {
+            Checkpoint::Recorded(tensors) => {
+                let (dtype, device) = (vb.dtype(), vb.device().clone());
+                let recorder = Recorder {
+                    vb,
+                    tensors: tensors.clone(),
+                };
+                VarBuilder::from_backend(Box::new(recorder), dtype, device)
+            }
+            Checkpoint::Unsupported(_) => vb,
+        }
+    }
+
+    /// Saves the recorded weights to a safetensors file, the float tensors are converted to
+    /// `dtype`.
+    pub(crate) fn save(&self, path: &Path, dtype: DType) -> Result<()> {
+        let tensors = match self {
+            Checkpoint::Recorded(tensors) => tensors.lock().unwrap().clone(),
+            Checkpoint::Unsupported(reason) => {
+                candle_core::bail!("The weights can't be saved, {reason}")
+            }
+        };
+        let mut saved = HashMap::with_capacity(tensors.len());
+        for (name, tensor) in tensors {
+            let tensor = if tensor.dtype().is_float() {
+                tensor.to_dtype(dtype)?
+            } else {
+                tensor
+            };
+            saved.insert(name, tensor);
+        }
+        candle_core::safetensors::save(&saved, path)
+    }
+}
+
+/// Reads the tensors with another `VarBuilder` and records them by their full name.
+struct Recorder {
+    vb: VarBuilder<'static>,
+    tensors: Arc<Mutex<BTreeMap<String, Tensor>>>,
+}
+
+impl SimpleBackend for Recorder {
+    fn get(
+        &self,
+        shape: Shape,
+        name: &str,
+        hints: Init,
+        dtype: DType,
+        _: &Device,
+    ) -> Result<Tensor> {
+        // layers may read their weights in another dtype, e.g. the F32 heads
+        let tensor = self.vb.to_dtype(dtype).get_with_hints(shape, name, hints)?;
+        self.tensors
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), tensor.clone());
+        Ok(tensor)
+    }
+
+    fn contains_tensor(&self, name: &str) -> bool {
+        self.vb.contains_tensor(name)
+    }
+}
@@ -0,0 +1,508 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use candle_core::safetensors::{Load, MmapedSafetensors};
+use candle_core::{DType, Device, Error, Result, Tensor};
+use safetensors::tensor::TensorView;
+use safetensors::Dtype;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::models::{safetensors_files, Weights};
+
+/// The order AutoAWQ packs 8 columns of 4-bit weights into an `i32`, by nibble.
+const AWQ_ORDER: [usize; 8] = [0, 2, 4, 6, 1, 3, 5, 7];
+
+/// The `quantization_config` of a checkpoint whose linear weights are packed into `i32`s as
+/// `qweight`, with the zero points in `qzeros` and the scales of each group in `scales`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "quant_method", rename_all = "lowercase")]
+pub(crate) enum QuantizationConfig {
+    Awq {
+        bits: usize,
+        group_size: usize,
+        version: Option<String>,
+    },
+    Gptq {
+        bits: usize,
+        group_size: isize,
+        #[serde(default)]
+        desc_act: bool,
+        /// `gptq_v2` checkpoints store the zero points without the offset of 1.
+        checkpoint_format: Option<String>,
+    },
+}
+
+impl QuantizationConfig {
+    /// Reads the `quantization_config` of `config.json`, if there is one. Only AWQ and GPTQ
+    /// checkpoints are dequantized, other quantization methods are ignored as before.
+    pub(crate) fn from_config(config: &str) -> Result<Option<Self>> {
+        let config: Value = serde_json::from_str(config).map_err(Error::wrap)?;
+        let Some(quantization) = config.get("quantization_config").filter(|q| !q.is_null()) else {
+            return Ok(None);
+        };
+        match quantization["quant_method"].as_str() {
+            Some("awq" | "gptq") => serde_json::from_value(quantization.clone())
+                .map(Some)
+                .map_err(|err| Error::Msg(format!("Unsupported quantization_config: {err}"))),
+            method => {
+                tracing::warn!("Ignoring the quantization_config of quant_method {method:?}");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Loads the checkpoint on the CPU, the packed linear weights are dequantized to F32 so the
+    /// model loads them like the weights of a full precision checkpoint.
+    pub(crate) fn dequantize(&self, weights: &Weights) -> Result<Weights> {
+        match self {
+            Self::Awq {
+                bits,
+                group_size,
+                version,
+            } => {
+                if *group_size == 0 {
+                    candle_core::bail!("Invalid AWQ group_size: 0")
+                }
+                if *bits != 4 {
+                    candle_core::bail!("AWQ checkpoints with {bits} bits are not supported")
+                }
+                if let Some(version) = version
+                    .as_deref()
+                    .filter(|v| !v.eq_ignore_ascii_case("gemm"))
+                {
+                    candle_core::bail!("AWQ {version} checkpoints are not supported")
+                }
+            }
+            Self::Gptq {
+                bits, group_size, ..
+            } => {
+                // -1 is a single group
+                if *group_size == 0 || *group_size < -1 {
+                    candle_core::bail!("Invalid GPTQ group_size: {group_size}")
+                }
+                // 3 bits values straddle the i32s
+                if !matches!(bits, 2 | 4 | 8) {
+                    candle_core::bail!("GPTQ checkpoints with {bits} bits are not supported")
+                }
+            }
+        }
+        let tensors = match weights {
+            Weights::Path(model_path) => {
+                let paths = safetensors_files(model_path)?;
+                let safetensors = unsafe { MmapedSafetensors::multi(&paths)? };
+                self.dequantize_views(safetensors.tensors())?
+            }
+            Weights::Bytes(shards) => self.dequantize_views(shards.views()?)?,
+            Weights::Tensors(_) => candle_core::bail!("The weights are already dequantized"),
+        };
+        Ok(Weights::Tensors(Arc::new(tensors)))
+    }
+
+    fn dequantize_views(
+        &self,
+        views: Vec<(String, TensorView)>,
+    ) -> Result<HashMap<String, Tensor>> {
+        let views: HashMap<String, TensorView> = views.into_iter().collect();
+        let mut tensors = HashMap::new();
+        for (name, view) in &views {
+            match name.rsplit_once('.') {
+                Some((prefix, "qweight")) => {
+                    let get = |suffix: &str| {
+                        let name = format!("{prefix}.{suffix}");
+                        views
+                            .get(&name)
+                            .ok_or_else(|| Error::Msg(format!("Missing {name}")))
+                    };
+                    // desc_act checkpoints reorder the groups of the input features
+                    let g_idx = match views.get(&format!("{prefix}.g_idx")) {
+                        Some(g_idx) => Some(ints(g_idx)?),
+                        None => None,
+                    };
+                    let weight = self.dequantize_linear(
+                        view,
+                        get("qzeros")?,
+                        get("scales")?,
+                        g_idx.as_deref(),
+                    )?;
+                    tensors.insert(format!("{prefix}.weight"), weight);
+                }
+                Some((_, "qzeros" | "scales" | "g_idx")) => {}
+                _ => {
+                    tensors.insert(name.clone(), view.load(&Device::Cpu)?);
+                }
+            }
+        }
+        Ok(tensors)
+    }
+
+    /// Returns the `(out_dim, in_dim)` weight of a linear layer.
+    fn dequantize_linear(
+        &self,
+        qweight: &TensorView,
+        qzeros: &TensorView,
+        scales: &TensorView,
+        g_idx: Option<&[i32]>,
+    ) -> Result<Tensor> {
+        match self {
+            Self::Awq { group_size, .. } => {
+                // AWQ packs the output features, the weights are stored as (in_dim, out_dim)
+                let (weight, in_dim, out_dim) = unpack_awq(qweight)?;
+                let (zeros, _, _) = unpack_awq(qzeros)?;
+                let scales = floats(scales)?;
+                check_groups(in_dim.div_ceil(*group_size), out_dim, &zeros, &scales)?;
+                let mut dequantized = vec![0f32; in_dim * out_dim];
+                for i in 0..in_dim {
+                    let group = (i / group_size) * out_dim;
+                    for o in 0..out_dim {
+                        let zero = zeros[group + o] as f32;
+                        let value = weight[i * out_dim + o] as f32;
+                        dequantized[o * in_dim + i] = (value - zero) * scales[group + o];
+                    }
+                }
+                Tensor::from_vec(dequantized, (out_dim, in_dim), &Device::Cpu)
+            }
+            Self::Gptq {
+                bits,
+                group_size,
+                desc_act,
+                checkpoint_format,
+            } => {
+                // GPTQ packs the input features, the weights are stored as (in_dim, out_dim)
+                let (weight, in_dim, out_dim) = unpack_rows(qweight, *bits)?;
+                let (zeros, _, _) = unpack_cols(qzeros, *bits)?;
+                let scales = floats(scales)?;
+                let offset = match checkpoint_format.as_deref() {
+                    Some("gptq_v2") => 0.,
+                    _ => 1.,
+                };
+                // a group size of -1 is a single group
+                let group_size = usize::try_from(*group_size).unwrap_or(in_dim).max(1);
+                check_groups(in_dim.div_ceil(group_size), out_dim, &zeros, &scales)?;
+                if let Some(g_idx) = g_idx {
+                    check_g_idx(g_idx, in_dim, scales.len() / out_dim)?;
+                } else if *desc_act {
+                    candle_core::bail!("desc_act checkpoints need a g_idx")
+                }
+                let group = |i: usize| match g_idx {
+                    Some(g_idx) => g_idx[i] as usize,
+                    None => i / group_size,
+                };
+                let mut dequantized = vec![0f32; in_dim * out_dim];
+                for i in 0..in_dim {
+                    let group = group(i) * out_dim;
+                    for o in 0..out_dim {
+                        let zero = zeros[group + o] as f32 + offset;
+                        let value = weight[i * out_dim + o] as f32;
+                        dequantized[o * in_dim + i] = (value - zero) * scales[group + o];
+                    }
+                }
+                Tensor::from_vec(dequantized, (out_dim, in_dim), &Device::Cpu)
+            }
+        }
+    }
+}
+
+/// Fails if the zero points or the scales don't cover `num_groups` groups of `out_dim` outputs.
+fn check_groups(num_groups: usize, out_dim: usize, zeros: &[u8], scales: &[f32]) -> Result<()> {
+    let expected = num_groups * out_dim;
+    if zeros.len() < expected || scales.len() < expected {
+        candle_core::bail!(
+            "Expected {num_groups} groups of {out_dim} zero points and scales, got {} and {}",
+            zeros.len(),
+            scales.len()
+        )
+    }
+    Ok(())
+}
+
+/// Fails unless `g_idx` maps each of the `in_dim` inputs to one of the `num_groups` groups.
+fn check_g_idx(g_idx: &[i32], in_dim: usize, num_groups: usize) -> Result<()> {
+    if g_idx.len() != in_dim {
+        candle_core::bail!("g_idx has {} entries for {in_dim} inputs", g_idx.len())
+    }
+    if let Some(g) = g_idx.iter().find(|&&g| g < 0 || g as usize >= num_groups) {
+        candle_core::bail!("g_idx {g} is out of range for {num_groups} groups")
+    }
+    Ok(())
+}
+
+/// Reads an `i32` tensor.
+fn ints(view: &TensorView) -> Result<Vec<i32>> {
+    if view.dtype() != Dtype::I32 {
+        candle_core::bail!("Expected an I32 tensor, got {:?}", view.dtype())
+    }
+    let values = view
+        .data()
+        .chunks_exact(4)
+        .map(|bytes| i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect();
+    Ok(values)
+}
+
+/// Reads a packed 2D `i32` tensor.
+fn read_packed(view: &TensorView) -> Result<(Vec<i32>, usize, usize)> {
+    match *view.shape() {
+        [rows, cols] => Ok((ints(view)?, rows, cols)),
+        ref shape => candle_core::bail!("Expected a 2D packed tensor, got {shape:?}"),
+    }
+}
+
+/// Reads the scales, they are usually saved in F16.
+fn floats(view: &TensorView) -> Result<Vec<f32>> {
+    view.load(&Device::Cpu)?
+        .to_dtype(DType::F32)?
+        .flatten_all()?
+        .to_vec1()
+}
+
+/// Unpacks 8 4-bit columns from each `i32`, returns the values and the unpacked shape.
+fn unpack_awq(view: &TensorView) -> Result<(Vec<u8>, usize, usize)> {
+    let (packed, rows, cols) = read_packed(view)?;
+    let mut values = vec![0u8; packed.len() * 8];
+    for (i, &value) in packed.iter().enumerate() {
+        let (row, col) = (i / cols, (i % cols) * 8);
+        for (nibble, &offset) in AWQ_ORDER.iter().enumerate() {
+            let index = row * cols * 8 + col + offset;
+            values[index] = ((value >> (4 * nibble)) & 0xF) as u8;
+        }
+    }
+    Ok((values, rows, cols * 8))
+}
+
+/// Unpacks `32 / bits` rows from each `i32`, lowest bits first.
+fn unpack_rows(view: &TensorView, bits: usize) -> Result<(Vec<u8>, usize, usize)> {
+    let (packed, rows, cols) = read_packed(view)?;
+    let pack = 32 / bits;
+    let mask = (1 << bits) - 1;
+    let mut values = vec![0u8; packed.len() * pack];
+    for (i, &value) in packed.iter().enumerate() {
+        let (row, col) = ((i / cols) * pack, i % cols);
+        for j in 0..pack {
+            values[(row + j) * cols + col] = ((value >> (bits * j)) & mask) as u8;
+        }
+    }
+    Ok((values, rows * pack, cols))
+}
+
+/// Unpacks `32 / bits` columns from each `i32`, lowest bits first.
+fn unpack_cols(view: &TensorView, bits: usize) -> Result<(Vec<u8>, usize, usize)> {
+    let (packed, rows, cols) = read_packed(view)?;
+    let pack = 32 / bits;
+    let mask = (1 << bits) - 1;
+    let mut values = vec![0u8; packed.len() * pack];
+    for (i, &value) in packed.iter().enumerate() {
+        for j in 0..pack {
+            values[i * pack + j] = ((value >> (bits * j)) & mask) as u8;
+        }
+    }
+    Ok((values, rows, cols * pack))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IN_DIM: usize = 4;
+    const OUT_DIM: usize = 8;
+
+    fn int_bytes(values: &[i32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    fn float_bytes(values: &[f32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    /// Packs a row of 8 4-bit values the way AutoAWQ does.
+    fn pack_awq(row: &[u8]) -> i32 {
+        AWQ_ORDER
+            .iter()
+            .enumerate()
+            .fold(0, |packed, (nibble, &col)| {
+                packed | (row[col] as i32) << (4 * nibble)
+            })
+    }
+
+    fn weight(i: usize, o: usize) -> u8 {
+        ((i * OUT_DIM + o) % 16) as u8
+    }
+
+    fn zero(group: usize, o: usize) -> u8 {
+        ((group * 3 + o) % 16) as u8
+    }
+
+    fn scale(group: usize, o: usize) -> f32 {
+        0.5 + group as f32 + o as f32 * 0.25
+    }
+
+    #[test]
+    fn unpacks_and_dequantizes_awq_weights() -> Result<()> {
+        let group_size = 2;
+        let num_groups = IN_DIM / group_size;
+        let rows = |f: &dyn Fn(usize, usize) -> u8, count: usize| -> Vec<i32> {
+            (0..count)
+                .map(|r| pack_awq(&(0..OUT_DIM).map(|o| f(r, o)).collect::<Vec<_>>()))
+                .collect()
+        };
+        let qweight = int_bytes(&rows(&weight, IN_DIM));
+        let qzeros = int_bytes(&rows(&zero, num_groups));
+        let scales: Vec<f32> = (0..num_groups)
+            .flat_map(|g| (0..OUT_DIM).map(move |o| scale(g, o)))
+            .collect();
+        let scales = float_bytes(&scales);
+        let qweight =
+            TensorView::new(Dtype::I32, vec![IN_DIM, 1], &qweight).map_err(Error::wrap)?;
+        let qzeros =
+            TensorView::new(Dtype::I32, vec![num_groups, 1], &qzeros).map_err(Error::wrap)?;
+        let scales =
+            TensorView::new(Dtype::F32, vec![num_groups, OUT_DIM], &scales).map_err(Error::wrap)?;
+
+        let (values, rows, cols) = unpack_awq(&qweight)?;
+        assert_eq!((rows, cols), (IN_DIM, OUT_DIM));
+        for i in 0..IN_DIM {
+            for o in 0..OUT_DIM {
+                assert_eq!(values[i * OUT_DIM + o], weight(i, o));
+            }
+        }
+
+        let config = QuantizationConfig::Awq {
+            bits: 4,
+            group_size,
+            version: None,
+        };
+        let dequantized: Vec<Vec<f32>> = config
+            .dequantize_linear(&qweight, &qzeros, &scales, None)?
+            .to_vec2()?;
+        for o in 0..OUT_DIM {
+            for i in 0..IN_DIM {
+                let g = i / group_size;
+                let expected = (weight(i, o) as f32 - zero(g, o) as f32) * scale(g, o);
+                assert_eq!(dequantized[o][i], expected);
+            }
+        }
+        Ok(())
+    }
+
+    /// Packs `values` with `bits` each into an `i32`, lowest bits first.
+    fn pack_bits(values: &[u8], bits: usize) -> i32 {
+        values.iter().enumerate().fold(0, |packed, (j, &value)| {
+            packed | (value as i32) << (bits * j)
+        })
+    }
+
+    #[test]
+    fn unpacks_gptq_rows_and_columns() -> Result<()> {
+        let bits = 8;
+        // 2 packed rows of 2 columns, 4 rows of 8 bits each
+        let rows = int_bytes(&[
+            pack_bits(&[1, 2, 3, 4], bits),
+            pack_bits(&[5, 6, 7, 8], bits),
+            pack_bits(&[9, 10, 11, 12], bits),
+            pack_bits(&[13, 14, 15, 255], bits),
+        ]);
+        let view = TensorView::new(Dtype::I32, vec![2, 2], &rows).map_err(Error::wrap)?;
+        let (values, in_dim, out_dim) = unpack_rows(&view, bits)?;
+        assert_eq!((in_dim, out_dim), (8, 2));
+        assert_eq!(values[..8], [1, 5, 2, 6, 3, 7, 4, 8]);
+        assert_eq!(values[8..], [9, 13, 10, 14, 11, 15, 12, 255]);
+
+        let (values, rows, cols) = unpack_cols(&view, bits)?;
+        assert_eq!((rows, cols), (2, 8));
+        assert_eq!(values[..8], [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(values[8..], [9, 10, 11, 12, 13, 14, 15, 255]);
+        Ok(())
+    }
+
+    /// Dequantizes a GPTQ checkpoint of 8 inputs and 8 outputs in 4 bits, in 2 groups.
+    fn dequantize_gptq(format: Option<&str>, g_idx: Option<&[i32]>) -> Result<Vec<Vec<f32>>> {
+        let (bits, in_dim, out_dim, num_groups) = (4, 8, 8, 2);
+        let qweight: Vec<i32> = (0..out_dim)
+            .map(|o| pack_bits(&(0..in_dim).map(|i| weight(i, o)).collect::<Vec<_>>(), bits))
+            .collect();
+        let qweight = int_bytes(&qweight);
+        let qzeros: Vec<i32> = (0..num_groups)
+            .map(|g| pack_bits(&(0..out_dim).map(|o| zero(g, o)).collect::<Vec<_>>(), bits))
+            .collect();
+        let qzeros = int_bytes(&qzeros);
+        let scales: Vec<f32> = (0..num_groups)
+            .flat_map(|g| (0..out_dim).map(move |o| scale(g, o)))
+            .collect();
+        let scales = float_bytes(&scales);
+        let qweight =
+            TensorView::new(Dtype::I32, vec![1, out_dim], &qweight).map_err(Error::wrap)?;
+        let qzeros =
+            TensorView::new(Dtype::I32, vec![num_groups, 1], &qzeros).map_err(Error::wrap)?;
+        let scales =
+            TensorView::new(Dtype::F32, vec![num_groups, out_dim], &scales).map_err(Error::wrap)?;
+        let config = QuantizationConfig::Gptq {
+            bits,
+            group_size: 4,
+            desc_act: g_idx.is_some(),
+            checkpoint_format: format.map(String::from),
+        };
+        config
+            .dequantize_linear(&qweight, &qzeros, &scales, g_idx)?
+            .to_vec2()
+    }
+
+    #[test]
+    fn dequantizes_gptq_weights_with_the_zero_offset() -> Result<()> {
+        // the default format stores the zero points minus 1, gptq_v2 stores them as is
+        for (format, offset) in [(None, 1.), (Some("gptq_v2"), 0.)] {
+            let dequantized = dequantize_gptq(format, None)?;
+            for (o, row) in dequantized.iter().enumerate() {
+                for (i, &value) in row.iter().enumerate() {
+                    let g = i / 4;
+                    let zero = zero(g, o) as f32 + offset;
+                    assert_eq!(value, (weight(i, o) as f32 - zero) * scale(g, o));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn validates_g_idx() -> Result<()> {
+        let g_idx = [1, 0, 1, 0, 1, 0, 1, 0];
+        let dequantized = dequantize_gptq(None, Some(&g_idx))?;
+        for (o, row) in dequantized.iter().enumerate() {
+            for (i, &value) in row.iter().enumerate() {
+                let g = g_idx[i] as usize;
+                let zero = zero(g, o) as f32 + 1.;
+                assert_eq!(value, (weight(i, o) as f32 - zero) * scale(g, o));
+            }
+        }
+        assert!(dequantize_gptq(None, Some(&g_idx[..4])).is_err());
+        assert!(dequantize_gptq(None, Some(&[0, 0, 0, 0, 2, 2, 2, 2])).is_err());
+        assert!(dequantize_gptq(None, Some(&[0, 0, 0, 0, -1, 1, 1, 1])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_a_zero_group_size() {
+        let config = QuantizationConfig::Awq {
+            bits: 4,
+            group_size: 0,
+            version: None,
+        };
+        let weights = Weights::Tensors(Arc::new(HashMap::new()));
+        assert!(config.dequantize(&weights).is_err());
+    }
+
+    #[test]
+    fn ignores_other_quantization_methods() -> Result<()> {
+        let config =
+            r#"{"quantization_config": {"quant_method": "bitsandbytes", "load_in_4bit": true}}"#;
+        assert!(QuantizationConfig::from_config(config)?.is_none());
+        let config = r#"{"quantization_config": {"quant_method": "awq", "bits": 4}}"#;
+        assert!(QuantizationConfig::from_config(config).is_err());
+        Ok(())
+    }
+}
@@ -1,9 +1,10 @@
 use candle_core::{DType, Device, Result, Tensor};
 use candle_nn::{Embedding, Module, VarBuilder};
-use candle_transformers::models::with_tracing::{layer_norm, linear, LayerNorm, Linear};
+use candle_transformers::models::with_tracing::{layer_norm, LayerNorm};
 use serde::Deserialize;
 
-use crate::models::Model;
+use crate::models::tensor_parallel::{ParallelLinear, Parallelism};
+use crate::models::{cancel, check_seq_len, numerics, DeviceMap, Model, Outputs};
 
 fn masked_fill(on_false: &Tensor, mask: &Tensor, on_true: f32) -> Result<Tensor> {
     let shape = mask.shape();
@@ -53,7 +54,7 @@ enum PositionEmbeddingType {
 pub struct DistilBertConfig {
     vocab_size: usize,
     dim: usize,
-    n_layers: usize,
+    pub(crate) n_layers: usize,
     n_heads: usize,
     hidden_dim: usize,
     activation: HiddenAct,
@@ -113,12 +114,14 @@ impl Embeddings {
         })
     }
 
-    fn forward(&self, input_ids: &Tensor) -> Result<Tensor> {
+    fn forward(&self, input_ids: &Tensor, position_ids: Option<&Tensor>) -> Result<Tensor> {
         let _enter = self.span.enter();
         let (_bsize, seq_len) = input_ids.dims2()?;
         let input_embeddings = self.word_embeddings.forward(input_ids)?;
-        let position_ids = (0..seq_len as u32).collect::<Vec<_>>();
-        let position_ids = Tensor::new(&position_ids[..], input_ids.device())?;
+        let position_ids = match position_ids {
+            Some(position_ids) => position_ids.to_device(input_ids.device())?,
+            None => Tensor::arange(0u32, seq_len as u32, input_ids.device())?,
+        };
         let embeddings =
             input_embeddings.broadcast_add(&self.position_embeddings.forward(&position_ids)?)?;
 
@@ -144,10 +147,10 @@ fn flash_attn(_: &Tensor, _: &Tensor, _: &Tensor, _: f32, _: bool) -> Result<Ten
 }
 
 struct MultiHeadSelfAttention {
-    q_lin: Linear,
-    k_lin: Linear,
-    v_lin: Linear,
-    out_lin: Linear,
+    q_lin: ParallelLinear,
+    k_lin: ParallelLinear,
+    v_lin: ParallelLinear,
+    out_lin: ParallelLinear,
     n_heads: usize,
     attention_head_size: usize,
     use_flash_attn: bool,
@@ -155,20 +158,21 @@ struct MultiHeadSelfAttention {
 }
 
 impl MultiHeadSelfAttention {
-    fn load(vb: VarBuilder, config: &DistilBertConfig) -> Result<Self> {
+    fn load(vb: VarBuilder, config: &DistilBertConfig, tp: &Parallelism) -> Result<Self> {
         let attention_head_size = config.dim / config.n_heads;
         let all_head_size = config.n_heads * attention_head_size;
         let dim = config.dim;
-        let q_lin = linear(dim, all_head_size, vb.pp("q_lin"))?;
-        let v_lin = linear(dim, all_head_size, vb.pp("v_lin"))?;
-        let k_lin = linear(dim, all_head_size, vb.pp("k_lin"))?;
-        let out_lin = linear(all_head_size, dim, vb.pp("out_lin"))?;
+        let q_lin = tp.column_linear(dim, all_head_size, vb.pp("q_lin"))?;
+        let v_lin = tp.column_linear(dim, all_head_size, vb.pp("v_lin"))?;
+        let k_lin = tp.column_linear(dim, all_head_size, vb.pp("k_lin"))?;
+        let out_lin = tp.row_linear(all_head_size, dim, vb.pp("out_lin"))?;
         Ok(Self {
             q_lin,
             k_lin,
             v_lin,
             out_lin,
-            n_heads: config.n_heads,
+            // each tensor parallel rank computes a subset of the heads
+            n_heads: tp.split(config.n_heads, "n_heads")?,
             attention_head_size,
             use_flash_attn: config.use_flash_attn.unwrap_or(false),
             span: tracing::span!(tracing::Level::TRACE, "attention"),
@@ -211,6 +215,8 @@ impl MultiHeadSelfAttention {
 
             let scores = masked_fill(&scores.to_dtype(DType::F32)?, &mask, f32::NEG_INFINITY)?;
             let weights = candle_nn::ops::softmax(&scores, candle_core::D::Minus1)?;
+            // the scores are masked in F32, the values keep the model dtype, e.g. BF16
+            let weights = weights.to_dtype(v.dtype())?;
 
             let context = weights.matmul(&v.contiguous()?)?;
             context
@@ -228,16 +234,16 @@ impl MultiHeadSelfAttention {
 
 #[allow(clippy::upper_case_acronyms)]
 struct FFN {
-    lin1: Linear,
-    lin2: Linear,
+    lin1: ParallelLinear,
+    lin2: ParallelLinear,
     activation: HiddenActLayer,
     span: tracing::Span,
 }
 
 impl FFN {
-    fn load(vb: VarBuilder, config: &DistilBertConfig) -> Result<Self> {
-        let lin1 = linear(config.dim, config.hidden_dim, vb.pp("lin1"))?;
-        let lin2 = linear(config.hidden_dim, config.dim, vb.pp("lin2"))?;
+    fn load(vb: VarBuilder, config: &DistilBertConfig, tp: &Parallelism) -> Result<Self> {
+        let lin1 = tp.column_linear(config.dim, config.hidden_dim, vb.pp("lin1"))?;
+        let lin2 = tp.row_linear(config.hidden_dim, config.dim, vb.pp("lin2"))?;
         Ok(Self {
             lin1,
             lin2,
@@ -266,10 +272,10 @@ struct TransformerBlock {
 }
 
 impl TransformerBlock {
-    fn load(vb: VarBuilder, config: &DistilBertConfig) -> Result<Self> {
-        let attention = MultiHeadSelfAttention::load(vb.pp("attention"), config)?;
+    fn load(vb: VarBuilder, config: &DistilBertConfig, tp: &Parallelism) -> Result<Self> {
+        let attention = MultiHeadSelfAttention::load(vb.pp("attention"), config, tp)?;
         let sa_layer_norm = layer_norm(config.dim, 1e-12, vb.pp("sa_layer_norm"))?;
-        let ffn = FFN::load(vb.pp("ffn"), config)?;
+        let ffn = FFN::load(vb.pp("ffn"), config, tp)?;
         let output_layer_norm = layer_norm(config.dim, 1e-12, vb.pp("output_layer_norm"))?;
         Ok(Self {
             attention,
@@ -301,16 +307,27 @@ impl TransformerBlock {
 // https://github.com/huggingface/transformers/blob/6eedfa6dd15dc1e22a55ae036f681914e5a0d9a1/src/transformers/models/bert/modeling_bert.py#L556
 struct Transformer {
     layers: Vec<TransformerBlock>,
+    devices: Vec<Device>,
     span: tracing::Span,
 }
 
 impl Transformer {
-    fn load(vb: VarBuilder, config: &DistilBertConfig) -> Result<Self> {
+    fn load(device_map: &DeviceMap, config: &DistilBertConfig) -> Result<Self> {
         let layers = (0..config.n_layers)
-            .map(|index| TransformerBlock::load(vb.pp(&format!("layer.{index}")), config))
+            .map(|index| {
+                let vb = device_map.layer(index).pp(&format!("layer.{index}"));
+                TransformerBlock::load(vb, config, device_map.parallelism())
+            })
             .collect::<Result<Vec<_>>>()?;
+        let devices = (0..config.n_layers)
+            .map(|index| device_map.layer(index).device().clone())
+            .collect();
         let span = tracing::span!(tracing::Level::TRACE, "encoder");
-        Ok(Transformer { layers, span })
+        Ok(Transformer {
+            layers,
+            devices,
+            span,
+        })
     }
 }
 
@@ -318,9 +335,15 @@ impl Transformer {
     fn forward(&self, hidden_states: &Tensor, attention_mask: &Tensor) -> Result<Tensor> {
         let _enter = self.span.enter();
         let mut hidden_states = hidden_states.clone();
+        let mut attention_mask = attention_mask.clone();
         // Use a loop rather than a fold as it's easier to modify when adding debug/...
-        for layer in self.layers.iter() {
-            hidden_states = layer.forward(&hidden_states, attention_mask)?;
+        for (index, (layer, device)) in self.layers.iter().zip(self.devices.iter()).enumerate() {
+            cancel::check()?;
+            // the inputs only move when the layers are sharded across devices
+            hidden_states = hidden_states.to_device(device)?;
+            attention_mask = attention_mask.to_device(device)?;
+            hidden_states = layer.forward(&hidden_states, &attention_mask)?;
+            numerics::check(format_args!("transformer.layer.{index}"), &hidden_states)?;
         }
         Ok(hidden_states)
     }
@@ -329,22 +352,27 @@ impl Transformer {
 pub struct DistilBertModel {
     embeddings: Embeddings,
     transformer: Transformer,
+    max_position_embeddings: usize,
     pub device: Device,
     span: tracing::Span,
 }
 
 impl DistilBertModel {
-    pub fn load(vb: VarBuilder, config: &DistilBertConfig) -> Result<Self> {
+    pub fn load(device_map: &DeviceMap, config: &DistilBertConfig) -> Result<Self> {
+        let vb = device_map.vb();
         let (embeddings, transformer) = match (
             Embeddings::load(vb.pp("embeddings"), config),
-            Transformer::load(vb.pp("transformer"), config),
+            Transformer::load(&device_map.pp("transformer"), config),
         ) {
             (Ok(embeddings), Ok(encoder)) => (embeddings, encoder),
             (Err(err), _) | (_, Err(err)) => {
                 if let Some(model_type) = &config.model_type {
                     if let (Ok(embeddings), Ok(encoder)) = (
                         Embeddings::load(vb.pp(&format!("{model_type}.embeddings")), config),
-                        Transformer::load(vb.pp(&format!("{model_type}.transformer")), config),
+                        Transformer::load(
+                            &device_map.pp(&format!("{model_type}.transformer")),
+                            config,
+                        ),
                     ) {
                         (embeddings, encoder)
                     } else {
@@ -358,6 +386,7 @@ impl DistilBertModel {
         Ok(Self {
             embeddings,
             transformer,
+            max_position_embeddings: config.max_position_embeddings,
             device: vb.device().clone(),
             span: tracing::span!(tracing::Level::TRACE, "model"),
         })
@@ -373,17 +402,32 @@ impl Model for DistilBertModel {
         return vec!["input_ids".to_string(), "attention_mask".to_string()];
     }
 
+    fn get_output_names(&self) -> Vec<String> {
+        vec!["last_hidden_state".to_string()]
+    }
+
+    fn device(&self) -> &Device {
+        &self.device
+    }
+
     fn forward(
         &self,
         input_ids: &Tensor,
         attention_mask: &Tensor,
         _token_type_ids: Option<&Tensor>,
-    ) -> Result<Tensor> {
+        position_ids: Option<&Tensor>,
+    ) -> Result<Outputs> {
         let _enter = self.span.enter();
-        let embedding_output = self.embeddings.forward(input_ids)?;
+        check_seq_len(
+            input_ids.dim(1)?,
+            position_ids,
+            self.max_position_embeddings,
+        )?;
+        let embedding_output = self.embeddings.forward(input_ids, position_ids)?;
+        numerics::check("embeddings", &embedding_output)?;
         let sequence_output = self
             .transformer
             .forward(&embedding_output, attention_mask)?;
-        Ok(sequence_output)
+        Ok(vec![("last_hidden_state".to_string(), sequence_output)])
     }
 }
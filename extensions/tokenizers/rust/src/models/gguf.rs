@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+
+use candle_core::quantized::gguf_file::Content;
+use candle_core::{DType, Device, Result, Tensor};
+use candle_transformers::models::quantized_llama::ModelWeights;
+
+use crate::models::{Model, Outputs};
+
+/// Returns the GGUF file of a model, `model_path` is either the file itself or a directory with a
+/// single `.gguf` file and no `config.json`.
+pub(crate) fn find_gguf(model_path: &Path) -> Result<Option<PathBuf>> {
+    let is_gguf = |path: &Path| path.extension().is_some_and(|ext| ext == "gguf");
+    if model_path.is_file() {
+        return Ok(is_gguf(model_path).then(|| model_path.to_path_buf()));
+    }
+    if model_path.join("config.json").exists() {
+        return Ok(None);
+    }
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(model_path)? {
+        let path = entry?.path();
+        if is_gguf(&path) {
+            files.push(path);
+        }
+    }
+    match files.len() {
+        0 => Ok(None),
+        1 => Ok(files.pop()),
+        _ => candle_core::bail!("Found more than one .gguf file in {}", model_path.display()),
+    }
+}
+
+/// Reads the header of a GGUF file, the tensors are loaded by `GgufModel::load`.
+pub(crate) fn read_content(path: &Path) -> Result<Content> {
+    let mut file = std::fs::File::open(path)?;
+    Content::read(&mut file).map_err(|err| err.with_path(path))
+}
+
+/// A llama.cpp checkpoint, e.g. a quantized LLaMA or Mistral. The matmuls run on the quantized
+/// weights.
+pub(crate) struct GgufModel {
+    // every forward mutates the kv-cache of its own copy, the copies share the quantized weights
+    model: ModelWeights,
+    device: Device,
+}
+
+impl GgufModel {
+    pub(crate) fn load(path: &Path, content: Content, device: &Device) -> Result<Self> {
+        let architecture = content
+            .metadata
+            .get("general.architecture")
+            .and_then(|value| value.to_string().ok())
+            .cloned()
+            .unwrap_or_default();
+        // Mistral checkpoints are converted with the llama architecture
+        if architecture != "llama" {
+            candle_core::bail!("Unsupported GGUF architecture: {architecture}")
+        }
+        tracing::info!("Starting GGUF {architecture} model on {:?}", device);
+        let mut file = std::fs::File::open(path)?;
+        let model = ModelWeights::from_gguf(content, &mut file, device)?;
+        Ok(Self {
+            model,
+            device: device.clone(),
+        })
+    }
+}
+
+impl Model for GgufModel {
+    fn is_padded(&self) -> bool {
+        false
+    }
+
+    fn get_input_names(&self) -> Vec<String> {
+        vec!["input_ids".to_string(), "attention_mask".to_string()]
+    }
+
+    fn get_output_names(&self) -> Vec<String> {
+        vec!["logits".to_string()]
+    }
+
+    fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Returns the `(batch, vocab_size)` logits of the next token.
+    fn forward(
+        &self,
+        input_ids: &Tensor,
+        attention_mask: &Tensor,
+        _token_type_ids: Option<&Tensor>,
+        position_ids: Option<&Tensor>,
+    ) -> Result<Outputs> {
+        if position_ids.is_some() {
+            candle_core::bail!("GGUF models don't accept position_ids")
+        }
+        // the attention is only causal, padding would be attended to
+        let min = attention_mask.flatten_all()?.to_dtype(DType::F32)?.min(0)?;
+        if min.to_scalar::<f32>()? == 0. {
+            candle_core::bail!("GGUF models don't support padded batches")
+        }
+        let input_ids = input_ids.to_dtype(DType::U32)?;
+        // a forward from position 0 doesn't read the kv-cache, so a copy without one is enough
+        let mut model = self.model.clone();
+        let logits = model.forward(&input_ids, 0)?;
+        Ok(vec![("logits".to_string(), logits)])
+    }
+}
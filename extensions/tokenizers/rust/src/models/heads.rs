@@ -0,0 +1,25 @@
+use std::cell::Cell;
+
+thread_local! {
+    static HIDDEN_STATE_ONLY: Cell<bool> = const { Cell::new(false) };
+}
+
+struct Restore(bool);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        HIDDEN_STATE_ONLY.with(|current| current.set(self.0));
+    }
+}
+
+/// Runs `op` with the forwards of the current thread returning only the last hidden state when
+/// `hidden_state_only` is set, the heads such as the BERT pooler are not computed.
+pub(crate) fn with_hidden_state_only<R>(hidden_state_only: bool, op: impl FnOnce() -> R) -> R {
+    let _restore = Restore(HIDDEN_STATE_ONLY.with(|current| current.replace(hidden_state_only)));
+    op()
+}
+
+/// Returns `true` if the forward running on the current thread returns the outputs of its heads.
+pub(crate) fn requested() -> bool {
+    !HIDDEN_STATE_ONLY.with(Cell::get)
+}
@@ -0,0 +1,125 @@
+use std::path::PathBuf;
+
+use candle_core::{Error, Result};
+use hf_hub::api::sync::ApiBuilder;
+use hf_hub::{Repo, RepoType};
+use jni::objects::{JObject, JString};
+use jni::JNIEnv;
+use serde::Deserialize;
+
+use crate::throw_error;
+
+/// The prefix of model paths that are Hugging Face Hub repo ids.
+pub(crate) const HUB_PREFIX: &str = "hf://";
+
+/// The load options of models downloaded from the Hugging Face Hub.
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct HubOptions {
+    /// A branch, tag or commit, `main` by default.
+    revision: Option<String>,
+    /// The token of private and gated repos, `HF_TOKEN` or the token saved by
+    /// `huggingface-cli login` by default.
+    hf_token: Option<String>,
+}
+
+impl HubOptions {
+    pub(crate) fn from_json(options: &str) -> Result<Self> {
+        serde_json::from_str(options)
+            .map_err(|err| Error::Msg(format!("Invalid load options: {err}")))
+    }
+
+    /// The options of the base model of an adapter, only the token applies to it.
+    pub(crate) fn base_model(&self) -> Self {
+        Self {
+            revision: None,
+            hf_token: self.hf_token.clone(),
+        }
+    }
+}
+
+/// Files loaded from the model directory besides the weights, e.g. `config.json`,
+/// `tokenizer.json` and `model.safetensors.index.json`.
+fn is_config(file: &str) -> bool {
+    !file.contains('/') && file.ends_with(".json")
+}
+
+/// The safetensors weights of full checkpoints, sharded checkpoints and PEFT adapters.
+fn is_weights(file: &str) -> bool {
+    file == "model.safetensors"
+        || file == "adapter_model.safetensors"
+        || (file.starts_with("model-") && file.contains("-of-") && file.ends_with(".safetensors"))
+}
+
+/// Downloads the config, the tokenizer and the safetensors weights of a model repo into the
+/// Hugging Face cache, files that are already cached are not downloaded again. Returns the
+/// snapshot directory, so the model loads like a local directory.
+pub(crate) fn download(repo_id: &str, options: &HubOptions) -> Result<PathBuf> {
+    let mut builder = ApiBuilder::new().with_progress(false);
+    let token = options
+        .hf_token
+        .clone()
+        .or_else(|| std::env::var("HF_TOKEN").ok());
+    if let Some(token) = token {
+        builder = builder.with_token(Some(token));
+    }
+    let api = builder.build().map_err(Error::wrap)?;
+    let revision = options.revision.as_deref().unwrap_or("main");
+    let repo = api.repo(Repo::with_revision(
+        repo_id.to_string(),
+        RepoType::Model,
+        revision.to_string(),
+    ));
+    let info = repo.info().map_err(|err| {
+        Error::Msg(format!(
+            "Couldn't find {repo_id} at revision {revision} on the Hugging Face Hub: {err}"
+        ))
+    })?;
+    let files: Vec<&str> = info
+        .siblings
+        .iter()
+        .map(|sibling| sibling.rfilename.as_str())
+        .filter(|file| is_config(file) || is_weights(file))
+        .collect();
+    if !files.iter().any(|file| is_weights(file)) {
+        candle_core::bail!("{repo_id} has no safetensors weights")
+    }
+    tracing::info!("Downloading {repo_id} at revision {revision}");
+    let mut snapshot = None;
+    for file in files {
+        let path = repo.get(file).map_err(Error::wrap)?;
+        // the files are at the top of the snapshot directory
+        snapshot = path.parent().map(PathBuf::from);
+    }
+    snapshot.ok_or_else(|| Error::Msg(format!("Couldn't download {repo_id}")))
+}
+
+/// Downloads a model from the Hugging Face Hub, returns the local model directory.
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_downloadModel<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    repo_id: JString,
+    options: JString,
+) -> JString<'local> {
+    let repo_id: String = env
+        .get_string(&repo_id)
+        .expect("Couldn't get java string!")
+        .into();
+    let options: String = env
+        .get_string(&options)
+        .expect("Couldn't get java string!")
+        .into();
+    let op = || {
+        let options = HubOptions::from_json(&options)?;
+        download(&repo_id, &options)
+    };
+    match op() {
+        Ok(path) => env
+            .new_string(path.to_string_lossy())
+            .expect("Couldn't create java string!"),
+        Err(err) => {
+            throw_error(&mut env, err);
+            JObject::null().into()
+        }
+    }
+}
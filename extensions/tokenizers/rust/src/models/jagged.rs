@@ -0,0 +1,106 @@
+use candle_core::{Device, Error, Result, Tensor};
+use jni::objects::{JLongArray, JObject, JObjectArray};
+use jni::sys::jlong;
+use jni::JNIEnv;
+
+use crate::models::packed::Packed;
+use crate::models::{cancel, model_of};
+use crate::ndarray::return_handles;
+
+/// Pads variable length sequences on the right to the longest one, returns the `(batch, seq_len)`
+/// ids and the attention mask that marks the padding.
+fn pad(sequences: &[Vec<u32>], device: &Device) -> Result<(Tensor, Tensor)> {
+    let seq_len = sequences.iter().map(Vec::len).max().unwrap_or(0);
+    let mut ids = Vec::with_capacity(sequences.len() * seq_len);
+    let mut mask = Vec::with_capacity(sequences.len() * seq_len);
+    for sequence in sequences {
+        let padding = seq_len - sequence.len();
+        ids.extend_from_slice(sequence);
+        ids.extend(std::iter::repeat(0).take(padding));
+        mask.extend(std::iter::repeat(1u32).take(sequence.len()));
+        mask.extend(std::iter::repeat(0u32).take(padding));
+    }
+    let shape = (sequences.len(), seq_len);
+    let ids = Tensor::from_vec(ids, shape, device)?;
+    let mask = Tensor::from_vec(mask, shape, device)?;
+    Ok((ids, mask))
+}
+
+fn read_sequences(env: &mut JNIEnv, array: &JObjectArray) -> Result<Vec<Vec<u32>>> {
+    let len = env.get_array_length(array).map_err(Error::wrap)?;
+    let mut sequences = Vec::with_capacity(len as usize);
+    for i in 0..len {
+        let sequence: JLongArray = env
+            .get_object_array_element(array, i)
+            .map_err(Error::wrap)?
+            .into();
+        let size = env.get_array_length(&sequence).map_err(Error::wrap)?;
+        let mut buf = vec![0; size as usize];
+        env.get_long_array_region(&sequence, 0, &mut buf)
+            .map_err(Error::wrap)?;
+        sequences.push(buf.into_iter().map(|id| id as u32).collect());
+    }
+    Ok(sequences)
+}
+
+/// Runs a forward on sequences of different lengths, the batch is packed for models that support
+/// it and padded natively otherwise. The outputs are followed by the attention mask, so callers
+/// can ignore the padding when pooling.
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_runInferenceJagged<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+    input_ids: JObjectArray<'local>,
+    token_type_ids: JObjectArray<'local>,
+    cancellation_token: jlong,
+) -> JLongArray<'local> {
+    let model = or_throw!(env, model_of(handle));
+    let token = or_throw!(env, cancel::from_handle(cancellation_token));
+    let mut op = || {
+        let ids = read_sequences(&mut env, &input_ids)?;
+        if ids.is_empty() {
+            candle_core::bail!("Expected at least one sequence")
+        }
+        let types = if token_type_ids.is_null() {
+            None
+        } else {
+            let types = read_sequences(&mut env, &token_type_ids)?;
+            let lengths =
+                |sequences: &[Vec<u32>]| sequences.iter().map(Vec::len).collect::<Vec<_>>();
+            if lengths(&types) != lengths(&ids) {
+                candle_core::bail!("token_type_ids don't match the shape of input_ids")
+            }
+            Some(types)
+        };
+        crate::threads::install(|| {
+            let device = model.device();
+            let (input_ids, attention_mask) = pad(&ids, device)?;
+            let outputs = if model.supports_packed() {
+                // no compute is spent on padding, the outputs are padded afterwards
+                let packed = Packed::new(ids.iter().map(Vec::len).collect(), device)?;
+                let input_ids = Packed::pack(&ids, device)?;
+                let token_type_ids = match &types {
+                    Some(types) => Some(Packed::pack(types, device)?),
+                    None => None,
+                };
+                cancel::with_token(token, || {
+                    model.forward_packed(&input_ids, token_type_ids.as_ref(), &packed)
+                })?
+            } else {
+                let token_type_ids = match &types {
+                    Some(types) => Some(pad(types, device)?.0),
+                    None => None,
+                };
+                cancel::with_token(token, || {
+                    model.forward(&input_ids, &attention_mask, token_type_ids.as_ref(), None)
+                })?
+            };
+            let mut tensors: Vec<Tensor> = outputs.into_iter().map(|(_, tensor)| tensor).collect();
+            tensors.push(attention_mask);
+            Ok(tensors)
+        })
+    };
+    let tensors = op();
+    return_handles(&mut env, tensors)
+}
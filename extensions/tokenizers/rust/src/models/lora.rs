@@ -0,0 +1,768 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use candle_core::{DType, Device, Error, Result, Tensor};
+use jni::objects::{JObject, JString};
+use jni::sys::jlong;
+use jni::JNIEnv;
+use serde::Deserialize;
+
+use crate::models::hub::{self, HubOptions};
+use crate::models::packed::Packed;
+use crate::models::{checkpoint_files, model_of, Model, Outputs};
+use crate::{throw_error, to_handle};
+
+/// The prefix PEFT adds to the names of the base model modules.
+const PEFT_PREFIX: &str = "base_model.model.";
+
+/// The suffixes of the adapter tensors that are read, other LoRA tensors such as `lora_B.bias`
+/// are rejected rather than ignored.
+const LORA_SUFFIXES: [&str; 6] = [
+    ".lora_A.weight",
+    ".lora_B.weight",
+    ".lora_embedding_A",
+    ".lora_embedding_B",
+    ".lora_magnitude_vector",
+    ".lora_magnitude_vector.weight",
+];
+
+/// The `adapter_config.json` of a PEFT LoRA adapter.
+#[derive(Debug, Deserialize)]
+struct AdapterConfig {
+    peft_type: Option<String>,
+    r: usize,
+    lora_alpha: f64,
+    #[serde(default)]
+    fan_in_fan_out: bool,
+    #[serde(default)]
+    use_rslora: bool,
+    /// The ranks and alphas of the modules that don't use `r` and `lora_alpha`.
+    #[serde(default)]
+    rank_pattern: HashMap<String, usize>,
+    #[serde(default)]
+    alpha_pattern: HashMap<String, f64>,
+    /// DoRA adapters also rescale each row of the adapted weights by a learned magnitude.
+    #[serde(default)]
+    use_dora: bool,
+    base_model_name_or_path: Option<String>,
+}
+
+/// A PEFT LoRA adapter, each adapted module `W` becomes `W + scaling * B @ A`. Modules saved in
+/// full by the adapter, e.g. a fine-tuned classifier, replace the ones of the base model.
+pub(crate) struct Adapter {
+    config: AdapterConfig,
+    tensors: HashMap<String, Tensor>,
+}
+
+impl Adapter {
+    /// Loads an adapter directory with `adapter_config.json` and `adapter_model.safetensors` or
+    /// `adapter_model.bin`.
+    pub(crate) fn load(adapter_path: &Path) -> Result<Self> {
+        let config = std::fs::read_to_string(adapter_path.join("adapter_config.json"))?;
+        let config: AdapterConfig = serde_json::from_str(&config)
+            .map_err(|err| Error::Msg(format!("Invalid adapter_config.json: {err}")))?;
+        if let Some(peft_type) = config.peft_type.as_deref().filter(|t| *t != "LORA") {
+            candle_core::bail!("Unsupported adapter peft_type: {peft_type}")
+        }
+        let safetensors_path = adapter_path.join("adapter_model.safetensors");
+        let tensors: HashMap<String, Tensor> = if safetensors_path.exists() {
+            candle_core::safetensors::load(safetensors_path, &Device::Cpu)?
+        } else {
+            candle_core::pickle::read_all(adapter_path.join("adapter_model.bin"))?
+                .into_iter()
+                .collect()
+        };
+        let tensors: HashMap<String, Tensor> = tensors
+            .into_iter()
+            .map(|(name, tensor)| {
+                let name = name.strip_prefix(PEFT_PREFIX).unwrap_or(&name).to_string();
+                (name, tensor)
+            })
+            .collect();
+        for name in tensors.keys() {
+            if name.contains(".lora_") && !LORA_SUFFIXES.iter().any(|s| name.ends_with(s)) {
+                candle_core::bail!("Unsupported adapter tensor {name}")
+            }
+        }
+        Ok(Self { config, tensors })
+    }
+
+    /// Returns the scaling of a module, `rank_pattern` and `alpha_pattern` override the rank and
+    /// the alpha of the modules they match.
+    fn scaling(&self, module: &str) -> Result<f64> {
+        let config = &self.config;
+        let r = pattern(&config.rank_pattern, module)?.map_or(config.r, |&r| r) as f64;
+        let alpha = pattern(&config.alpha_pattern, module)?.map_or(config.lora_alpha, |&a| a);
+        if config.use_rslora {
+            Ok(alpha / r.sqrt())
+        } else {
+            Ok(alpha / r)
+        }
+    }
+
+    /// Returns the low-rank factors by the name of the weight they adapt, the `(r, in_dim)` `A`
+    /// and the `(out_dim, r)` `B` already multiplied by the scaling.
+    pub(crate) fn factors(&self) -> Result<Vec<(String, Tensor, Tensor)>> {
+        let mut factors = Vec::new();
+        for (name, a) in &self.tensors {
+            let Some(module) = name.strip_suffix(".lora_A.weight") else {
+                continue;
+            };
+            let b = self.get(&format!("{module}.lora_B.weight"))?;
+            let b = (b.to_dtype(DType::F32)? * self.scaling(module)?)?;
+            factors.push((format!("{module}.weight"), a.to_dtype(DType::F32)?, b));
+        }
+        Ok(factors)
+    }
+
+    /// Merges the adapter into the weights of the base model.
+    pub(crate) fn merge(&self, tensors: &mut HashMap<String, Tensor>) -> Result<()> {
+        for (name, a, b) in self.factors()? {
+            let mut delta = b.matmul(&a)?;
+            if self.config.use_dora {
+                self.merge_dora(tensors, &name, &delta)?;
+                continue;
+            }
+            if self.config.fan_in_fan_out {
+                delta = delta.t()?;
+            }
+            add_delta(tensors, &name, &delta)?;
+        }
+        for (name, a) in &self.tensors {
+            let Some(module) = name.strip_suffix(".lora_embedding_A") else {
+                continue;
+            };
+            if self.config.use_dora {
+                candle_core::bail!("DoRA adapters of embeddings are not supported")
+            }
+            // the embedding factors are saved transposed
+            let b = self.get(&format!("{module}.lora_embedding_B"))?;
+            let delta = (b
+                .to_dtype(DType::F32)?
+                .matmul(&a.to_dtype(DType::F32)?)?
+                .t()?
+                * self.scaling(module)?)?;
+            add_delta(tensors, &format!("{module}.weight"), &delta)?;
+        }
+        for (name, tensor) in &self.tensors {
+            if !name.contains(".lora_") {
+                let name = resolve(tensors, name)?;
+                tensors.insert(name, tensor.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Merges a DoRA module, the rows of `W + delta` are normalized and scaled by the magnitude
+    /// vector of the adapter.
+    fn merge_dora(
+        &self,
+        tensors: &mut HashMap<String, Tensor>,
+        name: &str,
+        delta: &Tensor,
+    ) -> Result<()> {
+        if self.config.fan_in_fan_out {
+            candle_core::bail!("fan_in_fan_out DoRA adapters are not supported")
+        }
+        let module = name.strip_suffix(".weight").unwrap_or(name);
+        let magnitude = match self
+            .tensors
+            .get(&format!("{module}.lora_magnitude_vector.weight"))
+        {
+            Some(magnitude) => magnitude,
+            None => self.get(&format!("{module}.lora_magnitude_vector"))?,
+        };
+        let name = resolve(tensors, name)?;
+        let weight = &tensors[&name];
+        let merged = (weight.to_dtype(DType::F32)? + delta)?;
+        let norm = merged.sqr()?.sum_keepdim(1)?.sqrt()?;
+        let magnitude = magnitude.to_dtype(DType::F32)?.reshape(norm.shape())?;
+        let merged = merged.broadcast_mul(&(magnitude / norm)?)?;
+        tensors.insert(name, merged.to_dtype(weight.dtype())?);
+        Ok(())
+    }
+
+    fn get(&self, name: &str) -> Result<&Tensor> {
+        self.tensors
+            .get(name)
+            .ok_or_else(|| Error::Msg(format!("Missing {name} in the adapter")))
+    }
+}
+
+/// Returns the base model of a PEFT adapter directory, a directory with `adapter_config.json`
+/// and adapter weights but no model weights of its own. Adapter directories may also have a
+/// `config.json`, e.g. saved with the tokenizer. A relative `base_model_name_or_path` is resolved
+/// against the adapter directory first. Base models that are not local directories are downloaded
+/// from the Hugging Face Hub, PEFT saves their repo ids without the `hf://` prefix.
+pub(crate) fn find_base_model(
+    model_path: &Path,
+    hub_options: &HubOptions,
+) -> Result<Option<PathBuf>> {
+    let config_path = model_path.join("adapter_config.json");
+    let has_adapter = ["adapter_model.safetensors", "adapter_model.bin"]
+        .iter()
+        .any(|file| model_path.join(file).exists());
+    let has_weights = ["model.safetensors", "pytorch_model.bin"]
+        .iter()
+        .any(|file| {
+            !checkpoint_files(model_path, file)
+                .unwrap_or_default()
+                .is_empty()
+        })
+        || model_path.join("model.npz").exists();
+    if !config_path.exists() || !has_adapter || has_weights {
+        return Ok(None);
+    }
+    let config = std::fs::read_to_string(config_path)?;
+    let config: AdapterConfig = serde_json::from_str(&config)
+        .map_err(|err| Error::Msg(format!("Invalid adapter_config.json: {err}")))?;
+    let Some(base_model) = config.base_model_name_or_path else {
+        candle_core::bail!("adapter_config.json has no base_model_name_or_path")
+    };
+    let base_path = [model_path.join(&base_model), PathBuf::from(&base_model)]
+        .into_iter()
+        .find(|path| path.join("config.json").exists());
+    match base_path {
+        Some(base_path) => Ok(Some(base_path)),
+        None => {
+            let repo_id = base_model
+                .strip_prefix(hub::HUB_PREFIX)
+                .unwrap_or(&base_model);
+            hub::download(repo_id, &hub_options.base_model()).map(Some)
+        }
+    }
+}
+
+/// Returns the load options of the base model of an adapter directory, the adapter of the
+/// directory is merged into the weights.
+pub(crate) fn adapter_options(options: &str, adapter_path: &Path) -> Result<String> {
+    let mut options: serde_json::Map<String, serde_json::Value> = serde_json::from_str(options)
+        .map_err(|err| Error::Msg(format!("Invalid load options: {err}")))?;
+    if options.contains_key("adapter") {
+        candle_core::bail!(
+            "{} is an adapter directory, the adapter option can't be used with it",
+            adapter_path.display()
+        )
+    }
+    // the adapter path would otherwise be resolved against the base model
+    let adapter_path = adapter_path.canonicalize()?;
+    options.insert("adapter".to_string(), adapter_path.to_string_lossy().into());
+    serde_json::to_string(&options).map_err(Error::wrap)
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<String>> = RefCell::new(None);
+}
+
+struct Restore(Option<String>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        ACTIVE.with(|active| *active.borrow_mut() = previous);
+    }
+}
+
+/// Runs `op` with `adapter` applied by the linear layers of the current thread.
+pub(crate) fn with_adapter<R>(adapter: Option<&str>, op: impl FnOnce() -> R) -> R {
+    let adapter = adapter.map(str::to_string);
+    let _restore = Restore(ACTIVE.with(|active| active.replace(adapter)));
+    op()
+}
+
+#[derive(Default)]
+struct AdaptersState {
+    /// The device and dtype of each weight that can be adapted.
+    layers: HashMap<String, (Device, DType)>,
+    /// The scaled low-rank factors of each adapter by weight name.
+    adapters: HashMap<String, HashMap<String, (Tensor, Tensor)>>,
+}
+
+/// The LoRA adapters resident next to a model, they are applied without merging so every batch
+/// can use a different adapter. The factors are placed on the device and in the dtype of the
+/// layers they adapt.
+#[derive(Clone, Default)]
+pub(crate) struct Adapters {
+    // forwards only hold the read lock while they look up the factors of a layer, the write lock
+    // is taken by the rare loads and unloads of adapters
+    state: Arc<RwLock<AdaptersState>>,
+}
+
+impl Adapters {
+    /// Records a weight that adapters can apply to, called when the model is loaded.
+    pub(crate) fn register(&self, weight: &str, device: &Device, dtype: DType) {
+        let mut state = self.state.write().unwrap();
+        state
+            .layers
+            .insert(weight.to_string(), (device.clone(), dtype));
+    }
+
+    /// Loads or replaces the adapter `name`.
+    pub(crate) fn load(&self, name: &str, adapter_path: &Path) -> Result<()> {
+        let adapter = Adapter::load(adapter_path)?;
+        // only the linear factors are applied per batch, the other tensors change the weights
+        if adapter.config.fan_in_fan_out {
+            candle_core::bail!("fan_in_fan_out adapters can only be merged")
+        }
+        if adapter.config.use_dora {
+            candle_core::bail!("DoRA adapters can only be merged")
+        }
+        if let Some(name) = adapter
+            .tensors
+            .keys()
+            .find(|name| !name.ends_with(".lora_A.weight") && !name.ends_with(".lora_B.weight"))
+        {
+            candle_core::bail!(
+                "{name} can only be merged, load the adapter with the `adapter` option"
+            )
+        }
+        let mut factors = HashMap::new();
+        {
+            let state = self.state.read().unwrap();
+            if state.layers.is_empty() {
+                candle_core::bail!("The model has no layers that adapters can apply to")
+            }
+            for (weight, a, b) in adapter.factors()? {
+                let weight = resolve(&state.layers, &weight)?;
+                let (device, dtype) = &state.layers[&weight];
+                let a = a.to_device(device)?.to_dtype(*dtype)?;
+                let b = b.to_device(device)?.to_dtype(*dtype)?;
+                factors.insert(weight, (a, b));
+            }
+        }
+        let mut state = self.state.write().unwrap();
+        state.adapters.insert(name.to_string(), factors);
+        Ok(())
+    }
+
+    pub(crate) fn unload(&self, name: &str) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+        match state.adapters.remove(name) {
+            Some(_) => Ok(()),
+            None => candle_core::bail!("Adapter {name} is not loaded"),
+        }
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.state.read().unwrap().adapters.contains_key(name)
+    }
+
+    /// Returns the factors the active adapter of the current thread has for `weight`.
+    pub(crate) fn active(&self, weight: &str) -> Option<(Tensor, Tensor)> {
+        let name = ACTIVE.with(|active| active.borrow().clone())?;
+        let state = self.state.read().unwrap();
+        state.adapters.get(&name)?.get(weight).cloned()
+    }
+}
+
+/// A handle to a model that runs its forwards with one of the adapters of the model.
+struct AdaptedModel {
+    model: Arc<dyn Model>,
+    adapters: Adapters,
+    adapter: String,
+}
+
+impl AdaptedModel {
+    fn run<R>(&self, op: impl FnOnce() -> Result<R>) -> Result<R> {
+        // the adapter may have been unloaded since the handle was created
+        if !self.adapters.contains(&self.adapter) {
+            candle_core::bail!("Adapter {} is not loaded", self.adapter)
+        }
+        with_adapter(Some(&self.adapter), op)
+    }
+}
+
+impl Model for AdaptedModel {
+    fn is_padded(&self) -> bool {
+        self.model.is_padded()
+    }
+
+    fn get_input_names(&self) -> Vec<String> {
+        self.model.get_input_names()
+    }
+
+    fn get_output_names(&self) -> Vec<String> {
+        self.model.get_output_names()
+    }
+
+    fn device(&self) -> &Device {
+        self.model.device()
+    }
+
+    fn metadata(&self) -> Option<&str> {
+        self.model.metadata()
+    }
+
+    fn forward(
+        &self,
+        input_ids: &Tensor,
+        attention_mask: &Tensor,
+        token_type_ids: Option<&Tensor>,
+        position_ids: Option<&Tensor>,
+    ) -> Result<Outputs> {
+        self.run(|| {
+            self.model
+                .forward(input_ids, attention_mask, token_type_ids, position_ids)
+        })
+    }
+
+    fn supports_packed(&self) -> bool {
+        self.model.supports_packed()
+    }
+
+    fn forward_packed(
+        &self,
+        input_ids: &Tensor,
+        token_type_ids: Option<&Tensor>,
+        packed: &Packed,
+    ) -> Result<Outputs> {
+        self.run(|| self.model.forward_packed(input_ids, token_type_ids, packed))
+    }
+
+    fn save(&self, _path: &Path, _dtype: Option<DType>) -> Result<()> {
+        candle_core::bail!("Save the base model, adapters are not merged into its weights")
+    }
+
+    fn adapters(&self) -> Option<&Adapters> {
+        Some(&self.adapters)
+    }
+}
+
+fn adapters_of(model: &dyn Model) -> Result<&Adapters> {
+    match model.adapters() {
+        Some(adapters) => Ok(adapters),
+        None => candle_core::bail!("The model doesn't support adapters"),
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_loadAdapter<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    name: JString,
+    adapter_path: JString,
+) {
+    let model = or_throw!(env, model_of(handle));
+    let name: String = env
+        .get_string(&name)
+        .expect("Couldn't get java string!")
+        .into();
+    let adapter_path: String = env
+        .get_string(&adapter_path)
+        .expect("Couldn't get java string!")
+        .into();
+    let op = || adapters_of(model.as_ref())?.load(&name, Path::new(&adapter_path));
+    if let Err(err) = op() {
+        throw_error(&mut env, err);
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_unloadAdapter<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    name: JString,
+) {
+    let model = or_throw!(env, model_of(handle));
+    let name: String = env
+        .get_string(&name)
+        .expect("Couldn't get java string!")
+        .into();
+    let op = || adapters_of(model.as_ref())?.unload(&name);
+    if let Err(err) = op() {
+        throw_error(&mut env, err);
+    }
+}
+
+/// Returns a new model handle whose forwards apply the adapter `name`, it holds a reference to
+/// the model like `retainModel`.
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_createAdaptedModel<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    name: JString,
+) -> jlong {
+    let model = or_throw!(env, model_of(handle));
+    let name: String = env
+        .get_string(&name)
+        .expect("Couldn't get java string!")
+        .into();
+    let op = || {
+        let adapters = adapters_of(model.as_ref())?.clone();
+        if !adapters.contains(&name) {
+            candle_core::bail!("Adapter {name} is not loaded")
+        }
+        let adapted: Arc<dyn Model> = Arc::new(AdaptedModel {
+            model: model.clone(),
+            adapters,
+            adapter: name,
+        });
+        Ok(adapted)
+    };
+    match op() {
+        Ok(adapted) => to_handle(adapted),
+        Err(err) => {
+            throw_error(&mut env, err);
+            0
+        }
+    }
+}
+
+/// Finds the `rank_pattern` or `alpha_pattern` value of a module. PEFT matches the keys as regular
+/// expressions against the end of the module name, only plain module names are supported, the
+/// longest matching key wins.
+fn pattern<'a, V>(patterns: &'a HashMap<String, V>, module: &str) -> Result<Option<&'a V>> {
+    let mut found: Option<(&str, &V)> = None;
+    for (key, value) in patterns {
+        if key.contains(|c: char| "\\^$*+?()[]{}|".contains(c)) {
+            candle_core::bail!("Unsupported adapter pattern {key}, only module names are supported")
+        }
+        let matches = module == key || module.ends_with(&format!(".{key}"));
+        if matches && found.map_or(true, |(found, _)| found.len() < key.len()) {
+            found = Some((key, value));
+        }
+    }
+    Ok(found.map(|(_, value)| value))
+}
+
+fn add_delta(tensors: &mut HashMap<String, Tensor>, name: &str, delta: &Tensor) -> Result<()> {
+    let name = resolve(tensors, name)?;
+    let weight = &tensors[&name];
+    let merged = (weight.to_dtype(DType::F32)? + delta)?.to_dtype(weight.dtype())?;
+    tensors.insert(name, merged);
+    Ok(())
+}
+
+/// Finds the base model tensor of an adapter module. Checkpoints of task models prefix the
+/// names with the model type, e.g. `bert.encoder`, so a unique suffix match is accepted too.
+fn resolve<V>(tensors: &HashMap<String, V>, name: &str) -> Result<String> {
+    if tensors.contains_key(name) {
+        return Ok(name.to_string());
+    }
+    let suffix = format!(".{name}");
+    let mut matches = tensors.keys().filter(|key| key.ends_with(&suffix));
+    match (matches.next(), matches.next()) {
+        (Some(key), None) => Ok(key.clone()),
+        (Some(_), Some(_)) => candle_core::bail!("Adapter module {name} is ambiguous"),
+        (None, _) => candle_core::bail!("Adapter module {name} is not in the base model"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a PEFT adapter directory, the tensor names get the PEFT prefix.
+    fn write_adapter(name: &str, config: &str, tensors: &[(&str, &Tensor)]) -> Result<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("djl-lora-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("adapter_config.json"), config)?;
+        let tensors: HashMap<String, Tensor> = tensors
+            .iter()
+            .map(|(name, tensor)| (format!("{PEFT_PREFIX}{name}"), (*tensor).clone()))
+            .collect();
+        candle_core::safetensors::save(&tensors, dir.join("adapter_model.safetensors"))?;
+        Ok(dir)
+    }
+
+    fn randn(shape: (usize, usize)) -> Result<Tensor> {
+        Tensor::randn(0f32, 1., shape, &Device::Cpu)
+    }
+
+    fn assert_close(actual: &Tensor, expected: &Tensor) -> Result<()> {
+        let diff: f32 = (actual - expected)?
+            .abs()?
+            .flatten_all()?
+            .max(0)?
+            .to_scalar()?;
+        assert!(diff < 1e-4, "max difference {diff}");
+        Ok(())
+    }
+
+    #[test]
+    fn merge_applies_rank_and_alpha_patterns() -> Result<()> {
+        let (query_a, query_b) = (randn((2, 4))?, randn((3, 2))?);
+        let (value_a, value_b) = (randn((4, 4))?, randn((3, 4))?);
+        let config = r#"{
+            "peft_type": "LORA",
+            "r": 2,
+            "lora_alpha": 4,
+            "rank_pattern": {"value": 4},
+            "alpha_pattern": {"layer.value": 2}
+        }"#;
+        let dir = write_adapter(
+            "patterns",
+            config,
+            &[
+                ("layer.query.lora_A.weight", &query_a),
+                ("layer.query.lora_B.weight", &query_b),
+                ("layer.value.lora_A.weight", &value_a),
+                ("layer.value.lora_B.weight", &value_b),
+            ],
+        )?;
+        let (query, value) = (randn((3, 4))?, randn((3, 4))?);
+        let mut tensors = HashMap::from([
+            ("layer.query.weight".to_string(), query.clone()),
+            ("layer.value.weight".to_string(), value.clone()),
+        ]);
+        let merged = Adapter::load(&dir).and_then(|adapter| adapter.merge(&mut tensors));
+        std::fs::remove_dir_all(dir)?;
+        merged?;
+
+        // 4 / 2 for the query, the value has its own rank and alpha, 2 / 4
+        let query_delta = (query_b.matmul(&query_a)? * 2.)?;
+        let value_delta = (value_b.matmul(&value_a)? * 0.5)?;
+        assert_close(&tensors["layer.query.weight"], &(query + query_delta)?)?;
+        assert_close(&tensors["layer.value.weight"], &(value + value_delta)?)
+    }
+
+    #[test]
+    fn merge_rescales_dora_rows() -> Result<()> {
+        let (a, b) = (randn((2, 4))?, randn((3, 2))?);
+        let magnitude = Tensor::new(&[1f32, 2., 3.], &Device::Cpu)?;
+        let config = r#"{"peft_type": "LORA", "r": 2, "lora_alpha": 2, "use_dora": true}"#;
+        let dir = write_adapter(
+            "dora",
+            config,
+            &[
+                ("dense.lora_A.weight", &a),
+                ("dense.lora_B.weight", &b),
+                ("dense.lora_magnitude_vector", &magnitude),
+            ],
+        )?;
+        let weight = randn((3, 4))?;
+        let mut tensors = HashMap::from([("dense.weight".to_string(), weight.clone())]);
+        let merged = Adapter::load(&dir).and_then(|adapter| adapter.merge(&mut tensors));
+        std::fs::remove_dir_all(dir)?;
+        merged?;
+
+        let merged = (weight + b.matmul(&a)?)?;
+        let norm = merged.sqr()?.sum_keepdim(1)?.sqrt()?;
+        let expected = merged.broadcast_mul(&(magnitude.reshape((3, 1))? / norm)?)?;
+        assert_close(&tensors["dense.weight"], &expected)
+    }
+
+    #[test]
+    fn load_rejects_unknown_lora_tensors() -> Result<()> {
+        let (a, b) = (randn((2, 4))?, randn((3, 2))?);
+        let bias = Tensor::zeros(3, DType::F32, &Device::Cpu)?;
+        let config = r#"{"peft_type": "LORA", "r": 2, "lora_alpha": 2}"#;
+        let dir = write_adapter(
+            "bias",
+            config,
+            &[
+                ("dense.lora_A.weight", &a),
+                ("dense.lora_B.weight", &b),
+                ("dense.lora_B.bias", &bias),
+            ],
+        )?;
+        let loaded = Adapter::load(&dir);
+        std::fs::remove_dir_all(dir)?;
+        match loaded {
+            Ok(_) => panic!("lora_B.bias should be rejected"),
+            Err(err) => assert!(err.to_string().contains("dense.lora_B.bias"), "{err}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn resident_adapter_matches_merged_weights() -> Result<()> {
+        use crate::models::tensor_parallel::Parallelism;
+        use candle_core::Module;
+        use candle_nn::VarBuilder;
+
+        let (a, b) = (randn((2, 4))?, randn((3, 2))?);
+        let config = r#"{"peft_type": "LORA", "r": 2, "lora_alpha": 4}"#;
+        let dir = write_adapter(
+            "resident",
+            config,
+            &[("dense.lora_A.weight", &a), ("dense.lora_B.weight", &b)],
+        )?;
+        let weight = randn((3, 4))?;
+        let bias = Tensor::new(&[0.1f32, 0.2, 0.3], &Device::Cpu)?;
+        let weights = HashMap::from([
+            ("dense.weight".to_string(), weight.clone()),
+            ("dense.bias".to_string(), bias.clone()),
+        ]);
+        let adapters = Adapters::default();
+        let vb = VarBuilder::from_tensors(weights.clone(), DType::F32, &Device::Cpu);
+        let linear = Parallelism::default()
+            .with_adapters(adapters.clone())
+            .column_linear(4, 3, vb.pp("dense"))?;
+        let mut merged = weights;
+        let loaded = adapters
+            .load("resident", &dir)
+            .and_then(|_| Adapter::load(&dir)?.merge(&mut merged));
+        std::fs::remove_dir_all(dir)?;
+        loaded?;
+
+        let xs = randn((2, 4))?;
+        let linear_of = |weight: &Tensor| xs.matmul(&weight.t()?)?.broadcast_add(&bias);
+        let adapted = with_adapter(Some("resident"), || linear.forward(&xs))?;
+        assert_close(&adapted, &linear_of(&merged["dense.weight"])?)?;
+        // the base weights are used without an active adapter
+        assert_close(&linear.forward(&xs)?, &linear_of(&weight)?)
+    }
+
+    #[test]
+    fn finds_the_base_model_of_adapter_directories() -> Result<()> {
+        let (a, b) = (randn((2, 4))?, randn((3, 2))?);
+        let config = r#"{"peft_type": "LORA", "r": 2, "base_model_name_or_path": "base"}"#;
+        let dir = write_adapter(
+            "base",
+            config,
+            &[("dense.lora_A.weight", &a), ("dense.lora_B.weight", &b)],
+        )?;
+        let base = dir.join("base");
+        std::fs::create_dir_all(&base)?;
+        std::fs::write(base.join("config.json"), "{}")?;
+        // a config.json saved next to the adapter doesn't make it a model directory
+        std::fs::write(dir.join("config.json"), "{}")?;
+        let found = find_base_model(&dir, &HubOptions::default())?;
+        let options = adapter_options(r#"{"dtype": "f32"}"#, &dir)?;
+        let conflict = adapter_options(r#"{"adapter": "other"}"#, &dir);
+        // a directory with its own weights is the model, not its adapter
+        candle_core::safetensors::save(&HashMap::from([("w", a)]), dir.join("model.safetensors"))?;
+        let with_weights = find_base_model(&dir, &HubOptions::default())?;
+        let adapter_path = dir.canonicalize()?;
+        std::fs::remove_dir_all(&dir)?;
+
+        assert_eq!(found, Some(base));
+        assert_eq!(with_weights, None);
+        let options: serde_json::Value = serde_json::from_str(&options).map_err(Error::wrap)?;
+        assert_eq!(options["adapter"], adapter_path.to_string_lossy().as_ref());
+        assert_eq!(options["dtype"], "f32");
+        assert!(conflict.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn resident_adapters_reject_full_modules() -> Result<()> {
+        let (a, b) = (randn((2, 4))?, randn((3, 2))?);
+        let classifier = randn((2, 3))?;
+        let config = r#"{"peft_type": "LORA", "r": 2, "lora_alpha": 2}"#;
+        let dir = write_adapter(
+            "modules",
+            config,
+            &[
+                ("dense.lora_A.weight", &a),
+                ("dense.lora_B.weight", &b),
+                ("classifier.weight", &classifier),
+            ],
+        )?;
+        let adapters = Adapters::default();
+        adapters.register("dense.weight", &Device::Cpu, DType::F32);
+        let loaded = adapters.load("modules", &dir);
+        std::fs::remove_dir_all(dir)?;
+        match loaded {
+            Ok(_) => panic!("classifier.weight should be rejected"),
+            Err(err) => assert!(err.to_string().contains("classifier.weight"), "{err}"),
+        }
+        Ok(())
+    }
+}
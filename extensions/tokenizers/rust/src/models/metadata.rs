@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use candle_core::quantized::gguf_file::{self, Content};
+use candle_core::quantized::GgmlDType;
+use candle_core::{DType, Device, DeviceLocation, Error, Result, Tensor};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::models::checkpoint::Checkpoint;
+use crate::models::lora::Adapters;
+use crate::models::packed::Packed;
+use crate::models::{checkpoint_files, AttentionBackend, Model, Outputs, Weights};
+
+/// Describes a loaded model, so Java translators can configure themselves from it.
+#[derive(Serialize)]
+pub(crate) struct ModelMetadata {
+    architecture: String,
+    model_type: String,
+    hidden_size: Option<u64>,
+    num_layers: usize,
+    vocab_size: Option<u64>,
+    max_position_embeddings: Option<u64>,
+    dtype: String,
+    device: String,
+    attention_backend: String,
+    parameter_count: usize,
+    /// The quantization of the weights, e.g. `q4k` or `q8_0` for GGUF files.
+    quantization: Option<String>,
+    id2label: Option<BTreeMap<String, String>>,
+}
+
+impl ModelMetadata {
+    /// Reads the metadata from `config.json` and the weights, the dtype, device and attention
+    /// backend are only known once the model is loaded.
+    pub(crate) fn new(config: &str, num_layers: usize, weights: &Weights) -> Result<Self> {
+        let config: Value = serde_json::from_str(config).map_err(Error::wrap)?;
+        let number = |keys: &[&str]| keys.iter().find_map(|key| config[key].as_u64());
+        let model_type = config["model_type"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let architecture = config["architectures"][0]
+            .as_str()
+            .map_or_else(|| model_type.clone(), str::to_string);
+        let id2label = config["id2label"].as_object().map(|labels| {
+            labels
+                .iter()
+                .map(|(id, label)| (id.clone(), label.as_str().unwrap_or_default().to_string()))
+                .collect()
+        });
+        Ok(Self {
+            architecture,
+            model_type,
+            // DistilBERT names the hidden size `dim`
+            hidden_size: number(&["hidden_size", "dim"]),
+            num_layers,
+            vocab_size: number(&["vocab_size"]),
+            max_position_embeddings: number(&["max_position_embeddings"]),
+            dtype: String::new(),
+            device: String::new(),
+            attention_backend: String::new(),
+            parameter_count: parameter_count(weights)?,
+            quantization: None,
+            id2label,
+        })
+    }
+
+    /// Records the quantization of the linear weights requested when the model is loaded.
+    pub(crate) fn with_quantization(mut self, dtype: Option<GgmlDType>) -> Self {
+        self.quantization = dtype.map(quantization_name);
+        self
+    }
+
+    /// Reads the metadata from the header of a GGUF file, which replaces `config.json`.
+    pub(crate) fn from_gguf(content: &Content) -> Self {
+        let architecture = content
+            .metadata
+            .get("general.architecture")
+            .and_then(|value| value.to_string().ok())
+            .cloned()
+            .unwrap_or_default();
+        let number = |key: &str| {
+            let value = content.metadata.get(&format!("{architecture}.{key}"))?;
+            value.to_u32().map(u64::from).ok()
+        };
+        let vocab_size = match content.metadata.get("tokenizer.ggml.tokens") {
+            Some(gguf_file::Value::Array(tokens)) => Some(tokens.len() as u64),
+            _ => None,
+        };
+        Self {
+            architecture: architecture.clone(),
+            model_type: architecture.clone(),
+            hidden_size: number("embedding_length"),
+            num_layers: number("block_count").unwrap_or_default() as usize,
+            vocab_size,
+            max_position_embeddings: number("context_length"),
+            dtype: String::new(),
+            device: String::new(),
+            attention_backend: String::new(),
+            parameter_count: content
+                .tensor_infos
+                .values()
+                .map(|info| info.shape.elem_count())
+                .sum(),
+            quantization: quantization(content),
+            id2label: None,
+        }
+    }
+}
+
+/// Returns the dtype most of the parameters are stored in, the norms usually stay in F32.
+fn quantization(content: &Content) -> Option<String> {
+    let mut counts = BTreeMap::new();
+    for info in content.tensor_infos.values() {
+        let dtype = quantization_name(info.ggml_dtype);
+        *counts.entry(dtype).or_insert(0) += info.shape.elem_count();
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .map(|(dtype, _)| dtype)
+}
+
+fn quantization_name(dtype: GgmlDType) -> String {
+    format!("{dtype:?}").to_lowercase()
+}
+
+/// Counts the parameters from the tensor headers, without loading the weights again.
+fn parameter_count(weights: &Weights) -> Result<usize> {
+    let count = |shape: &[usize]| shape.iter().product::<usize>();
+    match weights {
+        Weights::Path(model_path) => {
+            let safetensors_paths = checkpoint_files(model_path, "model.safetensors")?;
+            if !safetensors_paths.is_empty() {
+                let tensors = unsafe {
+                    candle_core::safetensors::MmapedSafetensors::multi(&safetensors_paths)?
+                };
+                Ok(tensors
+                    .tensors()
+                    .iter()
+                    .map(|(_, view)| count(view.shape()))
+                    .sum())
+            } else {
+                let pth_paths = checkpoint_files(model_path, "pytorch_model.bin")?;
+                let mut total = 0;
+                if pth_paths.is_empty() {
+                    let tensors = candle_core::npy::NpzTensors::new(model_path.join("model.npz"))?;
+                    for name in tensors.names() {
+                        total += tensors.get_shape_and_dtype(name)?.0.elem_count();
+                    }
+                }
+                for path in pth_paths {
+                    let tensors = candle_core::pickle::read_pth_tensor_info(path, false)?;
+                    total += tensors
+                        .iter()
+                        .map(|info| info.layout.shape().elem_count())
+                        .sum::<usize>();
+                }
+                Ok(total)
+            }
+        }
+        Weights::Bytes(shards) => Ok(shards
+            .views()?
+            .iter()
+            .map(|(_, view)| count(view.shape()))
+            .sum()),
+        Weights::Tensors(tensors) => Ok(tensors.values().map(Tensor::elem_count).sum()),
+    }
+}
+
+/// Keeps the metadata and the checkpoint next to the model they describe.
+pub(crate) struct DescribedModel {
+    model: Box<dyn Model>,
+    metadata: String,
+    dtype: DType,
+    checkpoint: Checkpoint,
+    adapters: Option<Adapters>,
+}
+
+impl DescribedModel {
+    pub(crate) fn new(
+        model: Box<dyn Model>,
+        mut metadata: ModelMetadata,
+        dtype: DType,
+        backend: AttentionBackend,
+        checkpoint: Checkpoint,
+        adapters: Option<Adapters>,
+    ) -> Result<Self> {
+        metadata.dtype = format!("{dtype:?}").to_lowercase();
+        metadata.attention_backend = backend.name().to_string();
+        metadata.device = match model.device().location() {
+            DeviceLocation::Cpu => "cpu".to_string(),
+            DeviceLocation::Cuda { gpu_id } => format!("gpu({gpu_id})"),
+            DeviceLocation::Metal { gpu_id } => format!("mps({gpu_id})"),
+        };
+        let metadata = serde_json::to_string(&metadata).map_err(Error::wrap)?;
+        Ok(Self {
+            model,
+            metadata,
+            dtype,
+            checkpoint,
+            adapters,
+        })
+    }
+}
+
+impl Model for DescribedModel {
+    fn is_padded(&self) -> bool {
+        self.model.is_padded()
+    }
+
+    fn get_input_names(&self) -> Vec<String> {
+        self.model.get_input_names()
+    }
+
+    fn get_output_names(&self) -> Vec<String> {
+        self.model.get_output_names()
+    }
+
+    fn device(&self) -> &Device {
+        self.model.device()
+    }
+
+    fn metadata(&self) -> Option<&str> {
+        Some(&self.metadata)
+    }
+
+    fn forward(
+        &self,
+        input_ids: &Tensor,
+        attention_mask: &Tensor,
+        token_type_ids: Option<&Tensor>,
+        position_ids: Option<&Tensor>,
+    ) -> Result<Outputs> {
+        self.model
+            .forward(input_ids, attention_mask, token_type_ids, position_ids)
+    }
+
+    fn supports_packed(&self) -> bool {
+        self.model.supports_packed()
+    }
+
+    fn forward_packed(
+        &self,
+        input_ids: &Tensor,
+        token_type_ids: Option<&Tensor>,
+        packed: &Packed,
+    ) -> Result<Outputs> {
+        self.model.forward_packed(input_ids, token_type_ids, packed)
+    }
+
+    fn adapters(&self) -> Option<&Adapters> {
+        self.adapters.as_ref()
+    }
+
+    fn save(&self, path: &Path, dtype: Option<DType>) -> Result<()> {
+        self.checkpoint.save(path, dtype.unwrap_or(self.dtype))
+    }
+}
@@ -1,102 +1,840 @@
 mod bert;
+mod cancel;
+mod checkpoint;
+mod dequantize;
 mod distilbert;
+mod gguf;
+mod heads;
+mod hub;
+mod jagged;
+mod lora;
+mod metadata;
+mod numerics;
+mod packed;
+mod shards;
+mod streams;
+mod tensor_parallel;
 
-use crate::ndarray::as_data_type;
-use crate::{cast_handle, drop_handle, to_handle, to_string_array};
+use crate::ndarray::{as_data_type, cuda_device_count, get_device, return_handles, tensor_of};
+use crate::{borrow_handle, drop_handle, throw_error, to_handle, to_string_array};
 use bert::{BertConfig, BertModel};
+use candle_core::quantized::GgmlDType;
+use candle_core::safetensors::{Load, MmapedSafetensors};
 use candle_core::DType;
-use candle_core::{Device, Result, Tensor};
+use candle_core::{Device, Error, Result, Tensor};
 use candle_nn::VarBuilder;
+use checkpoint::Checkpoint;
+use dequantize::QuantizationConfig;
 use distilbert::{DistilBertConfig, DistilBertModel};
-use jni::objects::{JLongArray, JObject, JString, ReleaseMode};
-use jni::sys::{jint, jlong, jobjectArray};
+use gguf::GgufModel;
+use hub::HubOptions;
+use jni::objects::{
+    GlobalRef, JByteArray, JLongArray, JObject, JObjectArray, JString, ReleaseMode,
+};
+use jni::sys::{jboolean, jint, jlong, jobjectArray, JNI_FALSE, JNI_TRUE};
 use jni::JNIEnv;
+use lora::{Adapter, Adapters};
+use metadata::{DescribedModel, ModelMetadata};
+use packed::Packed;
 use serde::Deserialize;
-use std::path::PathBuf;
+use shards::Shards;
+use std::collections::{BTreeSet, HashMap};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use streams::StreamModel;
+use tensor_parallel::Parallelism;
+#[cfg(feature = "nccl")]
+use tensor_parallel::TensorParallelModel;
 
-pub(crate) trait Model {
+/// The outputs of a forward by name, in the order of `get_output_names`.
+pub(crate) type Outputs = Vec<(String, Tensor)>;
+
+/// A model handle is shared by all Java threads using it and forwards run in the inter-op thread
+/// pool, so `forward` only reads the weights and keeps any state it needs on the stack. Forwards
+/// of the same model run concurrently, the only locks they take are short ones: the lookup of the
+/// LoRA factors and the dispatch of tensor parallel requests to the ranks.
+pub(crate) trait Model: Send + Sync {
     #[allow(dead_code)]
     fn is_padded(&self) -> bool;
 
     fn get_input_names(&self) -> Vec<String>;
 
+    fn get_output_names(&self) -> Vec<String>;
+
+    /// Returns the device the inputs are expected on.
+    fn device(&self) -> &Device;
+
+    /// Returns the metadata of the model as JSON, see `ModelMetadata`.
+    fn metadata(&self) -> Option<&str> {
+        None
+    }
+
     fn forward(
         &self,
         _input_ids: &Tensor,
         _attention_mask: &Tensor,
         _token_type_ids: Option<&Tensor>,
-    ) -> Result<Tensor> {
+        _position_ids: Option<&Tensor>,
+    ) -> Result<Outputs> {
         candle_core::bail!("`forward` is not implemented for this model");
     }
+
+    /// Returns `true` if the model can run unpadded batches with `forward_packed`.
+    fn supports_packed(&self) -> bool {
+        false
+    }
+
+    /// Runs a forward on `(total,)` ids of sequences packed back to back. The outputs are padded
+    /// to the longest sequence, as if the batch was padded.
+    fn forward_packed(
+        &self,
+        _input_ids: &Tensor,
+        _token_type_ids: Option<&Tensor>,
+        _packed: &Packed,
+    ) -> Result<Outputs> {
+        candle_core::bail!("`forward_packed` is not implemented for this model");
+    }
+
+    /// Returns the LoRA adapters that can be applied to the model without merging them.
+    fn adapters(&self) -> Option<&Adapters> {
+        None
+    }
+
+    /// Saves the weights to a safetensors file, in `dtype` or the dtype the model runs in.
+    fn save(&self, _path: &Path, _dtype: Option<DType>) -> Result<()> {
+        candle_core::bail!("`save` is not implemented for this model");
+    }
 }
 
 fn load_model<'local>(
     env: &mut JNIEnv,
     model_path: JString,
     dtype: jint,
+    options: JString,
 ) -> Result<Box<dyn Model>> {
     let model_path: String = env
         .get_string(&model_path)
         .expect("Couldn't get java string!")
         .into();
+    let options: String = env
+        .get_string(&options)
+        .expect("Couldn't get java string!")
+        .into();
 
-    let model_path = PathBuf::from(model_path);
+    // `hf://` paths are Hugging Face Hub repo ids, e.g. `hf://BAAI/bge-small-en-v1.5`
+    let hub_options = HubOptions::from_json(&options)?;
+    let model_path = match model_path.strip_prefix(hub::HUB_PREFIX) {
+        Some(repo_id) => hub::download(repo_id, &hub_options)?,
+        None => PathBuf::from(model_path),
+    };
+    if !model_path.exists() {
+        candle_core::bail!(
+            "{} doesn't exist, Hugging Face Hub repos are loaded with the {} prefix",
+            model_path.display(),
+            hub::HUB_PREFIX
+        )
+    }
+    if let Some(gguf_path) = gguf::find_gguf(&model_path)? {
+        return load_gguf(&gguf_path);
+    }
+    // an adapter directory loads its base model with the adapter merged
+    let (model_path, options) = match lora::find_base_model(&model_path, &hub_options)? {
+        Some(base_path) => {
+            tracing::info!(
+                "Loading {} with adapter {}",
+                base_path.display(),
+                model_path.display()
+            );
+            (base_path, lora::adapter_options(&options, &model_path)?)
+        }
+        None => (model_path, options),
+    };
 
     // Load config
     let config: String = std::fs::read_to_string(model_path.join("config.json"))?;
-    let config: Config = serde_json::from_str(&config).unwrap();
+    build_model(&config, Weights::Path(model_path), dtype, &options)
+}
 
-    // Get candle device
-    let device = if candle_core::utils::cuda_is_available() {
-        Device::new_cuda(0)
+fn build_model(
+    config_json: &str,
+    weights: Weights,
+    dtype: jint,
+    options: &str,
+) -> Result<Box<dyn Model>> {
+    let options: LoadOptions = serde_json::from_str(options)
+        .map_err(|err| Error::Msg(format!("Invalid load options: {err}")))?;
+    check_cuda_graphs(&options)?;
+    let mut config: Config = serde_json::from_str(config_json)
+        .map_err(|err| Error::Msg(format!("Invalid config.json: {err}")))?;
+    if let Some(f32_heads) = &options.f32_heads {
+        let f32_heads = f32_heads
+            .parse()
+            .map_err(|_| Error::Msg(format!("Invalid f32_heads: {f32_heads}")))?;
+        config.set_f32_heads(f32_heads);
+    }
+    // relative adapter paths are resolved against the model directory
+    let adapter = options.adapter.as_ref().map(|adapter| match &weights {
+        Weights::Path(model_path) => model_path.join(adapter),
+        _ => PathBuf::from(adapter),
+    });
+    let weights = prepare_weights(config_json, weights, adapter.as_deref())?;
+    let quantization = options.quantization()?;
+    let metadata = ModelMetadata::new(config_json, config.num_layers(), &weights)?
+        .with_quantization(quantization);
+
+    // Get candle dtype
+    let dtype = if dtype == AUTO_DTYPE {
+        auto_dtype(config_json)?
+    } else {
+        as_data_type(dtype).unwrap()
+    };
+
+    let backend = AttentionBackend::select(options.attention_backend()?, dtype)?;
+    let use_flash_attn = backend == AttentionBackend::Flash;
+    let adapters = Adapters::default();
+    let (model, checkpoint) = load_weights(
+        config,
+        weights,
+        dtype,
+        use_flash_attn,
+        quantization,
+        adapters.clone(),
+        &options,
+    )?;
+    Ok(Box::new(DescribedModel::new(
+        model,
+        metadata,
+        dtype,
+        backend,
+        checkpoint,
+        Some(adapters),
+    )?))
+}
+
+/// Dequantizes the weights of AWQ and GPTQ checkpoints and merges the LoRA adapter, if any.
+fn prepare_weights(config_json: &str, weights: Weights, adapter: Option<&Path>) -> Result<Weights> {
+    let weights = match QuantizationConfig::from_config(config_json)? {
+        Some(quantization_config) => {
+            tracing::info!("Dequantizing {quantization_config:?} checkpoint");
+            quantization_config.dequantize(&weights)?
+        }
+        None => weights,
+    };
+    match adapter {
+        Some(adapter_path) => {
+            tracing::info!("Merging LoRA adapter {}", adapter_path.display());
+            let adapter = Adapter::load(adapter_path)?;
+            let mut tensors = read_tensors(&weights)?;
+            adapter.merge(&mut tensors)?;
+            Ok(Weights::Tensors(Arc::new(tensors)))
+        }
+        None => Ok(weights),
+    }
+}
+
+/// Loads a llama.cpp checkpoint, the weights keep their quantization and the activations are F32.
+fn load_gguf(path: &Path) -> Result<Box<dyn Model>> {
+    let content = gguf::read_content(path)?;
+    let metadata = ModelMetadata::from_gguf(&content);
+    let model = GgufModel::load(path, content, &default_device()?)?;
+    Ok(Box::new(DescribedModel::new(
+        Box::new(model),
+        metadata,
+        DType::F32,
+        AttentionBackend::Eager,
+        Checkpoint::Unsupported("the weights of GGUF models are held by llama.cpp layers"),
+        None,
+    )?))
+}
+
+/// The dtype requested by Java to use the `torch_dtype` of `config.json`.
+const AUTO_DTYPE: jint = -1;
+
+/// Picks the dtype the checkpoint was saved in, models without a `torch_dtype` run in F32.
+/// F16 and BF16 aren't efficient on CPU, so half precision checkpoints run in F32 without an
+/// accelerator.
+fn auto_dtype(config: &str) -> Result<DType> {
+    let config: serde_json::Value = serde_json::from_str(config).map_err(Error::wrap)?;
+    let accelerated =
+        candle_core::utils::cuda_is_available() || candle_core::utils::metal_is_available();
+    let dtype = match config["torch_dtype"].as_str() {
+        Some("float16") if accelerated => DType::F16,
+        Some("bfloat16") if accelerated => DType::BF16,
+        Some("float16" | "bfloat16" | "float32") | None => DType::F32,
+        Some(dtype) => candle_core::bail!("Unsupported torch_dtype: {dtype}"),
+    };
+    Ok(dtype)
+}
+
+/// Loads the model and the checkpoint its weights are saved from.
+fn load_weights(
+    config: Config,
+    weights: Weights,
+    dtype: DType,
+    use_flash_attn: bool,
+    quantization: Option<GgmlDType>,
+    adapters: Adapters,
+    options: &LoadOptions,
+) -> Result<(Box<dyn Model>, Checkpoint)> {
+    let device = default_device()?;
+
+    let num_layers = config.num_layers();
+    let tensor_parallel_degree = match options.tensor_parallel_degree.as_deref() {
+        None => 1,
+        Some("max") => cuda_device_count(),
+        Some(degree) => degree
+            .parse()
+            .map_err(|_| Error::Msg(format!("Invalid tensor_parallel_degree: {degree}")))?,
+    };
+    if tensor_parallel_degree > 1 {
+        if options.device_map.is_some() {
+            candle_core::bail!("device_map can't be combined with tensor_parallel_degree")
+        }
+        #[cfg(feature = "nccl")]
+        {
+            let loader = move |device: &Device, tp: &Parallelism| {
+                let vb = var_builder(&weights, dtype, device)?;
+                let tp = tp.clone().with_quantization(quantization);
+                let device_map = DeviceMap::new(vec![(vb, num_layers)], tp);
+                load_config(config.clone(), &device_map, use_flash_attn)
+            };
+            let model = TensorParallelModel::load(tensor_parallel_degree, Arc::new(loader))?;
+            let checkpoint = Checkpoint::Unsupported("they are split across tensor parallel ranks");
+            return Ok((Box::new(model), checkpoint));
+        }
+        #[cfg(not(feature = "nccl"))]
+        candle_core::bail!("tensor parallel inference requires the `nccl` feature");
+    }
+
+    let cuda_streams = options.cuda_streams()?;
+    if cuda_streams > 1 {
+        if options.device_map.is_some() {
+            candle_core::bail!("device_map can't be combined with cuda_streams")
+        }
+        // the replicas are on different devices, so adapters can't be shared between them
+        let loader = |device: &Device| {
+            let vb = var_builder(&weights, dtype, device)?;
+            let parallelism = Parallelism::default().with_quantization(quantization);
+            let device_map = DeviceMap::new(vec![(vb, num_layers)], parallelism);
+            load_config(config.clone(), &device_map, use_flash_attn)
+        };
+        let model = StreamModel::load(cuda_streams, &device, loader)?;
+        let checkpoint = Checkpoint::Unsupported("they are copied per CUDA stream");
+        return Ok((Box::new(model), checkpoint));
+    }
+
+    // the quantized weights, unlike the tensors they're quantized from, aren't recorded
+    let checkpoint = match quantization {
+        Some(_) => Checkpoint::Unsupported("they are quantized by the `quantize` load option"),
+        None => Checkpoint::recorded(),
+    };
+    let parallelism = Parallelism::default()
+        .with_quantization(quantization)
+        .with_adapters(adapters);
+    let device_map = match &options.device_map {
+        Some(device_map) => {
+            let mut builders = Vec::new();
+            for (device_id, layers) in parse_device_map(device_map, num_layers)? {
+                let device = get_device("gpu", device_id)?;
+                let vb = checkpoint.record(var_builder(&weights, dtype, &device)?);
+                builders.push((vb, layers));
+            }
+            DeviceMap::new(builders, parallelism)
+        }
+        None => {
+            let vb = checkpoint.record(var_builder(&weights, dtype, &device)?);
+            DeviceMap::new(vec![(vb, num_layers)], parallelism)
+        }
+    };
+    let model = load_config(config, &device_map, use_flash_attn)?;
+    Ok((model, checkpoint))
+}
+
+/// Returns the first GPU if there is one.
+fn default_device() -> Result<Device> {
+    if candle_core::utils::cuda_is_available() {
+        get_device("gpu", 0)
     } else if candle_core::utils::metal_is_available() {
-        Device::new_metal(0)
+        get_device("mps", 0)
     } else {
         Ok(Device::Cpu)
-    }?;
+    }
+}
 
-    // Get candle dtype
-    let dtype = as_data_type(dtype).unwrap();
+/// Fails with a readable error if the sequences are longer than the position embeddings, or if
+/// explicit `position_ids` are out of their range.
+pub(crate) fn check_seq_len(
+    seq_len: usize,
+    position_ids: Option<&Tensor>,
+    max_position_embeddings: usize,
+) -> Result<()> {
+    if seq_len > max_position_embeddings {
+        candle_core::bail!("input {seq_len} > max {max_position_embeddings}; enable truncation")
+    }
+    if let Some(position_ids) = position_ids.filter(|ids| ids.elem_count() > 0) {
+        let position_ids = position_ids.flatten_all()?.to_dtype(DType::I64)?;
+        let min: i64 = position_ids.min(0)?.to_scalar()?;
+        let max: i64 = position_ids.max(0)?.to_scalar()?;
+        if min < 0 || max >= max_position_embeddings as i64 {
+            candle_core::bail!(
+                "position_ids must be in 0..{max_position_embeddings}, got {min}..={max}"
+            )
+        }
+    }
+    Ok(())
+}
 
-    let safetensors_path = model_path.join("model.safetensors");
-    let vb = if safetensors_path.exists() {
-        unsafe {
-            VarBuilder::from_mmaped_safetensors(
-                &[model_path.join("model.safetensors")],
-                dtype,
-                &device,
-            )?
+/// Runs dummy forwards for batch sizes and sequence lengths in powers of two up to the given
+/// maximums, so kernels are compiled and memory is allocated before the first request.
+fn warmup(model: &dyn Model, max_batch: usize, max_seq_len: usize) -> Result<()> {
+    let sizes = |max: usize| {
+        let mut sizes: Vec<usize> = std::iter::successors(Some(1), |&size| Some(size * 2))
+            .take_while(|&size| size < max)
+            .collect();
+        sizes.push(max);
+        sizes
+    };
+    let has_token_type_ids = model.get_input_names().len() > 2;
+    for batch in sizes(max_batch) {
+        for seq_len in sizes(max_seq_len) {
+            let shape = (batch, seq_len);
+            let input_ids = Tensor::zeros(shape, DType::U32, model.device())?;
+            let attention_mask = Tensor::ones(shape, DType::U32, model.device())?;
+            let token_type_ids = has_token_type_ids.then(|| input_ids.clone());
+            model.forward(&input_ids, &attention_mask, token_type_ids.as_ref(), None)?;
         }
-    } else {
-        VarBuilder::from_pth(model_path.join("pytorch_model.bin"), dtype, &device)?
+    }
+    Ok(())
+}
+
+/// The output of an inference running in the background, it's set once by the worker.
+#[derive(Default)]
+struct InferenceFuture {
+    // the output is taken by `getInferenceResult`, so completion is tracked separately
+    state: Mutex<(bool, Option<Result<Outputs>>)>,
+    done: Condvar,
+}
+
+impl InferenceFuture {
+    fn complete(&self, output: Result<Outputs>) {
+        *self.state.lock().unwrap() = (true, Some(output));
+        self.done.notify_all();
+    }
+
+    fn is_done(&self) -> bool {
+        self.state.lock().unwrap().0
+    }
+
+    fn wait(&self) -> Result<Outputs> {
+        let state = self.state.lock().unwrap();
+        let mut state = self.done.wait_while(state, |(done, _)| !*done).unwrap();
+        state
+            .1
+            .take()
+            .unwrap_or_else(|| Err(Error::Msg("inference result was already taken".to_string())))
+    }
+}
+
+/// Where the weights of a model are read from.
+#[derive(Clone)]
+enum Weights {
+    /// A model directory with `model.safetensors` or `pytorch_model.bin`, both may be sharded,
+    /// or a NumPy `model.npz` archive.
+    Path(PathBuf),
+    /// safetensors shards held in memory, e.g. read from a jar or object storage.
+    Bytes(Arc<Shards>),
+    /// Tensors loaded on the CPU, e.g. the dequantized weights of an AWQ or GPTQ checkpoint or
+    /// the weights merged with a LoRA adapter.
+    Tensors(Arc<HashMap<String, Tensor>>),
+}
+
+/// Returns the files of a checkpoint, e.g. `model.safetensors`, or its shards listed in
+/// `model.safetensors.index.json`. Shards without an index are found by their name, e.g.
+/// `pytorch_model-00001-of-00002.bin`. Returns an empty list if the checkpoint doesn't exist.
+pub(crate) fn checkpoint_files(model_path: &Path, file_name: &str) -> Result<Vec<PathBuf>> {
+    let path = model_path.join(file_name);
+    if path.exists() {
+        return Ok(vec![path]);
+    }
+    if let Some(weight_map) = weight_map(model_path, file_name)? {
+        let files: BTreeSet<&str> = weight_map.values().filter_map(|v| v.as_str()).collect();
+        return Ok(files
+            .into_iter()
+            .map(|file| model_path.join(file))
+            .collect());
+    }
+    let (stem, extension) = file_name.rsplit_once('.').unwrap_or((file_name, ""));
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(model_path)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.starts_with(&format!("{stem}-"))
+            && name.contains("-of-")
+            && name.ends_with(&format!(".{extension}"))
+        {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Returns the `weight_map` of `{file_name}.index.json`, from the tensor names to their shard, if
+/// the checkpoint is sharded with an index.
+fn weight_map(
+    model_path: &Path,
+    file_name: &str,
+) -> Result<Option<serde_json::Map<String, serde_json::Value>>> {
+    let index_path = model_path.join(format!("{file_name}.index.json"));
+    if model_path.join(file_name).exists() || !index_path.exists() {
+        return Ok(None);
+    }
+    let mut index: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&index_path)?).map_err(Error::wrap)?;
+    match index["weight_map"].take() {
+        serde_json::Value::Object(weight_map) => Ok(Some(weight_map)),
+        _ => candle_core::bail!("No weight_map in {}", index_path.display()),
+    }
+}
+
+/// Returns the files of a safetensors checkpoint. The tensors of sharded checkpoints must be in
+/// the shard their index maps them to, a tensor saved in two shards would otherwise be loaded
+/// from the last one.
+pub(crate) fn safetensors_files(model_path: &Path) -> Result<Vec<PathBuf>> {
+    let paths = checkpoint_files(model_path, "model.safetensors")?;
+    let Some(weight_map) = weight_map(model_path, "model.safetensors")? else {
+        return Ok(paths);
     };
+    let mut shards = HashMap::new();
+    for path in &paths {
+        let safetensors = unsafe { MmapedSafetensors::new(path)? };
+        for (name, _) in safetensors.tensors() {
+            if let Some(other) = shards.insert(name.clone(), path) {
+                candle_core::bail!("{name} is in {} and {}", other.display(), path.display())
+            }
+            match weight_map.get(&name).and_then(|file| file.as_str()) {
+                Some(file) if model_path.join(file) == *path => {}
+                Some(file) => candle_core::bail!(
+                    "{name} is in {}, the weight_map maps it to {file}",
+                    path.display()
+                ),
+                None => candle_core::bail!("{name} of {} is not in the weight_map", path.display()),
+            }
+        }
+    }
+    if let Some(name) = weight_map.keys().find(|name| !shards.contains_key(*name)) {
+        candle_core::bail!("{name} of the weight_map is missing from the shards")
+    }
+    Ok(paths)
+}
 
-    let use_flash_attn = cfg!(feature = "cuda")
-        && cfg!(feature = "flash-attn")
-        && dtype == DType::F16
-        && std::env::var("USE_FLASH_ATTENTION")
-            .ok()
-            .map_or(true, |v| v.parse().unwrap_or(true));
+/// Reads all the tensors of the weights on the CPU.
+fn read_tensors(weights: &Weights) -> Result<HashMap<String, Tensor>> {
+    let mut tensors = HashMap::new();
+    match weights {
+        Weights::Path(model_path) => {
+            let safetensors_paths = safetensors_files(model_path)?;
+            let pth_paths = checkpoint_files(model_path, "pytorch_model.bin")?;
+            if !safetensors_paths.is_empty() {
+                for path in safetensors_paths {
+                    tensors.extend(candle_core::safetensors::load(path, &Device::Cpu)?);
+                }
+            } else if !pth_paths.is_empty() {
+                for path in pth_paths {
+                    tensors.extend(candle_core::pickle::read_all(path)?);
+                }
+            } else {
+                tensors.extend(Tensor::read_npz(model_path.join("model.npz"))?);
+            }
+        }
+        Weights::Bytes(shards) => {
+            for (name, view) in shards.views()? {
+                tensors.insert(name, view.load(&Device::Cpu)?);
+            }
+        }
+        Weights::Tensors(loaded) => tensors.extend(loaded.as_ref().clone()),
+    }
+    Ok(tensors)
+}
 
-    let model: Result<Box<dyn Model>> = match (config, &device) {
+fn var_builder(weights: &Weights, dtype: DType, device: &Device) -> Result<VarBuilder<'static>> {
+    match weights {
+        Weights::Path(model_path) => {
+            // only the shards listed in the index are read, other files may be adapters
+            let safetensors_paths = safetensors_files(model_path)?;
+            if !safetensors_paths.is_empty() {
+                unsafe { VarBuilder::from_mmaped_safetensors(&safetensors_paths, dtype, device) }
+            } else {
+                match checkpoint_files(model_path, "pytorch_model.bin")?.as_slice() {
+                    [] => {
+                        let npz_path = model_path.join("model.npz");
+                        if !npz_path.exists() {
+                            candle_core::bail!(
+                                "No model.safetensors, pytorch_model.bin or model.npz in {}",
+                                model_path.display()
+                            )
+                        }
+                        VarBuilder::from_npz(npz_path, dtype, device)
+                    }
+                    [path] => VarBuilder::from_pth(path, dtype, device),
+                    paths => {
+                        let mut tensors = HashMap::new();
+                        for path in paths {
+                            tensors.extend(candle_core::pickle::read_all(path)?);
+                        }
+                        Ok(VarBuilder::from_tensors(tensors, dtype, device))
+                    }
+                }
+            }
+        }
+        Weights::Bytes(shards) => Ok(shards.var_builder(dtype, device)),
+        Weights::Tensors(tensors) => Ok(VarBuilder::from_tensors(
+            tensors.as_ref().clone(),
+            dtype,
+            device,
+        )),
+    }
+}
+
+fn load_config(
+    config: Config,
+    device_map: &DeviceMap,
+    use_flash_attn: bool,
+) -> Result<Box<dyn Model>> {
+    let device = device_map.vb().device();
+    let model: Result<Box<dyn Model>> = match (config, device) {
         #[cfg(not(feature = "cuda"))]
         (_, Device::Cuda(_)) => panic!("`cuda` feature is not enabled"),
         (Config::Bert(mut config), _) => {
             tracing::info!("Starting Bert model on {:?}", device);
             config.use_flash_attn = Some(use_flash_attn);
-            Ok(Box::new(BertModel::load(vb, &config)?))
+            Ok(Box::new(BertModel::load(device_map, &config)?))
         }
         (Config::DistilBert(mut config), _) => {
             tracing::info!("Starting DistilBertModel model on {:?}", device);
             config.use_flash_attn = Some(use_flash_attn);
-            Ok(Box::new(DistilBertModel::load(vb, &config)?))
+            Ok(Box::new(DistilBertModel::load(device_map, &config)?))
         }
     };
 
     model
 }
 
-#[derive(Deserialize)]
+/// Parses the `device_map` load option into `(CUDA device id, number of layers)` pairs.
+///
+/// `auto` splits the layers evenly across all visible GPUs, otherwise the option is a comma
+/// separated list of device ids, each optionally followed by `:<layers>`, e.g. `0:10,1,2`.
+/// Devices without an explicit count share the remaining layers evenly.
+fn parse_device_map(device_map: &str, num_layers: usize) -> Result<Vec<(usize, usize)>> {
+    let entries: Vec<(usize, Option<usize>)> = if device_map == "auto" {
+        (0..cuda_device_count()).map(|i| (i, None)).collect()
+    } else {
+        let invalid = || Error::Msg(format!("Invalid device_map: {device_map}"));
+        device_map
+            .split(',')
+            .map(|entry| {
+                let (id, layers) = match entry.trim().split_once(':') {
+                    Some((id, layers)) => (id, Some(layers.parse().map_err(|_| invalid())?)),
+                    None => (entry.trim(), None),
+                };
+                Ok((id.parse().map_err(|_| invalid())?, layers))
+            })
+            .collect::<Result<_>>()?
+    };
+    if entries.is_empty() {
+        candle_core::bail!("No CUDA device available for device_map: {device_map}")
+    }
+    let assigned: usize = entries.iter().filter_map(|(_, layers)| *layers).sum();
+    let shared = entries
+        .iter()
+        .filter(|(_, layers)| layers.is_none())
+        .count();
+    if assigned > num_layers || (shared == 0 && assigned != num_layers) {
+        candle_core::bail!("device_map {device_map} doesn't match the {num_layers} layers")
+    }
+    let remaining = num_layers - assigned;
+    let mut index = 0;
+    let ret = entries
+        .into_iter()
+        .map(|(id, layers)| {
+            let layers = layers.unwrap_or_else(|| {
+                index += 1;
+                remaining / shared + usize::from(index <= remaining % shared)
+            });
+            (id, layers)
+        })
+        .collect();
+    Ok(ret)
+}
+
+/// Places the transformer layers of a model on one or more devices. Consecutive layers share a
+/// device, so the hidden states only move between devices at the boundaries.
+pub(crate) struct DeviceMap {
+    builders: Vec<VarBuilder<'static>>,
+    layers: Vec<usize>,
+    parallelism: Parallelism,
+}
+
+impl DeviceMap {
+    fn new(builders: Vec<(VarBuilder<'static>, usize)>, parallelism: Parallelism) -> Self {
+        let layers = builders
+            .iter()
+            .enumerate()
+            .flat_map(|(i, (_, layers))| std::iter::repeat(i).take(*layers))
+            .collect();
+        let builders = builders.into_iter().map(|(vb, _)| vb).collect();
+        Self {
+            builders,
+            layers,
+            parallelism,
+        }
+    }
+
+    /// Returns the builder of the first device, used for the layers before the encoder.
+    pub(crate) fn vb(&self) -> &VarBuilder<'static> {
+        &self.builders[0]
+    }
+
+    /// Returns the builder of the device the layer is placed on.
+    pub(crate) fn layer(&self, index: usize) -> &VarBuilder<'static> {
+        let device = self.layers.get(index).copied().unwrap_or(0);
+        &self.builders[device]
+    }
+
+    /// Returns a copy of this map with `s` pushed to the prefix of all builders.
+    pub(crate) fn pp(&self, s: &str) -> Self {
+        let builders = self.builders.iter().map(|vb| vb.pp(s)).collect();
+        Self {
+            builders,
+            layers: self.layers.clone(),
+            parallelism: self.parallelism.clone(),
+        }
+    }
+
+    /// Returns the tensor parallel rank of the layers.
+    pub(crate) fn parallelism(&self) -> &Parallelism {
+        &self.parallelism
+    }
+}
+
+/// Rejects the `cuda_graphs` load option. Replaying a decode step from a CUDA graph needs a
+/// decoder that runs one token per step into a static KV cache, the models here are encoders
+/// that run a single forward per batch. candle 0.4 also allocates the outputs of its kernels
+/// during the forward, which a captured graph can't replay.
+fn check_cuda_graphs(options: &LoadOptions) -> Result<()> {
+    match options.cuda_graphs.as_deref() {
+        None | Some("false") => Ok(()),
+        Some(_) => candle_core::bail!(
+            "cuda_graphs isn't supported, the encoder models have no decode steps to capture"
+        ),
+    }
+}
+
+#[derive(Default, Deserialize)]
+struct LoadOptions {
+    /// Captures the decode steps into CUDA graphs, not supported, see `check_cuda_graphs`.
+    cuda_graphs: Option<String>,
+    device_map: Option<String>,
+    tensor_parallel_degree: Option<String>,
+    attention_backend: Option<String>,
+    use_flash_attention: Option<String>,
+    f32_heads: Option<String>,
+    quantize: Option<String>,
+    /// The directory of a PEFT LoRA adapter to merge into the weights.
+    adapter: Option<String>,
+    /// The number of CUDA streams the forwards run on, each stream holds a copy of the weights.
+    cuda_streams: Option<String>,
+}
+
+impl LoadOptions {
+    /// Returns the requested attention backend, `use_flash_attention` is a boolean shorthand for
+    /// `flash` and `eager`.
+    fn attention_backend(&self) -> Result<Option<&str>> {
+        match (&self.attention_backend, &self.use_flash_attention) {
+            (Some(_), Some(_)) => {
+                candle_core::bail!("attention_backend can't be combined with use_flash_attention")
+            }
+            (Some(backend), None) => Ok(Some(backend)),
+            (None, Some(flash)) => match flash.parse() {
+                Ok(true) => Ok(Some("flash")),
+                Ok(false) => Ok(Some("eager")),
+                Err(_) => candle_core::bail!("Invalid use_flash_attention: {flash}"),
+            },
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Returns the number of CUDA streams, `1` by default.
+    fn cuda_streams(&self) -> Result<usize> {
+        match self.cuda_streams.as_deref() {
+            None => Ok(1),
+            Some(streams) => match streams.parse() {
+                Ok(streams) if streams > 0 => Ok(streams),
+                _ => candle_core::bail!("Invalid cuda_streams: {streams}"),
+            },
+        }
+    }
+
+    /// Returns the quantization of the linear weights for the `quantize` load option, `int8`
+    /// quantizes the weights to 8 bits in blocks of 32 with one scale each. `q4k` uses 4-bit
+    /// K-quants, blocks of 256 weights split into groups of 32 with their own scale and min.
+    fn quantization(&self) -> Result<Option<GgmlDType>> {
+        match self.quantize.as_deref() {
+            None | Some("none") => Ok(None),
+            Some("int8" | "q8_0") => Ok(Some(GgmlDType::Q8_0)),
+            Some("int4" | "q4k") => Ok(Some(GgmlDType::Q4K)),
+            Some(quantize) => candle_core::bail!("Invalid quantize: {quantize}"),
+        }
+    }
+}
+
+/// The attention implementation used by the encoder layers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AttentionBackend {
+    /// Matmul and softmax, supported on all devices and dtypes.
+    Eager,
+    /// flash-attn on CUDA, for half precision only.
+    Flash,
+}
+
+impl AttentionBackend {
+    /// Picks the backend for the `attention_backend` load option: `auto` (the default), `eager`,
+    /// `sdpa` or `flash`. `auto` uses flash-attn when it's available, unless the
+    /// `USE_FLASH_ATTENTION` environment variable is `false`. `flash` fails if flash-attn isn't
+    /// available, `sdpa` falls back to the eager attention.
+    fn select(option: Option<&str>, dtype: DType) -> Result<Self> {
+        let flash_available = cfg!(feature = "flash-attn")
+            && candle_core::utils::cuda_is_available()
+            && matches!(dtype, DType::F16 | DType::BF16);
+        let flash_enabled = std::env::var("USE_FLASH_ATTENTION")
+            .ok()
+            .map_or(true, |v| v.parse().unwrap_or(true));
+        match option.unwrap_or("auto") {
+            "auto" if flash_available && flash_enabled => Ok(Self::Flash),
+            "flash" if flash_available => Ok(Self::Flash),
+            "auto" | "eager" => Ok(Self::Eager),
+            "flash" => candle_core::bail!(
+                "flash attention requires the flash-attn feature, a CUDA device and F16 or BF16"
+            ),
+            // candle 0.4 has no fused attention kernel besides flash-attn
+            "sdpa" => {
+                tracing::warn!("sdpa attention is not available, using eager attention");
+                Ok(Self::Eager)
+            }
+            backend => candle_core::bail!("Invalid attention_backend: {backend}"),
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Eager => "eager",
+            Self::Flash => "flash",
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
 #[serde(tag = "model_type", rename_all = "kebab-case")]
 enum Config {
     Bert(BertConfig),
@@ -104,31 +842,116 @@ enum Config {
     DistilBert(DistilBertConfig),
 }
 
+impl Config {
+    fn num_layers(&self) -> usize {
+        match self {
+            Config::Bert(config) => config.num_hidden_layers,
+            Config::DistilBert(config) => config.n_layers,
+        }
+    }
+
+    fn set_f32_heads(&mut self, f32_heads: bool) {
+        match self {
+            Config::Bert(config) => config.f32_heads = Some(f32_heads),
+            // DistilBERT is loaded without heads
+            Config::DistilBert(_) => {}
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_loadModel<'local>(
     mut env: JNIEnv,
     _: JObject,
     model_path: JString,
     dtype: jint,
+    options: JString,
 ) -> jlong {
-    let model = load_model(&mut env, model_path, dtype);
+    let model = load_model(&mut env, model_path, dtype, options);
 
     match model {
-        Ok(output) => to_handle(output),
+        Ok(output) => to_handle::<Arc<dyn Model>>(Arc::from(output)),
+        Err(err) => {
+            throw_error(&mut env, err);
+            0
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_loadModelFromBytes<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    config: JString<'local>,
+    shards: JObjectArray<'local>,
+    dtype: jint,
+    options: JString<'local>,
+) -> jlong {
+    let mut load = || {
+        let config: String = env
+            .get_string(&config)
+            .expect("Couldn't get java string!")
+            .into();
+        let options: String = env
+            .get_string(&options)
+            .expect("Couldn't get java string!")
+            .into();
+        let len = env.get_array_length(&shards).map_err(Error::wrap)?;
+        let mut weights = Vec::with_capacity(len as usize);
+        for i in 0..len {
+            let shard: JByteArray = env
+                .get_object_array_element(&shards, i)
+                .map_err(Error::wrap)?
+                .into();
+            weights.push(env.convert_byte_array(&shard).map_err(Error::wrap)?);
+        }
+        let shards = Shards::new(weights)?;
+        build_model(&config, Weights::Bytes(Arc::new(shards)), dtype, &options)
+    };
+
+    match load() {
+        Ok(output) => to_handle::<Arc<dyn Model>>(Arc::from(output)),
         Err(err) => {
-            env.throw(err.to_string()).unwrap();
+            throw_error(&mut env, err);
             0
         }
     }
 }
 
+/// Returns the model behind `handle`, a forward holding it keeps the model alive through a
+/// concurrent deleteModel.
+pub(crate) fn model_of(handle: jlong) -> Result<Arc<dyn Model>> {
+    Ok((*borrow_handle::<Arc<dyn Model>>(handle)?).clone())
+}
+
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_deleteModel<'local>(
     _: JNIEnv,
     _: JObject,
     handle: jlong,
 ) {
-    drop_handle::<Box<dyn Model>>(handle);
+    drop_handle::<Arc<dyn Model>>(handle);
+}
+
+/// Returns a new handle to the same model, the model is freed when all its handles are deleted.
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_retainModel<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+) -> jlong {
+    let model = or_throw!(env, model_of(handle));
+    to_handle(model)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_getModelReferenceCount<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+) -> jlong {
+    let model = or_throw!(env, borrow_handle::<Arc<dyn Model>>(handle));
+    Arc::strong_count(&*model) as jlong
 }
 
 #[no_mangle]
@@ -137,39 +960,410 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_getInputNames<'local>
     _: JObject,
     handle: jlong,
 ) -> jobjectArray {
-    let model = cast_handle::<Box<dyn Model>>(handle);
+    let model = or_throw!(env, model_of(handle));
     let input_names: Vec<String> = model.get_input_names();
     to_string_array(&mut env, input_names).unwrap()
 }
 
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_saveModel<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    path: JString,
+    dtype: jint,
+) {
+    let model = or_throw!(env, model_of(handle));
+    let path: String = env
+        .get_string(&path)
+        .expect("Couldn't get java string!")
+        .into();
+    let op = || {
+        let dtype = if dtype == AUTO_DTYPE {
+            None
+        } else {
+            Some(as_data_type(dtype)?)
+        };
+        model.save(Path::new(&path), dtype)
+    };
+    if let Err(err) = op() {
+        throw_error(&mut env, err);
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_warmupModel<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    max_batch: jint,
+    max_seq_len: jint,
+) {
+    let model = or_throw!(env, model_of(handle));
+    let op = || {
+        if max_batch < 1 || max_seq_len < 1 {
+            candle_core::bail!("Invalid warmup shape: ({max_batch}, {max_seq_len})")
+        }
+        let max_batch = max_batch as usize;
+        let max_seq_len = max_seq_len as usize;
+        crate::threads::install(|| warmup(model.as_ref(), max_batch, max_seq_len))
+    };
+    if let Err(err) = op() {
+        throw_error(&mut env, err);
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_getOutputNames<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+) -> jobjectArray {
+    let model = or_throw!(env, model_of(handle));
+    to_string_array(&mut env, model.get_output_names()).unwrap()
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_getModelMetadata<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+) -> JString<'local> {
+    let model = or_throw!(env, model_of(handle));
+    let metadata = model.metadata().unwrap_or("{}");
+    env.new_string(metadata)
+        .expect("Couldn't create java string!")
+}
+
+/// Runs a forward with the inputs in the order of `get_input_names`, followed by optional
+/// `position_ids`. The positions default to `0..seq_len`, explicit positions are needed with left
+/// padding or custom position offsets.
+fn forward_inputs(model: &dyn Model, inputs: &[&Tensor]) -> Result<Outputs> {
+    let num_inputs = model.get_input_names().len();
+    if inputs.len() < 2 || inputs.len() > num_inputs + 1 {
+        candle_core::bail!(
+            "Expected {num_inputs} inputs and optional position_ids, got {}",
+            inputs.len()
+        )
+    }
+    let token_type_ids = if num_inputs > 2 { inputs.get(2) } else { None };
+    model.forward(
+        inputs[0],
+        inputs[1],
+        token_type_ids.copied(),
+        inputs.get(num_inputs).copied(),
+    )
+}
+
+fn run_inference(
+    env: &mut JNIEnv,
+    handle: jlong,
+    input_handles: &JLongArray,
+    cancellation_token: jlong,
+    hidden_state_only: bool,
+) -> Result<Outputs> {
+    // the forward holds a reference, so a concurrent deleteModel doesn't free the model
+    let model = model_of(handle)?;
+    let token = cancel::from_handle(cancellation_token)?;
+    let input_handles =
+        unsafe { env.get_array_elements(input_handles, ReleaseMode::NoCopyBack) }.unwrap();
+
+    let inputs = input_handles
+        .iter()
+        .map(|&i| tensor_of(i))
+        .collect::<Result<Vec<Tensor>>>()?;
+    let input_vec: Vec<&Tensor> = inputs.iter().collect();
+
+    crate::threads::install(|| {
+        cancel::with_token(token, || {
+            heads::with_hidden_state_only(hidden_state_only, || {
+                forward_inputs(model.as_ref(), &input_vec)
+            })
+        })
+    })
+}
+
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_runInference<'local>(
     mut env: JNIEnv,
     _: JObject,
     handle: jlong,
     input_handles: JLongArray<'local>,
+    cancellation_token: jlong,
+) -> jlong {
+    // the heads are skipped, only the last hidden state is returned
+    let result = run_inference(&mut env, handle, &input_handles, cancellation_token, true);
+
+    match result {
+        Ok(outputs) => to_handle(outputs.into_iter().next().unwrap().1),
+        Err(err) => {
+            throw_error(&mut env, err);
+            0
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_runInferenceMulti<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+    input_handles: JLongArray<'local>,
+    cancellation_token: jlong,
+) -> JLongArray<'local> {
+    let outputs = run_inference(&mut env, handle, &input_handles, cancellation_token, false);
+    let tensors = outputs.map(|outputs| outputs.into_iter().map(|(_, tensor)| tensor).collect());
+    return_handles(&mut env, tensors)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_runInferenceAsync<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    input_handles: JLongArray<'local>,
+    cancellation_token: jlong,
+    callback: JObject<'local>,
 ) -> jlong {
-    let model = cast_handle::<Box<dyn Model>>(handle);
+    // the worker holds a reference, so the model can be deleted before the future is done
+    let model = or_throw!(env, model_of(handle));
+    let token = or_throw!(env, cancel::from_handle(cancellation_token));
     let input_handles =
         unsafe { env.get_array_elements(&input_handles, ReleaseMode::NoCopyBack) }.unwrap();
+    let inputs = input_handles
+        .iter()
+        .map(|&i| tensor_of(i))
+        .collect::<Result<Vec<Tensor>>>();
+    drop(input_handles);
+    let inputs = or_throw!(env, inputs);
+    if inputs.len() < 2 {
+        throw_error(
+            &mut env,
+            format!("Expected at least 2 inputs, got {}", inputs.len()),
+        );
+        return 0;
+    }
+    let callback: Option<GlobalRef> = if callback.is_null() {
+        None
+    } else {
+        Some(env.new_global_ref(callback).unwrap())
+    };
+    let vm = env.get_java_vm().unwrap();
 
-    let mut input_vec: Vec<&Tensor> = Vec::new();
-    for &i in input_handles.iter() {
-        let tensor = cast_handle::<Tensor>(i);
-        input_vec.push(tensor);
+    let future = Arc::new(InferenceFuture::default());
+    let worker = future.clone();
+    crate::threads::spawn(move || {
+        let output = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            let inputs: Vec<&Tensor> = inputs.iter().collect();
+            cancel::with_token(token, || forward_inputs(model.as_ref(), &inputs))
+        }))
+        .unwrap_or_else(|_| Err(Error::Msg("inference panicked".to_string())));
+        worker.complete(output);
+        if let Some(callback) = callback {
+            // pool threads are reused, so they stay attached to the JVM
+            let mut env = vm.attach_current_thread_permanently().unwrap();
+            if env.call_method(&callback, "run", "()V", &[]).is_err() {
+                let _ = env.exception_describe();
+                let _ = env.exception_clear();
+            }
+        }
+    });
+    to_handle(future)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_isInferenceDone<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+) -> jboolean {
+    let future = or_throw!(env, borrow_handle::<Arc<InferenceFuture>>(handle));
+    if future.is_done() {
+        JNI_TRUE
+    } else {
+        JNI_FALSE
     }
+}
 
-    let result = model.forward(
-        input_vec.get(0).unwrap(),
-        input_vec.get(1).unwrap(),
-        input_vec.get(2).map(|&x| x),
-    );
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_getInferenceResult<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+) -> JLongArray<'local> {
+    let future = or_throw!(env, borrow_handle::<Arc<InferenceFuture>>(handle));
+    let outputs = future.wait();
+    let tensors = outputs.map(|outputs| outputs.into_iter().map(|(_, tensor)| tensor).collect());
+    return_handles(&mut env, tensors)
+}
 
-    match result {
-        Ok(output) => to_handle(output),
-        Err(err) => {
-            env.throw(err.to_string()).unwrap();
-            0
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_deleteInferenceFuture<'local>(
+    _: JNIEnv,
+    _: JObject,
+    handle: jlong,
+) {
+    drop_handle::<Arc<InferenceFuture>>(handle);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testdata(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata")
+            .join(name)
+    }
+
+    fn temp_dir(name: &str) -> Result<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("djl-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    #[test]
+    fn loads_sharded_pytorch_checkpoints() -> Result<()> {
+        // the shards are found by their name, there is no index
+        let model_path = testdata("sharded-pth");
+        assert_eq!(checkpoint_files(&model_path, "pytorch_model.bin")?.len(), 2);
+        let weights = Weights::Path(model_path);
+        let vb = var_builder(&weights, DType::F32, &Device::Cpu)?;
+        let first: Vec<Vec<f32>> = vb.get((2, 3), "first.weight")?.to_vec2()?;
+        assert_eq!(first, [[0., 1., 2.], [3., 4., 5.]]);
+        let second: Vec<f32> = vb.get(3, "second.weight")?.to_vec1()?;
+        assert_eq!(second, [6., 7., 8.]);
+        assert_eq!(read_tensors(&weights)?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn loads_npz_checkpoints() -> Result<()> {
+        let model_path = temp_dir("npz")?;
+        let weight = Tensor::arange(0f32, 6., &Device::Cpu)?.reshape((2, 3))?;
+        Tensor::write_npz(&[("first.weight", &weight)], model_path.join("model.npz"))?;
+        let weights = Weights::Path(model_path);
+        let vb = var_builder(&weights, DType::F32, &Device::Cpu)?;
+        let first: Vec<Vec<f32>> = vb.get((2, 3), "first.weight")?.to_vec2()?;
+        assert_eq!(first, [[0., 1., 2.], [3., 4., 5.]]);
+        assert_eq!(read_tensors(&weights)?.len(), 1);
+        Ok(())
+    }
+
+    /// Writes safetensors shards and a `model.safetensors.index.json` mapping the tensors to them.
+    fn write_shards(
+        name: &str,
+        shards: &[(&str, &[(&str, &Tensor)])],
+        weight_map: &[(&str, &str)],
+    ) -> Result<PathBuf> {
+        let model_path = temp_dir(name)?;
+        for (file, tensors) in shards {
+            let tensors: HashMap<String, Tensor> = tensors
+                .iter()
+                .map(|(name, tensor)| (name.to_string(), (*tensor).clone()))
+                .collect();
+            candle_core::safetensors::save(&tensors, model_path.join(file))?;
         }
+        let weight_map: serde_json::Map<String, serde_json::Value> = weight_map
+            .iter()
+            .map(|(name, file)| (name.to_string(), (*file).into()))
+            .collect();
+        let index = serde_json::json!({ "weight_map": weight_map });
+        std::fs::write(
+            model_path.join("model.safetensors.index.json"),
+            index.to_string(),
+        )?;
+        Ok(model_path)
+    }
+
+    #[test]
+    fn loads_the_shards_of_the_weight_map() -> Result<()> {
+        let first = Tensor::arange(0f32, 6., &Device::Cpu)?.reshape((2, 3))?;
+        let second = Tensor::arange(6f32, 9., &Device::Cpu)?;
+        let model_path = write_shards(
+            "index",
+            &[
+                (
+                    "model-00001-of-00002.safetensors",
+                    &[("first.weight", &first)],
+                ),
+                (
+                    "model-00002-of-00002.safetensors",
+                    &[("second.weight", &second)],
+                ),
+                // not in the index, e.g. an adapter saved next to the model
+                ("adapter.safetensors", &[("adapter.weight", &second)]),
+            ],
+            &[
+                ("first.weight", "model-00001-of-00002.safetensors"),
+                ("second.weight", "model-00002-of-00002.safetensors"),
+            ],
+        )?;
+        assert_eq!(safetensors_files(&model_path)?.len(), 2);
+        let weights = Weights::Path(model_path);
+        let vb = var_builder(&weights, DType::F32, &Device::Cpu)?;
+        let second: Vec<f32> = vb.get(3, "second.weight")?.to_vec1()?;
+        assert_eq!(second, [6., 7., 8.]);
+        assert!(!vb.contains_tensor("adapter.weight"));
+        assert_eq!(read_tensors(&weights)?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_tensors_outside_of_their_shard() -> Result<()> {
+        let first = Tensor::arange(0f32, 6., &Device::Cpu)?;
+        let second = Tensor::arange(6f32, 9., &Device::Cpu)?;
+        let shards: &[(&str, &[(&str, &Tensor)])] = &[
+            (
+                "model-00001-of-00002.safetensors",
+                &[("first.weight", &first)],
+            ),
+            (
+                "model-00002-of-00002.safetensors",
+                &[("first.weight", &second), ("second.weight", &second)],
+            ),
+        ];
+        let weight_map = [
+            ("first.weight", "model-00001-of-00002.safetensors"),
+            ("second.weight", "model-00002-of-00002.safetensors"),
+        ];
+        let model_path = write_shards("duplicate", shards, &weight_map)?;
+        assert!(safetensors_files(&model_path).is_err());
+        assert!(var_builder(&Weights::Path(model_path), DType::F32, &Device::Cpu).is_err());
+
+        // second.weight is mapped to the first shard but isn't in it
+        let weight_map = [
+            ("first.weight", "model-00001-of-00002.safetensors"),
+            ("second.weight", "model-00001-of-00002.safetensors"),
+        ];
+        let model_path = write_shards("missing", &shards[..1], &weight_map)?;
+        assert!(safetensors_files(&model_path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn fails_explicit_flash_attention_when_unavailable() -> Result<()> {
+        // flash-attn doesn't support F32
+        assert!(AttentionBackend::select(Some("flash"), DType::F32).is_err());
+        assert_eq!(
+            AttentionBackend::select(None, DType::F32)?,
+            AttentionBackend::Eager
+        );
+        assert_eq!(
+            AttentionBackend::select(Some("sdpa"), DType::F32)?,
+            AttentionBackend::Eager
+        );
+        assert!(AttentionBackend::select(Some("fused"), DType::F32).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn checks_position_ids() -> Result<()> {
+        let positions = |ids: &[i64]| Tensor::new(ids, &Device::Cpu)?.unsqueeze(0);
+        check_seq_len(3, Some(&positions(&[5, 6, 7])?), 8)?;
+        assert!(check_seq_len(9, None, 8).is_err());
+        assert!(check_seq_len(3, Some(&positions(&[6, 7, 8])?), 8).is_err());
+        assert!(check_seq_len(3, Some(&positions(&[-1, 0, 1])?), 8).is_err());
+        Ok(())
     }
 }
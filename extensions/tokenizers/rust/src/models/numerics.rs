@@ -0,0 +1,47 @@
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use candle_core::{DType, Result, Tensor};
+use jni::objects::JObject;
+use jni::sys::{jboolean, JNI_FALSE, JNI_TRUE};
+use jni::JNIEnv;
+
+static CHECK_NUMERICS: AtomicBool = AtomicBool::new(false);
+
+/// Fails if `xs` holds NaN or Inf values and the checks are enabled. The checks synchronize with
+/// the device after each layer, so they are only meant for debugging.
+pub(crate) fn check(layer: impl Display, xs: &Tensor) -> Result<()> {
+    if !CHECK_NUMERICS.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    // `x - x` is 0 for finite values and NaN for NaN and Inf
+    let sum = (xs - xs)?.to_dtype(DType::F32)?.sum_all()?;
+    if sum.to_scalar::<f32>()?.is_nan() {
+        candle_core::bail!(
+            "{layer} produced NaN or Inf values with dtype {:?}",
+            xs.dtype()
+        )
+    }
+    Ok(())
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_setCheckNumerics(
+    _: JNIEnv,
+    _: JObject,
+    enabled: jboolean,
+) {
+    CHECK_NUMERICS.store(enabled == JNI_TRUE, Ordering::Relaxed);
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_isCheckNumerics(
+    _: JNIEnv,
+    _: JObject,
+) -> jboolean {
+    if CHECK_NUMERICS.load(Ordering::Relaxed) {
+        JNI_TRUE
+    } else {
+        JNI_FALSE
+    }
+}
@@ -0,0 +1,113 @@
+use candle_core::{Device, Result, Tensor};
+
+/// A batch of sequences concatenated along the first dimension without padding, the attention of
+/// each sequence is bounded by `cu_seqlens` as in flash-attn's varlen kernels.
+pub(crate) struct Packed {
+    lengths: Vec<usize>,
+    /// The cumulative sequence lengths, starting with `0`.
+    cu_seqlens: Tensor,
+    max_seqlen: usize,
+}
+
+impl Packed {
+    pub(crate) fn new(lengths: Vec<usize>, device: &Device) -> Result<Self> {
+        let cu_seqlens: Vec<u32> = std::iter::once(0)
+            .chain(lengths.iter().scan(0, |total, &len| {
+                *total += len as u32;
+                Some(*total)
+            }))
+            .collect();
+        let cu_seqlens = Tensor::new(cu_seqlens.as_slice(), device)?;
+        let max_seqlen = lengths.iter().copied().max().unwrap_or(0);
+        Ok(Self {
+            lengths,
+            cu_seqlens,
+            max_seqlen,
+        })
+    }
+
+    /// Returns the same batch with `cu_seqlens` on `device`.
+    pub(crate) fn to_device(&self, device: &Device) -> Result<Self> {
+        Ok(Self {
+            lengths: self.lengths.clone(),
+            cu_seqlens: self.cu_seqlens.to_device(device)?,
+            max_seqlen: self.max_seqlen,
+        })
+    }
+
+    pub(crate) fn max_seqlen(&self) -> usize {
+        self.max_seqlen
+    }
+
+    /// Concatenates the sequences, e.g. the input ids.
+    pub(crate) fn pack(sequences: &[Vec<u32>], device: &Device) -> Result<Tensor> {
+        let packed: Vec<u32> = sequences.iter().flatten().copied().collect();
+        Tensor::new(packed.as_slice(), device)
+    }
+
+    /// Returns the positions of the tokens within their own sequence.
+    pub(crate) fn position_ids(&self, device: &Device) -> Result<Tensor> {
+        let positions: Vec<u32> = self.lengths.iter().flat_map(|&len| 0..len as u32).collect();
+        Tensor::new(positions.as_slice(), device)
+    }
+
+    /// Splits packed `(total, ..)` states into a `(batch, max_seqlen, ..)` tensor padded with
+    /// zeros, so the outputs match the ones of a padded batch.
+    pub(crate) fn unpack(&self, xs: &Tensor) -> Result<Tensor> {
+        let mut start = 0;
+        let mut sequences = Vec::with_capacity(self.lengths.len());
+        for &len in &self.lengths {
+            let sequence = xs.narrow(0, start, len)?;
+            sequences.push(sequence.pad_with_zeros(0, 0, self.max_seqlen - len)?);
+            start += len;
+        }
+        Tensor::stack(&sequences, 0)
+    }
+
+    /// Runs flash-attn on `(total, num_heads, head_dim)` queries, keys and values.
+    pub(crate) fn attention(
+        &self,
+        q: &Tensor,
+        k: &Tensor,
+        v: &Tensor,
+        softmax_scale: f32,
+    ) -> Result<Tensor> {
+        // the layers may be sharded across devices
+        let cu_seqlens = self.cu_seqlens.to_device(q.device())?;
+        flash_attn_varlen(q, k, v, &cu_seqlens, self.max_seqlen, softmax_scale)
+    }
+}
+
+#[cfg(feature = "flash-attn")]
+fn flash_attn_varlen(
+    q: &Tensor,
+    k: &Tensor,
+    v: &Tensor,
+    cu_seqlens: &Tensor,
+    max_seqlen: usize,
+    softmax_scale: f32,
+) -> Result<Tensor> {
+    candle_flash_attn::flash_attn_varlen(
+        q,
+        k,
+        v,
+        cu_seqlens,
+        cu_seqlens,
+        max_seqlen,
+        max_seqlen,
+        softmax_scale,
+        false,
+    )
+}
+
+#[cfg(not(feature = "flash-attn"))]
+fn flash_attn_varlen(
+    _: &Tensor,
+    _: &Tensor,
+    _: &Tensor,
+    _: &Tensor,
+    _: usize,
+    _: f32,
+) -> Result<Tensor> {
+    unimplemented!("compile with '--features flash-attn'")
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use candle_core::safetensors::Load;
+use candle_core::{DType, Device, Error, Result, Shape, Tensor};
+use candle_nn::var_builder::SimpleBackend;
+use candle_nn::{Init, VarBuilder};
+use safetensors::tensor::{TensorInfo, TensorView};
+use safetensors::SafeTensors;
+
+/// safetensors shards held in memory. The headers are parsed once, the tensors are only loaded
+/// when a layer asks for them and on the device of that layer.
+pub(crate) struct Shards {
+    buffers: Vec<Vec<u8>>,
+    /// The shard, the offset of the data section and the header of each tensor.
+    tensors: HashMap<String, (usize, usize, TensorInfo)>,
+}
+
+impl Shards {
+    pub(crate) fn new(buffers: Vec<Vec<u8>>) -> Result<Self> {
+        let mut tensors = HashMap::new();
+        for (shard, buffer) in buffers.iter().enumerate() {
+            let (header_len, metadata) = SafeTensors::read_metadata(buffer).map_err(Error::wrap)?;
+            for (name, info) in metadata.tensors() {
+                if tensors.contains_key(&name) {
+                    candle_core::bail!("{name} is in more than one shard")
+                }
+                tensors.insert(name, (shard, 8 + header_len, info.clone()));
+            }
+        }
+        Ok(Self { buffers, tensors })
+    }
+
+    fn view(&self, name: &str) -> Result<TensorView<'_>> {
+        let Some((shard, offset, info)) = self.tensors.get(name) else {
+            candle_core::bail!("cannot find tensor {name}")
+        };
+        let (start, end) = info.data_offsets;
+        let data = &self.buffers[*shard][offset + start..offset + end];
+        TensorView::new(info.dtype, info.shape.clone(), data).map_err(Error::wrap)
+    }
+
+    /// Returns the views of all the tensors.
+    pub(crate) fn views(&self) -> Result<Vec<(String, TensorView<'_>)>> {
+        self.tensors
+            .keys()
+            .map(|name| Ok((name.clone(), self.view(name)?)))
+            .collect()
+    }
+
+    /// Returns a builder loading the tensors on `device` when they are requested.
+    pub(crate) fn var_builder(
+        self: &Arc<Self>,
+        dtype: DType,
+        device: &Device,
+    ) -> VarBuilder<'static> {
+        VarBuilder::from_backend(Box::new(self.clone()), dtype, device.clone())
+    }
+}
+
+impl SimpleBackend for Arc<Shards> {
+    fn get(&self, shape: Shape, name: &str, _: Init, dtype: DType, dev: &Device) -> Result<Tensor> {
+        let tensor = self.view(name)?.load(dev)?.to_dtype(dtype)?;
+        if tensor.shape() != &shape {
+            Err(Error::UnexpectedShape {
+                msg: format!("shape mismatch for {name}"),
+                expected: shape,
+                got: tensor.shape().clone(),
+            }
+            .bt())?
+        }
+        Ok(tensor)
+    }
+
+    fn contains_tensor(&self, name: &str) -> bool {
+        self.tensors.contains_key(name)
+    }
+}
@@ -0,0 +1,133 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use candle_core::{Device, DeviceLocation, Result, Tensor};
+
+use super::packed::Packed;
+use super::{Model, Outputs};
+
+// threads are numbered in the order they run their first forward
+static NEXT_THREAD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static THREAD_INDEX: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+fn thread_index() -> usize {
+    THREAD_INDEX.with(|index| match index.get() {
+        Some(index) => index,
+        None => {
+            let next = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+            index.set(Some(next));
+            next
+        }
+    })
+}
+
+/// Replicas of a model on CUDA devices with their own stream, so forwards from different threads
+/// overlap on the GPU instead of queueing on one stream. A candle device launches all its kernels
+/// on one stream and ops can't mix tensors of two devices, so every stream holds a copy of the
+/// weights.
+///
+/// `Device::new_cuda_with_stream` is the only way to get another stream, the new device has its
+/// own id and candle rejects ops that mix it with the weights of the first device. The copies
+/// multiply the GPU memory of the weights by `num_streams`, models that don't fit that many times
+/// can serve concurrent requests on the inter-op pool, or be sharded with `device_map` or
+/// `tensor_parallel_degree`, instead.
+///
+/// A thread always runs on the same replica. The inputs are copied to the replica and the outputs
+/// back to the device of the first replica, the device Java creates its tensors on.
+pub(crate) struct StreamModel {
+    replicas: Vec<Box<dyn Model>>,
+}
+
+impl StreamModel {
+    /// Loads `num_streams` replicas with `load`, the first one on `device` and the others on new
+    /// streams of the same GPU.
+    pub(crate) fn load(
+        num_streams: usize,
+        device: &Device,
+        load: impl Fn(&Device) -> Result<Box<dyn Model>>,
+    ) -> Result<Self> {
+        let DeviceLocation::Cuda { gpu_id } = device.location() else {
+            candle_core::bail!("cuda_streams requires a CUDA device")
+        };
+        let mut replicas = vec![load(device)?];
+        for _ in 1..num_streams {
+            replicas.push(load(&Device::new_cuda_with_stream(gpu_id)?)?);
+        }
+        tracing::info!("Loaded {num_streams} replicas on separate CUDA streams");
+        Ok(Self { replicas })
+    }
+
+    fn replica(&self) -> &dyn Model {
+        self.replicas[thread_index() % self.replicas.len()].as_ref()
+    }
+
+    fn to_output_device(&self, outputs: Outputs) -> Result<Outputs> {
+        outputs
+            .into_iter()
+            .map(|(name, tensor)| Ok((name, tensor.to_device(self.device())?)))
+            .collect()
+    }
+}
+
+fn to_device(tensor: Option<&Tensor>, device: &Device) -> Result<Option<Tensor>> {
+    tensor.map(|tensor| tensor.to_device(device)).transpose()
+}
+
+impl Model for StreamModel {
+    fn is_padded(&self) -> bool {
+        self.replicas[0].is_padded()
+    }
+
+    fn get_input_names(&self) -> Vec<String> {
+        self.replicas[0].get_input_names()
+    }
+
+    fn get_output_names(&self) -> Vec<String> {
+        self.replicas[0].get_output_names()
+    }
+
+    fn device(&self) -> &Device {
+        self.replicas[0].device()
+    }
+
+    fn forward(
+        &self,
+        input_ids: &Tensor,
+        attention_mask: &Tensor,
+        token_type_ids: Option<&Tensor>,
+        position_ids: Option<&Tensor>,
+    ) -> Result<Outputs> {
+        let replica = self.replica();
+        let device = replica.device();
+        let outputs = replica.forward(
+            &input_ids.to_device(device)?,
+            &attention_mask.to_device(device)?,
+            to_device(token_type_ids, device)?.as_ref(),
+            to_device(position_ids, device)?.as_ref(),
+        )?;
+        self.to_output_device(outputs)
+    }
+
+    fn supports_packed(&self) -> bool {
+        self.replicas[0].supports_packed()
+    }
+
+    fn forward_packed(
+        &self,
+        input_ids: &Tensor,
+        token_type_ids: Option<&Tensor>,
+        packed: &Packed,
+    ) -> Result<Outputs> {
+        let replica = self.replica();
+        let device = replica.device();
+        let outputs = replica.forward_packed(
+            &input_ids.to_device(device)?,
+            to_device(token_type_ids, device)?.as_ref(),
+            &packed.to_device(device)?,
+        )?;
+        self.to_output_device(outputs)
+    }
+}
@@ -0,0 +1,460 @@
+use candle_core::quantized::{GgmlDType, QMatMul, QTensor};
+use candle_core::{DType, Module, Result, Tensor};
+use candle_nn::VarBuilder;
+
+use crate::models::lora::Adapters;
+
+#[cfg(feature = "nccl")]
+pub(crate) use nccl::TensorParallelModel;
+
+/// The tensor parallel rank of a model replica. Linear layers are split across the ranks and
+/// the partial results are summed with an NCCL all-reduce. The weights of each rank are quantized
+/// after they are split, if a quantization is requested.
+#[derive(Clone)]
+pub(crate) struct Parallelism {
+    rank: usize,
+    world_size: usize,
+    quantization: Option<GgmlDType>,
+    adapters: Option<Adapters>,
+    #[cfg(feature = "nccl")]
+    comm: Option<std::sync::Arc<nccl::RankComm>>,
+}
+
+impl Default for Parallelism {
+    fn default() -> Self {
+        Self {
+            rank: 0,
+            world_size: 1,
+            quantization: None,
+            adapters: None,
+            #[cfg(feature = "nccl")]
+            comm: None,
+        }
+    }
+}
+
+impl Parallelism {
+    /// Quantizes the weights of the linear layers to `dtype` when they are loaded.
+    pub(crate) fn with_quantization(mut self, dtype: Option<GgmlDType>) -> Self {
+        self.quantization = dtype;
+        self
+    }
+
+    /// Lets LoRA adapters apply to the linear layers, the shards of tensor parallel layers can't
+    /// be adapted.
+    pub(crate) fn with_adapters(mut self, adapters: Adapters) -> Self {
+        self.adapters = Some(adapters);
+        self
+    }
+
+    /// Returns the part of `size` held by each rank, e.g. the number of attention heads.
+    pub(crate) fn split(&self, size: usize, name: &str) -> Result<usize> {
+        if size % self.world_size != 0 {
+            candle_core::bail!(
+                "{name} {size} is not divisible by {} ranks",
+                self.world_size
+            )
+        }
+        Ok(size / self.world_size)
+    }
+
+    /// Loads a linear layer whose output features are split across the ranks.
+    pub(crate) fn column_linear(
+        &self,
+        in_dim: usize,
+        out_dim: usize,
+        vb: VarBuilder,
+    ) -> Result<ParallelLinear> {
+        let weight = self.shard(&vb.get((out_dim, in_dim), "weight")?, 0)?;
+        let bias = self.shard(&vb.get(out_dim, "bias")?, 0)?;
+        let linear = ParallelLinear::new(self.matmul(weight)?, Some(bias));
+        Ok(self.adapt(linear, &vb))
+    }
+
+    /// Loads a linear layer whose input features are split across the ranks, the outputs of all
+    /// ranks are summed before the bias is added.
+    pub(crate) fn row_linear(
+        &self,
+        in_dim: usize,
+        out_dim: usize,
+        vb: VarBuilder,
+    ) -> Result<ParallelLinear> {
+        let weight = vb.get((out_dim, in_dim), "weight")?;
+        let bias = vb.get(out_dim, "bias")?;
+        if self.world_size == 1 {
+            let linear = ParallelLinear::new(self.matmul(weight)?, Some(bias));
+            return Ok(self.adapt(linear, &vb));
+        }
+        let weight = self.shard(&weight, 1)?;
+        #[allow(unused_mut)]
+        let mut linear = ParallelLinear::new(self.matmul(weight)?, Some(bias));
+        #[cfg(feature = "nccl")]
+        {
+            linear.all_reduce = self.comm.clone().map(nccl::AllReduce::new);
+        }
+        Ok(linear)
+    }
+
+    fn adapt(&self, mut linear: ParallelLinear, vb: &VarBuilder) -> ParallelLinear {
+        if let (Some(adapters), 1) = (&self.adapters, self.world_size) {
+            let weight = format!("{}.weight", vb.prefix());
+            adapters.register(&weight, vb.device(), vb.dtype());
+            linear.adapter = Some((adapters.clone(), weight));
+        }
+        linear
+    }
+
+    /// Quantizes a `(out_dim, in_dim)` weight, weights whose rows can't be split into blocks of
+    /// the quantization stay unquantized.
+    fn matmul(&self, weight: Tensor) -> Result<QMatMul> {
+        match self.quantization {
+            Some(dtype) if weight.dim(1)? % dtype.block_size() == 0 => {
+                QMatMul::from_qtensor(QTensor::quantize(&weight, dtype)?)
+            }
+            Some(dtype) => {
+                tracing::warn!(
+                    "{:?} weight isn't divisible into {dtype:?} blocks, it isn't quantized",
+                    weight.shape()
+                );
+                Ok(QMatMul::Tensor(weight))
+            }
+            None => Ok(QMatMul::Tensor(weight)),
+        }
+    }
+
+    fn shard(&self, tensor: &Tensor, dim: usize) -> Result<Tensor> {
+        if self.world_size == 1 {
+            return Ok(tensor.clone());
+        }
+        let size = self.split(tensor.dim(dim)?, "linear dim")?;
+        let start = (self.rank * size) as u32;
+        // a narrowed view would keep the full weight alive, the shard is gathered instead
+        let indices = Tensor::arange(start, start + size as u32, tensor.device())?;
+        tensor.index_select(&indices, dim)
+    }
+}
+
+/// A linear layer that holds one shard of the weights of a tensor parallel model. The bias is
+/// added after the all-reduce, so it's only added once.
+pub(crate) struct ParallelLinear {
+    weight: QMatMul,
+    #[cfg(feature = "nccl")]
+    all_reduce: Option<nccl::AllReduce>,
+    bias: Option<Tensor>,
+    /// The adapters and the name of the weight they are looked up by.
+    adapter: Option<(Adapters, String)>,
+    span: tracing::Span,
+}
+
+impl ParallelLinear {
+    fn new(weight: QMatMul, bias: Option<Tensor>) -> Self {
+        let span = tracing::span!(tracing::Level::TRACE, "linear");
+        Self {
+            weight,
+            #[cfg(feature = "nccl")]
+            all_reduce: None,
+            bias,
+            adapter: None,
+            span,
+        }
+    }
+}
+
+impl Module for ParallelLinear {
+    fn forward(&self, xs: &Tensor) -> Result<Tensor> {
+        let _enter = self.span.enter();
+        let ys = match &self.weight {
+            // the quantized kernels take F32 activations
+            QMatMul::QTensor(_) => {
+                let input = xs.to_dtype(DType::F32)?.contiguous()?;
+                self.weight.forward(&input)?.to_dtype(xs.dtype())?
+            }
+            weight => weight.forward(xs)?,
+        };
+        #[cfg(feature = "nccl")]
+        let ys = match &self.all_reduce {
+            Some(all_reduce) => ys.apply_op1_no_bwd(all_reduce)?,
+            None => ys,
+        };
+        let ys = match &self.bias {
+            Some(bias) => ys.broadcast_add(bias)?,
+            None => ys,
+        };
+        let factors = self
+            .adapter
+            .as_ref()
+            .and_then(|(adapters, weight)| adapters.active(weight));
+        match factors {
+            // B is already scaled
+            Some((a, b)) => ys + xs.broadcast_matmul(&a.t()?)?.broadcast_matmul(&b.t()?)?,
+            None => Ok(ys),
+        }
+    }
+}
+
+#[cfg(feature = "nccl")]
+mod nccl {
+    use std::sync::mpsc::{self, Sender};
+    use std::sync::{Arc, Mutex};
+    use std::thread::{self, JoinHandle};
+
+    use candle_core::backend::BackendStorage;
+    use candle_core::cuda_backend::WrapErr;
+    use candle_core::{CpuStorage, CudaStorage, CustomOp1, DType, Device, Error, Layout};
+    use candle_core::{Result, Shape, Tensor};
+    use cudarc::driver::CudaDevice;
+    use cudarc::nccl::safe::{Comm, ReduceOp};
+    use half::{bf16, f16};
+
+    use super::Parallelism;
+    use crate::models::{cancel, heads, Model, Outputs};
+    use crate::ndarray::get_device;
+
+    /// The NCCL communicator of a rank, it's created by the loading thread and moved to the worker
+    /// thread of the rank.
+    pub(crate) struct RankComm(Comm);
+
+    // NCCL communicators may be used from any thread, but not concurrently. Only the worker thread
+    // of the rank calls into it, one forward at a time.
+    unsafe impl Send for RankComm {}
+    unsafe impl Sync for RankComm {}
+
+    /// Sums a tensor across all ranks.
+    pub(crate) struct AllReduce {
+        comm: Arc<RankComm>,
+    }
+
+    impl AllReduce {
+        pub(crate) fn new(comm: Arc<RankComm>) -> Self {
+            Self { comm }
+        }
+    }
+
+    macro_rules! all_reduce {
+        ($comm:expr, $storage:expr, $layout:expr, $ty:ty) => {{
+            let dev = $storage.device().clone();
+            let src = $storage.as_cuda_slice::<$ty>()?;
+            let src = match $layout.contiguous_offsets() {
+                Some((0, len)) if len == src.len() => src,
+                _ => candle_core::bail!("all-reduce expects a contiguous tensor"),
+            };
+            let mut dst = unsafe { dev.alloc::<$ty>(src.len()) }.w()?;
+            $comm
+                .0
+                .all_reduce(src, &mut dst, &ReduceOp::Sum)
+                .map_err(Error::debug)?;
+            CudaStorage::wrap_cuda_slice(dst, dev)
+        }};
+    }
+
+    impl CustomOp1 for AllReduce {
+        fn name(&self) -> &'static str {
+            "all-reduce"
+        }
+
+        fn cpu_fwd(&self, _: &CpuStorage, _: &Layout) -> Result<(CpuStorage, Shape)> {
+            candle_core::bail!("all-reduce is only supported on CUDA devices")
+        }
+
+        fn cuda_fwd(&self, storage: &CudaStorage, layout: &Layout) -> Result<(CudaStorage, Shape)> {
+            let dst = match storage.dtype() {
+                DType::F16 => all_reduce!(self.comm, storage, layout, f16),
+                DType::BF16 => all_reduce!(self.comm, storage, layout, bf16),
+                DType::F32 => all_reduce!(self.comm, storage, layout, f32),
+                dtype => candle_core::bail!("all-reduce doesn't support {dtype:?}"),
+            };
+            Ok((dst, layout.shape().clone()))
+        }
+    }
+
+    type Loader = dyn Fn(&Device, &Parallelism) -> Result<Box<dyn Model>> + Send + Sync;
+    /// The inputs, whether only the last hidden state is requested and where to send the outputs.
+    type Request = (Vec<Option<Tensor>>, bool, Sender<Result<Outputs>>);
+
+    /// Runs one replica per GPU on its own thread, each replica holds a shard of the weights and
+    /// all replicas take part in every forward.
+    pub(crate) struct TensorParallelModel {
+        // the collectives of the ranks pair up by order, so the requests must reach all ranks in
+        // the same order. The lock is only held while they are sent, each rank runs its requests
+        // one at a time anyway.
+        workers: Mutex<Vec<Sender<Request>>>,
+        handles: Vec<JoinHandle<()>>,
+        input_names: Vec<String>,
+        output_names: Vec<String>,
+        padded: bool,
+        // the workers move the inputs to their own GPU
+        device: Device,
+    }
+
+    impl TensorParallelModel {
+        pub(crate) fn load(world_size: usize, loader: Arc<Loader>) -> Result<Self> {
+            // the communicators of all ranks are created at once, a rank that fails to start
+            // can't leave the others waiting for it in the NCCL bootstrap
+            let devices = (0..world_size)
+                .map(|rank| CudaDevice::new(rank).map_err(Error::debug))
+                .collect::<Result<Vec<_>>>()?;
+            let comms = Comm::from_devices(devices).map_err(Error::debug)?;
+            let (init_tx, init_rx) = mpsc::channel();
+            let mut workers = Vec::with_capacity(world_size);
+            let mut handles = Vec::with_capacity(world_size);
+            for (rank, comm) in comms.into_iter().enumerate() {
+                let (tx, rx) = mpsc::channel::<Request>();
+                let loader = loader.clone();
+                let init_tx = init_tx.clone();
+                let comm = RankComm(comm);
+                handles.push(thread::spawn(move || {
+                    let (device, model) = match init(rank, world_size, comm, &loader) {
+                        Ok(ret) => ret,
+                        Err(err) => {
+                            let _ = init_tx.send(Err(err));
+                            return;
+                        }
+                    };
+                    let names = (model.get_input_names(), model.get_output_names());
+                    let _ = init_tx.send(Ok((names, model.is_padded())));
+                    for (inputs, hidden_state_only, reply) in rx {
+                        let outputs = heads::with_hidden_state_only(hidden_state_only, || {
+                            forward(model.as_ref(), &device, &inputs)
+                        });
+                        let _ = reply.send(outputs);
+                    }
+                }));
+                workers.push(tx);
+            }
+            drop(init_tx);
+
+            let mut info = None;
+            let mut error = None;
+            for ret in init_rx.iter().take(world_size) {
+                match ret {
+                    Ok(ret) => {
+                        info.get_or_insert(ret);
+                    }
+                    Err(err) => {
+                        error.get_or_insert(err);
+                    }
+                }
+            }
+            let info = match (info, error) {
+                (Some(info), None) => info,
+                (_, error) => {
+                    // the ranks that loaded stop once their queue is closed, dropping their
+                    // model aborts the communicator
+                    drop(workers);
+                    for handle in handles {
+                        let _ = handle.join();
+                    }
+                    return Err(error.unwrap_or_else(|| {
+                        Error::Msg("tensor parallel worker stopped".to_string())
+                    }));
+                }
+            };
+            let ((input_names, output_names), padded) = info;
+            Ok(Self {
+                workers: Mutex::new(workers),
+                handles,
+                input_names,
+                output_names,
+                padded,
+                device: Device::Cpu,
+            })
+        }
+    }
+
+    fn init(
+        rank: usize,
+        world_size: usize,
+        comm: RankComm,
+        loader: &Loader,
+    ) -> Result<(Device, Box<dyn Model>)> {
+        let device = get_device("gpu", rank)?;
+        let parallelism = Parallelism {
+            rank,
+            world_size,
+            quantization: None,
+            adapters: None,
+            comm: Some(Arc::new(comm)),
+        };
+        let model = loader(&device, &parallelism)?;
+        Ok((device, model))
+    }
+
+    fn forward(model: &dyn Model, device: &Device, inputs: &[Option<Tensor>]) -> Result<Outputs> {
+        let inputs = inputs
+            .iter()
+            .map(|tensor| {
+                tensor
+                    .as_ref()
+                    .map(|tensor| tensor.to_device(device))
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let input = |i: usize| inputs[i].as_ref();
+        model.forward(input(0).unwrap(), input(1).unwrap(), input(2), input(3))
+    }
+
+    impl Model for TensorParallelModel {
+        fn is_padded(&self) -> bool {
+            self.padded
+        }
+
+        fn get_input_names(&self) -> Vec<String> {
+            self.input_names.clone()
+        }
+
+        fn get_output_names(&self) -> Vec<String> {
+            self.output_names.clone()
+        }
+
+        fn device(&self) -> &Device {
+            &self.device
+        }
+
+        fn forward(
+            &self,
+            input_ids: &Tensor,
+            attention_mask: &Tensor,
+            token_type_ids: Option<&Tensor>,
+            position_ids: Option<&Tensor>,
+        ) -> Result<Outputs> {
+            // the ranks must run the same layers, so cancellation is only checked before dispatch
+            cancel::check()?;
+            let inputs = vec![
+                Some(input_ids.clone()),
+                Some(attention_mask.clone()),
+                token_type_ids.cloned(),
+                position_ids.cloned(),
+            ];
+            let workers = self.workers.lock().unwrap();
+            let replies = workers
+                .iter()
+                .map(|worker| {
+                    let (tx, rx) = mpsc::channel();
+                    worker
+                        .send((inputs.clone(), !heads::requested(), tx))
+                        .map_err(|_| Error::Msg("tensor parallel worker stopped".to_string()))?;
+                    Ok(rx)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            drop(workers);
+            let mut outputs = replies
+                .into_iter()
+                .map(|rx| {
+                    rx.recv()
+                        .map_err(|_| Error::Msg("tensor parallel worker stopped".to_string()))?
+                })
+                .collect::<Result<Vec<_>>>()?;
+            // every rank ends with the complete outputs
+            Ok(outputs.swap_remove(0))
+        }
+    }
+
+    impl Drop for TensorParallelModel {
+        fn drop(&mut self) {
+            self.workers.lock().unwrap().clear();
+            for handle in self.handles.drain(..) {
+                let _ = handle.join();
+            }
+        }
+    }
+}
@@ -1,10 +1,10 @@
-use candle_core::Tensor;
+use candle_core::{Result, Shape, Tensor};
 use jni::objects::JObject;
-use jni::sys::jlong;
+use jni::sys::{jdouble, jlong};
 use jni::JNIEnv;
 
-use crate::cast_handle;
-use crate::ndarray::return_handle;
+use crate::ndarray::{replace_tensor, return_handle, tensor_of};
+use crate::throw_error;
 
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_add<'local>(
@@ -14,8 +14,8 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_add<'local>(
     other_handle: jlong,
 ) -> jlong {
     let op = || {
-        let lhs = cast_handle::<Tensor>(handle);
-        let rhs = cast_handle::<Tensor>(other_handle).to_dtype(lhs.dtype())?;
+        let lhs = &tensor_of(handle)?;
+        let rhs = tensor_of(other_handle)?.to_dtype(lhs.dtype())?;
         lhs.broadcast_add(&rhs)
     };
     let ret = op();
@@ -30,8 +30,8 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_sub<'local>(
     other_handle: jlong,
 ) -> jlong {
     let op = || {
-        let lhs = cast_handle::<Tensor>(handle);
-        let rhs = cast_handle::<Tensor>(other_handle).to_dtype(lhs.dtype())?;
+        let lhs = &tensor_of(handle)?;
+        let rhs = tensor_of(other_handle)?.to_dtype(lhs.dtype())?;
         lhs.broadcast_sub(&rhs)
     };
     let ret = op();
@@ -46,8 +46,8 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_mul<'local>(
     other_handle: jlong,
 ) -> jlong {
     let op = || {
-        let lhs = cast_handle::<Tensor>(handle);
-        let rhs = cast_handle::<Tensor>(other_handle).to_dtype(lhs.dtype())?;
+        let lhs = &tensor_of(handle)?;
+        let rhs = tensor_of(other_handle)?.to_dtype(lhs.dtype())?;
         lhs.broadcast_mul(&rhs)
     };
     let ret = op();
@@ -62,14 +62,67 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_div<'local>(
     other_handle: jlong,
 ) -> jlong {
     let op = || {
-        let lhs = cast_handle::<Tensor>(handle);
-        let rhs = cast_handle::<Tensor>(other_handle).to_dtype(lhs.dtype())?;
+        let lhs = &tensor_of(handle)?;
+        let rhs = tensor_of(other_handle)?.to_dtype(lhs.dtype())?;
         lhs.broadcast_div(&rhs)
     };
     let ret = op();
     return_handle(&mut env, ret)
 }
 
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_addInplace<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    other_handle: jlong,
+) {
+    update(&mut env, handle, other_handle, |lhs, rhs| lhs.add(rhs))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_subInplace<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    other_handle: jlong,
+) {
+    update(&mut env, handle, other_handle, |lhs, rhs| lhs.sub(rhs))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_mulInplace<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    other_handle: jlong,
+) {
+    update(&mut env, handle, other_handle, |lhs, rhs| lhs.mul(rhs))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_divInplace<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    other_handle: jlong,
+) {
+    update(&mut env, handle, other_handle, |lhs, rhs| lhs.div(rhs))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_addScaledInplace<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    other_handle: jlong,
+    alpha: jdouble,
+) {
+    update(&mut env, handle, other_handle, |lhs, rhs| {
+        lhs.add(&rhs.affine(alpha, 0.)?)
+    })
+}
+
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_maximum<'local>(
     mut env: JNIEnv,
@@ -78,8 +131,8 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_maximum<'local>(
     other_handle: jlong,
 ) -> jlong {
     let op = || {
-        let lhs = cast_handle::<Tensor>(handle);
-        let rhs = cast_handle::<Tensor>(other_handle).to_dtype(lhs.dtype())?;
+        let lhs = &tensor_of(handle)?;
+        let rhs = tensor_of(other_handle)?.to_dtype(lhs.dtype())?;
         lhs.broadcast_maximum(&rhs)
     };
     let ret = op();
@@ -94,8 +147,8 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_minimum<'local>(
     other_handle: jlong,
 ) -> jlong {
     let op = || {
-        let lhs = cast_handle::<Tensor>(handle);
-        let rhs = cast_handle::<Tensor>(other_handle).to_dtype(lhs.dtype())?;
+        let lhs = &tensor_of(handle)?;
+        let rhs = tensor_of(other_handle)?.to_dtype(lhs.dtype())?;
         lhs.broadcast_minimum(&rhs)
     };
     let ret = op();
@@ -110,8 +163,8 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_pow<'local>(
     other_handle: jlong,
 ) -> jlong {
     let op = || {
-        let lhs = cast_handle::<Tensor>(handle);
-        let rhs = cast_handle::<Tensor>(other_handle).to_dtype(lhs.dtype())?;
+        let lhs = &tensor_of(handle)?;
+        let rhs = tensor_of(other_handle)?.to_dtype(lhs.dtype())?;
         lhs.broadcast_pow(&rhs)
     };
     let ret = op();
@@ -126,9 +179,9 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_matmul<'local>(
     other_handle: jlong,
 ) -> jlong {
     let op = || {
-        let lhs = cast_handle::<Tensor>(handle);
-        let rhs = cast_handle::<Tensor>(other_handle).to_dtype(lhs.dtype())?;
-        lhs.broadcast_matmul(&rhs)
+        let lhs = &tensor_of(handle)?;
+        let rhs = tensor_of(other_handle)?.to_dtype(lhs.dtype())?;
+        broadcast_matmul(lhs, &rhs)
     };
     let ret = op();
     return_handle(&mut env, ret)
@@ -142,10 +195,61 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_batchMatMul<'local>(
     other_handle: jlong,
 ) -> jlong {
     let op = || {
-        let lhs = cast_handle::<Tensor>(handle);
-        let rhs = cast_handle::<Tensor>(other_handle).to_dtype(lhs.dtype())?;
+        let lhs = &tensor_of(handle)?;
+        let rhs = tensor_of(other_handle)?.to_dtype(lhs.dtype())?;
         lhs.matmul(&rhs)
     };
     let ret = op();
     return_handle(&mut env, ret)
 }
+
+/// Matrix product with numpy semantics: 1-D operands are promoted to matrices and the leading
+/// batch dimensions are broadcast.
+pub(crate) fn broadcast_matmul(lhs: &Tensor, rhs: &Tensor) -> Result<Tensor> {
+    let (lhs, squeeze_lhs) = if lhs.rank() == 1 {
+        (lhs.unsqueeze(0)?, true)
+    } else {
+        (lhs.clone(), false)
+    };
+    let (rhs, squeeze_rhs) = if rhs.rank() == 1 {
+        (rhs.unsqueeze(1)?, true)
+    } else {
+        (rhs.clone(), false)
+    };
+    let (lhs_batch, lhs_matrix) = lhs.dims().split_at(lhs.rank() - 2);
+    let (rhs_batch, rhs_matrix) = rhs.dims().split_at(rhs.rank() - 2);
+    let batch =
+        Shape::from(lhs_batch).broadcast_shape_binary_op(&Shape::from(rhs_batch), "matmul")?;
+    let lhs_shape = [batch.dims(), lhs_matrix].concat();
+    let rhs_shape = [batch.dims(), rhs_matrix].concat();
+    // the matmul kernels don't support zero strides, so broadcast dims are materialized
+    let lhs = lhs.broadcast_as(lhs_shape)?.contiguous()?;
+    let rhs = rhs.broadcast_as(rhs_shape)?.contiguous()?;
+    let mut ret = lhs.matmul(&rhs)?;
+    if squeeze_rhs {
+        ret = ret.squeeze(ret.rank() - 1)?;
+    }
+    if squeeze_lhs {
+        ret = ret.squeeze(ret.rank() - 2)?;
+    }
+    Ok(ret)
+}
+
+/// Replaces the tensor behind `handle` with `op(lhs, rhs)`, `rhs` is broadcast to the shape of
+/// `lhs` so the handle keeps its shape and data type. Tensors read before the update keep the old
+/// values, of concurrent updates of one handle the last one wins.
+fn update<F>(env: &mut JNIEnv, handle: jlong, other_handle: jlong, op: F)
+where
+    F: FnOnce(&Tensor, &Tensor) -> Result<Tensor>,
+{
+    let ret = || {
+        let lhs = &tensor_of(handle)?;
+        let rhs = tensor_of(other_handle)?
+            .to_dtype(lhs.dtype())?
+            .broadcast_as(lhs.shape())?;
+        replace_tensor(handle, op(lhs, &rhs)?)
+    };
+    if let Err(err) = ret() {
+        throw_error(env, err);
+    }
+}
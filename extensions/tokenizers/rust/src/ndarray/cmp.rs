@@ -1,10 +1,10 @@
-use candle_core::{DType, Tensor};
+use candle_core::{CmpOp, DType, Result, Tensor};
 use jni::objects::JObject;
-use jni::sys::{jboolean, jlong, JNI_FALSE, JNI_TRUE};
+use jni::sys::{jboolean, jdouble, jlong, JNI_FALSE, JNI_TRUE};
 use jni::JNIEnv;
 
-use crate::cast_handle;
-use crate::ndarray::return_handle;
+use crate::ndarray::{return_handle, tensor_of};
+use crate::throw_error;
 
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_eq<'local>(
@@ -13,9 +13,21 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_eq<'local>(
     handle: jlong,
     other_handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
-    let other = cast_handle::<Tensor>(other_handle);
-    let ret = tensor.broadcast_eq(&*other);
+    let tensor = &or_throw!(env, tensor_of(handle));
+    let other = &or_throw!(env, tensor_of(other_handle));
+    let ret = compare(tensor, other, CmpOp::Eq);
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_eqScalar<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    value: jdouble,
+) -> jlong {
+    let tensor = &or_throw!(env, tensor_of(handle));
+    let ret = compare_scalar(tensor, value, CmpOp::Eq);
     return_handle(&mut env, ret)
 }
 
@@ -26,9 +38,21 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_neq<'local>(
     handle: jlong,
     other_handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
-    let other = cast_handle::<Tensor>(other_handle);
-    let ret = tensor.broadcast_ne(&*other);
+    let tensor = &or_throw!(env, tensor_of(handle));
+    let other = &or_throw!(env, tensor_of(other_handle));
+    let ret = compare(tensor, other, CmpOp::Ne);
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_neqScalar<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    value: jdouble,
+) -> jlong {
+    let tensor = &or_throw!(env, tensor_of(handle));
+    let ret = compare_scalar(tensor, value, CmpOp::Ne);
     return_handle(&mut env, ret)
 }
 
@@ -39,9 +63,21 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_gt<'local>(
     handle: jlong,
     other_handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
-    let other = cast_handle::<Tensor>(other_handle);
-    let ret = tensor.broadcast_gt(&*other);
+    let tensor = &or_throw!(env, tensor_of(handle));
+    let other = &or_throw!(env, tensor_of(other_handle));
+    let ret = compare(tensor, other, CmpOp::Gt);
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_gtScalar<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    value: jdouble,
+) -> jlong {
+    let tensor = &or_throw!(env, tensor_of(handle));
+    let ret = compare_scalar(tensor, value, CmpOp::Gt);
     return_handle(&mut env, ret)
 }
 
@@ -52,9 +88,21 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_gte<'local>(
     handle: jlong,
     other_handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
-    let other = cast_handle::<Tensor>(other_handle);
-    let ret = tensor.broadcast_ge(&*other);
+    let tensor = &or_throw!(env, tensor_of(handle));
+    let other = &or_throw!(env, tensor_of(other_handle));
+    let ret = compare(tensor, other, CmpOp::Ge);
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_gteScalar<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    value: jdouble,
+) -> jlong {
+    let tensor = &or_throw!(env, tensor_of(handle));
+    let ret = compare_scalar(tensor, value, CmpOp::Ge);
     return_handle(&mut env, ret)
 }
 
@@ -65,9 +113,21 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_lt<'local>(
     handle: jlong,
     other_handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
-    let other = cast_handle::<Tensor>(other_handle);
-    let ret = tensor.broadcast_lt(&*other);
+    let tensor = &or_throw!(env, tensor_of(handle));
+    let other = &or_throw!(env, tensor_of(other_handle));
+    let ret = compare(tensor, other, CmpOp::Lt);
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_ltScalar<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    value: jdouble,
+) -> jlong {
+    let tensor = &or_throw!(env, tensor_of(handle));
+    let ret = compare_scalar(tensor, value, CmpOp::Lt);
     return_handle(&mut env, ret)
 }
 
@@ -78,9 +138,21 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_lte<'local>(
     handle: jlong,
     other_handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
-    let other = cast_handle::<Tensor>(other_handle);
-    let ret = tensor.broadcast_le(&*other);
+    let tensor = &or_throw!(env, tensor_of(handle));
+    let other = &or_throw!(env, tensor_of(other_handle));
+    let ret = compare(tensor, other, CmpOp::Le);
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_lteScalar<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    value: jdouble,
+) -> jlong {
+    let tensor = &or_throw!(env, tensor_of(handle));
+    let ret = compare_scalar(tensor, value, CmpOp::Le);
     return_handle(&mut env, ret)
 }
 
@@ -91,10 +163,10 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_contentEqual<'local>(
     handle: jlong,
     other_handle: jlong,
 ) -> jboolean {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let size = tensor.shape().elem_count();
     let cmp = || {
-        let other = cast_handle::<Tensor>(other_handle);
+        let other = &tensor_of(other_handle)?;
         let sum = tensor.eq(&*other)?.sum_all()?;
         sum.to_dtype(DType::U32)?.to_scalar::<u32>()
     };
@@ -108,9 +180,50 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_contentEqual<'local>(
             }
         }
         Err(err) => {
-            env.throw_new("ai/djl/engine/EngineException", err.to_string())
-                .unwrap();
+            throw_error(&mut env, err);
             JNI_FALSE
         }
     }
 }
+
+/// Compares with broadcasting, an integer operand is promoted if the other is floating point.
+fn compare(tensor: &Tensor, other: &Tensor, op: CmpOp) -> Result<Tensor> {
+    let (lhs, rhs) = (tensor.dtype(), other.dtype());
+    let dtype = if lhs == rhs || (lhs.is_float() && rhs.is_int()) {
+        lhs
+    } else if lhs.is_int() && rhs.is_float() {
+        rhs
+    } else if lhs.is_float() {
+        DType::F64
+    } else {
+        DType::I64
+    };
+    let tensor = tensor.to_dtype(dtype)?;
+    let other = other.to_dtype(dtype)?;
+    let shape = tensor
+        .shape()
+        .broadcast_shape_binary_op(other.shape(), "cmp")?;
+    tensor
+        .broadcast_as(&shape)?
+        .cmp(&other.broadcast_as(&shape)?, op)
+}
+
+/// Compares with a scalar, in F64 if the scalar cannot be represented in the tensor data type.
+fn compare_scalar(tensor: &Tensor, value: f64, op: CmpOp) -> Result<Tensor> {
+    let exact = value.fract() == 0f64;
+    let fits = match tensor.dtype() {
+        DType::U8 => exact && (0f64..=u8::MAX as f64).contains(&value),
+        DType::U32 => exact && (0f64..=u32::MAX as f64).contains(&value),
+        DType::I64 => exact && (i64::MIN as f64..i64::MAX as f64).contains(&value),
+        _ => true,
+    };
+    let tensor = if fits {
+        tensor.clone()
+    } else {
+        tensor.to_dtype(DType::F64)?
+    };
+    let other = Tensor::new(value, tensor.device())?
+        .to_dtype(tensor.dtype())?
+        .broadcast_as(tensor.shape())?;
+    tensor.cmp(&other, op)
+}
@@ -1,9 +1,10 @@
-use crate::cast_handle;
-use crate::ndarray::{as_data_type, as_device, as_shape, return_handle};
-use candle_core::{DType, Error, Tensor};
+use crate::ndarray::pinned::tensor_from_bytes;
+use crate::ndarray::random::{normal, on_device, sample, uniform};
+use crate::ndarray::{as_data_type, as_device, as_shape, return_handle, tensor_of};
+use candle_core::{DType, Device, Error, Result, Tensor};
 use half::{bf16, f16};
 use jni::objects::{JByteBuffer, JLongArray, JObject, JString};
-use jni::sys::{jfloat, jint, jlong};
+use jni::sys::{jboolean, jfloat, jint, jlong, JNI_TRUE};
 use jni::JNIEnv;
 use std::slice;
 
@@ -25,7 +26,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_tensorOf<'local>(
         let len = env.get_direct_buffer_capacity(&buffer).unwrap();
         let data = env.get_direct_buffer_address(&buffer).unwrap();
         let data = unsafe { slice::from_raw_parts(data, len) };
-        Tensor::from_raw_buffer(data, dtype, shape.dims(), &device)
+        tensor_from_bytes(data, dtype, &shape, &device)
     };
     let ret = tensor();
     return_handle(&mut env, ret)
@@ -117,25 +118,49 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_arange<'local>(
     let tensor = || {
         let device = as_device(&mut env, device_type, device_id as usize)?;
         let dtype = as_data_type(dtype)?;
-        match dtype {
-            DType::U8 => Tensor::arange_step(start as u8, stop as u8, step as u8, &device),
-            DType::U32 => Tensor::arange_step(start as u32, stop as u32, step as u32, &device),
-            DType::I64 => Tensor::arange_step(start as i64, stop as i64, step as i64, &device),
-            DType::BF16 => Tensor::arange_step(
-                bf16::from_f32(start),
-                bf16::from_f32(stop),
-                bf16::from_f32(step),
-                &device,
-            ),
-            DType::F16 => Tensor::arange_step(
-                f16::from_f32(start),
-                f16::from_f32(stop),
-                f16::from_f32(step),
-                &device,
-            ),
-            DType::F32 => Tensor::arange_step(start as f32, stop as f32, step as f32, &device),
-            DType::F64 => Tensor::arange_step(start as f64, stop as f64, step as f64, &device),
+        if step == 0f32 {
+            return Err(Error::Msg("arange step cannot be zero".to_string()));
+        }
+        let (start, stop, step) = (start as f64, stop as f64, step as f64);
+        let num = ((stop - start) / step).ceil().max(0f64) as usize;
+        sequence(start, step, num, dtype, &device)
+    };
+    let ret = tensor();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_linspace<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    start: jfloat,
+    stop: jfloat,
+    num: jint,
+    endpoint: jboolean,
+    dtype: jint,
+    device_type: JString,
+    device_id: jint,
+) -> jlong {
+    let tensor = || {
+        let device = as_device(&mut env, device_type, device_id as usize)?;
+        let dtype = as_data_type(dtype)?;
+        if num < 0 {
+            return Err(Error::Msg(format!(
+                "linspace num must be non-negative: {num}"
+            )));
         }
+        let (start, stop, num) = (start as f64, stop as f64, num as usize);
+        let div = if endpoint == JNI_TRUE {
+            num.saturating_sub(1)
+        } else {
+            num
+        };
+        let step = if div == 0 {
+            0f64
+        } else {
+            (stop - start) / div as f64
+        };
+        sequence(start, step, num, dtype, &device)
     };
     let ret = tensor();
     return_handle(&mut env, ret)
@@ -146,7 +171,8 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_eye<'local>(
     mut env: JNIEnv,
     _: JObject,
     rows: jint,
-    _columns: jint,
+    columns: jint,
+    k: jint,
     dtype: jint,
     device_type: JString,
     device_id: jint,
@@ -154,7 +180,16 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_eye<'local>(
     let tensor = || {
         let device = as_device(&mut env, device_type, device_id as usize)?;
         let dtype = as_data_type(dtype)?;
-        Tensor::eye(rows as usize, dtype, &device)
+        if rows == columns && k == 0 {
+            return Tensor::eye(rows as usize, dtype, &device);
+        }
+        // the (i, j) element is one where j - i == k
+        let rows = Tensor::arange(k as i64, rows as i64 + k as i64, &Device::Cpu)?;
+        let columns = Tensor::arange(0i64, columns as i64, &Device::Cpu)?;
+        rows.unsqueeze(1)?
+            .broadcast_eq(&columns.unsqueeze(0)?)?
+            .to_dtype(dtype)?
+            .to_device(&device)
     };
     let ret = tensor();
     return_handle(&mut env, ret)
@@ -170,16 +205,22 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_uniform<'local>(
     dtype: jint,
     device_type: JString,
     device_id: jint,
+    generator: jlong,
 ) -> jlong {
     let tensor = || {
         let shape = as_shape(&mut env, &shape);
         let device = as_device(&mut env, device_type, device_id as usize)?;
         let dtype = as_data_type(dtype)?;
-        match dtype {
-            DType::F32 => Tensor::rand(low as f32, high as f32, &shape, &device),
-            DType::F64 => Tensor::rand(low as f64, high as f64, &shape, &device),
-            _ => Err(Error::UnsupportedDTypeForOp(dtype, "rand_uniform")),
+        let (low, high) = (low as f64, high as f64);
+        if let Some(ret) = sample(generator, &shape, dtype, &device, |rng| {
+            uniform(rng, low, high)
+        }) {
+            return ret;
         }
+        on_device(dtype, |dtype| match dtype {
+            DType::F64 => Tensor::rand(low, high, &shape, &device),
+            _ => Tensor::rand(low as f32, high as f32, &shape, &device),
+        })
     };
     let ret = tensor();
     return_handle(&mut env, ret)
@@ -195,16 +236,22 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_randomNormal<'local>(
     dtype: jint,
     device_type: JString,
     device_id: jint,
+    generator: jlong,
 ) -> jlong {
     let tensor = || {
         let shape = as_shape(&mut env, &shape);
         let device = as_device(&mut env, device_type, device_id as usize)?;
         let dtype = as_data_type(dtype)?;
-        match dtype {
-            DType::F32 => Tensor::randn(mean as f32, std as f32, &shape, &device),
-            DType::F64 => Tensor::randn(mean as f64, std as f64, &shape, &device),
-            _ => Err(Error::UnsupportedDTypeForOp(dtype, "rand_norm")),
+        let (mean, std) = (mean as f64, std as f64);
+        if let Some(ret) = sample(generator, &shape, dtype, &device, |rng| {
+            normal(rng, mean, std)
+        }) {
+            return ret;
         }
+        on_device(dtype, |dtype| match dtype {
+            DType::F64 => Tensor::randn(mean, std, &shape, &device),
+            _ => Tensor::randn(mean as f32, std as f32, &shape, &device),
+        })
     };
     let ret = tensor();
     return_handle(&mut env, ret)
@@ -216,6 +263,15 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_duplicate<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     return_handle(&mut env, tensor.copy())
 }
+
+// start + i * step for i in [0, num), computed in f64 on the host to avoid accumulating errors
+fn sequence(start: f64, step: f64, num: usize, dtype: DType, device: &Device) -> Result<Tensor> {
+    Tensor::arange(0u32, num as u32, &Device::Cpu)?
+        .to_dtype(DType::F64)?
+        .affine(step, start)?
+        .to_dtype(dtype)?
+        .to_device(device)
+}
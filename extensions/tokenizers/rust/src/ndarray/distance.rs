@@ -0,0 +1,127 @@
+use candle_core::{DType, Result, Tensor};
+use jni::objects::JObject;
+use jni::sys::{jfloat, jint, jlong};
+use jni::JNIEnv;
+
+use crate::ndarray::binary::broadcast_matmul;
+use crate::ndarray::reduce::vector_norm;
+use crate::ndarray::sort::as_axis;
+use crate::ndarray::{return_handle, tensor_of};
+
+// the same default as torch.nn.functional.cosine_similarity
+const COSINE_EPS: f64 = 1e-8;
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_cosineSimilarity<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    other_handle: jlong,
+    axis: jint,
+) -> jlong {
+    let cosine_similarity = || {
+        let tensor = &tensor_of(handle)?;
+        let other = tensor_of(other_handle)?.to_dtype(tensor.dtype())?;
+        let shape = tensor
+            .shape()
+            .broadcast_shape_binary_op(other.shape(), "cosine_similarity")?;
+        let lhs = upcast(&tensor.broadcast_as(&shape)?)?;
+        let rhs = upcast(&other.broadcast_as(&shape)?)?;
+        let axis = as_axis(&lhs, axis)?;
+        let lhs = l2_normalize(&lhs, axis)?;
+        let rhs = l2_normalize(&rhs, axis)?;
+        (lhs * rhs)?.sum(axis)?.to_dtype(output_dtype(tensor))
+    };
+    let ret = cosine_similarity();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_cosineSimilarityMatrix<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    other_handle: jlong,
+) -> jlong {
+    let cosine_similarity = || {
+        let tensor = &tensor_of(handle)?;
+        let other = tensor_of(other_handle)?.to_dtype(tensor.dtype())?;
+        let lhs = l2_normalize(&upcast(tensor)?, tensor.rank() - 1)?;
+        let rhs = l2_normalize(&upcast(&other)?, other.rank() - 1)?;
+        let rhs = if rhs.rank() == 1 {
+            rhs.unsqueeze(0)?
+        } else {
+            rhs
+        };
+        broadcast_matmul(&lhs, &rhs.t()?)?.to_dtype(output_dtype(tensor))
+    };
+    let ret = cosine_similarity();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_cdist<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    other_handle: jlong,
+    p: jfloat,
+) -> jlong {
+    let cdist = || {
+        let tensor = &tensor_of(handle)?;
+        let other = &tensor_of(other_handle)?;
+        if tensor.rank() < 2 || other.rank() < 2 {
+            let (lhs, rhs) = (tensor.dims(), other.dims());
+            candle_core::bail!("cdist expects at least 2 dims, got {lhs:?} and {rhs:?}")
+        }
+        let lhs = upcast(tensor)?;
+        let rhs = upcast(&other.to_dtype(tensor.dtype())?)?;
+        let p = p as f64;
+        let ret = if p == 2f64 {
+            // |x - y|^2 = |x|^2 + |y|^2 - 2xy avoids materializing all the (n, m, d) differences
+            let last = lhs.rank() - 1;
+            let lhs_sqr = lhs.sqr()?.sum_keepdim(last)?;
+            let rhs_sqr = rhs.sqr()?.sum_keepdim(rhs.rank() - 1)?.t()?;
+            let dot = broadcast_matmul(&lhs, &rhs.t()?)?;
+            lhs_sqr
+                .broadcast_add(&rhs_sqr)?
+                .broadcast_sub(&dot.affine(2f64, 0f64)?)?
+                .relu()?
+                .sqrt()?
+        } else {
+            let lhs = lhs.unsqueeze(lhs.rank() - 1)?;
+            let rhs = rhs.unsqueeze(rhs.rank() - 2)?;
+            let diff = lhs.broadcast_sub(&rhs)?;
+            vector_norm(&diff, p, &[diff.rank() - 1], false)?
+        };
+        ret.to_dtype(output_dtype(tensor))
+    };
+    let ret = cdist();
+    return_handle(&mut env, ret)
+}
+
+/// Divides by the L2 norm along the axis, the norm is clamped to avoid division by zero.
+fn l2_normalize(tensor: &Tensor, axis: usize) -> Result<Tensor> {
+    let norm = tensor
+        .sqr()?
+        .sum_keepdim(axis)?
+        .sqrt()?
+        .maximum(COSINE_EPS)?;
+    tensor.broadcast_div(&norm)
+}
+
+/// Distances are computed in F32 for integer and half precision inputs.
+fn upcast(tensor: &Tensor) -> Result<Tensor> {
+    match tensor.dtype() {
+        DType::F32 | DType::F64 => Ok(tensor.clone()),
+        _ => tensor.to_dtype(DType::F32),
+    }
+}
+
+// floating point results keep the input data type
+fn output_dtype(tensor: &Tensor) -> DType {
+    match tensor.dtype() {
+        DType::F16 | DType::BF16 | DType::F64 => tensor.dtype(),
+        _ => DType::F32,
+    }
+}
@@ -0,0 +1,212 @@
+use candle_core::{bail, Result, Tensor};
+use jni::objects::{JLongArray, JObject, JString, ReleaseMode};
+use jni::sys::jlong;
+use jni::JNIEnv;
+
+use crate::ndarray::{return_handle, tensor_of};
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_einsum<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    equation: JString<'local>,
+    handles: JLongArray<'local>,
+) -> jlong {
+    let equation: String = env
+        .get_string(&equation)
+        .expect("Couldn't get java string!")
+        .into();
+    let handles = unsafe { env.get_array_elements(&handles, ReleaseMode::NoCopyBack) }.unwrap();
+    let tensors = handles
+        .iter()
+        .map(|h| tensor_of(*h))
+        .collect::<Result<Vec<Tensor>>>();
+    drop(handles);
+    let ret = tensors.and_then(|tensors| einsum(&equation, tensors));
+    return_handle(&mut env, ret)
+}
+
+/// Evaluates an einsum equation such as `bqd,bkd->bqk`.
+///
+/// Operands are contracted pairwise from left to right, each contraction is lowered to a batched
+/// matmul. Ellipsis and repeated labels within a single operand are not supported.
+pub(crate) fn einsum(equation: &str, tensors: Vec<Tensor>) -> Result<Tensor> {
+    let equation = equation.replace(' ', "");
+    let (inputs, output) = match equation.split_once("->") {
+        Some((inputs, output)) => (inputs.to_string(), Some(output.to_string())),
+        None => (equation.clone(), None),
+    };
+    if equation.contains("...") {
+        bail!("einsum ellipsis is not supported: {equation}")
+    }
+    let inputs = inputs
+        .split(',')
+        .map(|s| s.chars().collect::<Vec<char>>())
+        .collect::<Vec<Vec<char>>>();
+    if inputs.len() != tensors.len() {
+        bail!(
+            "einsum equation expects {} operands, got {}",
+            inputs.len(),
+            tensors.len()
+        )
+    }
+    for (labels, tensor) in inputs.iter().zip(tensors.iter()) {
+        if labels.len() != tensor.rank() {
+            bail!(
+                "einsum operand {} does not match tensor of rank {}",
+                labels.iter().collect::<String>(),
+                tensor.rank()
+            )
+        }
+        for (i, c) in labels.iter().enumerate() {
+            if !c.is_ascii_alphabetic() || labels[i + 1..].contains(c) {
+                bail!("invalid or repeated einsum label '{c}' in {equation}")
+            }
+        }
+    }
+    let output = match output {
+        Some(output) => output.chars().collect::<Vec<char>>(),
+        None => {
+            // implicit mode: labels that appear exactly once, in alphabetical order
+            let mut labels = inputs.iter().flatten().copied().collect::<Vec<char>>();
+            labels.sort();
+            let mut output = labels.clone();
+            output.dedup();
+            output.retain(|c| labels.iter().filter(|l| *l == c).count() == 1);
+            output
+        }
+    };
+    for c in output.iter() {
+        if !inputs.iter().any(|labels| labels.contains(c)) {
+            bail!("einsum output label '{c}' does not appear in the inputs")
+        }
+    }
+
+    let mut operands = inputs.into_iter().zip(tensors.into_iter());
+    let (mut labels, mut tensor) = operands.next().unwrap();
+    let rest = operands.collect::<Vec<(Vec<char>, Tensor)>>();
+    for (i, (other_labels, other)) in rest.iter().enumerate() {
+        // labels still needed by the output or by the remaining operands
+        let keep = output
+            .iter()
+            .chain(rest[i + 1..].iter().flat_map(|(l, _)| l.iter()))
+            .copied()
+            .collect::<Vec<char>>();
+        (labels, tensor) = contract(&labels, &tensor, other_labels, other, &keep)?;
+    }
+
+    let (labels, tensor) = sum_unused(&labels, &tensor, &output)?;
+    let dims = output
+        .iter()
+        .map(|c| labels.iter().position(|l| l == c).unwrap())
+        .collect::<Vec<usize>>();
+    if dims.is_empty() {
+        Ok(tensor)
+    } else {
+        tensor.permute(dims)?.contiguous()
+    }
+}
+
+/// Sums out the dimensions whose labels are not in `keep`.
+fn sum_unused(labels: &[char], tensor: &Tensor, keep: &[char]) -> Result<(Vec<char>, Tensor)> {
+    let dims = (0..labels.len())
+        .filter(|i| !keep.contains(&labels[*i]))
+        .collect::<Vec<usize>>();
+    let labels = labels
+        .iter()
+        .filter(|c| keep.contains(c))
+        .copied()
+        .collect::<Vec<char>>();
+    if dims.is_empty() {
+        Ok((labels, tensor.clone()))
+    } else {
+        Ok((labels, tensor.sum(dims)?))
+    }
+}
+
+fn contract(
+    a_labels: &[char],
+    a: &Tensor,
+    b_labels: &[char],
+    b: &Tensor,
+    keep: &[char],
+) -> Result<(Vec<char>, Tensor)> {
+    // labels only used by one side can be reduced before the matmul
+    let a_keep = keep
+        .iter()
+        .chain(b_labels.iter())
+        .copied()
+        .collect::<Vec<char>>();
+    let (a_labels, a) = sum_unused(a_labels, a, &a_keep)?;
+    let b_keep = keep
+        .iter()
+        .chain(a_labels.iter())
+        .copied()
+        .collect::<Vec<char>>();
+    let (b_labels, b) = sum_unused(b_labels, b, &b_keep)?;
+
+    let batch = a_labels
+        .iter()
+        .filter(|c| b_labels.contains(c) && keep.contains(c))
+        .copied()
+        .collect::<Vec<char>>();
+    let summed = a_labels
+        .iter()
+        .filter(|c| b_labels.contains(c) && !keep.contains(c))
+        .copied()
+        .collect::<Vec<char>>();
+    let a_only = a_labels
+        .iter()
+        .filter(|c| !b_labels.contains(c))
+        .copied()
+        .collect::<Vec<char>>();
+    let b_only = b_labels
+        .iter()
+        .filter(|c| !a_labels.contains(c))
+        .copied()
+        .collect::<Vec<char>>();
+
+    let size_of = |labels: &[char], tensor: &Tensor, group: &[char]| -> Result<Vec<usize>> {
+        group
+            .iter()
+            .map(|c| tensor.dim(labels.iter().position(|l| l == c).unwrap()))
+            .collect()
+    };
+    let batch_dims = size_of(&a_labels, &a, &batch)?;
+    let summed_dims = size_of(&a_labels, &a, &summed)?;
+    let a_dims = size_of(&a_labels, &a, &a_only)?;
+    let b_dims = size_of(&b_labels, &b, &b_only)?;
+    for c in batch.iter().chain(summed.iter()) {
+        let a_dim = size_of(&a_labels, &a, &[*c])?[0];
+        let b_dim = size_of(&b_labels, &b, &[*c])?[0];
+        if a_dim != b_dim {
+            bail!("einsum label '{c}' has mismatched sizes {a_dim} and {b_dim}")
+        }
+    }
+
+    let b_size = batch_dims.iter().product::<usize>();
+    let m = a_dims.iter().product::<usize>();
+    let k = summed_dims.iter().product::<usize>();
+    let n = b_dims.iter().product::<usize>();
+
+    let order = |labels: &[char], groups: &[&[char]]| -> Vec<usize> {
+        groups
+            .iter()
+            .flat_map(|g| g.iter())
+            .map(|c| labels.iter().position(|l| l == c).unwrap())
+            .collect()
+    };
+    let a = a
+        .permute(order(&a_labels, &[&batch, &a_only, &summed]))?
+        .contiguous()?
+        .reshape((b_size, m, k))?;
+    let b = b
+        .permute(order(&b_labels, &[&batch, &summed, &b_only]))?
+        .contiguous()?
+        .reshape((b_size, k, n))?;
+    let result = a.matmul(&b)?;
+
+    let dims = [batch_dims, a_dims, b_dims].concat();
+    let labels = [batch, a_only, b_only].concat();
+    Ok((labels, result.reshape(dims)?))
+}
@@ -0,0 +1,243 @@
+use candle_core::npy::NpzTensors;
+use candle_core::safetensors::MmapedSafetensors;
+use candle_core::{Result, Tensor};
+use jni::objects::{JLongArray, JObject, JObjectArray, JString, ReleaseMode};
+use jni::sys::{jint, jlong, jobjectArray};
+use jni::JNIEnv;
+use std::collections::HashMap;
+
+use crate::ndarray::{as_device, return_handle, return_handles, tensor_of};
+use crate::{throw_error, to_string_array};
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_saveTensors<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    names: JObjectArray<'local>,
+    handles: JLongArray<'local>,
+    path: JString,
+) {
+    let names = as_strings(&mut env, &names);
+    let handles = unsafe { env.get_array_elements(&handles, ReleaseMode::NoCopyBack) }
+        .unwrap()
+        .iter()
+        .map(|i| *i)
+        .collect::<Vec<jlong>>();
+    let path: String = env
+        .get_string(&path)
+        .expect("Couldn't get java string!")
+        .into();
+    let save = || {
+        if names.len() != handles.len() {
+            candle_core::bail!("got {} names for {} tensors", names.len(), handles.len())
+        }
+        let tensors = names
+            .into_iter()
+            .zip(handles.iter())
+            .map(|(name, handle)| Ok((name, tensor_of(*handle)?)))
+            .collect::<Result<HashMap<String, Tensor>>>()?;
+        candle_core::safetensors::save(&tensors, path)
+    };
+    if let Err(err) = save() {
+        throw_error(&mut env, err);
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_listTensors<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    path: JString,
+) -> jobjectArray {
+    let path: String = env
+        .get_string(&path)
+        .expect("Couldn't get java string!")
+        .into();
+    let list = || {
+        let safetensors = unsafe { MmapedSafetensors::new(path)? };
+        let mut names = safetensors
+            .tensors()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<String>>();
+        names.sort();
+        Ok::<Vec<String>, candle_core::Error>(names)
+    };
+    match list() {
+        Ok(names) => to_string_array(&mut env, names).unwrap(),
+        Err(err) => {
+            throw_error(&mut env, err);
+            JObject::null().into_raw()
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_loadTensors<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    path: JString,
+    names: JObjectArray<'local>,
+    device_type: JString,
+    device_id: jint,
+) -> JLongArray<'local> {
+    let path: String = env
+        .get_string(&path)
+        .expect("Couldn't get java string!")
+        .into();
+    let names = as_strings(&mut env, &names);
+    let load = || {
+        let device = as_device(&mut env, device_type, device_id as usize)?;
+        // the file is memory mapped, only the requested tensors are copied
+        let safetensors = unsafe { MmapedSafetensors::new(path)? };
+        names
+            .iter()
+            .map(|name| safetensors.load(name, &device))
+            .collect::<Result<Vec<Tensor>>>()
+    };
+    let ret = load();
+    return_handles(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_readNpy<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    path: JString,
+    device_type: JString,
+    device_id: jint,
+) -> jlong {
+    let path: String = env
+        .get_string(&path)
+        .expect("Couldn't get java string!")
+        .into();
+    let read = || {
+        let device = as_device(&mut env, device_type, device_id as usize)?;
+        Tensor::read_npy(path)?.to_device(&device)
+    };
+    let ret = read();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_writeNpy<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+    path: JString,
+) {
+    let path: String = env
+        .get_string(&path)
+        .expect("Couldn't get java string!")
+        .into();
+    let tensor = &or_throw!(env, tensor_of(handle));
+    if let Err(err) = tensor.write_npy(path) {
+        throw_error(&mut env, err);
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_listNpz<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    path: JString,
+) -> jobjectArray {
+    let path: String = env
+        .get_string(&path)
+        .expect("Couldn't get java string!")
+        .into();
+    let list = || {
+        let npz = NpzTensors::new(path)?;
+        let mut names = npz.names().into_iter().cloned().collect::<Vec<String>>();
+        names.sort();
+        Ok::<Vec<String>, candle_core::Error>(names)
+    };
+    match list() {
+        Ok(names) => to_string_array(&mut env, names).unwrap(),
+        Err(err) => {
+            throw_error(&mut env, err);
+            JObject::null().into_raw()
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_readNpz<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    path: JString,
+    names: JObjectArray<'local>,
+    device_type: JString,
+    device_id: jint,
+) -> JLongArray<'local> {
+    let path: String = env
+        .get_string(&path)
+        .expect("Couldn't get java string!")
+        .into();
+    let names = as_strings(&mut env, &names);
+    let read = || {
+        let device = as_device(&mut env, device_type, device_id as usize)?;
+        let npz = NpzTensors::new(path)?;
+        names
+            .iter()
+            .map(|name| match npz.get(name)? {
+                Some(tensor) => tensor.to_device(&device),
+                None => candle_core::bail!("cannot find tensor {name}"),
+            })
+            .collect::<Result<Vec<Tensor>>>()
+    };
+    let ret = read();
+    return_handles(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_writeNpz<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    names: JObjectArray<'local>,
+    handles: JLongArray<'local>,
+    path: JString,
+) {
+    let names = as_strings(&mut env, &names);
+    let handles = unsafe { env.get_array_elements(&handles, ReleaseMode::NoCopyBack) }
+        .unwrap()
+        .iter()
+        .map(|i| *i)
+        .collect::<Vec<jlong>>();
+    let path: String = env
+        .get_string(&path)
+        .expect("Couldn't get java string!")
+        .into();
+    let write = || {
+        if names.len() != handles.len() {
+            candle_core::bail!("got {} names for {} tensors", names.len(), handles.len())
+        }
+        let tensors = handles
+            .iter()
+            .map(|handle| tensor_of(*handle))
+            .collect::<Result<Vec<Tensor>>>()?;
+        let tensors = names
+            .iter()
+            .zip(tensors.iter())
+            .map(|(name, tensor)| (name.as_str(), tensor))
+            .collect::<Vec<(&str, &Tensor)>>();
+        Tensor::write_npz(&tensors, path)
+    };
+    if let Err(err) = write() {
+        throw_error(&mut env, err);
+    }
+}
+
+fn as_strings(env: &mut JNIEnv, array: &JObjectArray) -> Vec<String> {
+    let len = env.get_array_length(array).unwrap();
+    let mut strings: Vec<String> = Vec::with_capacity(len as usize);
+    for i in 0..len {
+        let item = env.get_object_array_element(array, i).unwrap().into();
+        let value: String = env
+            .get_string(&item)
+            .expect("Couldn't get java string!")
+            .into();
+        strings.push(value);
+    }
+    strings
+}
@@ -1,62 +1,86 @@
-use candle_core::{DType, Device, Error, Result, Shape, Tensor, WithDType};
+#[cfg(feature = "cuda")]
+use candle_core::cuda_backend::cudarc::driver::sys;
+use candle_core::{
+    CpuStorage, DType, Device, DeviceLocation, Error, Result, Shape, Storage, Tensor, WithDType,
+};
 use half::{bf16, f16};
 use jni::objects::{JByteBuffer, JIntArray, JLongArray, JObject, JString, ReleaseMode};
 use jni::sys::{jint, jlong};
 use jni::JNIEnv;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
 
-use crate::{cast_handle, drop_handle, to_handle};
+use crate::{borrow_handle, drop_handle, throw_error, to_handle, update_handle};
 
 mod binary;
 mod cmp;
 mod creation;
+mod distance;
+mod einsum;
+mod io;
+mod nn;
 mod other;
+mod pinned;
+mod random;
 mod reduce;
+mod sort;
 mod unary;
 
-static CUDA_DEVICE: std::sync::Mutex<Option<Device>> = std::sync::Mutex::new(None);
-static METAL_DEVICE: std::sync::Mutex<Option<Device>> = std::sync::Mutex::new(None);
+/// Returns the tensor behind `handle`, it's not affected by later in-place ops on the handle.
+pub(crate) fn tensor_of(handle: jlong) -> Result<Tensor> {
+    Ok(Tensor::clone(&borrow_handle::<Tensor>(handle)?))
+}
+
+/// Replaces the tensor behind `handle`, e.g. with the result of an in-place op. Only the entry of
+/// the handle is swapped, readers keep a clone sharing the storage the old tensor leaves untouched.
+pub(crate) fn replace_tensor(handle: jlong, tensor: Tensor) -> Result<()> {
+    let old = update_handle(handle, |current: &mut Tensor| {
+        std::mem::replace(current, tensor)
+    })?;
+    drop(old);
+    Ok(())
+}
+
+// candle devices are expensive to create and each one owns its own streams, so they are cached
+// by ordinal
+static CUDA_DEVICES: Mutex<BTreeMap<usize, Device>> = Mutex::new(BTreeMap::new());
+static METAL_DEVICES: Mutex<BTreeMap<usize, Device>> = Mutex::new(BTreeMap::new());
 
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_getDataType(
-    _: JNIEnv,
+    mut env: JNIEnv,
     _: JObject,
     handle: jlong,
 ) -> jint {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     to_data_type(tensor.dtype())
 }
 
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_getDevice<'local>(
-    env: JNIEnv<'local>,
+    mut env: JNIEnv<'local>,
     _: JObject,
     handle: jlong,
 ) -> JIntArray<'local> {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let device = tensor.device();
     let array = env.new_int_array(2).unwrap();
-    let mut device_type = 0;
-    let mut device_id = -1;
-    if device.is_cpu() {
-        device_type = 0;
-    } else if device.is_cuda() {
-        device_type = 1;
-        device_id = 0;
-    } else if device.is_metal() {
-        device_type = 2;
-    }
-    let values = [device_type, device_id];
+    let values = match device.location() {
+        DeviceLocation::Cpu => [0, -1],
+        DeviceLocation::Cuda { gpu_id } => [1, gpu_id as jint],
+        DeviceLocation::Metal { gpu_id } => [2, gpu_id as jint],
+    };
     env.set_int_array_region(&array, 0, &values).unwrap();
     array
 }
 
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_getShape<'local>(
-    env: JNIEnv<'local>,
+    mut env: JNIEnv<'local>,
     _: JObject,
     handle: jlong,
 ) -> JLongArray<'local> {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let shape = tensor.shape();
     let dims = shape
         .dims()
@@ -70,25 +94,118 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_getShape<'local>(
     array
 }
 
+/// Returns a new handle sharing the storage of the tensor.
 #[no_mangle]
-pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_getByteBuffer<'local>(
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_retainTensor(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+) -> jlong {
+    let tensor = &or_throw!(env, tensor_of(handle));
+    to_handle(tensor.clone())
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_getDirectByteBuffer<'local>(
     mut env: JNIEnv<'local>,
     _: JObject,
     handle: jlong,
 ) -> JByteBuffer<'local> {
-    let tensor = cast_handle::<Tensor>(handle).flatten_all().unwrap();
-    let (ptr, len) = match tensor.dtype() {
-        DType::U8 => convert_back_::<u8>(tensor.to_vec1().unwrap()),
-        DType::U32 => convert_back_::<u32>(tensor.to_vec1().unwrap()),
-        DType::I64 => convert_back_::<i64>(tensor.to_vec1().unwrap()),
-        DType::F16 => convert_back_::<f16>(tensor.to_vec1().unwrap()),
-        DType::BF16 => convert_back_::<bf16>(tensor.to_vec1().unwrap()),
-        DType::F32 => convert_back_::<f32>(tensor.to_vec1().unwrap()),
-        DType::F64 => convert_back_::<f64>(tensor.to_vec1().unwrap()),
+    let tensor = &or_throw!(env, tensor_of(handle));
+    // the buffer shares the tensor memory, Java keeps a handle from `retainTensor` alive with it
+    match storage_bytes(tensor) {
+        Some((ptr, len)) => unsafe { env.new_direct_byte_buffer(ptr, len) }.unwrap(),
+        None => JByteBuffer::from(JObject::null()),
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_copyToBuffer<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+    buffer: JByteBuffer<'local>,
+) {
+    let copy = || {
+        let tensor = tensor_of(handle)?.flatten_all()?;
+        let len = env.get_direct_buffer_capacity(&buffer).unwrap();
+        let data = env.get_direct_buffer_address(&buffer).unwrap();
+        let dst = unsafe { std::slice::from_raw_parts_mut(data, len) };
+        if pinned::copy_to_host(&tensor, dst)? {
+            return Ok(());
+        }
+        match tensor.dtype() {
+            DType::U8 => copy_bytes(&tensor.to_vec1::<u8>()?, dst),
+            DType::U32 => copy_bytes(&tensor.to_vec1::<u32>()?, dst),
+            DType::I64 => copy_bytes(&tensor.to_vec1::<i64>()?, dst),
+            DType::F16 => copy_bytes(&tensor.to_vec1::<f16>()?, dst),
+            DType::BF16 => copy_bytes(&tensor.to_vec1::<bf16>()?, dst),
+            DType::F32 => copy_bytes(&tensor.to_vec1::<f32>()?, dst),
+            DType::F64 => copy_bytes(&tensor.to_vec1::<f64>()?, dst),
+        }
     };
+    if let Err(err) = copy() {
+        throw_error(&mut env, err);
+    }
+}
 
-    let buf = unsafe { env.new_direct_byte_buffer(ptr, len) }.unwrap();
-    buf
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_getCudaDeviceCount(
+    _: JNIEnv,
+    _: JObject,
+) -> jint {
+    cuda_device_count() as jint
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_getGpuMemory<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    device_id: jint,
+) -> JLongArray<'local> {
+    match gpu_memory(device_id as usize) {
+        Ok((free, total)) => {
+            let array = env.new_long_array(2).unwrap();
+            env.set_long_array_region(&array, 0, &[free as jlong, total as jlong])
+                .unwrap();
+            array
+        }
+        Err(err) => {
+            throw_error(&mut env, err);
+            JLongArray::from(JObject::null())
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_emptyCache<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    device_id: jint,
+) {
+    if let Err(err) = empty_cache(device_id as usize) {
+        throw_error(&mut env, err);
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_getMemoryPoolStats<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    device_id: jint,
+) -> JLongArray<'local> {
+    match memory_pool_stats(device_id as usize) {
+        Ok(stats) => {
+            let stats = stats.map(|value| value as jlong);
+            let array = env.new_long_array(stats.len() as jint).unwrap();
+            env.set_long_array_region(&array, 0, &stats).unwrap();
+            array
+        }
+        Err(err) => {
+            throw_error(&mut env, err);
+            JLongArray::from(JObject::null())
+        }
+    }
 }
 
 #[no_mangle]
@@ -101,7 +218,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_toDevice<'local>(
 ) -> jlong {
     let to_device = || {
         let device = as_device(&mut env, device_type, device_id as usize)?;
-        let tensor = cast_handle::<Tensor>(handle);
+        let tensor = &tensor_of(handle)?;
         tensor.to_device(&device)
     };
     let ret = to_device();
@@ -117,7 +234,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_toDataType<'local>(
 ) -> jlong {
     let to_data_type = || {
         let dtype = as_data_type(dtype)?;
-        let tensor = cast_handle::<Tensor>(handle);
+        let tensor = &tensor_of(handle)?;
         tensor.to_dtype(dtype)
     };
     let ret = to_data_type();
@@ -131,8 +248,8 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_toBoolean<'local>(
     handle: jlong,
 ) -> jlong {
     let to_boolean = || {
-        let tensor = cast_handle::<Tensor>(handle);
-        let tensor = tensor.to_dtype(DType::U8)?;
+        // compare in the original data type, casting first would truncate values like 0.5
+        let tensor = &tensor_of(handle)?;
         let zeros = tensor.zeros_like()?;
         tensor.ne(&zeros)
     };
@@ -147,28 +264,62 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_fullSlice<'local>(
     handle: jlong,
     min: JLongArray<'local>,
     max: JLongArray<'local>,
-    _: JLongArray<'local>,
+    step: JLongArray<'local>,
 ) -> jlong {
     let mut index = || {
-        let tensor = cast_handle::<Tensor>(handle);
-        let min = unsafe { env.get_array_elements(&min, ReleaseMode::NoCopyBack) }.unwrap();
-        let min = min.into_iter().map(|i| *i as usize).collect::<Vec<usize>>();
-        let max = unsafe { env.get_array_elements(&max, ReleaseMode::NoCopyBack) }.unwrap();
-        let max = max.into_iter().map(|i| *i as usize).collect::<Vec<usize>>();
+        let tensor = &tensor_of(handle)?;
+        let min = unsafe { env.get_array_elements(&min, ReleaseMode::NoCopyBack) }
+            .unwrap()
+            .iter()
+            .map(|i| *i)
+            .collect::<Vec<jlong>>();
+        let max = unsafe { env.get_array_elements(&max, ReleaseMode::NoCopyBack) }
+            .unwrap()
+            .iter()
+            .map(|i| *i)
+            .collect::<Vec<jlong>>();
+        let step = unsafe { env.get_array_elements(&step, ReleaseMode::NoCopyBack) }
+            .unwrap()
+            .iter()
+            .map(|i| *i)
+            .collect::<Vec<jlong>>();
         if min.len() == 0 {
-            tensor.copy()
-        } else {
-            let mut slice = tensor.narrow(0, min[0], max[0] - min[0])?;
-            for i in 1..min.len() {
-                slice = slice.narrow(i, min[i], max[i] - min[i])?;
-            }
-            Ok(slice)
+            return tensor.copy();
+        }
+        let mut slice = tensor.clone();
+        for i in 0..min.len() {
+            let step = step.get(i).copied().unwrap_or(1);
+            slice = strided_slice(&slice, i, min[i], max[i], step)?;
         }
+        Ok(slice)
     };
     let ret = index();
     return_handle(&mut env, ret)
 }
 
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_narrow<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    axis: jint,
+    start: jlong,
+    length: jlong,
+) -> jlong {
+    let op = || {
+        let tensor = &tensor_of(handle)?;
+        let dim = sort::as_axis(tensor, axis)?;
+        let size = tensor.dim(dim)? as i64;
+        let start = if start < 0 { start + size } else { start };
+        if start < 0 || length < 0 {
+            candle_core::bail!("invalid narrow, start: {start}, length: {length}, size: {size}")
+        }
+        tensor.narrow(dim, start as usize, length as usize)
+    };
+    let ret = op();
+    return_handle(&mut env, ret)
+}
+
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_gather<'local>(
     mut env: JNIEnv,
@@ -177,9 +328,31 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_gather<'local>(
     index_handle: jlong,
     axis: jint,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
-    let index_tensor = cast_handle::<Tensor>(index_handle);
-    let ret = tensor.gather(&index_tensor, axis as usize);
+    let gather = || {
+        let tensor = &tensor_of(handle)?;
+        let index_tensor = as_index(&tensor_of(index_handle)?)?;
+        let axis = sort::as_axis(tensor, axis)?;
+        tensor.contiguous()?.gather(&index_tensor, axis)
+    };
+    let ret = gather();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_indexSelect<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    axis: jint,
+    index_handle: jlong,
+) -> jlong {
+    let index_select = || {
+        let tensor = &tensor_of(handle)?;
+        let index_tensor = as_index(&tensor_of(index_handle)?)?.flatten_all()?;
+        let axis = sort::as_axis(tensor, axis)?;
+        tensor.contiguous()?.index_select(&index_tensor, axis)
+    };
+    let ret = index_select();
     return_handle(&mut env, ret)
 }
 
@@ -192,10 +365,16 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_scatter<'local>(
     value_handle: jlong,
     axis: jint,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
-    let index_tensor = cast_handle::<Tensor>(index_handle);
-    let value_tensor = cast_handle::<Tensor>(value_handle);
-    let ret = tensor.scatter_add(&index_tensor, &value_tensor, axis as usize);
+    let scatter = || {
+        let tensor = &tensor_of(handle)?;
+        let index_tensor = as_index(&tensor_of(index_handle)?)?;
+        let value_tensor = tensor_of(value_handle)?.to_dtype(tensor.dtype())?;
+        let axis = sort::as_axis(tensor, axis)?;
+        tensor
+            .contiguous()?
+            .scatter_add(&index_tensor, &value_tensor.contiguous()?, axis)
+    };
+    let ret = scatter();
     return_handle(&mut env, ret)
 }
 
@@ -206,7 +385,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_countNonzero<'local>(
     handle: jlong,
 ) -> jlong {
     let count = || {
-        let tensor = cast_handle::<Tensor>(handle).to_dtype(DType::F32)?;
+        let tensor = tensor_of(handle)?.to_dtype(DType::F32)?;
         let zeros = tensor.zeros_like()?;
         tensor.ne(&zeros)?.sum_all()?.to_dtype(DType::I64)
     };
@@ -222,7 +401,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_countNonzeroWithAxis<
     axis: jint,
 ) -> jlong {
     let count = || {
-        let tensor = cast_handle::<Tensor>(handle).to_dtype(DType::U32)?;
+        let tensor = tensor_of(handle)?.to_dtype(DType::U32)?;
         let zeros = tensor.zeros_like()?;
         tensor.ne(&zeros)?.sum(axis as usize)?.to_dtype(DType::I64)
     };
@@ -239,17 +418,61 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_deleteTensor(
     drop_handle::<Tensor>(handle);
 }
 
-fn convert_back_<T: WithDType>(mut vs: Vec<T>) -> (*mut u8, usize) {
-    let size_in_bytes = T::DTYPE.size_in_bytes();
-    let length = vs.len() * size_in_bytes;
-    let ptr = vs.as_mut_ptr() as *mut u8;
-    // Don't run the destructor for Vec<T>
-    std::mem::forget(vs);
-    // SAFETY:
-    //
-    // Every T is larger than u8, so there is no issue regarding alignment.
-    // This re-interpret the Vec<T> as a Vec<u8>.
-    (ptr, length)
+/// Returns the address and the size in bytes of a contiguous CPU tensor's memory.
+fn storage_bytes(tensor: &Tensor) -> Option<(*mut u8, usize)> {
+    let (storage, layout) = tensor.storage_and_layout();
+    let (start, end) = layout.contiguous_offsets()?;
+    let ptr = match &*storage {
+        Storage::Cpu(CpuStorage::U8(v)) => v.as_ptr() as *mut u8,
+        Storage::Cpu(CpuStorage::U32(v)) => v.as_ptr() as *mut u8,
+        Storage::Cpu(CpuStorage::I64(v)) => v.as_ptr() as *mut u8,
+        Storage::Cpu(CpuStorage::F16(v)) => v.as_ptr() as *mut u8,
+        Storage::Cpu(CpuStorage::BF16(v)) => v.as_ptr() as *mut u8,
+        Storage::Cpu(CpuStorage::F32(v)) => v.as_ptr() as *mut u8,
+        Storage::Cpu(CpuStorage::F64(v)) => v.as_ptr() as *mut u8,
+        _ => return None,
+    };
+    let size = tensor.dtype().size_in_bytes();
+    // SAFETY: the offsets are within the storage, the storage outlives the returned address
+    // as long as a tensor sharing it is alive since candle never reallocates the storage.
+    Some((unsafe { ptr.add(start * size) }, (end - start) * size))
+}
+
+fn copy_bytes<T: WithDType>(values: &[T], dst: &mut [u8]) -> Result<()> {
+    let size = std::mem::size_of_val(values);
+    if dst.len() < size {
+        candle_core::bail!("buffer of {} bytes can not hold {size} bytes", dst.len())
+    }
+    let src = unsafe { std::slice::from_raw_parts(values.as_ptr() as *const u8, size) };
+    dst[..size].copy_from_slice(src);
+    Ok(())
+}
+
+/// Selects `start..stop` with the given step along `dim`, negative bounds count from the end.
+fn strided_slice(tensor: &Tensor, dim: usize, start: i64, stop: i64, step: i64) -> Result<Tensor> {
+    if step <= 0 {
+        candle_core::bail!("slice step must be positive, got {step}")
+    }
+    let size = tensor.dim(dim)? as i64;
+    let clamp = |i: i64| if i < 0 { i + size } else { i }.clamp(0, size) as usize;
+    let (start, stop) = (clamp(start), clamp(stop));
+    let len = stop.saturating_sub(start);
+    let slice = tensor.narrow(dim, start, len)?;
+    if step == 1 || len <= 1 {
+        return Ok(slice);
+    }
+    // candle can't create strided views, the selected rows are gathered instead
+    let indices = (0..len as u32).step_by(step as usize).collect::<Vec<_>>();
+    let indices = Tensor::new(indices.as_slice(), tensor.device())?;
+    slice.index_select(&indices, dim)
+}
+
+/// Converts the index tensor to an integer type supported by the candle indexing kernels.
+fn as_index(index: &Tensor) -> Result<Tensor> {
+    match index.dtype() {
+        DType::U8 | DType::U32 | DType::I64 => index.contiguous(),
+        _ => index.to_dtype(DType::I64)?.contiguous(),
+    }
 }
 
 fn as_shape<'local>(env: &mut JNIEnv, shape: &JLongArray<'local>) -> Shape {
@@ -286,53 +509,179 @@ fn to_data_type(data_type: DType) -> i32 {
     }
 }
 
-fn as_device<'local>(env: &mut JNIEnv<'local>, device_type: JString, _: usize) -> Result<Device> {
+pub(crate) fn as_device<'local>(
+    env: &mut JNIEnv<'local>,
+    device_type: JString,
+    device_id: usize,
+) -> Result<Device> {
     let device_type: String = env
         .get_string(&device_type)
         .expect("Couldn't get java string!")
         .into();
+    get_device(&device_type, device_id)
+}
 
-    match device_type.as_str() {
+/// Returns the shared candle device, tensors can only interact with tensors of the same instance.
+pub(crate) fn get_device(device_type: &str, device_id: usize) -> Result<Device> {
+    match device_type {
         "cpu" => Ok(Device::Cpu),
-        "gpu" => {
-            let mut device = CUDA_DEVICE.lock().unwrap();
-            if let Some(device) = device.as_ref() {
-                return Ok(device.clone());
-            };
-            let d = Device::new_cuda(0).unwrap();
-            *device = Some(d.clone());
-            Ok(d)
+        "gpu" => cached_device(&CUDA_DEVICES, device_id, Device::new_cuda),
+        "mps" => cached_device(&METAL_DEVICES, device_id, Device::new_metal),
+        _ => Err(Error::Msg(format!("Invalid device type: {}", device_type))),
+    }
+}
+
+/// Returns the number of visible CUDA devices.
+pub(crate) fn cuda_device_count() -> usize {
+    #[cfg(feature = "cuda")]
+    {
+        use candle_core::cuda_backend::cudarc::driver::CudaDevice;
+        CudaDevice::count().map_or(0, |count| count as usize)
+    }
+    #[cfg(not(feature = "cuda"))]
+    {
+        0
+    }
+}
+
+/// Returns the free and total memory in bytes of the CUDA device.
+fn gpu_memory(device_id: usize) -> Result<(usize, usize)> {
+    #[cfg(feature = "cuda")]
+    {
+        use candle_core::cuda_backend::cudarc::driver::result;
+        match get_device("gpu", device_id)? {
+            Device::Cuda(device) => {
+                // the driver reports the memory of the context bound to the current thread
+                device.bind_to_thread().map_err(Error::debug)?;
+                result::mem_get_info().map_err(Error::debug)
+            }
+            _ => unreachable!(),
         }
-        "mps" => {
-            let mut device = METAL_DEVICE.lock().unwrap();
-            if let Some(device) = device.as_ref() {
-                return Ok(device.clone());
-            };
-            let d = Device::new_metal(0).unwrap();
-            *device = Some(d.clone());
-            Ok(d)
+    }
+    #[cfg(not(feature = "cuda"))]
+    {
+        Err(Error::Msg(format!(
+            "GPU {device_id} is not available, compile with the `cuda` feature"
+        )))
+    }
+}
+
+/// Releases the memory cached in the pool of the CUDA device back to the driver.
+fn empty_cache(device_id: usize) -> Result<()> {
+    #[cfg(feature = "cuda")]
+    {
+        let (device, pool) = memory_pool(device_id)?;
+        // the pool only releases memory that no pending work refers to
+        device.synchronize().map_err(Error::debug)?;
+        unsafe { sys::cuMemPoolTrimTo(pool, 0) }
+            .result()
+            .map_err(Error::debug)
+    }
+    #[cfg(not(feature = "cuda"))]
+    {
+        Err(Error::Msg(format!(
+            "GPU {device_id} is not available, compile with the `cuda` feature"
+        )))
+    }
+}
+
+/// Returns the reserved, peak reserved, used and peak used bytes of the pool of the CUDA device.
+/// Reserved memory that is not used is cached for later allocations.
+fn memory_pool_stats(device_id: usize) -> Result<[u64; 4]> {
+    #[cfg(feature = "cuda")]
+    {
+        use sys::CUmemPool_attribute::*;
+        let (_, pool) = memory_pool(device_id)?;
+        let mut stats = [0u64; 4];
+        let attributes = [
+            CU_MEMPOOL_ATTR_RESERVED_MEM_CURRENT,
+            CU_MEMPOOL_ATTR_RESERVED_MEM_HIGH,
+            CU_MEMPOOL_ATTR_USED_MEM_CURRENT,
+            CU_MEMPOOL_ATTR_USED_MEM_HIGH,
+        ];
+        for (value, attribute) in stats.iter_mut().zip(attributes) {
+            let value = value as *mut u64 as *mut std::ffi::c_void;
+            unsafe { sys::cuMemPoolGetAttribute(pool, attribute, value) }
+                .result()
+                .map_err(Error::debug)?;
         }
-        _ => Err(Error::Msg(format!("Invalid device type: {}", device_type))),
+        Ok(stats)
+    }
+    #[cfg(not(feature = "cuda"))]
+    {
+        Err(Error::Msg(format!(
+            "GPU {device_id} is not available, compile with the `cuda` feature"
+        )))
     }
 }
 
+/// Returns the default memory pool of the CUDA device, candle allocates from it when the device
+/// supports stream ordered allocations.
+#[cfg(feature = "cuda")]
+fn memory_pool(device_id: usize) -> Result<(candle_core::CudaDevice, sys::CUmemoryPool)> {
+    match get_device("gpu", device_id)? {
+        Device::Cuda(device) => {
+            device.bind_to_thread().map_err(Error::debug)?;
+            let mut pool = std::ptr::null_mut();
+            unsafe { sys::cuDeviceGetDefaultMemPool(&mut pool, *device.cu_device()) }
+                .result()
+                .map_err(Error::debug)?;
+            Ok((device, pool))
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn cached_device(
+    cache: &Mutex<BTreeMap<usize, Device>>,
+    device_id: usize,
+    new_device: fn(usize) -> Result<Device>,
+) -> Result<Device> {
+    let mut devices = cache.lock().unwrap();
+    if let Some(device) = devices.get(&device_id) {
+        return Ok(device.clone());
+    }
+    let device = new_device(device_id)?;
+    devices.insert(device_id, device.clone());
+    Ok(device)
+}
+
 fn return_handle(env: &mut JNIEnv, tensor: Result<Tensor>) -> jlong {
     match tensor {
         Ok(output) => to_handle(output),
+        Err(
+            err @ (Error::UnexpectedDType { .. }
+            | Error::DTypeMismatchBinaryOp { .. }
+            | Error::UnsupportedDTypeForOp(_, _)),
+        ) => {
+            env.throw_new("java/lang/UnsupportedOperationException", err.to_string())
+                .unwrap();
+            0
+        }
         Err(err) => {
-            let msg = format!("{err:?}");
-            match err {
-                Error::UnexpectedDType { .. }
-                | Error::DTypeMismatchBinaryOp { .. }
-                | Error::UnsupportedDTypeForOp(_, _) => {
-                    env.throw_new("java/lang/UnsupportedOperationException", msg)
-                        .unwrap();
-                }
-                _ => {
-                    env.throw_new("ai/djl/engine/EngineException", msg).unwrap();
-                }
-            }
+            throw_error(env, err);
             0
         }
     }
 }
+
+pub(crate) fn return_handles<'local>(
+    env: &mut JNIEnv<'local>,
+    tensors: Result<Vec<Tensor>>,
+) -> JLongArray<'local> {
+    match tensors {
+        Ok(tensors) => {
+            let handles = tensors
+                .into_iter()
+                .map(|t| to_handle(t))
+                .collect::<Vec<jlong>>();
+            let array = env.new_long_array(handles.len() as jint).unwrap();
+            env.set_long_array_region(&array, 0, &handles).unwrap();
+            array
+        }
+        Err(err) => {
+            return_handle(env, Err(err));
+            JLongArray::from(JObject::null())
+        }
+    }
+}
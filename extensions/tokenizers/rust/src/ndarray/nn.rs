@@ -0,0 +1,431 @@
+use candle_core::{DType, Result, Tensor};
+use jni::objects::{JLongArray, JObject, JString};
+use jni::sys::{jboolean, jfloat, jint, jlong, JNI_TRUE};
+use jni::JNIEnv;
+
+use crate::ndarray::{as_shape, return_handle, tensor_of};
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_layerNorm<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    normalized_shape: JLongArray<'local>,
+    weight_handle: jlong,
+    bias_handle: jlong,
+    eps: jfloat,
+) -> jlong {
+    let normalized_shape = as_shape(&mut env, &normalized_shape);
+    let layer_norm = || {
+        let tensor = &tensor_of(handle)?;
+        let dims = normalized_dims(tensor, normalized_shape.rank())?;
+        let x = upcast(tensor)?;
+        let mean = x.mean_keepdim(dims.as_slice())?;
+        let x = x.broadcast_sub(&mean)?;
+        let var = x.sqr()?.mean_keepdim(dims.as_slice())?;
+        let x = x.broadcast_div(&var.affine(1f64, eps as f64)?.sqrt()?)?;
+        affine(&x.to_dtype(tensor.dtype())?, weight_handle, bias_handle)
+    };
+    let ret = layer_norm();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_rmsNorm<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    normalized_shape: JLongArray<'local>,
+    weight_handle: jlong,
+    eps: jfloat,
+) -> jlong {
+    let normalized_shape = as_shape(&mut env, &normalized_shape);
+    let rms_norm = || {
+        let tensor = &tensor_of(handle)?;
+        let dims = normalized_dims(tensor, normalized_shape.rank())?;
+        let x = upcast(tensor)?;
+        let rms = x.sqr()?.mean_keepdim(dims.as_slice())?;
+        let x = x.broadcast_div(&rms.affine(1f64, eps as f64)?.sqrt()?)?;
+        affine(&x.to_dtype(tensor.dtype())?, weight_handle, 0)
+    };
+    let ret = rms_norm();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_convolution<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    weight_handle: jlong,
+    bias_handle: jlong,
+    stride: JLongArray<'local>,
+    padding: JLongArray<'local>,
+    dilation: JLongArray<'local>,
+    groups: jint,
+) -> jlong {
+    let stride = as_shape(&mut env, &stride);
+    let padding = as_shape(&mut env, &padding);
+    let dilation = as_shape(&mut env, &dilation);
+    let convolution = || {
+        let tensor = &tensor_of(handle)?;
+        let weight = tensor_of(weight_handle)?.to_dtype(tensor.dtype())?;
+        let spatial = tensor.rank().saturating_sub(2);
+        let stride = as_uniform(stride.dims(), spatial, 1, "stride")?;
+        let dilation = as_uniform(dilation.dims(), spatial, 1, "dilation")?;
+        let groups = groups.max(1) as usize;
+
+        let padding = padding.dims();
+        let (tensor, padding) = match padding.first() {
+            Some(first) if padding.iter().any(|p| p != first) => {
+                // candle only supports the same padding on every spatial dim
+                let mut padded = tensor.clone();
+                for (i, p) in padding.iter().enumerate() {
+                    padded = padded.pad_with_zeros(i + 2, *p, *p)?;
+                }
+                (padded, 0)
+            }
+            first => (tensor.clone(), first.copied().unwrap_or(0)),
+        };
+
+        let ret = match spatial {
+            1 => tensor.conv1d(&weight, padding, stride, dilation, groups)?,
+            2 => tensor.conv2d(&weight, padding, stride, dilation, groups)?,
+            _ => candle_core::bail!(
+                "only conv1d and conv2d are supported, got input rank {}",
+                tensor.rank()
+            ),
+        };
+        if bias_handle == 0 {
+            return Ok(ret);
+        }
+        let bias = tensor_of(bias_handle)?.to_dtype(ret.dtype())?;
+        let mut dims = vec![1, bias.elem_count()];
+        dims.resize(ret.rank(), 1);
+        ret.broadcast_add(&bias.reshape(dims)?)
+    };
+    let ret = convolution();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_maxPool<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    kernel_shape: JLongArray<'local>,
+    stride: JLongArray<'local>,
+    padding: JLongArray<'local>,
+    ceil_mode: jboolean,
+) -> jlong {
+    let kernel_shape = as_shape(&mut env, &kernel_shape);
+    let stride = as_shape(&mut env, &stride);
+    let padding = as_shape(&mut env, &padding);
+    let max_pool = || {
+        let tensor = &tensor_of(handle)?;
+        let pool = Pooling::new(
+            tensor,
+            kernel_shape.dims(),
+            stride.dims(),
+            padding.dims(),
+            ceil_mode == JNI_TRUE,
+        )?;
+        pool.max_pool(tensor)
+    };
+    let ret = max_pool();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_avgPool<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    kernel_shape: JLongArray<'local>,
+    stride: JLongArray<'local>,
+    padding: JLongArray<'local>,
+    ceil_mode: jboolean,
+    count_include_pad: jboolean,
+) -> jlong {
+    let kernel_shape = as_shape(&mut env, &kernel_shape);
+    let stride = as_shape(&mut env, &stride);
+    let padding = as_shape(&mut env, &padding);
+    let avg_pool = || {
+        let tensor = &tensor_of(handle)?;
+        let pool = Pooling::new(
+            tensor,
+            kernel_shape.dims(),
+            stride.dims(),
+            padding.dims(),
+            ceil_mode == JNI_TRUE,
+        )?;
+        pool.avg_pool(tensor, count_include_pad == JNI_TRUE)
+    };
+    let ret = avg_pool();
+    return_handle(&mut env, ret)
+}
+
+/// 1D and 2D pooling on top of the candle 2D pooling kernels, which have no padding support.
+///
+/// 1D inputs are pooled as `(N, C, 1, L)`, padding and the extra right padding required by the
+/// ceil mode are applied explicitly.
+struct Pooling {
+    kernel: (usize, usize),
+    stride: (usize, usize),
+    // (left, right) padding for each of the 2 spatial dims
+    padding: [(usize, usize); 2],
+    // symmetric padding requested by the user, the remaining is added by the ceil mode
+    user_padding: [usize; 2],
+    is_1d: bool,
+}
+
+impl Pooling {
+    fn new(
+        tensor: &Tensor,
+        kernel: &[usize],
+        stride: &[usize],
+        padding: &[usize],
+        ceil_mode: bool,
+    ) -> Result<Self> {
+        let spatial = kernel.len();
+        if (spatial != 1 && spatial != 2) || tensor.rank() != spatial + 2 {
+            candle_core::bail!(
+                "only 1D and 2D pooling are supported, got kernel {kernel:?} for input rank {}",
+                tensor.rank()
+            )
+        }
+        let stride = if stride.is_empty() { kernel } else { stride };
+        let padding = if padding.is_empty() {
+            vec![0; spatial]
+        } else {
+            padding.to_vec()
+        };
+        if stride.len() != spatial || padding.len() != spatial {
+            candle_core::bail!("kernel, stride and padding must have {spatial} dims")
+        }
+        let is_1d = spatial == 1;
+        let expand = |v: &[usize], default: usize| -> [usize; 2] {
+            if is_1d {
+                [default, v[0]]
+            } else {
+                [v[0], v[1]]
+            }
+        };
+        let kernel = expand(kernel, 1);
+        let stride = expand(stride, 1);
+        let user_padding = expand(&padding, 0);
+        let dims = tensor.dims();
+        let sizes = if is_1d {
+            [1, dims[2]]
+        } else {
+            [dims[2], dims[3]]
+        };
+
+        let mut padding = [(0, 0); 2];
+        for i in 0..2 {
+            let (k, s, p) = (kernel[i], stride[i], user_padding[i]);
+            let padded = sizes[i] + 2 * p;
+            if padded < k {
+                candle_core::bail!("kernel size {k} is larger than the padded input {padded}")
+            }
+            let mut right = p;
+            if ceil_mode {
+                let mut out = (padded - k + s - 1) / s + 1;
+                // the last window must start inside the input or the left padding
+                if (out - 1) * s >= sizes[i] + p {
+                    out -= 1;
+                }
+                let needed = (out - 1) * s + k;
+                if needed > padded {
+                    right += needed - padded;
+                }
+            }
+            padding[i] = (p, right);
+        }
+        Ok(Self {
+            kernel: (kernel[0], kernel[1]),
+            stride: (stride[0], stride[1]),
+            padding,
+            user_padding,
+            is_1d,
+        })
+    }
+
+    fn max_pool(&self, tensor: &Tensor) -> Result<Tensor> {
+        let x = self.pad(&self.expand(tensor)?, f64::NEG_INFINITY, f64::NEG_INFINITY)?;
+        self.squeeze(x.max_pool2d_with_stride(self.kernel, self.stride)?)
+    }
+
+    fn avg_pool(&self, tensor: &Tensor, count_include_pad: bool) -> Result<Tensor> {
+        let x = self.expand(tensor)?;
+        let padded = self.pad(&x, 0f64, 0f64)?;
+        let ret = padded.avg_pool2d_with_stride(self.kernel, self.stride)?;
+        if padded.dims() == x.dims() {
+            return self.squeeze(ret);
+        }
+        // divide by the number of counted elements in each window instead of the kernel size
+        let pad_value = if count_include_pad { 1f64 } else { 0f64 };
+        let mask = self.pad(&x.ones_like()?, pad_value, 0f64)?;
+        let count = mask.avg_pool2d_with_stride(self.kernel, self.stride)?;
+        self.squeeze(ret.div(&count)?)
+    }
+
+    fn expand(&self, tensor: &Tensor) -> Result<Tensor> {
+        if self.is_1d {
+            tensor.unsqueeze(2)
+        } else {
+            Ok(tensor.clone())
+        }
+    }
+
+    fn squeeze(&self, tensor: Tensor) -> Result<Tensor> {
+        if self.is_1d {
+            tensor.squeeze(2)
+        } else {
+            Ok(tensor)
+        }
+    }
+
+    /// Pads the user padding with `value` and the extra ceil mode padding with `ceil_value`.
+    fn pad(&self, tensor: &Tensor, value: f64, ceil_value: f64) -> Result<Tensor> {
+        let mut tensor = tensor.clone();
+        for (i, (left, right)) in self.padding.iter().enumerate() {
+            let dim = i + 2;
+            let p = self.user_padding[i];
+            tensor = pad_with_value(&tensor, dim, *left, p, value)?;
+            tensor = pad_with_value(&tensor, dim, 0, right - p, ceil_value)?;
+        }
+        Ok(tensor)
+    }
+}
+
+fn pad_with_reflection(tensor: &Tensor, dim: usize, left: usize, right: usize) -> Result<Tensor> {
+    if left == 0 && right == 0 {
+        return Ok(tensor.clone());
+    }
+    let size = tensor.dim(dim)?;
+    if left >= size || right >= size {
+        candle_core::bail!(
+            "reflect padding ({left}, {right}) must be smaller than the dim size {size}"
+        )
+    }
+    let indices = (1..=left)
+        .rev()
+        .chain(0..size)
+        .chain((size - 1 - right..size - 1).rev())
+        .map(|i| i as u32)
+        .collect::<Vec<u32>>();
+    let indices = Tensor::new(indices, tensor.device())?;
+    tensor.index_select(&indices, dim)
+}
+
+fn pad_with_value(
+    tensor: &Tensor,
+    dim: usize,
+    left: usize,
+    right: usize,
+    value: f64,
+) -> Result<Tensor> {
+    if left == 0 && right == 0 {
+        return Ok(tensor.clone());
+    }
+    let mut parts = Vec::with_capacity(3);
+    let mut dims = tensor.dims().to_vec();
+    if left > 0 {
+        dims[dim] = left;
+        parts
+            .push(Tensor::full(value, dims.as_slice(), tensor.device())?.to_dtype(tensor.dtype())?);
+    }
+    parts.push(tensor.clone());
+    if right > 0 {
+        dims[dim] = right;
+        parts
+            .push(Tensor::full(value, dims.as_slice(), tensor.device())?.to_dtype(tensor.dtype())?);
+    }
+    Tensor::cat(&parts, dim)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_pad<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    padding: JLongArray<'local>,
+    mode: JString,
+    value: jfloat,
+) -> jlong {
+    let padding = as_shape(&mut env, &padding);
+    let mode: String = env
+        .get_string(&mode)
+        .expect("Couldn't get java string!")
+        .into();
+    let pad = || {
+        let tensor = &tensor_of(handle)?;
+        let padding = padding.dims();
+        if padding.len() % 2 != 0 || padding.len() / 2 > tensor.rank() {
+            candle_core::bail!(
+                "invalid padding {padding:?} for tensor of rank {}",
+                tensor.rank()
+            )
+        }
+        // same as torch.nn.functional.pad, the pairs start from the last dim
+        let mut ret = tensor.clone();
+        for (i, pair) in padding.chunks(2).enumerate() {
+            let dim = tensor.rank() - 1 - i;
+            let (left, right) = (pair[0], pair[1]);
+            ret = match mode.as_str() {
+                "constant" => pad_with_value(&ret, dim, left, right, value as f64)?,
+                "replicate" => ret.pad_with_same(dim, left, right)?,
+                "reflect" => pad_with_reflection(&ret, dim, left, right)?,
+                _ => candle_core::bail!("unsupported padding mode: {mode}"),
+            };
+        }
+        Ok(ret)
+    };
+    let ret = pad();
+    return_handle(&mut env, ret)
+}
+
+/// Returns the trailing dimensions covered by a normalized shape of the given rank.
+fn normalized_dims(tensor: &Tensor, rank: usize) -> Result<Vec<usize>> {
+    let dims = tensor.rank();
+    if rank == 0 || rank > dims {
+        candle_core::bail!("invalid normalized shape of rank {rank} for tensor of rank {dims}")
+    }
+    Ok((dims - rank..dims).collect())
+}
+
+/// Half precision inputs are normalized in F32 to avoid overflow in the variance.
+fn upcast(tensor: &Tensor) -> Result<Tensor> {
+    match tensor.dtype() {
+        DType::F16 | DType::BF16 => tensor.to_dtype(DType::F32),
+        _ => Ok(tensor.clone()),
+    }
+}
+
+/// Applies the optional element-wise weight and bias, a zero handle means absent.
+fn affine(tensor: &Tensor, weight_handle: jlong, bias_handle: jlong) -> Result<Tensor> {
+    let mut tensor = tensor.clone();
+    if weight_handle != 0 {
+        let weight = tensor_of(weight_handle)?.to_dtype(tensor.dtype())?;
+        tensor = tensor.broadcast_mul(&weight)?;
+    }
+    if bias_handle != 0 {
+        let bias = tensor_of(bias_handle)?.to_dtype(tensor.dtype())?;
+        tensor = tensor.broadcast_add(&bias)?;
+    }
+    Ok(tensor)
+}
+
+/// Returns the value shared by every spatial dim, candle doesn't support per-dim values.
+fn as_uniform(values: &[usize], rank: usize, default: usize, name: &str) -> Result<usize> {
+    match values.first() {
+        None => Ok(default),
+        Some(first) => {
+            if values.iter().any(|v| v != first) || (values.len() != 1 && values.len() != rank) {
+                candle_core::bail!("{name} must be the same on every spatial dim: {values:?}")
+            }
+            Ok(*first)
+        }
+    }
+}
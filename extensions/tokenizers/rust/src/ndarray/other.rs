@@ -1,10 +1,10 @@
-use candle_core::{Tensor, D};
+use candle_core::{DType, Device, Result, Tensor};
 use jni::objects::{JIntArray, JLongArray, JObject, ReleaseMode};
-use jni::sys::{jdouble, jint, jlong, jsize};
+use jni::sys::{jdouble, jfloat, jint, jlong};
 use jni::JNIEnv;
 
-use crate::ndarray::{as_shape, return_handle};
-use crate::{cast_handle, to_handle};
+use crate::ndarray::sort::as_axis;
+use crate::ndarray::{as_data_type, as_shape, return_handle, return_handles, tensor_of};
 
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_flatten<'local>(
@@ -12,7 +12,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_flatten<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = tensor.flatten_all();
     return_handle(&mut env, ret)
 }
@@ -25,7 +25,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_flattenWithDims<'loca
     start_dim: jint,
     end_dim: jint,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = tensor.flatten(start_dim as usize, end_dim as usize);
     return_handle(&mut env, ret)
 }
@@ -37,7 +37,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_reshape<'local>(
     handle: jlong,
     shape: JLongArray<'local>,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let shape = unsafe { env.get_array_elements(&shape, ReleaseMode::NoCopyBack) }.unwrap();
     let dims = shape
         .into_iter()
@@ -54,28 +54,32 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_squeeze<'local>(
     handle: jlong,
     dims: JIntArray<'local>,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
-    let shape = tensor.shape();
-    let mut squeeze = || {
-        let mut shape = Vec::from(shape.dims());
-        let dims = unsafe { env.get_array_elements(&dims, ReleaseMode::NoCopyBack) }.unwrap();
-        for i in dims.iter().rev() {
-            let mut pos = *i as i32;
-            if pos < 0 {
-                pos = shape.len() as i32 + pos;
-            }
-            if shape[pos as usize] == 1 {
-                shape.remove(pos as usize);
+    let dims = unsafe { env.get_array_elements(&dims, ReleaseMode::NoCopyBack) }
+        .unwrap()
+        .iter()
+        .map(|i| *i)
+        .collect::<Vec<i32>>();
+    let squeeze = || {
+        let tensor = &tensor_of(handle)?;
+        if tensor.rank() == 0 {
+            return tensor.copy();
+        }
+        let mut dims = dims
+            .iter()
+            .map(|i| as_axis(tensor, *i))
+            .collect::<Result<Vec<usize>>>()?;
+        dims.sort();
+        dims.dedup();
+        let mut shape = Vec::from(tensor.dims());
+        for dim in dims.iter().rev() {
+            if shape[*dim] == 1 {
+                shape.remove(*dim);
             }
         }
         tensor.reshape(shape)
     };
-    if shape.rank() == 0 {
-        return_handle(&mut env, tensor.copy())
-    } else {
-        let ret = squeeze();
-        return_handle(&mut env, ret)
-    }
+    let ret = squeeze();
+    return_handle(&mut env, ret)
 }
 
 #[no_mangle]
@@ -85,12 +89,17 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_expandDims<'local>(
     handle: jlong,
     axis: jint,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
-    let ret = if axis == -1 {
-        tensor.unsqueeze(D::Minus1)
-    } else {
-        tensor.unsqueeze(axis as usize)
+    let unsqueeze = || {
+        let tensor = &tensor_of(handle)?;
+        // the new axis can be inserted after the last dim
+        let rank = tensor.rank() as i32 + 1;
+        let dim = if axis < 0 { rank + axis } else { axis };
+        if dim < 0 || dim >= rank {
+            candle_core::bail!("axis {axis} is out of bounds for expandDims of rank {rank}")
+        }
+        tensor.unsqueeze(dim as usize)
     };
+    let ret = unsqueeze();
     return_handle(&mut env, ret)
 }
 
@@ -101,12 +110,22 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_stack<'local>(
     handles: JLongArray<'local>,
     axis: jint,
 ) -> jlong {
-    let handles = unsafe { env.get_array_elements(&handles, ReleaseMode::NoCopyBack) }.unwrap();
-    let tensors = handles
-        .into_iter()
-        .map(|h| cast_handle::<Tensor>(*h))
-        .collect::<Vec<&mut Tensor>>();
-    let ret = Tensor::stack(&tensors, axis as usize);
+    let handles = unsafe { env.get_array_elements(&handles, ReleaseMode::NoCopyBack) }
+        .unwrap()
+        .iter()
+        .map(|h| *h)
+        .collect::<Vec<jlong>>();
+    let op = || {
+        let tensors = as_tensors(&handles)?;
+        // the new axis can be inserted after the last dim
+        let rank = tensors[0].rank() as i32 + 1;
+        let dim = if axis < 0 { rank + axis } else { axis };
+        if dim < 0 || dim >= rank {
+            candle_core::bail!("axis {axis} is out of bounds for stack of rank {rank}")
+        }
+        Tensor::stack(&tensors, dim as usize)
+    };
+    let ret = op();
     return_handle(&mut env, ret)
 }
 
@@ -117,12 +136,17 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_concat<'local>(
     handles: JLongArray<'local>,
     axis: jint,
 ) -> jlong {
-    let handles = unsafe { env.get_array_elements(&handles, ReleaseMode::NoCopyBack) }.unwrap();
-    let tensors = handles
-        .into_iter()
-        .map(|h| cast_handle::<Tensor>(*h))
-        .collect::<Vec<&mut Tensor>>();
-    let ret = Tensor::cat(&tensors, axis as usize);
+    let handles = unsafe { env.get_array_elements(&handles, ReleaseMode::NoCopyBack) }
+        .unwrap()
+        .iter()
+        .map(|h| *h)
+        .collect::<Vec<jlong>>();
+    let op = || {
+        let tensors = as_tensors(&handles)?;
+        let axis = as_axis(&tensors[0], axis)?;
+        Tensor::cat(&tensors, axis)
+    };
+    let ret = op();
     return_handle(&mut env, ret)
 }
 
@@ -134,22 +158,42 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_split<'local>(
     indices: JLongArray<'local>,
     axis: jint,
 ) -> JLongArray<'local> {
-    let tensor = cast_handle::<Tensor>(handle);
-    let indices = unsafe { env.get_array_elements(&indices, ReleaseMode::NoCopyBack) }.unwrap();
-    let mut array: Vec<jlong> = Vec::new();
-    let mut prev = 0;
-    for i in indices.into_iter() {
-        let len = *i as usize - prev;
-        if len > 0 {
-            let slice = tensor.narrow(axis as usize, prev, len).unwrap();
-            array.push(to_handle(slice));
+    let indices = as_shape(&mut env, &indices);
+    let split = || {
+        let tensor = &tensor_of(handle)?;
+        let axis = as_axis(tensor, axis)?;
+        let mut slices = Vec::new();
+        let mut prev = 0;
+        for i in indices.dims() {
+            if *i > prev {
+                slices.push(tensor.narrow(axis, prev, *i - prev)?);
+            }
+            prev = *i;
         }
-        prev = *i as usize;
-    }
+        Ok(slices)
+    };
+    let ret = split();
+    return_handles(&mut env, ret)
+}
 
-    let ret = env.new_long_array(array.len() as jsize).unwrap();
-    env.set_long_array_region(&ret, 0, &array).unwrap();
-    ret
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_chunk<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+    chunks: jint,
+    axis: jint,
+) -> JLongArray<'local> {
+    let chunk = || {
+        let tensor = &tensor_of(handle)?;
+        let axis = as_axis(tensor, axis)?;
+        if chunks <= 0 {
+            candle_core::bail!("chunks must be positive, got {chunks}")
+        }
+        tensor.chunk(chunks as usize, axis)
+    };
+    let ret = chunk();
+    return_handles(&mut env, ret)
 }
 
 #[no_mangle]
@@ -159,8 +203,60 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_cumSum<'local>(
     handle: jlong,
     axis: jint,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
-    let ret = tensor.cumsum(axis as usize);
+    let cumsum = || {
+        let tensor = &tensor_of(handle)?;
+        let axis = as_axis(tensor, axis)?;
+        if tensor.dtype().is_int() {
+            // the cumsum is implemented with a matmul, which doesn't support integers. Metal has no
+            // F64, the sums are exact in F32 up to 2^24
+            let dtype = if tensor.device().is_metal() {
+                DType::F32
+            } else {
+                DType::F64
+            };
+            tensor
+                .to_dtype(dtype)?
+                .cumsum(axis)?
+                .to_dtype(tensor.dtype())
+        } else {
+            tensor.cumsum(axis)
+        }
+    };
+    let ret = cumsum();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_cumProd<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    axis: jint,
+) -> jlong {
+    let cumprod = || {
+        let tensor = &tensor_of(handle)?;
+        let axis = as_axis(tensor, axis)?;
+        cum_prod(tensor, axis)
+    };
+    let ret = cumprod();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_cumProdWithType<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    axis: jint,
+    dtype: jint,
+) -> jlong {
+    let cumprod = || {
+        let dtype = as_data_type(dtype)?;
+        let tensor = tensor_of(handle)?.to_dtype(dtype)?;
+        let axis = as_axis(&tensor, axis)?;
+        cum_prod(&tensor, axis)
+    };
+    let ret = cumprod();
     return_handle(&mut env, ret)
 }
 
@@ -172,8 +268,19 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_clip<'local>(
     min: jdouble,
     max: jdouble,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
-    let ret = tensor.clamp(min as f64, max as f64);
+    let tensor = &or_throw!(env, tensor_of(handle));
+    let clamp = || {
+        // an infinite bound is absent, which also keeps integer tensors away from overflow
+        let mut ret = tensor.clone();
+        if min > f64::NEG_INFINITY {
+            ret = ret.maximum(min)?;
+        }
+        if max < f64::INFINITY {
+            ret = ret.minimum(max)?;
+        }
+        Ok(ret)
+    };
+    let ret = clamp();
     return_handle(&mut env, ret)
 }
 
@@ -185,7 +292,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_transpose<'local>(
     dim1: jint,
     dim2: jint,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = tensor.transpose(dim1 as usize, dim2 as usize);
     return_handle(&mut env, ret)
 }
@@ -197,7 +304,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_permute<'local>(
     handle: jlong,
     axes: JIntArray<'local>,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let axes = unsafe { env.get_array_elements(&axes, ReleaseMode::NoCopyBack) }.unwrap();
     let dims = axes
         .into_iter()
@@ -208,6 +315,99 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_permute<'local>(
     return_handle(&mut env, ret)
 }
 
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_flip<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    axes: JIntArray<'local>,
+) -> jlong {
+    let axes = unsafe { env.get_array_elements(&axes, ReleaseMode::NoCopyBack) }
+        .unwrap()
+        .iter()
+        .map(|i| *i)
+        .collect::<Vec<jint>>();
+    let flip = || {
+        let tensor = &tensor_of(handle)?;
+        let mut ret = tensor.clone();
+        for axis in axes {
+            let axis = as_axis(tensor, axis)?;
+            let size = tensor.dim(axis)?;
+            let indices = (0..size as i64).rev().collect::<Vec<i64>>();
+            let indices = Tensor::from_vec(indices, size, tensor.device())?;
+            ret = ret.index_select(&indices, axis)?;
+        }
+        Ok(ret)
+    };
+    let ret = flip();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_roll<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    shifts: JLongArray<'local>,
+    axes: JIntArray<'local>,
+) -> jlong {
+    let shifts = unsafe { env.get_array_elements(&shifts, ReleaseMode::NoCopyBack) }
+        .unwrap()
+        .iter()
+        .map(|i| *i)
+        .collect::<Vec<jlong>>();
+    let axes = unsafe { env.get_array_elements(&axes, ReleaseMode::NoCopyBack) }
+        .unwrap()
+        .iter()
+        .map(|i| *i)
+        .collect::<Vec<jint>>();
+    let roll = || {
+        let tensor = &tensor_of(handle)?;
+        if axes.is_empty() {
+            // like torch.roll, the tensor is flattened if no axis is given
+            if shifts.len() != 1 {
+                candle_core::bail!("expected a single shift without axes, got {shifts:?}")
+            }
+            let flat = roll_axis(&tensor.flatten_all()?, shifts[0], 0)?;
+            return flat.reshape(tensor.shape());
+        }
+        if shifts.len() != axes.len() {
+            candle_core::bail!("shifts {shifts:?} and axes {axes:?} must have the same length")
+        }
+        let mut ret = tensor.clone();
+        for (shift, axis) in shifts.iter().zip(axes.iter()) {
+            ret = roll_axis(&ret, *shift, as_axis(tensor, *axis)?)?;
+        }
+        Ok(ret)
+    };
+    let ret = roll();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_tril<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    k: jint,
+) -> jlong {
+    let tensor = &or_throw!(env, tensor_of(handle));
+    let ret = triangle(tensor, k as i64, true);
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_triu<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    k: jint,
+) -> jlong {
+    let tensor = &or_throw!(env, tensor_of(handle));
+    let ret = triangle(tensor, k as i64, false);
+    return_handle(&mut env, ret)
+}
+
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_broadcast<'local>(
     mut env: JNIEnv,
@@ -215,44 +415,415 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_broadcast<'local>(
     handle: jlong,
     shape: JLongArray<'local>,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
-    let shape = as_shape(&mut env, &shape);
-    let ret = tensor.broadcast_as(shape);
+    let shape = unsafe { env.get_array_elements(&shape, ReleaseMode::NoCopyBack) }
+        .unwrap()
+        .iter()
+        .map(|i| *i)
+        .collect::<Vec<jlong>>();
+    let broadcast = || {
+        let tensor = &tensor_of(handle)?;
+        let dims = tensor.dims();
+        if shape.len() < dims.len() {
+            candle_core::bail!("cannot broadcast {dims:?} to {shape:?}")
+        }
+        // like torch.expand, -1 keeps the size of the matching (right aligned) dim
+        let offset = shape.len() - dims.len();
+        let shape = shape
+            .iter()
+            .enumerate()
+            .map(|(i, size)| {
+                if *size == -1 && i >= offset {
+                    dims[i - offset]
+                } else {
+                    *size as usize
+                }
+            })
+            .collect::<Vec<usize>>();
+        tensor.broadcast_as(shape)
+    };
+    let ret = broadcast();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_nonZero<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+) -> jlong {
+    let non_zero = || {
+        let tensor = &tensor_of(handle)?;
+        let dims = tensor.dims().to_vec();
+        // the output size depends on the data, only the mask is copied to the host
+        let mask = tensor
+            .ne(&tensor.zeros_like()?)?
+            .flatten_all()?
+            .to_vec1::<u8>()?;
+        let mut indices: Vec<i64> = Vec::new();
+        let mut rows = 0usize;
+        for (i, _) in mask.iter().enumerate().filter(|(_, m)| **m != 0) {
+            rows += 1;
+            let start = indices.len();
+            let mut rem = i;
+            for dim in dims.iter().rev() {
+                indices.push((rem % dim) as i64);
+                rem /= dim;
+            }
+            indices[start..].reverse();
+        }
+        Tensor::from_vec(indices, (rows, dims.len()), tensor.device())
+    };
+    let ret = non_zero();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_oneHot<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    depth: jint,
+    on_value: jfloat,
+    off_value: jfloat,
+    dtype: jint,
+) -> jlong {
+    let one_hot = || {
+        let tensor = &tensor_of(handle)?;
+        let dtype = as_data_type(dtype)?;
+        if depth < 0 {
+            candle_core::bail!("depth must be non-negative: {depth}")
+        }
+        // out of range indices are all off, the same as MXNet
+        let classes = Tensor::arange(0i64, depth as i64, tensor.device())?;
+        let (on_value, off_value) = (on_value as f64, off_value as f64);
+        tensor
+            .to_dtype(DType::I64)?
+            .unsqueeze(tensor.rank())?
+            .broadcast_eq(&classes)?
+            .to_dtype(DType::F32)?
+            .affine(on_value - off_value, off_value)?
+            .to_dtype(dtype)
+    };
+    let ret = one_hot();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_where<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    condition_handle: jlong,
+    handle: jlong,
+    other_handle: jlong,
+) -> jlong {
+    let op = || {
+        let condition = &tensor_of(condition_handle)?;
+        let tensor = &tensor_of(handle)?;
+        let other = tensor_of(other_handle)?.to_dtype(tensor.dtype())?;
+        where_cond(condition, tensor, &other)
+    };
+    let ret = op();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_maskedFill<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    mask_handle: jlong,
+    value: jfloat,
+) -> jlong {
+    let op = || {
+        let tensor = &tensor_of(handle)?;
+        let mask = &tensor_of(mask_handle)?;
+        let value = Tensor::new(value, tensor.device())?.to_dtype(tensor.dtype())?;
+        where_cond(mask, &value, tensor)
+    };
+    let ret = op();
     return_handle(&mut env, ret)
 }
 
 #[no_mangle]
-pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_avgPool2d<'local>(
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_booleanMask<'local>(
     mut env: JNIEnv,
     _: JObject,
     handle: jlong,
-    kernel_size: JLongArray<'local>,
-    stride: JLongArray<'local>,
+    mask_handle: jlong,
+    axis: jint,
 ) -> jlong {
-    let mut op = || {
-        let tensor = cast_handle::<Tensor>(handle);
-        let kernel_size = as_shape(&mut env, &kernel_size).dims2()?;
-        let stride = as_shape(&mut env, &stride).dims2()?;
-        tensor.avg_pool2d_with_stride(kernel_size, stride)
+    let op = || {
+        let tensor = &tensor_of(handle)?;
+        let mask = &tensor_of(mask_handle)?;
+        let axis = as_axis(tensor, axis)?;
+        let dims = tensor.dims();
+        let end = axis + mask.rank();
+        if end > dims.len() || &dims[axis..end] != mask.dims() {
+            candle_core::bail!(
+                "mask of shape {:?} does not match {dims:?} at axis {axis}",
+                mask.dims()
+            )
+        }
+        // the masked dims are merged into one and the selected rows are kept
+        let mut shape = dims[..axis].to_vec();
+        shape.push(mask.elem_count());
+        shape.extend_from_slice(&dims[end..]);
+        let indices = mask_indices(mask)?;
+        tensor.reshape(shape)?.index_select(&indices, axis)
     };
     let ret = op();
     return_handle(&mut env, ret)
 }
 
 #[no_mangle]
-pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_maxPool2d<'local>(
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_maskedSelect<'local>(
     mut env: JNIEnv,
     _: JObject,
     handle: jlong,
-    kernel_size: JLongArray<'local>,
-    stride: JLongArray<'local>,
+    mask_handle: jlong,
 ) -> jlong {
-    let mut op = || {
-        let tensor = cast_handle::<Tensor>(handle);
-        let kernel_size = as_shape(&mut env, &kernel_size).dims2()?;
-        let stride = as_shape(&mut env, &stride).dims2()?;
-        tensor.max_pool2d_with_stride(kernel_size, stride)
+    let op = || {
+        let tensor = &tensor_of(handle)?;
+        let mask = &tensor_of(mask_handle)?;
+        let shape = tensor
+            .shape()
+            .broadcast_shape_binary_op(mask.shape(), "masked_select")?;
+        let indices = mask_indices(&mask.broadcast_as(&shape)?)?;
+        tensor
+            .broadcast_as(&shape)?
+            .flatten_all()?
+            .index_select(&indices, 0)
     };
     let ret = op();
     return_handle(&mut env, ret)
 }
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_maskedScatter<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    mask_handle: jlong,
+    source_handle: jlong,
+) -> jlong {
+    let op = || {
+        let tensor = &tensor_of(handle)?;
+        let mask = tensor_of(mask_handle)?.broadcast_as(tensor.shape())?;
+        let source = &tensor_of(source_handle)?;
+        let indices = mask_indices(&mask)?;
+        let count = indices.elem_count();
+        if source.elem_count() < count {
+            candle_core::bail!(
+                "source has {} elements, but the mask selects {count}",
+                source.elem_count()
+            )
+        }
+        // the source elements are written in order to the masked positions
+        let source = source
+            .flatten_all()?
+            .narrow(0, 0, count)?
+            .to_dtype(tensor.dtype())?;
+        where_cond(&mask, &tensor.zeros_like()?, tensor)?
+            .flatten_all()?
+            .index_add(&indices, &source, 0)?
+            .reshape(tensor.shape())
+    };
+    let ret = op();
+    return_handle(&mut env, ret)
+}
+
+/// Selects from `on_true` where the condition is non-zero, otherwise from `on_false`, all three
+/// tensors are broadcast to a common shape.
+fn where_cond(condition: &Tensor, on_true: &Tensor, on_false: &Tensor) -> Result<Tensor> {
+    let shape = condition
+        .shape()
+        .broadcast_shape_binary_op(on_true.shape(), "where")?
+        .broadcast_shape_binary_op(on_false.shape(), "where")?;
+    let condition = if condition.dtype() == DType::U8 {
+        condition.clone()
+    } else {
+        condition.ne(&condition.zeros_like()?)?
+    };
+    condition.broadcast_as(&shape)?.where_cond(
+        &on_true.broadcast_as(&shape)?,
+        &on_false.broadcast_as(&shape)?,
+    )
+}
+
+/// Computes the cumulative product along the axis on the host, candle has no kernel for it.
+fn cum_prod(tensor: &Tensor, axis: usize) -> Result<Tensor> {
+    let last = tensor.rank() - 1;
+    let transposed = tensor.transpose(axis, last)?.contiguous()?;
+    let dims = transposed.dims().to_vec();
+    // the products are computed on the host in F64, which Metal doesn't support
+    let mut values = transposed
+        .flatten_all()?
+        .to_device(&Device::Cpu)?
+        .to_dtype(DType::F64)?
+        .to_vec1::<f64>()?;
+    for row in values.chunks_mut(dims[last].max(1)) {
+        for i in 1..row.len() {
+            row[i] *= row[i - 1];
+        }
+    }
+    Tensor::from_vec(values, dims, &Device::Cpu)?
+        .to_dtype(tensor.dtype())?
+        .to_device(tensor.device())?
+        .transpose(axis, last)?
+        .contiguous()
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_tile<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    repeats: JLongArray<'local>,
+) -> jlong {
+    let repeats = as_shape(&mut env, &repeats);
+    let tile = || {
+        let tensor = &tensor_of(handle)?;
+        let repeats = repeats.dims();
+        // like numpy, missing leading repeats are 1
+        let mut dims = vec![1; tensor.rank().saturating_sub(repeats.len())];
+        dims.extend_from_slice(repeats);
+        tensor.repeat(dims)
+    };
+    let ret = tile();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_tileWithAxis<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    axis: jint,
+    repeat: jlong,
+) -> jlong {
+    let tile = || {
+        let tensor = &tensor_of(handle)?;
+        let axis = as_axis(tensor, axis)?;
+        let mut dims = vec![1; tensor.rank()];
+        dims[axis] = repeat as usize;
+        tensor.repeat(dims)
+    };
+    let ret = tile();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_tileWithShape<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    shape: JLongArray<'local>,
+) -> jlong {
+    let shape = as_shape(&mut env, &shape);
+    let tile = || {
+        let tensor = &tensor_of(handle)?;
+        let shape = shape.dims();
+        let dims = tensor.dims();
+        if shape.len() > dims.len() {
+            candle_core::bail!("the desired shape {shape:?} has too many dimensions")
+        }
+        let offset = dims.len() - shape.len();
+        let mut repeats = vec![1; dims.len()];
+        for (i, size) in shape.iter().enumerate() {
+            let dim = dims[offset + i];
+            if dim == 0 || size % dim != 0 {
+                candle_core::bail!("the desired shape {shape:?} is not a multiple of {dims:?}")
+            }
+            repeats[offset + i] = size / dim;
+        }
+        tensor.repeat(repeats)
+    };
+    let ret = tile();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_repeat<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    repeat: jlong,
+    axis: jint,
+) -> jlong {
+    let repeat_interleave = || {
+        let tensor = &tensor_of(handle)?;
+        let axis = as_axis(tensor, axis)?;
+        let mut dims = tensor.dims().to_vec();
+        let mut expanded = dims.clone();
+        expanded.insert(axis + 1, repeat as usize);
+        dims[axis] *= repeat as usize;
+        tensor
+            .unsqueeze(axis + 1)?
+            .broadcast_as(expanded)?
+            .contiguous()?
+            .reshape(dims)
+    };
+    let ret = repeat_interleave();
+    return_handle(&mut env, ret)
+}
+
+/// Returns the tensors of the handles, converted to the data type of the first one.
+fn as_tensors(handles: &[jlong]) -> Result<Vec<Tensor>> {
+    if handles.is_empty() {
+        candle_core::bail!("at least one tensor is required")
+    }
+    let dtype = tensor_of(handles[0])?.dtype();
+    handles
+        .iter()
+        .map(|h| tensor_of(*h)?.to_dtype(dtype))
+        .collect()
+}
+
+/// Returns the I64 flat indices of the non-zero elements of the mask.
+fn mask_indices(mask: &Tensor) -> Result<Tensor> {
+    let values = mask
+        .ne(&mask.zeros_like()?)?
+        .flatten_all()?
+        .to_vec1::<u8>()?;
+    let indices = values
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| **v != 0)
+        .map(|(i, _)| i as i64)
+        .collect::<Vec<i64>>();
+    let count = indices.len();
+    Tensor::from_vec(indices, count, mask.device())
+}
+
+/// Zeros the elements above (lower) or below (upper) the k-th diagonal of the last two dims.
+fn triangle(tensor: &Tensor, k: i64, lower: bool) -> Result<Tensor> {
+    let dims = tensor.dims();
+    if dims.len() < 2 {
+        candle_core::bail!("expected at least 2 dims, got {dims:?}")
+    }
+    let (rows, columns) = (dims[dims.len() - 2], dims[dims.len() - 1]);
+    let device = tensor.device();
+    // the (i, j) element is on the k-th diagonal if j - i == k
+    let rows = Tensor::arange(k, rows as i64 + k, device)?.unsqueeze(1)?;
+    let columns = Tensor::arange(0i64, columns as i64, device)?.unsqueeze(0)?;
+    let mask = if lower {
+        columns.broadcast_le(&rows)?
+    } else {
+        columns.broadcast_ge(&rows)?
+    };
+    where_cond(&mask, tensor, &tensor.zeros_like()?)
+}
+
+fn roll_axis(tensor: &Tensor, shift: i64, axis: usize) -> Result<Tensor> {
+    let size = tensor.dim(axis)?;
+    if size == 0 {
+        return Ok(tensor.clone());
+    }
+    let shift = shift.rem_euclid(size as i64) as usize;
+    if shift == 0 {
+        return Ok(tensor.clone());
+    }
+    let head = tensor.narrow(axis, size - shift, shift)?;
+    let tail = tensor.narrow(axis, 0, size - shift)?;
+    Tensor::cat(&[head, tail], axis)
+}
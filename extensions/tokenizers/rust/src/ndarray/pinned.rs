@@ -0,0 +1,305 @@
+use candle_core::{DType, Device, Error, Result, Shape, Tensor};
+use jni::objects::{JByteBuffer, JObject};
+use jni::sys::jlong;
+use jni::JNIEnv;
+
+use crate::throw_error;
+
+#[cfg(feature = "cuda")]
+use cuda::{allocate, free};
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_allocatePinned<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    size: jlong,
+) -> JByteBuffer<'local> {
+    let alloc = || {
+        #[cfg(feature = "cuda")]
+        {
+            let ptr = allocate(size as usize)?;
+            // the memory is only released by freePinned
+            unsafe { env.new_direct_byte_buffer(ptr, size as usize) }.or_else(|err| {
+                free(ptr, size as usize)?;
+                Err(Error::wrap(err))
+            })
+        }
+        #[cfg(not(feature = "cuda"))]
+        Err(Error::Msg(format!(
+            "pinned memory of {size} bytes requires the `cuda` feature"
+        )))
+    };
+    match alloc() {
+        Ok(buffer) => buffer,
+        Err(err) => {
+            throw_error(&mut env, err);
+            JByteBuffer::from(JObject::null())
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_freePinned<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    buffer: JByteBuffer<'local>,
+) {
+    let free = || {
+        let ptr = env
+            .get_direct_buffer_address(&buffer)
+            .map_err(Error::wrap)?;
+        #[cfg(feature = "cuda")]
+        {
+            let len = env
+                .get_direct_buffer_capacity(&buffer)
+                .map_err(Error::wrap)?;
+            free(ptr, len)
+        }
+        #[cfg(not(feature = "cuda"))]
+        Err(Error::Msg(format!(
+            "pinned memory {ptr:?} requires the `cuda` feature"
+        )))
+    };
+    if let Err(err) = free() {
+        throw_error(&mut env, err);
+    }
+}
+
+/// Creates a tensor from raw host memory. Copies to a CUDA device are done with DMA from pinned
+/// memory, large pageable buffers are first staged into a pinned buffer of the calling thread.
+pub(crate) fn tensor_from_bytes(
+    data: &[u8],
+    dtype: DType,
+    shape: &Shape,
+    device: &Device,
+) -> Result<Tensor> {
+    #[cfg(feature = "cuda")]
+    if let Device::Cuda(cuda_device) = device {
+        if data.as_ptr() as usize % dtype.size_in_bytes() == 0 {
+            cuda_device.bind_to_thread().map_err(Error::debug)?;
+            return cuda::upload(data, dtype, shape, device);
+        }
+    }
+    Tensor::from_raw_buffer(data, dtype, shape.dims(), device)
+}
+
+/// Copies a contiguous CUDA tensor straight into host memory, without an intermediate vector.
+/// Returns `false` if the tensor has to be copied through the CPU instead.
+pub(crate) fn copy_to_host(tensor: &Tensor, dst: &mut [u8]) -> Result<bool> {
+    #[cfg(feature = "cuda")]
+    if tensor.device().is_cuda() && dst.as_ptr() as usize % tensor.dtype().size_in_bytes() == 0 {
+        return cuda::download(tensor, dst);
+    }
+    let _ = (tensor, dst);
+    Ok(false)
+}
+
+#[cfg(feature = "cuda")]
+mod cuda {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::ffi::c_void;
+    use std::sync::Mutex;
+
+    use candle_core::backend::BackendStorage;
+    use candle_core::cuda_backend::cudarc::driver::sys;
+    use candle_core::cuda_backend::WrapErr;
+    use candle_core::{CpuStorage, CudaStorage, DType, Device, Error, InplaceOp1, Layout};
+    use candle_core::{Result, Shape, Storage, Tensor};
+    use half::{bf16, f16};
+
+    use crate::ndarray::get_device;
+
+    /// Pageable copies of at least this size are staged through pinned memory.
+    const STAGING_MIN: usize = 1 << 20;
+    /// Larger copies aren't staged to bound the pinned memory held by each thread.
+    const STAGING_MAX: usize = 64 << 20;
+
+    struct Staging {
+        ptr: *mut u8,
+        len: usize,
+    }
+
+    impl Drop for Staging {
+        fn drop(&mut self) {
+            let _ = free_pinned(self.ptr);
+        }
+    }
+
+    thread_local! {
+        static STAGING: RefCell<Option<Staging>> = RefCell::new(None);
+    }
+
+    // the sizes of the buffers returned by `allocatePinned` by address, other buffers are rejected
+    // by `freePinned`
+    static ALLOCATIONS: Mutex<Option<HashMap<usize, usize>>> = Mutex::new(None);
+
+    /// Allocates pinned memory for Java, it's only released by `free`.
+    pub(super) fn allocate(len: usize) -> Result<*mut u8> {
+        let ptr = alloc_pinned(len)?;
+        let mut allocations = ALLOCATIONS.lock().unwrap();
+        allocations
+            .get_or_insert_with(HashMap::new)
+            .insert(ptr as usize, len);
+        Ok(ptr)
+    }
+
+    /// Releases the memory of a buffer returned by `allocate`.
+    pub(super) fn free(ptr: *mut u8, len: usize) -> Result<()> {
+        let mut allocations = ALLOCATIONS.lock().unwrap();
+        let allocations = allocations.get_or_insert_with(HashMap::new);
+        if allocations.get(&(ptr as usize)) != Some(&len) {
+            candle_core::bail!("{ptr:?} is not a buffer allocated by allocatePinned")
+        }
+        allocations.remove(&(ptr as usize));
+        free_pinned(ptr)
+    }
+
+    fn alloc_pinned(len: usize) -> Result<*mut u8> {
+        // portable memory can be used with the contexts of all devices
+        if let Device::Cuda(device) = get_device("gpu", 0)? {
+            device.bind_to_thread().map_err(Error::debug)?;
+        }
+        let mut ptr = std::ptr::null_mut();
+        unsafe { sys::cuMemHostAlloc(&mut ptr, len, sys::CU_MEMHOSTALLOC_PORTABLE) }
+            .result()
+            .map_err(Error::debug)?;
+        Ok(ptr as *mut u8)
+    }
+
+    fn free_pinned(ptr: *mut u8) -> Result<()> {
+        unsafe { sys::cuMemFreeHost(ptr as *mut c_void) }
+            .result()
+            .map_err(Error::debug)
+    }
+
+    fn is_pinned(data: &[u8]) -> bool {
+        let mut flags = 0;
+        let ptr = data.as_ptr() as *mut c_void;
+        unsafe { sys::cuMemHostGetFlags(&mut flags, ptr) }
+            .result()
+            .is_ok()
+    }
+
+    pub(super) fn upload(
+        data: &[u8],
+        dtype: DType,
+        shape: &Shape,
+        device: &Device,
+    ) -> Result<Tensor> {
+        let op = |data: &[u8]| {
+            // the data is copied straight into the storage of the new tensor
+            let tensor = Tensor::zeros(shape.clone(), dtype, device)?;
+            tensor.inplace_op1(&Upload { data })?;
+            Ok(tensor)
+        };
+        if data.len() < STAGING_MIN || data.len() > STAGING_MAX || is_pinned(data) {
+            return op(data);
+        }
+        STAGING.with(|staging| {
+            let mut staging = staging.borrow_mut();
+            if staging
+                .as_ref()
+                .map_or(true, |staging| staging.len < data.len())
+            {
+                *staging = None;
+                let len = data.len().next_power_of_two();
+                *staging = Some(Staging {
+                    ptr: alloc_pinned(len)?,
+                    len,
+                });
+            }
+            let ptr = staging.as_ref().unwrap().ptr;
+            let pinned = unsafe { std::slice::from_raw_parts_mut(ptr, data.len()) };
+            pinned.copy_from_slice(data);
+            op(pinned)
+        })
+    }
+
+    /// Copies host memory into the storage of a new contiguous tensor.
+    struct Upload<'a> {
+        data: &'a [u8],
+    }
+
+    macro_rules! upload {
+        ($storage:expr, $data:expr, $ty:ty) => {{
+            let dev = $storage.device().clone();
+            let len = $data.len() / std::mem::size_of::<$ty>();
+            let src = unsafe { std::slice::from_raw_parts($data.as_ptr() as *const $ty, len) };
+            dev.htod_sync_copy_into(src, $storage.as_cuda_slice_mut::<$ty>()?)
+                .w()?;
+        }};
+    }
+
+    impl InplaceOp1 for Upload<'_> {
+        fn name(&self) -> &'static str {
+            "upload"
+        }
+
+        fn cpu_fwd(&self, _: &mut CpuStorage, _: &Layout) -> Result<()> {
+            candle_core::bail!("upload is only supported on CUDA devices")
+        }
+
+        fn cuda_fwd(&self, storage: &mut CudaStorage, layout: &Layout) -> Result<()> {
+            let dtype = storage.dtype();
+            let size = layout.shape().elem_count() * dtype.size_in_bytes();
+            if self.data.len() < size {
+                candle_core::bail!(
+                    "buffer of {} bytes can not hold {size} bytes",
+                    self.data.len()
+                )
+            }
+            let data = &self.data[..size];
+            match dtype {
+                DType::U8 => upload!(storage, data, u8),
+                DType::U32 => upload!(storage, data, u32),
+                DType::I64 => upload!(storage, data, i64),
+                DType::F16 => upload!(storage, data, f16),
+                DType::BF16 => upload!(storage, data, bf16),
+                DType::F32 => upload!(storage, data, f32),
+                DType::F64 => upload!(storage, data, f64),
+            }
+            Ok(())
+        }
+    }
+
+    macro_rules! download {
+        ($storage:expr, $offset:expr, $len:expr, $dst:expr, $ty:ty) => {{
+            let dev = $storage.device();
+            let src = $storage
+                .as_cuda_slice::<$ty>()?
+                .slice($offset..$offset + $len);
+            let size = $len * std::mem::size_of::<$ty>();
+            if $dst.len() < size {
+                candle_core::bail!("buffer of {} bytes can not hold {size} bytes", $dst.len())
+            }
+            let dst =
+                unsafe { std::slice::from_raw_parts_mut($dst.as_mut_ptr() as *mut $ty, $len) };
+            dev.dtoh_sync_copy_into(&src, dst).w()?;
+        }};
+    }
+
+    pub(super) fn download(tensor: &Tensor, dst: &mut [u8]) -> Result<bool> {
+        let (storage, layout) = tensor.storage_and_layout();
+        let (offset, end) = match layout.contiguous_offsets() {
+            Some(offsets) => offsets,
+            None => return Ok(false),
+        };
+        let len = end - offset;
+        let storage = match &*storage {
+            Storage::Cuda(storage) => storage,
+            _ => return Ok(false),
+        };
+        storage.device().bind_to_thread().map_err(Error::debug)?;
+        match tensor.dtype() {
+            DType::U8 => download!(storage, offset, len, dst, u8),
+            DType::U32 => download!(storage, offset, len, dst, u32),
+            DType::I64 => download!(storage, offset, len, dst, i64),
+            DType::F16 => download!(storage, offset, len, dst, f16),
+            DType::BF16 => download!(storage, offset, len, dst, bf16),
+            DType::F32 => download!(storage, offset, len, dst, f32),
+            DType::F64 => download!(storage, offset, len, dst, f64),
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{borrow_handle, drop_handle, to_handle};
+use candle_core::{DType, Device, Result, Shape, Tensor};
+use jni::objects::JObject;
+use jni::sys::jlong;
+use jni::JNIEnv;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+use std::f64::consts::PI;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// Set by Engine.setRandomSeed(), candle's own generators cannot be seeded on CPU. Only the seed is
+// shared, each thread reseeds its own generator when the epoch changes so sampling never locks.
+// The pair is published as a seqlock: an odd epoch is being written and 0 was never seeded.
+static SEED: AtomicU64 = AtomicU64::new(0);
+static EPOCH: AtomicU64 = AtomicU64::new(0);
+// Serializes concurrent manualSeed calls only, readers never take it.
+static SEED_WRITER: Mutex<()> = Mutex::new(());
+
+thread_local! {
+    static GENERATOR: RefCell<Option<(u64, StdRng)>> = const { RefCell::new(None) };
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_manualSeed(
+    _: JNIEnv,
+    _: JObject,
+    seed: jlong,
+) {
+    let _writer = SEED_WRITER.lock().unwrap();
+    EPOCH.fetch_add(1, Ordering::Relaxed);
+    fence(Ordering::Release);
+    SEED.store(seed as u64, Ordering::Relaxed);
+    EPOCH.fetch_add(1, Ordering::Release);
+}
+
+/// Reads the global seed and its epoch, or `None` if Engine.setRandomSeed() was never called.
+fn global_seed() -> Option<(u64, u64)> {
+    loop {
+        let epoch = EPOCH.load(Ordering::Acquire);
+        if epoch == 0 {
+            return None;
+        }
+        if epoch % 2 == 0 {
+            let seed = SEED.load(Ordering::Relaxed);
+            fence(Ordering::Acquire);
+            if EPOCH.load(Ordering::Relaxed) == epoch {
+                return Some((seed, epoch));
+            }
+        }
+        std::hint::spin_loop();
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_newGenerator(
+    _: JNIEnv,
+    _: JObject,
+    seed: jlong,
+) -> jlong {
+    to_handle(Mutex::new(StdRng::seed_from_u64(seed as u64)))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_deleteGenerator(
+    _: JNIEnv,
+    _: JObject,
+    handle: jlong,
+) {
+    drop_handle::<Mutex<StdRng>>(handle);
+}
+
+/// Samples with the given generator handle, or the thread's generator if a seed has been set.
+///
+/// Returns `None` if neither is available, the device generator should be used in that case.
+pub(crate) fn sample<F>(
+    generator: jlong,
+    shape: &Shape,
+    dtype: DType,
+    device: &Device,
+    f: F,
+) -> Option<Result<Tensor>>
+where
+    F: Fn(&mut StdRng) -> f64,
+{
+    let draw = |rng: &mut StdRng| {
+        (0..shape.elem_count())
+            .map(|_| f(rng))
+            .collect::<Vec<f64>>()
+    };
+    let data = if generator != 0 {
+        let rng = match borrow_handle::<Mutex<StdRng>>(generator) {
+            Ok(rng) => rng,
+            Err(err) => return Some(Err(err.into())),
+        };
+        let mut rng = rng.lock().unwrap();
+        draw(&mut rng)
+    } else {
+        let (seed, epoch) = global_seed()?;
+        GENERATOR.with(|local| {
+            let mut local = local.borrow_mut();
+            match local.as_mut() {
+                Some((current, rng)) if *current == epoch => draw(rng),
+                _ => {
+                    let (_, rng) = local.insert((epoch, StdRng::seed_from_u64(seed)));
+                    draw(rng)
+                }
+            }
+        })
+    };
+    let tensor = || {
+        Tensor::from_vec(data, shape, &Device::Cpu)?
+            .to_dtype(dtype)?
+            .to_device(device)
+    };
+    Some(tensor())
+}
+
+/// Samples with the device generator in the requested dtype, like [`sample`] does.
+///
+/// candle only samples floats, other dtypes are sampled as `F32` and converted.
+pub(crate) fn on_device<F>(dtype: DType, f: F) -> Result<Tensor>
+where
+    F: FnOnce(DType) -> Result<Tensor>,
+{
+    match dtype {
+        DType::F32 | DType::F64 => f(dtype),
+        _ => f(DType::F32)?.to_dtype(dtype),
+    }
+}
+
+pub(crate) fn uniform(rng: &mut StdRng, low: f64, high: f64) -> f64 {
+    low + (high - low) * rng.gen::<f64>()
+}
+
+// Box-Muller transform
+pub(crate) fn normal(rng: &mut StdRng, mean: f64, std: f64) -> f64 {
+    let u1 = 1.0 - rng.gen::<f64>();
+    let u2 = rng.gen::<f64>();
+    mean + std * (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
@@ -1,10 +1,10 @@
-use candle_core::{DType, Tensor};
+use candle_core::{DType, Result, Tensor};
 use jni::objects::{JIntArray, JObject, ReleaseMode};
 use jni::sys::{jboolean, jdouble, jint, jlong, JNI_TRUE};
 use jni::JNIEnv;
 
-use crate::cast_handle;
-use crate::ndarray::return_handle;
+use crate::ndarray::sort::as_axis;
+use crate::ndarray::{return_handle, tensor_of};
 
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_sum<'local>(
@@ -12,7 +12,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_sum<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let dtype = tensor.dtype();
     let ret = if dtype.is_int() {
         tensor.to_dtype(DType::I64).unwrap().sum_all()
@@ -30,7 +30,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_sumWithAxis<'local>(
     axes: JIntArray<'local>,
     keep_dims: jboolean,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let rank = tensor.shape().rank() as i32;
     let axes = unsafe { env.get_array_elements(&axes, ReleaseMode::NoCopyBack) }.unwrap();
     let dims = axes
@@ -66,8 +66,11 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_mean<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
-    let ret = tensor.mean_all();
+    let mean = || {
+        let tensor = &tensor_of(handle)?;
+        as_float(tensor)?.mean_all()
+    };
+    let ret = mean();
     return_handle(&mut env, ret)
 }
 
@@ -79,18 +82,55 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_meanWithAxis<'local>(
     axes: JIntArray<'local>,
     keep_dims: jboolean,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
-    let axes = unsafe { env.get_array_elements(&axes, ReleaseMode::NoCopyBack) }.unwrap();
-    let dims = axes
-        .into_iter()
-        .map(|i| *i as usize)
-        .collect::<Vec<usize>>();
+    let mean = || {
+        let tensor = &tensor_of(handle)?;
+        let dims = as_dims(&mut env, tensor, &axes)?;
+        let tensor = as_float(tensor)?;
+        if keep_dims == JNI_TRUE {
+            tensor.mean_keepdim(dims)
+        } else {
+            tensor.mean(dims)
+        }
+    };
+    let ret = mean();
+    return_handle(&mut env, ret)
+}
 
-    let ret = if keep_dims == JNI_TRUE {
-        tensor.mean_keepdim(dims)
-    } else {
-        tensor.mean(dims)
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_var<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    axes: JIntArray<'local>,
+    unbiased: jboolean,
+    keep_dims: jboolean,
+) -> jlong {
+    let var = || {
+        let tensor = &tensor_of(handle)?;
+        let dims = as_dims(&mut env, tensor, &axes)?;
+        let var = variance(tensor, &dims, unbiased == JNI_TRUE, keep_dims == JNI_TRUE)?;
+        var.to_dtype(float_dtype(tensor.dtype()))
+    };
+    let ret = var();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_std<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    axes: JIntArray<'local>,
+    unbiased: jboolean,
+    keep_dims: jboolean,
+) -> jlong {
+    let std = || {
+        let tensor = &tensor_of(handle)?;
+        let dims = as_dims(&mut env, tensor, &axes)?;
+        let var = variance(tensor, &dims, unbiased == JNI_TRUE, keep_dims == JNI_TRUE)?;
+        var.sqrt()?.to_dtype(float_dtype(tensor.dtype()))
     };
+    let ret = std();
     return_handle(&mut env, ret)
 }
 
@@ -101,7 +141,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_min<'local>(
     handle: jlong,
 ) -> jlong {
     let min = || {
-        let tensor = cast_handle::<Tensor>(handle);
+        let tensor = &tensor_of(handle)?;
         tensor.flatten_all()?.min(0usize)
     };
     let ret = min();
@@ -116,7 +156,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_minWithAxis<'local>(
     axis: jint,
     keep_dims: jboolean,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = if keep_dims == JNI_TRUE {
         tensor.min_keepdim(axis as usize)
     } else {
@@ -132,7 +172,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_max<'local>(
     handle: jlong,
 ) -> jlong {
     let max = || {
-        let tensor = cast_handle::<Tensor>(handle);
+        let tensor = &tensor_of(handle)?;
         tensor.flatten_all()?.max(0usize)
     };
     let ret = max();
@@ -147,7 +187,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_maxWithAxis<'local>(
     axis: jint,
     keep_dims: jboolean,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = if keep_dims == JNI_TRUE {
         tensor.max_keepdim(axis as usize)
     } else {
@@ -163,7 +203,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_argMin<'local>(
     handle: jlong,
 ) -> jlong {
     let argmin = || {
-        let tensor = cast_handle::<Tensor>(handle);
+        let tensor = &tensor_of(handle)?;
         tensor.flatten_all()?.argmin(0usize)?.to_dtype(DType::I64)
     };
     let ret = argmin();
@@ -179,11 +219,15 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_argMinWithAxis<'local
     keep_dims: jboolean,
 ) -> jlong {
     let argmin = || {
-        let tensor = cast_handle::<Tensor>(handle);
+        let tensor = &tensor_of(handle)?;
+        let axis = as_axis(tensor, axis)?;
+        if tensor.dim(axis)? == 0 {
+            candle_core::bail!("attempt to get argmin of an empty dimension {axis}")
+        }
         let tensor = if keep_dims == JNI_TRUE {
-            tensor.argmin_keepdim(axis as usize)
+            tensor.argmin_keepdim(axis)
         } else {
-            tensor.argmin(axis as usize)
+            tensor.argmin(axis)
         };
         tensor?.to_dtype(DType::I64)
     };
@@ -198,7 +242,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_argMax<'local>(
     handle: jlong,
 ) -> jlong {
     let argmax = || {
-        let tensor = cast_handle::<Tensor>(handle);
+        let tensor = &tensor_of(handle)?;
         tensor.flatten_all()?.argmax(0usize)?.to_dtype(DType::I64)
     };
     let ret = argmax();
@@ -214,11 +258,15 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_argMaxWithAxis<'local
     keep_dims: jboolean,
 ) -> jlong {
     let argmax = || {
-        let tensor = cast_handle::<Tensor>(handle);
+        let tensor = &tensor_of(handle)?;
+        let axis = as_axis(tensor, axis)?;
+        if tensor.dim(axis)? == 0 {
+            candle_core::bail!("attempt to get argmax of an empty dimension {axis}")
+        }
         let tensor = if keep_dims == JNI_TRUE {
-            tensor.argmax_keepdim(axis as usize)
+            tensor.argmax_keepdim(axis)
         } else {
-            tensor.argmax(axis as usize)
+            tensor.argmax(axis)
         };
         tensor?.to_dtype(DType::I64)
     };
@@ -226,29 +274,160 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_argMaxWithAxis<'local
     return_handle(&mut env, ret)
 }
 
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_norm<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    order: jint,
+    axes: JIntArray<'local>,
+    keep_dims: jboolean,
+) -> jlong {
+    let axes = unsafe { env.get_array_elements(&axes, ReleaseMode::NoCopyBack) }
+        .unwrap()
+        .iter()
+        .map(|i| *i)
+        .collect::<Vec<i32>>();
+    let norm = || {
+        let tensor = &tensor_of(handle)?;
+        let dims = if axes.is_empty() {
+            (0..tensor.rank()).collect::<Vec<usize>>()
+        } else {
+            axes.iter()
+                .map(|i| as_axis(tensor, *i))
+                .collect::<Result<Vec<usize>>>()?
+        };
+        let p = match order {
+            i32::MAX => f64::INFINITY,
+            i32::MIN => f64::NEG_INFINITY,
+            _ => order as f64,
+        };
+        vector_norm(tensor, p, &dims, keep_dims == JNI_TRUE)
+    };
+    let ret = norm();
+    return_handle(&mut env, ret)
+}
+
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_normalize<'local>(
     mut env: JNIEnv,
     _: JObject,
     handle: jlong,
     p: jdouble,
-    dim: jint,
+    dim: jlong,
     eps: jdouble,
 ) -> jlong {
     let normalize = || {
-        let tensor = cast_handle::<Tensor>(handle);
-        let device = tensor.device();
-        let pow = Tensor::new(vec![p as f64], device)?.to_dtype(tensor.dtype())?;
-        let root = Tensor::new(vec![1f64 / p as f64], device)?.to_dtype(tensor.dtype())?;
-        let eps = Tensor::new(vec![eps as f64], device)?.to_dtype(tensor.dtype())?;
-        let sum = if p as u32 % 2 == 0 {
-            tensor.abs()?.broadcast_pow(&pow)?.sum_keepdim(dim as usize)
-        } else {
-            tensor.broadcast_pow(&pow)?.sum_keepdim(dim as usize)
-        };
-        let norm = sum?.broadcast_pow(&root)?.broadcast_maximum(&eps)?;
-        tensor.broadcast_div(&norm)
+        let tensor = &tensor_of(handle)?;
+        let dim = as_axis(tensor, dim as jint)?;
+        let norm = vector_norm(tensor, p, &[dim], true)?;
+        let eps = Tensor::new(eps, tensor.device())?.to_dtype(norm.dtype())?;
+        tensor.broadcast_div(&norm.broadcast_maximum(&eps)?)
     };
     let ret = normalize();
     return_handle(&mut env, ret)
 }
+
+/// Computes the p-norm over the dimensions, `p` can be infinite.
+pub(crate) fn vector_norm(
+    tensor: &Tensor,
+    p: f64,
+    dims: &[usize],
+    keep_dims: bool,
+) -> Result<Tensor> {
+    let abs = if tensor.dtype().is_int() {
+        tensor.to_dtype(DType::F32)?.abs()?
+    } else {
+        tensor.abs()?
+    };
+    let mut norm = if p == f64::INFINITY || p == f64::NEG_INFINITY {
+        let mut norm = abs;
+        for dim in dims {
+            norm = if p > 0f64 {
+                norm.max_keepdim(*dim)?
+            } else {
+                norm.min_keepdim(*dim)?
+            };
+        }
+        norm
+    } else if p == 0f64 {
+        abs.ne(&abs.zeros_like()?)?
+            .to_dtype(abs.dtype())?
+            .sum_keepdim(dims)?
+    } else if p == 1f64 {
+        abs.sum_keepdim(dims)?
+    } else if p == 2f64 {
+        abs.sqr()?.sum_keepdim(dims)?.sqrt()?
+    } else {
+        abs.powf(p)?.sum_keepdim(dims)?.powf(1f64 / p)?
+    };
+    if !keep_dims {
+        let mut dims = dims.to_vec();
+        dims.sort();
+        for dim in dims.iter().rev() {
+            norm = norm.squeeze(*dim)?;
+        }
+    }
+    Ok(norm)
+}
+
+/// Normalizes and deduplicates the axes, negative axes count from the last dimension.
+fn as_dims(env: &mut JNIEnv, tensor: &Tensor, axes: &JIntArray) -> Result<Vec<usize>> {
+    let axes = unsafe { env.get_array_elements(axes, ReleaseMode::NoCopyBack) }.unwrap();
+    let mut dims = axes
+        .iter()
+        .map(|axis| as_axis(tensor, *axis))
+        .collect::<Result<Vec<usize>>>()?;
+    dims.sort();
+    dims.dedup();
+    Ok(dims)
+}
+
+// integer statistics are computed in F32, candle would truncate the division
+fn float_dtype(dtype: DType) -> DType {
+    if dtype.is_int() {
+        DType::F32
+    } else {
+        dtype
+    }
+}
+
+fn as_float(tensor: &Tensor) -> Result<Tensor> {
+    tensor.to_dtype(float_dtype(tensor.dtype()))
+}
+
+/// Computes the variance in at least F32 precision, with Bessel's correction if unbiased. No
+/// axes reduce all the axes. Like NumPy, the variance is NaN without degrees of freedom, e.g. for
+/// a single element with the correction.
+fn variance(tensor: &Tensor, dims: &[usize], unbiased: bool, keep_dims: bool) -> Result<Tensor> {
+    let tensor = match tensor.dtype() {
+        DType::F32 | DType::F64 => tensor.clone(),
+        _ => tensor.to_dtype(DType::F32)?,
+    };
+    let all_dims: Vec<usize> = (0..tensor.rank()).collect();
+    let dims = if dims.is_empty() {
+        all_dims.as_slice()
+    } else {
+        dims
+    };
+    let count = dims
+        .iter()
+        .map(|dim| tensor.dims()[*dim])
+        .product::<usize>();
+    let count = if unbiased {
+        count as f64 - 1f64
+    } else {
+        count as f64
+    };
+    let mean = tensor.mean_keepdim(dims)?;
+    let squares = tensor.broadcast_sub(&mean)?.sqr()?;
+    let sum = if keep_dims {
+        squares.sum_keepdim(dims)?
+    } else {
+        squares.sum(dims)?
+    };
+    if count <= 0f64 {
+        return sum.affine(0f64, f64::NAN);
+    }
+    sum.affine(1f64 / count, 0f64)
+}
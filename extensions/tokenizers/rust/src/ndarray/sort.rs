@@ -0,0 +1,209 @@
+use candle_core::{DType, Result, Tensor};
+use jni::objects::{JLongArray, JObject};
+use jni::sys::{jboolean, jint, jlong, JNI_TRUE};
+use jni::JNIEnv;
+use std::cmp::Ordering;
+
+use crate::ndarray::{return_handle, return_handles, tensor_of};
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_topK<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+    k: jint,
+    axis: jint,
+    largest: jboolean,
+    sorted: jboolean,
+) -> JLongArray<'local> {
+    let top_k = || {
+        let tensor = &tensor_of(handle)?;
+        let axis = as_axis(tensor, axis)?;
+        let k = k as usize;
+        let size = tensor.dim(axis)?;
+        if k > size {
+            candle_core::bail!("k ({k}) is larger than dimension {axis} of size {size}")
+        }
+        let mut indices = arg_sort(tensor, axis, largest == JNI_TRUE)?
+            .narrow(axis, 0, k)?
+            .contiguous()?;
+        if sorted != JNI_TRUE {
+            // unsorted results keep the order of the elements along the axis
+            let order = arg_sort(&indices, axis, false)?;
+            indices = indices.gather(&order, axis)?;
+        }
+        let values = tensor.contiguous()?.gather(&indices, axis)?;
+        Ok(vec![values, indices])
+    };
+    let ret = top_k();
+    return_handles(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_argSort<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    axis: jint,
+    ascending: jboolean,
+) -> jlong {
+    let arg_sort = || {
+        let tensor = &tensor_of(handle)?;
+        let axis = as_axis(tensor, axis)?;
+        self::arg_sort(tensor, axis, ascending != JNI_TRUE)
+    };
+    let ret = arg_sort();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_sort<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    axis: jint,
+    ascending: jboolean,
+) -> jlong {
+    let sort = || {
+        let tensor = &tensor_of(handle)?;
+        let axis = as_axis(tensor, axis)?;
+        let indices = arg_sort(tensor, axis, ascending != JNI_TRUE)?;
+        tensor.contiguous()?.gather(&indices, axis)
+    };
+    let ret = sort();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_unique<'local>(
+    mut env: JNIEnv<'local>,
+    _: JObject,
+    handle: jlong,
+    axis: jint,
+    flatten: jboolean,
+    sorted: jboolean,
+) -> JLongArray<'local> {
+    let unique = || {
+        let tensor = &tensor_of(handle)?;
+        let shape = tensor.shape().clone();
+        let (tensor, axis) = if flatten == JNI_TRUE {
+            (tensor.flatten_all()?, 0)
+        } else {
+            (tensor.clone(), as_axis(tensor, axis)?)
+        };
+        // each slice along the axis is compared as a row
+        let moved = tensor.transpose(0, axis)?.contiguous()?;
+        let size = moved.dim(0)?;
+        let values = moved
+            .flatten_all()?
+            .to_dtype(DType::F64)?
+            .to_vec1::<f64>()?;
+        let width = values.len().checked_div(size).unwrap_or(0);
+        let rows = (0..size)
+            .map(|i| &values[i * width..(i + 1) * width])
+            .collect::<Vec<&[f64]>>();
+
+        let mut order = (0..size).collect::<Vec<usize>>();
+        order.sort_by(|a, b| compare_rows(rows[*a], rows[*b]));
+
+        // groups hold the first index and the count of each unique row in sorted order
+        let mut groups: Vec<(usize, i64)> = Vec::new();
+        let mut inverse = vec![0usize; size];
+        for (i, index) in order.iter().enumerate() {
+            if i == 0 || compare_rows(rows[order[i - 1]], rows[*index]) != Ordering::Equal {
+                groups.push((*index, 0));
+            }
+            let last = groups.len() - 1;
+            groups[last].1 += 1;
+            inverse[*index] = last;
+        }
+        if sorted != JNI_TRUE {
+            // keep the order of first occurrence
+            let mut ids = (0..groups.len()).collect::<Vec<usize>>();
+            ids.sort_by_key(|id| groups[*id].0);
+            let mut remap = vec![0usize; groups.len()];
+            for (new_id, old_id) in ids.iter().enumerate() {
+                remap[*old_id] = new_id;
+            }
+            inverse.iter_mut().for_each(|id| *id = remap[*id]);
+            groups = ids.into_iter().map(|id| groups[id]).collect();
+        }
+
+        let device = tensor.device();
+        let first = groups.iter().map(|g| g.0 as i64).collect::<Vec<i64>>();
+        let first = Tensor::from_vec(first, groups.len(), device)?;
+        let output = moved.index_select(&first, 0)?.transpose(0, axis)?;
+        let inverse = inverse
+            .into_iter()
+            .map(|id| id as i64)
+            .collect::<Vec<i64>>();
+        let mut inverse = Tensor::from_vec(inverse, size, device)?;
+        if flatten == JNI_TRUE {
+            inverse = inverse.reshape(shape)?;
+        }
+        let counts = groups.iter().map(|g| g.1).collect::<Vec<i64>>();
+        let counts = Tensor::from_vec(counts, groups.len(), device)?;
+        Ok(vec![output.contiguous()?, inverse, counts])
+    };
+    let ret = unique();
+    return_handles(&mut env, ret)
+}
+
+pub(crate) fn as_axis(tensor: &Tensor, axis: jint) -> Result<usize> {
+    let rank = tensor.rank() as i32;
+    let dim = if axis < 0 { rank + axis } else { axis };
+    if dim < 0 || dim >= rank {
+        candle_core::bail!("axis {axis} is out of bounds for tensor of rank {rank}")
+    }
+    Ok(dim as usize)
+}
+
+/// The longest rows the CUDA and Metal sort kernels handle, they sort a row within one block.
+const MAX_DEVICE_SORT: usize = 1024;
+
+/// Returns the I64 indices that sort the tensor along the axis.
+///
+/// The sort runs on the device of the tensor. Rows longer than the GPU kernels handle are sorted
+/// on the host, which is also the only sort that is stable on GPUs.
+pub(crate) fn arg_sort(tensor: &Tensor, axis: usize, descending: bool) -> Result<Tensor> {
+    let last = tensor.rank() - 1;
+    let transposed = tensor.transpose(axis, last)?.contiguous()?;
+    let dims = transposed.dims().to_vec();
+    let size = dims[last];
+    if tensor.device().is_cpu() || size <= MAX_DEVICE_SORT {
+        return transposed
+            .arg_sort_last_dim(!descending)?
+            .to_dtype(DType::I64)?
+            .transpose(axis, last)?
+            .contiguous();
+    }
+    let values = transposed
+        .flatten_all()?
+        .to_dtype(DType::F64)?
+        .to_vec1::<f64>()?;
+
+    let mut indices: Vec<i64> = Vec::with_capacity(values.len());
+    for row in values.chunks(size.max(1)) {
+        let mut order = (0..row.len()).collect::<Vec<usize>>();
+        if descending {
+            order.sort_by(|a, b| row[*b].total_cmp(&row[*a]));
+        } else {
+            order.sort_by(|a, b| row[*a].total_cmp(&row[*b]));
+        }
+        indices.extend(order.into_iter().map(|i| i as i64));
+    }
+    Tensor::from_vec(indices, dims, tensor.device())?
+        .transpose(axis, last)?
+        .contiguous()
+}
+
+// -0.0 and 0.0 are the same value, NaN is ordered as in total_cmp
+fn compare_rows(a: &[f64], b: &[f64]) -> Ordering {
+    for (x, y) in a.iter().zip(b.iter()) {
+        let ordering = x.partial_cmp(y).unwrap_or_else(|| x.total_cmp(y));
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
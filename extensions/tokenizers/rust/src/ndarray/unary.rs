@@ -1,10 +1,10 @@
 use candle_core::Tensor;
 use jni::objects::JObject;
-use jni::sys::jlong;
+use jni::sys::{jint, jlong};
 use jni::JNIEnv;
 
-use crate::cast_handle;
-use crate::ndarray::return_handle;
+use crate::ndarray::sort::as_axis;
+use crate::ndarray::{return_handle, tensor_of};
 
 #[no_mangle]
 pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_exp<'local>(
@@ -12,7 +12,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_exp<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = tensor.exp();
     return_handle(&mut env, ret)
 }
@@ -23,7 +23,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_log<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = tensor.log();
     return_handle(&mut env, ret)
 }
@@ -34,7 +34,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_sin<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = tensor.sin();
     return_handle(&mut env, ret)
 }
@@ -45,7 +45,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_cos<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = tensor.cos();
     return_handle(&mut env, ret)
 }
@@ -56,7 +56,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_tanh<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = tensor.tanh();
     return_handle(&mut env, ret)
 }
@@ -67,7 +67,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_abs<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = tensor.abs();
     return_handle(&mut env, ret)
 }
@@ -78,7 +78,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_neg<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = tensor.neg();
     return_handle(&mut env, ret)
 }
@@ -89,7 +89,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_square<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = tensor.sqr();
     return_handle(&mut env, ret)
 }
@@ -100,7 +100,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_sqrt<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = tensor.sqrt();
     return_handle(&mut env, ret)
 }
@@ -111,7 +111,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_floor<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = tensor.floor();
     return_handle(&mut env, ret)
 }
@@ -122,7 +122,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_ceil<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = tensor.ceil();
     return_handle(&mut env, ret)
 }
@@ -133,7 +133,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_round<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = tensor.round();
     return_handle(&mut env, ret)
 }
@@ -144,7 +144,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_gelu<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = tensor.gelu();
     return_handle(&mut env, ret)
 }
@@ -155,7 +155,7 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_relu<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = tensor.relu();
     return_handle(&mut env, ret)
 }
@@ -166,7 +166,39 @@ pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_erf<'local>(
     _: JObject,
     handle: jlong,
 ) -> jlong {
-    let tensor = cast_handle::<Tensor>(handle);
+    let tensor = &or_throw!(env, tensor_of(handle));
     let ret = tensor.erf();
     return_handle(&mut env, ret)
 }
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_softmax<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    axis: jint,
+) -> jlong {
+    let softmax = || {
+        let tensor = &tensor_of(handle)?;
+        let axis = as_axis(tensor, axis)?;
+        candle_nn::ops::softmax(tensor, axis)
+    };
+    let ret = softmax();
+    return_handle(&mut env, ret)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_logSoftmax<'local>(
+    mut env: JNIEnv,
+    _: JObject,
+    handle: jlong,
+    axis: jint,
+) -> jlong {
+    let log_softmax = || {
+        let tensor = &tensor_of(handle)?;
+        let axis = as_axis(tensor, axis)?;
+        candle_nn::ops::log_softmax(tensor, axis)
+    };
+    let ret = log_softmax();
+    return_handle(&mut env, ret)
+}
@@ -0,0 +1,132 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::{Arc, RwLock};
+
+use jni::objects::JObject;
+use jni::sys::jint;
+use jni::JNIEnv;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::throw_error;
+
+// a new pool replaces the old one, inferences already running keep the pool they started in
+static INTEROP_POOL: RwLock<Option<Arc<ThreadPool>>> = RwLock::new(None);
+// the pool the ops of a forward run their parallel work in, shared by all inter-op threads. It's
+// created by the first inference and replaced like the inter-op pool when its size changes.
+static INTRAOP_POOL: RwLock<Option<Arc<ThreadPool>>> = RwLock::new(None);
+
+fn new_intra_op_pool(num_threads: usize) -> Result<Arc<ThreadPool>, rayon::ThreadPoolBuildError> {
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .thread_name(|i| format!("djl-rust-intraop-{i}"))
+        .build()?;
+    Ok(Arc::new(pool))
+}
+
+/// Returns the intra-op pool, a pool with the default number of threads is created if it doesn't
+/// exist yet. candle splits its gemm work by the default count, the splits run on the threads of
+/// the pool.
+fn intra_op_pool() -> Arc<ThreadPool> {
+    if let Some(pool) = INTRAOP_POOL.read().unwrap().as_ref() {
+        return pool.clone();
+    }
+    let mut pool = INTRAOP_POOL.write().unwrap();
+    pool.get_or_insert_with(|| {
+        new_intra_op_pool(candle_core::utils::get_num_threads())
+            .expect("Couldn't create the intra-op thread pool")
+    })
+    .clone()
+}
+
+/// Runs `op` and the parallel work of its ops in the intra-op pool. A configured inter-op pool
+/// bounds the number of forwards running at once, but not the threads each forward uses.
+pub(crate) fn install<R: Send>(op: impl FnOnce() -> R + Send) -> R {
+    let pool = INTEROP_POOL.read().unwrap().clone();
+    match pool {
+        Some(pool) => pool.install(|| intra_op_pool().install(op)),
+        None => intra_op_pool().install(op),
+    }
+}
+
+/// Runs `op` in the background like `install`.
+pub(crate) fn spawn(op: impl FnOnce() + Send + 'static) {
+    match INTEROP_POOL.read().unwrap().as_ref() {
+        Some(pool) => pool.spawn(move || intra_op_pool().install(op)),
+        None => intra_op_pool().spawn(op),
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_setNumThreads(
+    mut env: JNIEnv,
+    _: JObject,
+    num_threads: jint,
+) {
+    let num_threads = if num_threads > 0 {
+        num_threads as usize
+    } else {
+        candle_core::utils::get_num_threads()
+    };
+    match new_intra_op_pool(num_threads) {
+        Ok(pool) => *INTRAOP_POOL.write().unwrap() = Some(pool),
+        Err(err) => throw_error(&mut env, err),
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_getNumThreads(
+    _: JNIEnv,
+    _: JObject,
+) -> jint {
+    match INTRAOP_POOL.read().unwrap().as_ref() {
+        Some(pool) => pool.current_num_threads() as jint,
+        None => candle_core::utils::get_num_threads() as jint,
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_setNumInteropThreads(
+    mut env: JNIEnv,
+    _: JObject,
+    num_threads: jint,
+) {
+    let pool = if num_threads > 0 {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads as usize)
+            .thread_name(|i| format!("djl-rust-interop-{i}"))
+            .build();
+        match pool {
+            Ok(pool) => Some(Arc::new(pool)),
+            Err(err) => {
+                throw_error(&mut env, err);
+                return;
+            }
+        }
+    } else {
+        None
+    };
+    *INTEROP_POOL.write().unwrap() = pool;
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_djl_engine_rust_RustLibrary_getNumInteropThreads(
+    _: JNIEnv,
+    _: JObject,
+) -> jint {
+    match INTEROP_POOL.read().unwrap().as_ref() {
+        Some(pool) => pool.current_num_threads() as jint,
+        None => 1,
+    }
+}
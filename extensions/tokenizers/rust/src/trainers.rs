@@ -0,0 +1,179 @@
+use std::collections::{HashMap, HashSet};
+
+use tk::decoders::wordpiece::WordPiece as WordPieceDecoder;
+use tk::models::bpe::{BpeTrainer, BPE};
+use tk::models::unigram::{Unigram, UnigramTrainer};
+use tk::models::wordpiece::{WordPiece, WordPieceTrainer};
+use tk::models::TrainerWrapper;
+use tk::normalizers::bert::BertNormalizer;
+use tk::pre_tokenizers::bert::BertPreTokenizer;
+use tk::pre_tokenizers::byte_level::ByteLevel;
+use tk::pre_tokenizers::metaspace::{Metaspace, PrependScheme};
+use tk::{AddedToken, NormalizedString, Normalizer, Result, Tokenizer};
+
+// Collects the training corpus, each line of the files is used as a training sequence.
+fn read_corpus(files: Vec<String>, texts: Vec<String>) -> Result<Vec<String>> {
+    let mut corpus = texts;
+    for file in files {
+        let content = std::fs::read_to_string(&file)
+            .map_err(|err| format!("Failed to read training file {file}: {err}"))?;
+        corpus.extend(content.lines().map(String::from));
+    }
+    if corpus.is_empty() {
+        return Err("Training corpus is empty".into());
+    }
+    Ok(corpus)
+}
+
+fn to_added_tokens(special_tokens: Vec<String>) -> Vec<AddedToken> {
+    special_tokens
+        .into_iter()
+        .map(|token| AddedToken::from(token, true))
+        .collect()
+}
+
+// The most frequent characters that together cover `coverage` of all the characters in the
+// corpus, like SentencePiece's `character_coverage`.
+fn covered_chars(corpus: &[String], coverage: f32) -> HashSet<char> {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in corpus.iter().flat_map(|line| line.chars()) {
+        if !c.is_whitespace() {
+            *counts.entry(c).or_default() += 1;
+        }
+    }
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let total: usize = counts.iter().map(|(_, count)| count).sum();
+    let target = (total as f64 * coverage.clamp(0f32, 1f32) as f64).ceil() as usize;
+    let mut chars = HashSet::new();
+    let mut accumulated = 0;
+    for (c, count) in counts {
+        if accumulated >= target {
+            break;
+        }
+        chars.insert(c);
+        accumulated += count;
+    }
+    chars
+}
+
+// Applies the tokenizer's normalizer, the trainer only sees the normalized sequences.
+fn normalize_corpus(normalizer: &impl Normalizer, corpus: &[String]) -> Result<Vec<String>> {
+    corpus
+        .iter()
+        .map(|line| {
+            let mut normalized = NormalizedString::from(line.as_str());
+            normalizer.normalize(&mut normalized)?;
+            Ok(normalized.get().to_string())
+        })
+        .collect()
+}
+
+// Trains a GPT-2 style byte-level BPE tokenizer
+pub(crate) fn train_bpe(
+    files: Vec<String>,
+    texts: Vec<String>,
+    vocab_size: usize,
+    min_frequency: u64,
+    special_tokens: Vec<String>,
+) -> Result<Tokenizer> {
+    let corpus = read_corpus(files, texts)?;
+
+    let mut tokenizer = Tokenizer::new(BPE::default());
+    tokenizer.with_pre_tokenizer(ByteLevel::new(false, true, true));
+    tokenizer.with_decoder(ByteLevel::default());
+    tokenizer.with_post_processor(ByteLevel::default());
+
+    let trainer = BpeTrainer::builder()
+        .vocab_size(vocab_size)
+        .min_frequency(min_frequency)
+        .special_tokens(to_added_tokens(special_tokens))
+        .initial_alphabet(ByteLevel::alphabet())
+        .show_progress(false)
+        .build();
+    let mut trainer = TrainerWrapper::BpeTrainer(trainer);
+    tokenizer.train(&mut trainer, corpus.iter())?;
+
+    Ok(tokenizer)
+}
+
+// Trains a BERT style WordPiece tokenizer, characters outside of the coverage are not part of
+// the alphabet and are encoded as the unknown token.
+pub(crate) fn train_wordpiece(
+    files: Vec<String>,
+    texts: Vec<String>,
+    vocab_size: usize,
+    min_frequency: u64,
+    special_tokens: Vec<String>,
+    character_coverage: f32,
+    unk_token: String,
+) -> Result<Tokenizer> {
+    let corpus = read_corpus(files, texts)?;
+    let normalizer = BertNormalizer::default();
+    let alphabet = covered_chars(&normalize_corpus(&normalizer, &corpus)?, character_coverage);
+
+    let mut special_tokens = special_tokens;
+    if !special_tokens.contains(&unk_token) {
+        special_tokens.insert(0, unk_token.clone());
+    }
+
+    let model = WordPiece::builder().unk_token(unk_token).build()?;
+    let mut tokenizer = Tokenizer::new(model);
+    tokenizer.with_normalizer(normalizer);
+    tokenizer.with_pre_tokenizer(BertPreTokenizer);
+    tokenizer.with_decoder(WordPieceDecoder::default());
+
+    let trainer = WordPieceTrainer::builder()
+        .vocab_size(vocab_size)
+        .min_frequency(min_frequency)
+        .special_tokens(to_added_tokens(special_tokens))
+        .limit_alphabet(alphabet.len())
+        .show_progress(false)
+        .build();
+    let mut trainer = TrainerWrapper::WordPieceTrainer(trainer);
+    tokenizer.train(&mut trainer, corpus.iter())?;
+
+    Ok(tokenizer)
+}
+
+// Trains a SentencePiece style Unigram tokenizer. The unigram trainer has no notion of character
+// coverage, so the uncovered characters are removed from the corpus before training.
+pub(crate) fn train_unigram(
+    files: Vec<String>,
+    texts: Vec<String>,
+    vocab_size: u32,
+    special_tokens: Vec<String>,
+    character_coverage: f32,
+    unk_token: String,
+) -> Result<Tokenizer> {
+    let mut corpus = read_corpus(files, texts)?;
+    if character_coverage < 1f32 {
+        let alphabet = covered_chars(&corpus, character_coverage);
+        for line in corpus.iter_mut() {
+            line.retain(|c| c.is_whitespace() || alphabet.contains(&c));
+        }
+    }
+
+    let mut special_tokens = special_tokens;
+    if !special_tokens.contains(&unk_token) {
+        special_tokens.insert(0, unk_token.clone());
+    }
+
+    let mut tokenizer = Tokenizer::new(Unigram::default());
+    let metaspace = Metaspace::new('▁', PrependScheme::Always, true);
+    tokenizer.with_pre_tokenizer(metaspace.clone());
+    tokenizer.with_decoder(metaspace);
+
+    let trainer = UnigramTrainer::builder()
+        .vocab_size(vocab_size)
+        .special_tokens(to_added_tokens(special_tokens))
+        .unk_token(Some(unk_token))
+        .show_progress(false)
+        .build()
+        .map_err(|err| format!("Invalid unigram trainer options: {err}"))?;
+    let mut trainer = TrainerWrapper::UnigramTrainer(trainer);
+    tokenizer.train(&mut trainer, corpus.iter())?;
+
+    Ok(tokenizer)
+}
@@ -0,0 +1,97 @@
+"""Writes the PyTorch checkpoints of the tests without depending on torch.
+
+The files have the layout of torch.save: a zip archive with the pickled state dict in
+archive/data.pkl and the storage of each tensor in archive/data/<key>.
+"""
+import collections
+import io
+import pickle
+import struct
+import sys
+import types
+import zipfile
+from pathlib import Path
+
+torch = types.ModuleType("torch")
+torch_utils = types.ModuleType("torch._utils")
+sys.modules["torch"] = torch
+sys.modules["torch._utils"] = torch_utils
+
+
+class FloatStorage:
+    pass
+
+
+FloatStorage.__module__ = "torch"
+torch.FloatStorage = FloatStorage
+
+
+def _rebuild_tensor_v2(*args):
+    pass
+
+
+_rebuild_tensor_v2.__module__ = "torch._utils"
+torch_utils._rebuild_tensor_v2 = _rebuild_tensor_v2
+
+
+class Storage:
+    def __init__(self, key, values):
+        self.key = key
+        self.values = values
+
+
+class Tensor:
+    def __init__(self, storage, shape):
+        self.storage = storage
+        self.shape = shape
+
+    def __reduce__(self):
+        stride = tuple(_prod(self.shape[i + 1 :]) for i in range(len(self.shape)))
+        return (
+            _rebuild_tensor_v2,
+            (self.storage, 0, self.shape, stride, False, collections.OrderedDict()),
+        )
+
+
+def _prod(shape):
+    result = 1
+    for dim in shape:
+        result *= dim
+    return result
+
+
+class Pickler(pickle.Pickler):
+    def persistent_id(self, obj):
+        if isinstance(obj, Storage):
+            return ("storage", FloatStorage, obj.key, "cpu", len(obj.values))
+        return None
+
+
+def save(path, tensors):
+    """Saves `{name: (shape, values)}` of F32 tensors."""
+    state_dict = collections.OrderedDict()
+    storages = []
+    for key, (name, (shape, values)) in enumerate(tensors.items()):
+        storage = Storage(str(key), values)
+        storages.append(storage)
+        state_dict[name] = Tensor(storage, shape)
+    with zipfile.ZipFile(path, "w", zipfile.ZIP_STORED) as archive:
+        data = io.BytesIO()
+        Pickler(data, protocol=2).dump(state_dict)
+        archive.writestr("archive/data.pkl", data.getvalue())
+        for storage in storages:
+            values = struct.pack(f"<{len(storage.values)}f", *storage.values)
+            archive.writestr(f"archive/data/{storage.key}", values)
+        archive.writestr("archive/version", "3\n")
+
+
+if __name__ == "__main__":
+    directory = Path(__file__).parent / "sharded-pth"
+    save(
+        directory / "pytorch_model-00001-of-00002.bin",
+        {"first.weight": ((2, 3), [0.0, 1.0, 2.0, 3.0, 4.0, 5.0])},
+    )
+    save(
+        directory / "pytorch_model-00002-of-00002.bin",
+        {"second.weight": ((3,), [6.0, 7.0, 8.0])},
+    )
@@ -0,0 +1,76 @@
+/*
+ * Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License"). You may not use this file except in compliance
+ * with the License. A copy of the License is located at
+ *
+ * http://aws.amazon.com/apache2.0/
+ *
+ * or in the "license" file accompanying this file. This file is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES
+ * OR CONDITIONS OF ANY KIND, either express or implied. See the License for the specific language governing permissions
+ * and limitations under the License.
+ */
+package ai.djl.engine.rust;
+
+import java.lang.ref.PhantomReference;
+import java.lang.ref.Reference;
+import java.lang.ref.ReferenceQueue;
+import java.nio.ByteBuffer;
+import java.util.Collections;
+import java.util.Set;
+import java.util.concurrent.ConcurrentHashMap;
+
+/**
+ * Keeps the storage of a tensor alive while a direct buffer viewing it is reachable.
+ *
+ * <p>Each view holds a native handle sharing the storage of the tensor, so the buffer stays valid
+ * after the {@link RsNDArray} is closed. The handle is deleted once the buffer is garbage
+ * collected, the next time a view is created.
+ */
+final class BufferViews {
+
+    private static final ReferenceQueue<ByteBuffer> QUEUE = new ReferenceQueue<>();
+    // the references must stay reachable until they are enqueued
+    private static final Set<View> VIEWS = Collections.newSetFromMap(new ConcurrentHashMap<>());
+
+    private BufferViews() {}
+
+    /**
+     * Returns a direct buffer viewing the memory of a contiguous CPU tensor, or {@code null} if the
+     * tensor can't be viewed.
+     *
+     * @param handle the tensor handle
+     * @return the buffer, or {@code null}
+     */
+    static ByteBuffer view(long handle) {
+        release();
+        long retained = RustLibrary.retainTensor(handle);
+        ByteBuffer bb = RustLibrary.getDirectByteBuffer(retained);
+        if (bb == null) {
+            RustLibrary.deleteTensor(retained);
+            return null;
+        }
+        VIEWS.add(new View(bb, retained));
+        return bb;
+    }
+
+    /** Deletes the handles of the buffers that were garbage collected. */
+    static void release() {
+        Reference<? extends ByteBuffer> ref;
+        while ((ref = QUEUE.poll()) != null) {
+            View view = (View) ref;
+            VIEWS.remove(view);
+            RustLibrary.deleteTensor(view.handle);
+        }
+    }
+
+    private static final class View extends PhantomReference<ByteBuffer> {
+
+        final long handle;
+
+        View(ByteBuffer buffer, long handle) {
+            super(buffer, QUEUE);
+            this.handle = handle;
+        }
+    }
+}
@@ -0,0 +1,62 @@
+/*
+ * Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License"). You may not use this file except in compliance
+ * with the License. A copy of the License is located at
+ *
+ * http://aws.amazon.com/apache2.0/
+ *
+ * or in the "license" file accompanying this file. This file is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES
+ * OR CONDITIONS OF ANY KIND, either express or implied. See the License for the specific language governing permissions
+ * and limitations under the License.
+ */
+package ai.djl.engine.rust;
+
+import java.util.concurrent.atomic.AtomicReference;
+
+/**
+ * {@code RsCancellationToken} stops the inferences it's passed to, a cancelled inference fails
+ * before its next layer.
+ *
+ * <p>Pass the token to {@link RsSymbolBlock} forwards with the {@code cancellation_token}
+ * parameter.
+ */
+public class RsCancellationToken implements AutoCloseable {
+
+    private AtomicReference<Long> handle;
+
+    /** Constructs a new {@code RsCancellationToken}. */
+    public RsCancellationToken() {
+        handle = new AtomicReference<>(RustLibrary.createCancellationToken());
+    }
+
+    /** Cancels the inferences using this token, it has no effect once the token is closed. */
+    public synchronized void cancel() {
+        Long pointer = handle.get();
+        if (pointer != null) {
+            RustLibrary.cancelInference(pointer);
+        }
+    }
+
+    /**
+     * Gets the native Rust pointer.
+     *
+     * @return the pointer
+     */
+    public Long getHandle() {
+        Long reference = handle.get();
+        if (reference == null) {
+            throw new IllegalStateException("Rust cancellation token has been released!");
+        }
+        return reference;
+    }
+
+    /** {@inheritDoc} */
+    @Override
+    public synchronized void close() {
+        Long pointer = handle.getAndSet(null);
+        if (pointer != null) {
+            RustLibrary.deleteCancellationToken(pointer);
+        }
+    }
+}
@@ -19,6 +19,14 @@ import ai.djl.engine.EngineException;
 import ai.djl.engine.StandardCapabilities;
 import ai.djl.huggingface.tokenizers.jni.LibUtils;
 import ai.djl.ndarray.NDManager;
+import ai.djl.util.JsonUtils;
+
+import com.google.gson.reflect.TypeToken;
+
+import java.lang.management.MemoryUsage;
+import java.lang.reflect.Type;
+import java.util.LinkedHashMap;
+import java.util.Map;
 
 /** The {@code RsEngine} is an implementation of the {@link Engine} rust engine. */
 public final class RsEngine extends Engine {
@@ -32,6 +40,20 @@ public final class RsEngine extends Engine {
     static Engine newInstance() {
         try {
             LibUtils.checkStatus();
+            if (Boolean.getBoolean("ai.djl.rust.deterministic")) {
+                RustLibrary.setDeterministic(true);
+            }
+            Integer numThreads = Integer.getInteger("ai.djl.rust.num_threads");
+            if (numThreads != null) {
+                RustLibrary.setNumThreads(numThreads);
+            }
+            Integer numInteropThreads = Integer.getInteger("ai.djl.rust.num_interop_threads");
+            if (numInteropThreads != null) {
+                RustLibrary.setNumInteropThreads(numInteropThreads);
+            }
+            if (Boolean.getBoolean("ai.djl.rust.check_numerics")) {
+                RustLibrary.setCheckNumerics(true);
+            }
             return new RsEngine();
         } catch (EngineException e) {
             throw e;
@@ -68,13 +90,194 @@ public final class RsEngine extends Engine {
     @Override
     public boolean hasCapability(String capability) {
         if (StandardCapabilities.MKL.equals(capability)) {
-            return true;
+            return "mkl".equals(RustLibrary.getBlasBackend());
         } else if (StandardCapabilities.CUDA.equals(capability)) {
             return RustLibrary.isCudaAvailable();
         }
         return false;
     }
 
+    /** {@inheritDoc} */
+    @Override
+    public Device defaultDevice() {
+        if (getGpuCount() > 0) {
+            return Device.gpu();
+        } else if (RustLibrary.isMetalAvailable()) {
+            return Device.of("mps", 0);
+        }
+        return Device.cpu();
+    }
+
+    /** {@inheritDoc} */
+    @Override
+    public int getGpuCount() {
+        return RustLibrary.getCudaDeviceCount();
+    }
+
+    /**
+     * Returns the memory usage of a CUDA device, the committed and max values are the total
+     * memory of the device.
+     *
+     * @param device the GPU device
+     * @return the {@link MemoryUsage} of the device
+     */
+    public MemoryUsage getGpuMemory(Device device) {
+        if (!device.isGpu()) {
+            throw new IllegalArgumentException("Only GPU device is allowed.");
+        }
+        long[] memory = RustLibrary.getGpuMemory(device.getDeviceId());
+        long used = memory[1] - memory[0];
+        return new MemoryUsage(-1, used, memory[1], memory[1]);
+    }
+
+    /**
+     * Releases the memory cached by the allocator of a CUDA device, so it can be used by other
+     * models or processes.
+     *
+     * @param device the GPU device
+     */
+    public void emptyCache(Device device) {
+        if (!device.isGpu()) {
+            throw new IllegalArgumentException("Only GPU device is allowed.");
+        }
+        RustLibrary.emptyCache(device.getDeviceId());
+    }
+
+    /**
+     * Returns the statistics of the allocator of a CUDA device in bytes. The difference between
+     * the reserved and used memory is cached for later allocations.
+     *
+     * @param device the GPU device
+     * @return the reserved, peak reserved, used and peak used memory
+     */
+    public Map<String, Long> getMemoryPoolStats(Device device) {
+        if (!device.isGpu()) {
+            throw new IllegalArgumentException("Only GPU device is allowed.");
+        }
+        long[] stats = RustLibrary.getMemoryPoolStats(device.getDeviceId());
+        Map<String, Long> map = new LinkedHashMap<>();
+        map.put("reserved", stats[0]);
+        map.put("reserved_peak", stats[1]);
+        map.put("used", stats[2]);
+        map.put("used_peak", stats[3]);
+        return map;
+    }
+
+    /**
+     * Returns the number of live native handles by kind, e.g. {@code Tensor} or {@code Model}.
+     * Counts that keep growing in a long-running service point to arrays or models that are never
+     * closed.
+     *
+     * @return the number of live handles by kind
+     */
+    public Map<String, Long> getLiveHandles() {
+        Type type = new TypeToken<Map<String, Long>>() {}.getType();
+        return JsonUtils.GSON.fromJson(RustLibrary.getLiveHandles(), type);
+    }
+
+    /**
+     * Frees all live native handles of a kind. Java objects still holding one of the handles must
+     * not be used afterwards, the native library checks every handle and throws an {@link
+     * EngineException} on a freed one rather than reading freed memory.
+     *
+     * @param kind the kind of handles to free, as returned by {@link #getLiveHandles()}
+     * @return the number of handles freed
+     */
+    public long freeAll(String kind) {
+        return RustLibrary.freeAllHandles(kind);
+    }
+
+    /**
+     * Sets the number of threads used within an operator such as a matrix multiplication, a
+     * non-positive value uses the default. Inferences already running keep the threads they
+     * started with.
+     *
+     * @param numThreads the number of intra-op threads
+     */
+    public void setNumThreads(int numThreads) {
+        RustLibrary.setNumThreads(numThreads);
+    }
+
+    /**
+     * Returns the number of threads used within an operator.
+     *
+     * @return the number of intra-op threads
+     */
+    public int getNumThreads() {
+        return RustLibrary.getNumThreads();
+    }
+
+    /**
+     * Sets the size of the thread pool that runs model inference, a non-positive value runs the
+     * inference on the calling thread.
+     *
+     * @param numThreads the number of inter-op threads
+     */
+    public void setNumInteropThreads(int numThreads) {
+        RustLibrary.setNumInteropThreads(numThreads);
+    }
+
+    /**
+     * Returns the size of the thread pool that runs model inference.
+     *
+     * @return the number of inter-op threads
+     */
+    public int getNumInteropThreads() {
+        return RustLibrary.getNumInteropThreads();
+    }
+
+    /**
+     * Enables checking the activations of each layer for NaN and Inf values, a forward producing
+     * them fails with an error naming the layer. The checks slow down inference, they are meant
+     * to debug half precision models.
+     *
+     * @param enabled whether the checks are enabled
+     */
+    public void setCheckNumerics(boolean enabled) {
+        RustLibrary.setCheckNumerics(enabled);
+    }
+
+    /**
+     * Returns whether the activations are checked for NaN and Inf values.
+     *
+     * @return whether the checks are enabled
+     */
+    public boolean isCheckNumerics() {
+        return RustLibrary.isCheckNumerics();
+    }
+
+    /**
+     * Makes cuBLAS and MKL use reproducible algorithms, so the same inputs give bit identical
+     * outputs across runs. The cuBLAS matmuls stop using TF32 and reduced precision reductions,
+     * MKL reads its setting on the first inference, set the {@code ai.djl.rust.deterministic}
+     * system property or call this before it.
+     *
+     * <p>cuBLAS also reads {@code CUBLAS_WORKSPACE_CONFIG=:4096:8} when it's initialized, the
+     * variable has to be set in the environment before the JVM starts.
+     *
+     * @param enabled whether the execution is deterministic
+     * @throws EngineException if MKL is already initialized with another setting
+     */
+    public void setDeterministic(boolean enabled) {
+        RustLibrary.setDeterministic(enabled);
+    }
+
+    /**
+     * Returns whether the math libraries run with the reproducible settings.
+     *
+     * @return whether the execution is deterministic
+     */
+    public boolean isDeterministic() {
+        return RustLibrary.isDeterministic();
+    }
+
+    /** {@inheritDoc} */
+    @Override
+    public void setRandomSeed(int seed) {
+        super.setRandomSeed(seed);
+        RustLibrary.manualSeed(seed);
+    }
+
     /** {@inheritDoc} */
     @Override
     public Model newModel(String name, Device device) {
@@ -101,10 +304,17 @@ public final class RsEngine extends Engine {
         sb.append(getEngineName())
                 .append(':')
                 .append(getVersion())
-                .append(", capabilities: [\n\t" + StandardCapabilities.MKL);
+                .append(", capabilities: [\n\tBLAS: ")
+                .append(RustLibrary.getBlasBackend());
+        if (hasCapability(StandardCapabilities.MKL)) {
+            sb.append(",\n\t").append(StandardCapabilities.MKL); // NOPMD
+        }
         if (hasCapability(StandardCapabilities.CUDA)) {
             sb.append(",\n\t").append(StandardCapabilities.CUDA); // NOPMD
         }
+        if (RustLibrary.isMetalAvailable()) {
+            sb.append(",\n\tMETAL");
+        }
         sb.append(']');
         return sb.toString();
     }
@@ -17,11 +17,18 @@ import ai.djl.Device;
 import ai.djl.MalformedModelException;
 import ai.djl.Model;
 import ai.djl.ndarray.types.DataType;
+import ai.djl.util.JsonUtils;
+
+import com.google.gson.JsonElement;
+import com.google.gson.JsonObject;
 
 import java.io.FileNotFoundException;
 import java.io.IOException;
+import java.io.Reader;
+import java.io.Writer;
 import java.nio.file.Files;
 import java.nio.file.Path;
+import java.nio.file.Paths;
 import java.util.Map;
 import java.util.concurrent.atomic.AtomicReference;
 
@@ -54,13 +61,85 @@ public class RsModel extends BaseModel {
         }
         setModelDir(modelPath);
         if (block == null) {
-            handle.set(RustLibrary.loadModel(modelDir.toString(), dataType.ordinal()));
+            String json = toJson(options);
+            int dtype = toRustDataType(options);
+            handle.set(RustLibrary.loadModel(modelDir.toString(), dtype, json));
             block = new RsSymbolBlock((RsNDManager) manager, handle.get());
+            setMetadataProperties();
         } else {
             loadBlock(prefix, options);
         }
     }
 
+    /**
+     * Downloads the config, the tokenizer and the safetensors weights of a Hugging Face Hub repo
+     * into the Hugging Face cache and loads the model. The {@code revision} option picks a branch,
+     * tag or commit, and {@code hf_token} is the token of private and gated repos, {@code
+     * HF_TOKEN} by default.
+     *
+     * @param repoId the id of the repo, for example {@code BAAI/bge-small-en-v1.5}
+     * @param options the load options, for example {@code revision}
+     * @throws IOException if the model directory can't be read
+     * @throws MalformedModelException if the model is malformed
+     */
+    public void loadFromHub(String repoId, Map<String, ?> options)
+            throws IOException, MalformedModelException {
+        String modelDir = RustLibrary.downloadModel(repoId, toJson(options));
+        load(Paths.get(modelDir), null, options);
+    }
+
+    /**
+     * Loads the model from a {@code config.json} and safetensors shards held in memory, so models
+     * packaged in a jar or streamed from object storage don't need a model directory.
+     *
+     * @param config the content of {@code config.json}
+     * @param shards the content of the safetensors files
+     * @param options the load options, for example {@code device_map}
+     */
+    public void load(String config, byte[][] shards, Map<String, ?> options) {
+        if (block != null) {
+            throw new IllegalStateException("Model has already been loaded.");
+        }
+        String json = toJson(options);
+        int dtype = toRustDataType(options);
+        handle.set(RustLibrary.loadModelFromBytes(config, shards, dtype, json));
+        block = new RsSymbolBlock((RsNDManager) manager, handle.get());
+        setMetadataProperties();
+    }
+
+    /**
+     * Saves the weights as {@code model.safetensors} in the model data type, next to a copy of
+     * {@code config.json}, so the directory can be loaded again by the Rust engine.
+     *
+     * @param modelPath the directory to save the model to
+     * @param newModelName the name of the model, unused since the file names are fixed
+     * @throws IOException if the config can't be written
+     */
+    @Override
+    public void save(Path modelPath, String newModelName) throws IOException {
+        if (block == null) {
+            throw new IllegalStateException("Model has not be trained or loaded yet.");
+        }
+        Files.createDirectories(modelPath);
+        ((RsSymbolBlock) block).save(modelPath.resolve("model.safetensors"), dataType);
+        Path configFile = modelDir == null ? null : modelDir.resolve("config.json");
+        if (configFile != null && Files.isRegularFile(configFile)) {
+            JsonObject config;
+            try (Reader reader = Files.newBufferedReader(configFile)) {
+                config = JsonUtils.GSON.fromJson(reader, JsonObject.class);
+            }
+            // the saved weights are not quantized
+            config.remove("quantization_config");
+            String torchDtype = toTorchDtype(dataType);
+            if (torchDtype != null) {
+                config.addProperty("torch_dtype", torchDtype);
+            }
+            try (Writer writer = Files.newBufferedWriter(modelPath.resolve("config.json"))) {
+                JsonUtils.GSON_PRETTY.toJson(config, writer);
+            }
+        }
+    }
+
     /** {@inheritDoc} */
     @Override
     public void close() {
@@ -70,4 +149,82 @@ public class RsModel extends BaseModel {
         }
         super.close();
     }
+
+    /**
+     * Returns the dtype passed to the native loader, the {@code dtype} option {@code auto} uses
+     * the {@code torch_dtype} of {@code config.json}.
+     *
+     * @param options the model load options
+     * @return the Rust dtype, or {@code -1} for {@code auto}
+     */
+    private int toRustDataType(Map<String, ?> options) {
+        if (options != null && "auto".equals(options.get("dtype"))) {
+            return -1;
+        }
+        return dataType.ordinal();
+    }
+
+    private static String toTorchDtype(DataType dataType) {
+        switch (dataType) {
+            case FLOAT16:
+                return "float16";
+            case BFLOAT16:
+                return "bfloat16";
+            case FLOAT32:
+                return "float32";
+            default:
+                return null;
+        }
+    }
+
+    /**
+     * Exposes the scalar model metadata, for example {@code max_position_embeddings}, as model
+     * properties so translators can configure themselves. Properties set by the user are kept,
+     * the data type of the model is updated to the one it was loaded in.
+     */
+    private void setMetadataProperties() {
+        JsonObject metadata = ((RsSymbolBlock) block).getMetadata();
+        switch (metadata.get("dtype").getAsString()) {
+            case "f16":
+                dataType = DataType.FLOAT16;
+                break;
+            case "bf16":
+                dataType = DataType.BFLOAT16;
+                break;
+            case "f32":
+                dataType = DataType.FLOAT32;
+                break;
+            case "f64":
+                dataType = DataType.FLOAT64;
+                break;
+            default:
+                break;
+        }
+        for (Map.Entry<String, JsonElement> entry : metadata.entrySet()) {
+            JsonElement value = entry.getValue();
+            if (value.isJsonPrimitive() && getProperty(entry.getKey()) == null) {
+                setProperty(entry.getKey(), value.getAsString());
+            }
+        }
+    }
+
+    /**
+     * Converts the load options to the JSON object parsed by the native model loader, for
+     * example {@code device_map} or {@code attention_backend}. Values are passed as strings.
+     *
+     * @param options the model load options
+     * @return the JSON string of the options
+     */
+    private static String toJson(Map<String, ?> options) {
+        JsonObject json = new JsonObject();
+        if (options != null) {
+            for (Map.Entry<String, ?> entry : options.entrySet()) {
+                Object value = entry.getValue();
+                if (value != null) {
+                    json.addProperty(entry.getKey(), value.toString());
+                }
+            }
+        }
+        return json.toString();
+    }
 }
@@ -200,14 +200,47 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
         throw new UnsupportedOperationException("Not implemented");
     }
 
+    /** {@inheritDoc} */
+    @Override
+    public float[] toFloatArray() {
+        if (getDataType() == DataType.BFLOAT16) {
+            // Java has no bfloat16, the values are widened natively
+            try (RsNDArray array = toType(DataType.FLOAT32, false)) {
+                return array.toFloatArray();
+            }
+        }
+        return NDArray.super.toFloatArray();
+    }
+
     /** {@inheritDoc} */
     @Override
     public ByteBuffer toByteBuffer(boolean tryDirect) {
-        ByteBuffer bb = RustLibrary.getByteBuffer(getHandle());
-        bb.order(ByteOrder.nativeOrder());
+        if (tryDirect) {
+            // only contiguous CPU tensors can share their memory
+            ByteBuffer bb = BufferViews.view(getHandle());
+            if (bb != null) {
+                return bb.order(ByteOrder.nativeOrder());
+            }
+        }
+        int size = Math.toIntExact(size() * getDataType().getNumOfBytes());
+        ByteBuffer bb = manager.allocateDirect(size);
+        RustLibrary.copyToBuffer(getHandle(), bb);
         return bb;
     }
 
+    /**
+     * Copies the data of this array into a direct buffer, a buffer from {@link
+     * RsNDManager#allocatePinned(int)} is copied from CUDA devices with DMA.
+     *
+     * @param buffer the direct buffer to copy the data into
+     */
+    public void copyTo(ByteBuffer buffer) {
+        if (!buffer.isDirect()) {
+            throw new IllegalArgumentException("Only direct buffer is allowed.");
+        }
+        RustLibrary.copyToBuffer(getHandle(), buffer);
+    }
+
     /** {@inheritDoc} */
     @Override
     public String[] toStringArray(Charset charset) {
@@ -245,12 +278,10 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public NDArray gather(NDArray index, int axis) {
-        //        try (NDScope ignore = new NDScope()) {
-        //            long indexHandle = manager.from(index).getHandle();
-        //            return toArray(RustLibrary.gather(getHandle(), indexHandle, axis), true);
-        //        }
-        // TODO:
-        throw new UnsupportedOperationException("Not implemented");
+        try (NDScope ignore = new NDScope()) {
+            long indexHandle = manager.from(index).getHandle();
+            return toArray(RustLibrary.gather(getHandle(), indexHandle, axis), true);
+        }
     }
 
     /** {@inheritDoc} */
@@ -284,14 +315,82 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public NDArray scatter(NDArray index, NDArray value, int axis) {
-        //        try (NDScope ignore = new NDScope()) {
-        //            long indexHandle = manager.from(index).getHandle();
-        //            long valueHandle = manager.from(value).getHandle();
-        //            return toArray(RustLibrary.scatter(getHandle(), indexHandle, valueHandle,
-        // axis), true);
-        //        }
-        // TODO:
-        throw new UnsupportedOperationException("Not implemented");
+        try (NDScope ignore = new NDScope()) {
+            long indexHandle = manager.from(index).getHandle();
+            long valueHandle = manager.from(value).getHandle();
+            long newHandle = RustLibrary.scatter(getHandle(), indexHandle, valueHandle, axis);
+            return toArray(newHandle, true);
+        }
+    }
+
+    /**
+     * Returns a new {@code NDArray} that selects the entries along the axis with the 1-D index.
+     *
+     * @param index the 1-D index of the entries to select
+     * @param axis the axis to select from
+     * @return the selected {@code NDArray}
+     */
+    public NDArray indexSelect(NDArray index, int axis) {
+        try (NDScope ignore = new NDScope()) {
+            long indexHandle = manager.from(index).getHandle();
+            return toArray(RustLibrary.indexSelect(getHandle(), axis, indexHandle), true);
+        }
+    }
+
+    /**
+     * Returns a new {@code NDArray} with the entries set to the value where the mask is true.
+     *
+     * @param mask the boolean mask, broadcastable to the shape of this {@code NDArray}
+     * @param value the value to fill
+     * @return the filled {@code NDArray}
+     */
+    public NDArray maskedFill(NDArray mask, float value) {
+        try (NDScope ignore = new NDScope()) {
+            long maskHandle = manager.from(mask).getHandle();
+            return toArray(RustLibrary.maskedFill(getHandle(), maskHandle, value), true);
+        }
+    }
+
+    /**
+     * Returns a 1-D {@code NDArray} of the elements where the mask is {@code true}, the same as
+     * {@code torch.masked_select}. The mask is broadcast with this {@code NDArray}.
+     *
+     * @param mask the boolean mask
+     * @return the selected elements
+     */
+    public RsNDArray maskedSelect(NDArray mask) {
+        try (NDScope ignore = new NDScope()) {
+            long maskHandle = manager.from(mask).getHandle();
+            return toArray(RustLibrary.maskedSelect(getHandle(), maskHandle), true);
+        }
+    }
+
+    /**
+     * Copies the elements of the source in order to the positions where the mask is {@code true},
+     * the same as {@code torch.Tensor.masked_scatter}.
+     *
+     * @param mask the boolean mask, broadcast to the shape of this {@code NDArray}
+     * @param source the source with at least as many elements as the mask selects
+     * @return the updated {@code NDArray}
+     */
+    public RsNDArray maskedScatter(NDArray mask, NDArray source) {
+        try (NDScope ignore = new NDScope()) {
+            long maskHandle = manager.from(mask).getHandle();
+            long sourceHandle = manager.from(source).getHandle();
+            return toArray(RustLibrary.maskedScatter(getHandle(), maskHandle, sourceHandle), true);
+        }
+    }
+
+    /**
+     * Pads the {@code NDArray}, the same as {@code torch.nn.functional.pad}.
+     *
+     * @param padding the (left, right) padding pairs, starting from the last dimension
+     * @param mode the padding mode, one of "constant", "reflect" or "replicate"
+     * @param value the fill value for the "constant" mode
+     * @return the padded {@code NDArray}
+     */
+    public RsNDArray pad(long[] padding, String mode, float value) {
+        return toArray(RustLibrary.pad(getHandle(), padding, mode, value));
     }
 
     /** {@inheritDoc} */
@@ -335,7 +434,10 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public RsNDArray booleanMask(NDArray index, int axis) {
-        throw new UnsupportedOperationException("Not implemented");
+        try (NDScope ignore = new NDScope()) {
+            long indexHandle = manager.from(index).getHandle();
+            return toArray(RustLibrary.booleanMask(getHandle(), indexHandle, axis), true);
+        }
     }
 
     /** {@inheritDoc} */
@@ -371,9 +473,8 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public RsNDArray eq(Number n) {
-        try (NDArray number = manager.create(n)) {
-            return eq(number);
-        }
+        long newHandle = RustLibrary.eqScalar(getHandle(), n.doubleValue());
+        return toArray(newHandle, DataType.BOOLEAN, false, false);
     }
 
     /** {@inheritDoc} */
@@ -388,9 +489,8 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public RsNDArray neq(Number n) {
-        try (NDArray number = manager.create(n)) {
-            return neq(number);
-        }
+        long newHandle = RustLibrary.neqScalar(getHandle(), n.doubleValue());
+        return toArray(newHandle, DataType.BOOLEAN, false, false);
     }
 
     /** {@inheritDoc} */
@@ -405,9 +505,8 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public RsNDArray gt(Number n) {
-        try (NDArray number = manager.create(n)) {
-            return gt(number);
-        }
+        long newHandle = RustLibrary.gtScalar(getHandle(), n.doubleValue());
+        return toArray(newHandle, DataType.BOOLEAN, false, false);
     }
 
     /** {@inheritDoc} */
@@ -422,9 +521,8 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public RsNDArray gte(Number n) {
-        try (NDArray number = manager.create(n)) {
-            return gte(number);
-        }
+        long newHandle = RustLibrary.gteScalar(getHandle(), n.doubleValue());
+        return toArray(newHandle, DataType.BOOLEAN, false, false);
     }
 
     /** {@inheritDoc} */
@@ -439,9 +537,8 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public RsNDArray lt(Number n) {
-        try (NDArray number = manager.create(n)) {
-            return lt(number);
-        }
+        long newHandle = RustLibrary.ltScalar(getHandle(), n.doubleValue());
+        return toArray(newHandle, DataType.BOOLEAN, false, false);
     }
 
     /** {@inheritDoc} */
@@ -456,9 +553,8 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public RsNDArray lte(Number n) {
-        try (NDArray number = manager.create(n)) {
-            return lte(number);
-        }
+        long newHandle = RustLibrary.lteScalar(getHandle(), n.doubleValue());
+        return toArray(newHandle, DataType.BOOLEAN, false, false);
     }
 
     /** {@inheritDoc} */
@@ -589,7 +685,23 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public RsNDArray addi(NDArray other) {
-        intern(add(other));
+        try (NDScope ignore = new NDScope()) {
+            RustLibrary.addInplace(getHandle(), manager.from(other).getHandle());
+        }
+        return this;
+    }
+
+    /**
+     * Adds {@code alpha * other} to this {@code NDArray} in place, like {@code torch.add_}.
+     *
+     * @param other the array to add, broadcast to the shape of this array
+     * @param alpha the scale of {@code other}
+     * @return this array after the update
+     */
+    public RsNDArray addScaledi(NDArray other, double alpha) {
+        try (NDScope ignore = new NDScope()) {
+            RustLibrary.addScaledInplace(getHandle(), manager.from(other).getHandle(), alpha);
+        }
         return this;
     }
 
@@ -604,7 +716,9 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public RsNDArray subi(NDArray other) {
-        intern(sub(other));
+        try (NDScope ignore = new NDScope()) {
+            RustLibrary.subInplace(getHandle(), manager.from(other).getHandle());
+        }
         return this;
     }
 
@@ -619,7 +733,9 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public RsNDArray muli(NDArray other) {
-        intern(mul(other));
+        try (NDScope ignore = new NDScope()) {
+            RustLibrary.mulInplace(getHandle(), manager.from(other).getHandle());
+        }
         return this;
     }
 
@@ -634,7 +750,9 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public RsNDArray divi(NDArray other) {
-        intern(div(other));
+        try (NDScope ignore = new NDScope()) {
+            RustLibrary.divInplace(getHandle(), manager.from(other).getHandle());
+        }
         return this;
     }
 
@@ -1004,7 +1122,8 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public NDArray cumProd(int axis, DataType dataType) {
-        return toArray(RustLibrary.cumProdWithType(getHandle(), axis, dataType.ordinal()));
+        int dType = manager.toRustDataType(dataType);
+        return toArray(RustLibrary.cumProdWithType(getHandle(), axis, dType));
     }
 
     /** {@inheritDoc} */
@@ -1034,12 +1153,50 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
         return toArray(RustLibrary.meanWithAxis(getHandle(), axes, keepDims));
     }
 
+    /**
+     * Returns the variance of the elements along the given axes. The variance is {@code NaN}
+     * without degrees of freedom, for example of a single element when {@code unbiased}.
+     *
+     * @param axes the axes along which to compute the variance, all the axes if empty
+     * @param unbiased {@code true} to divide by {@code N - 1} instead of {@code N}
+     * @param keepDims {@code true} to keep the reduced axes with size 1
+     * @return the variance of the elements
+     */
+    public RsNDArray var(int[] axes, boolean unbiased, boolean keepDims) {
+        return toArray(RustLibrary.var(getHandle(), axes, unbiased, keepDims));
+    }
+
+    /**
+     * Returns the standard deviation of the elements along the given axes.
+     *
+     * @param axes the axes along which to compute the standard deviation, all the axes if empty
+     * @param unbiased {@code true} to divide by {@code N - 1} instead of {@code N}
+     * @param keepDims {@code true} to keep the reduced axes with size 1
+     * @return the standard deviation of the elements
+     */
+    public RsNDArray std(int[] axes, boolean unbiased, boolean keepDims) {
+        return toArray(RustLibrary.std(getHandle(), axes, unbiased, keepDims));
+    }
+
     /** {@inheritDoc} */
     @Override
     public RsNDArray normalize(double p, long dim, double eps) {
         return toArray(RustLibrary.normalize(getHandle(), p, dim, eps));
     }
 
+    /**
+     * Performs {@link #normalize(double, long, double)} in place.
+     *
+     * @param p the exponent value in the norm formulation
+     * @param dim the dimension to reduce
+     * @param eps the small value to avoid division by zero
+     * @return the normalized {@code NDArray}
+     */
+    public RsNDArray normalizei(double p, long dim, double eps) {
+        intern(normalize(p, dim, eps));
+        return this;
+    }
+
     /** {@inheritDoc} */
     @Override
     public RsNDArray rotate90(int times, int[] axes) {
@@ -1061,7 +1218,8 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
         if (indices.length == 0) {
             return new NDList(this);
         }
-        long lastIndex = getShape().get(axis);
+        Shape shape = getShape();
+        long lastIndex = shape.get(axis < 0 ? axis + shape.dimension() : axis);
         if (indices[indices.length - 1] != lastIndex) {
             long[] tmp = new long[indices.length + 1];
             System.arraycopy(indices, 0, tmp, 0, indices.length);
@@ -1071,6 +1229,18 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
         return toList(RustLibrary.split(getHandle(), indices, axis));
     }
 
+    /**
+     * Splits the {@code NDArray} into the given number of chunks along the axis, the same as
+     * {@code torch.chunk}. The last chunk is smaller if the size is not divisible.
+     *
+     * @param chunks the number of chunks
+     * @param axis the axis to split along
+     * @return the chunks, there may be fewer than requested
+     */
+    public NDList chunk(int chunks, int axis) {
+        return toList(RustLibrary.chunk(getHandle(), chunks, axis));
+    }
+
     /** {@inheritDoc} */
     @Override
     public RsNDArray flatten() {
@@ -1154,7 +1324,20 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public NDList unique(Integer dim, boolean sorted, boolean returnInverse, boolean returnCounts) {
-        throw new UnsupportedOperationException("Not implemented");
+        int axis = dim == null ? 0 : dim;
+        NDList list = toList(RustLibrary.unique(getHandle(), axis, dim == null, sorted));
+        NDList ret = new NDList(list.get(0));
+        if (returnInverse) {
+            ret.add(list.get(1));
+        } else {
+            list.get(1).close();
+        }
+        if (returnCounts) {
+            ret.add(list.get(2));
+        } else {
+            list.get(2).close();
+        }
+        return ret;
     }
 
     /** {@inheritDoc} */
@@ -1205,7 +1388,7 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public RsNDArray sort(int axis) {
-        return toArray(RustLibrary.sort(getHandle(), axis, false));
+        return toArray(RustLibrary.sort(getHandle(), axis, true));
     }
 
     /** {@inheritDoc} */
@@ -1236,9 +1419,6 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public RsNDArray cumSum(int axis) {
-        if (getShape().dimension() > 3) {
-            throw new UnsupportedOperationException("Only 3 dimensions or less is supported");
-        }
         return toArray(RustLibrary.cumSum(getHandle(), axis));
     }
 
@@ -1376,8 +1556,8 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public NDArray matMul(NDArray other) {
-        if (getShape().dimension() < 2 || getShape().dimension() < 2) {
-            throw new IllegalArgumentException("only 2d tensors are supported for matMul()");
+        if (isScalar() || other.isScalar()) {
+            throw new IllegalArgumentException("scalar is not allowed for matMul()");
         }
         try (NDScope ignore = new NDScope()) {
             long otherHandle = manager.from(other).getHandle();
@@ -1385,6 +1565,52 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
         }
     }
 
+    /**
+     * Returns the cosine similarity between this {@code NDArray} and the other along the axis,
+     * the same as {@code torch.nn.functional.cosine_similarity}. The inputs are broadcast.
+     *
+     * @param other the other {@code NDArray}
+     * @param axis the axis of the vectors
+     * @return the cosine similarity, with the axis reduced
+     */
+    public RsNDArray cosineSimilarity(NDArray other, int axis) {
+        try (NDScope ignore = new NDScope()) {
+            long otherHandle = manager.from(other).getHandle();
+            return toArray(RustLibrary.cosineSimilarity(getHandle(), otherHandle, axis), true);
+        }
+    }
+
+    /**
+     * Returns the cosine similarity of all pairs of vectors, for example the {@code (n, m)} scores
+     * of {@code (n, d)} queries and {@code (m, d)} documents. Leading batch dimensions are
+     * broadcast.
+     *
+     * @param other the other {@code NDArray}, with vectors along the last axis
+     * @return the cosine similarity matrix
+     */
+    public RsNDArray cosineSimilarityMatrix(NDArray other) {
+        try (NDScope ignore = new NDScope()) {
+            long otherHandle = manager.from(other).getHandle();
+            return toArray(RustLibrary.cosineSimilarityMatrix(getHandle(), otherHandle), true);
+        }
+    }
+
+    /**
+     * Returns the p-norm distance of all pairs of vectors, the same as {@code torch.cdist}. The
+     * {@code (n, d)} and {@code (m, d)} inputs produce {@code (n, m)} distances, leading batch
+     * dimensions are broadcast.
+     *
+     * @param other the other {@code NDArray}, with vectors along the last axis
+     * @param p the order of the norm, for example 1 for L1 or 2 for L2 distances
+     * @return the distance matrix
+     */
+    public RsNDArray cdist(NDArray other, float p) {
+        try (NDScope ignore = new NDScope()) {
+            long otherHandle = manager.from(other).getHandle();
+            return toArray(RustLibrary.cdist(getHandle(), otherHandle, p), true);
+        }
+    }
+
     /** {@inheritDoc} */
     @Override
     public NDArray batchMatMul(NDArray other) {
@@ -1400,7 +1626,9 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public RsNDArray clip(Number min, Number max) {
-        return toArray(RustLibrary.clip(getHandle(), min.doubleValue(), max.doubleValue()));
+        double lower = min == null ? Double.NEGATIVE_INFINITY : min.doubleValue();
+        double upper = max == null ? Double.POSITIVE_INFINITY : max.doubleValue();
+        return toArray(RustLibrary.clip(getHandle(), lower, upper));
     }
 
     /** {@inheritDoc} */
@@ -1411,10 +1639,71 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
 
     /** {@inheritDoc} */
     @Override
-    public NDArray flip(int... axes) {
+    public RsNDArray flip(int... axes) {
         return toArray(RustLibrary.flip(getHandle(), axes));
     }
 
+    /**
+     * Returns the elements from {@code start} (inclusive) to {@code stop} (exclusive) with the
+     * given step for each leading axis, like the python slice {@code a[1:7:2]}. Negative bounds
+     * count from the end of the axis and out of range bounds are clamped.
+     *
+     * @param start the first index of each axis
+     * @param stop the end index of each axis
+     * @param step the positive step of each axis
+     * @return the sliced {@code NDArray}
+     */
+    public RsNDArray slice(long[] start, long[] stop, long[] step) {
+        return toArray(RustLibrary.fullSlice(getHandle(), start, stop, step));
+    }
+
+    /**
+     * Returns a view of {@code length} elements along the axis starting at {@code start}, the
+     * same as {@code torch.narrow}.
+     *
+     * @param axis the axis to narrow
+     * @param start the first index, negative values count from the end
+     * @param length the number of elements to keep
+     * @return the narrowed {@code NDArray}
+     */
+    public RsNDArray narrow(int axis, long start, long length) {
+        return toArray(RustLibrary.narrow(getHandle(), axis, start, length));
+    }
+
+    /**
+     * Rolls the elements along the given axes, the same as {@code torch.roll}. Elements shifted
+     * beyond the last position are re-introduced at the first position.
+     *
+     * @param shifts the number of places to shift along each axis
+     * @param axes the axes to roll along, the {@code NDArray} is flattened if empty
+     * @return the rolled {@code NDArray}
+     */
+    public RsNDArray roll(long[] shifts, int... axes) {
+        return toArray(RustLibrary.roll(getHandle(), shifts, axes));
+    }
+
+    /**
+     * Returns the lower triangular part of the last two dimensions, the other elements are set to
+     * zero. Use {@code tril(0)} of a square {@code NDArray} of ones to build a causal mask.
+     *
+     * @param k the diagonal to keep, 0 is the main diagonal and positive values are above it
+     * @return the lower triangular {@code NDArray}
+     */
+    public RsNDArray tril(int k) {
+        return toArray(RustLibrary.tril(getHandle(), k));
+    }
+
+    /**
+     * Returns the upper triangular part of the last two dimensions, the other elements are set to
+     * zero.
+     *
+     * @param k the diagonal to keep, 0 is the main diagonal and positive values are above it
+     * @return the upper triangular {@code NDArray}
+     */
+    public RsNDArray triu(int k) {
+        return toArray(RustLibrary.triu(getHandle(), k));
+    }
+
     /** {@inheritDoc} */
     @Override
     public RsNDArray transpose() {
@@ -1453,10 +1742,21 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public RsNDArray argMax(int axis) {
+        return argMax(axis, false);
+    }
+
+    /**
+     * Returns the indices of the maximum values along the given axis.
+     *
+     * @param axis the axis along which to find maximum values
+     * @param keepDims {@code true} to keep the reduced axis with size 1
+     * @return the indices of the maximum values along the axis
+     */
+    public RsNDArray argMax(int axis, boolean keepDims) {
         if (isScalar()) {
             return (RsNDArray) manager.create(0L);
         }
-        return toArray(RustLibrary.argMaxWithAxis(getHandle(), axis, false));
+        return toArray(RustLibrary.argMaxWithAxis(getHandle(), axis, keepDims));
     }
 
     /** {@inheritDoc} */
@@ -1480,10 +1780,21 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public RsNDArray argMin(int axis) {
+        return argMin(axis, false);
+    }
+
+    /**
+     * Returns the indices of the minimum values along the given axis.
+     *
+     * @param axis the axis along which to find minimum values
+     * @param keepDims {@code true} to keep the reduced axis with size 1
+     * @return the indices of the minimum values along the axis
+     */
+    public RsNDArray argMin(int axis, boolean keepDims) {
         if (isScalar()) {
             return (RsNDArray) manager.create(0L);
         }
-        return toArray(RustLibrary.argMinWithAxis(getHandle(), axis, false));
+        return toArray(RustLibrary.argMinWithAxis(getHandle(), axis, keepDims));
     }
 
     /** {@inheritDoc} */
@@ -1567,8 +1878,9 @@ public class RsNDArray extends NativeResource<Long> implements NDArray {
     /** {@inheritDoc} */
     @Override
     public NDArray oneHot(int depth, float onValue, float offValue, DataType dataType) {
-        return toArray(
-                RustLibrary.oneHot(getHandle(), depth, onValue, offValue, dataType.ordinal()));
+        int dType = manager.toRustDataType(dataType);
+        long handle = RustLibrary.oneHot(getHandle(), depth, onValue, offValue, dType);
+        return toArray(handle, dataType, false, false);
     }
 
     /** {@inheritDoc} */
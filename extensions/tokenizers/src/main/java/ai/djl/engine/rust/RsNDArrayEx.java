@@ -210,13 +210,15 @@ public class RsNDArrayEx implements NDArrayEx {
             Shape padding,
             boolean ceilMode,
             boolean countIncludePad) {
-        if (kernelShape.size() != 2) {
-            throw new UnsupportedOperationException("Only avgPool2d is supported");
-        }
         return new RsNDArray(
                 array.getManager(),
-                RustLibrary.avgPool2d(
-                        array.getHandle(), kernelShape.getShape(), stride.getShape()));
+                RustLibrary.avgPool(
+                        array.getHandle(),
+                        kernelShape.getShape(),
+                        stride.getShape(),
+                        padding.getShape(),
+                        ceilMode,
+                        countIncludePad));
     }
 
     /** {@inheritDoc} */
@@ -355,7 +357,24 @@ public class RsNDArrayEx implements NDArrayEx {
             Shape padding,
             Shape dilation,
             int groups) {
-        throw new UnsupportedOperationException("Not implemented");
+        RsNDManager manager = array.getManager();
+        try (NDScope ignore = new NDScope()) {
+            long inputHandle = manager.from(input).getHandle();
+            long weightHandle = manager.from(weight).getHandle();
+            long biasHandle = bias == null ? 0 : manager.from(bias).getHandle();
+            long newHandle =
+                    RustLibrary.convolution(
+                            inputHandle,
+                            weightHandle,
+                            biasHandle,
+                            stride.getShape(),
+                            padding.getShape(),
+                            dilation.getShape(),
+                            groups);
+            RsNDArray ret = new RsNDArray(manager, newHandle);
+            NDScope.unregister(ret);
+            return new NDList(ret);
+        }
     }
 
     /** {@inheritDoc} */
@@ -400,7 +419,40 @@ public class RsNDArrayEx implements NDArrayEx {
     @Override
     public NDList layerNorm(
             NDArray input, Shape normalizedShape, NDArray gamma, NDArray beta, float eps) {
-        throw new UnsupportedOperationException("Not implemented");
+        RsNDManager manager = array.getManager();
+        try (NDScope ignore = new NDScope()) {
+            long inputHandle = manager.from(input).getHandle();
+            long gammaHandle = gamma == null ? 0 : manager.from(gamma).getHandle();
+            long betaHandle = beta == null ? 0 : manager.from(beta).getHandle();
+            long[] shape = normalizedShape.getShape();
+            long newHandle =
+                    RustLibrary.layerNorm(inputHandle, shape, gammaHandle, betaHandle, eps);
+            RsNDArray ret = new RsNDArray(manager, newHandle);
+            NDScope.unregister(ret);
+            return new NDList(ret);
+        }
+    }
+
+    /**
+     * Applies root mean square normalization over the trailing dimensions of the input.
+     *
+     * @param input the input {@code NDArray}
+     * @param normalizedShape the trailing dimensions to normalize
+     * @param gamma the optional weight {@code NDArray}
+     * @param eps the small value added to the mean square for numerical stability
+     * @return the normalized {@code NDArray}
+     */
+    public NDList rmsNorm(NDArray input, Shape normalizedShape, NDArray gamma, float eps) {
+        RsNDManager manager = array.getManager();
+        try (NDScope ignore = new NDScope()) {
+            long inputHandle = manager.from(input).getHandle();
+            long gammaHandle = gamma == null ? 0 : manager.from(gamma).getHandle();
+            long[] shape = normalizedShape.getShape();
+            long newHandle = RustLibrary.rmsNorm(inputHandle, shape, gammaHandle, eps);
+            RsNDArray ret = new RsNDArray(manager, newHandle);
+            NDScope.unregister(ret);
+            return new NDList(ret);
+        }
     }
 
     /** {@inheritDoc} */
@@ -515,11 +567,6 @@ public class RsNDArrayEx implements NDArrayEx {
     /** {@inheritDoc} */
     @Override
     public RsNDArray where(NDArray condition, NDArray other) {
-        // Try to broadcast if shape mismatch
-        if (!condition.getShape().equals(array.getShape())) {
-            throw new UnsupportedOperationException(
-                    "condition and self shape mismatch, broadcast is not supported");
-        }
         RsNDManager manager = array.getManager();
         try (NDScope ignore = new NDScope()) {
             long conditionHandle = manager.from(condition).getHandle();
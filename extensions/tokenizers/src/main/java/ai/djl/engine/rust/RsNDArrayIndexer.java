@@ -64,8 +64,8 @@ public class RsNDArrayIndexer extends NDArrayIndexer {
         long[] max = fullSlice.getMax();
         long[] step = fullSlice.getStep();
         long[] s = array.getShape().getShape().clone();
-        if (Arrays.stream(step).anyMatch(i -> i != 1)) {
-            throw new UnsupportedOperationException("only step 1 is supported");
+        if (Arrays.stream(step).anyMatch(i -> i < 1)) {
+            throw new UnsupportedOperationException("only positive steps are supported");
         }
         for (int i = 0; i < min.length; i++) {
             if (min[i] >= max[i] || min[i] >= s[i]) {
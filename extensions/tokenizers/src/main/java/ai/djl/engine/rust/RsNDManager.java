@@ -14,9 +14,13 @@ package ai.djl.engine.rust;
 
 import ai.djl.Device;
 import ai.djl.engine.Engine;
+import ai.djl.huggingface.tokenizers.jni.TensorEncoder;
+import ai.djl.huggingface.tokenizers.jni.TokenizersLibrary;
 import ai.djl.ndarray.BaseNDManager;
 import ai.djl.ndarray.NDArray;
+import ai.djl.ndarray.NDList;
 import ai.djl.ndarray.NDManager;
+import ai.djl.ndarray.NDScope;
 import ai.djl.ndarray.types.DataType;
 import ai.djl.ndarray.types.Shape;
 
@@ -24,9 +28,14 @@ import java.nio.Buffer;
 import java.nio.ByteBuffer;
 import java.nio.ByteOrder;
 import java.nio.charset.Charset;
+import java.nio.file.Path;
+import java.util.LinkedHashMap;
+import java.util.Map;
+import java.util.Objects;
+import java.util.UUID;
 
 /** {@code PtNDManager} is the Rust implementation of {@link NDManager}. */
-public class RsNDManager extends BaseNDManager {
+public class RsNDManager extends BaseNDManager implements TensorEncoder {
 
     private static final RsNDManager SYSTEM_MANAGER = new SystemManager();
 
@@ -44,6 +53,43 @@ public class RsNDManager extends BaseNDManager {
         return ByteBuffer.allocateDirect(capacity).order(ByteOrder.nativeOrder());
     }
 
+    /**
+     * Allocates a direct buffer in page-locked host memory, CUDA devices copy it with DMA instead
+     * of staging it through a driver buffer. The memory is released when the manager is closed,
+     * a regular direct buffer is returned when no GPU is available.
+     *
+     * @param capacity the new buffer's capacity, in bytes
+     * @return the new pinned byte buffer
+     */
+    public ByteBuffer allocatePinned(int capacity) {
+        if (Engine.getEngine(RsEngine.ENGINE_NAME).getGpuCount() == 0) {
+            return allocateDirect(capacity);
+        }
+        ByteBuffer buf = RustLibrary.allocatePinned(capacity).order(ByteOrder.nativeOrder());
+        attachInternal(UUID.randomUUID().toString(), () -> RustLibrary.freePinned(buf));
+        return buf;
+    }
+
+    /** {@inheritDoc} */
+    @Override
+    public NDList batchEncode(
+            long tokenizer, String[] inputs, boolean addSpecialTokens, boolean withTokenType) {
+        String deviceType = device.getDeviceType();
+        int deviceId = device.getDeviceId();
+        long[] handles =
+                TokenizersLibrary.LIB.batchEncodeToTensors(
+                        tokenizer, inputs, addSpecialTokens, deviceType, deviceId);
+        NDList list = new NDList(withTokenType ? 3 : 2);
+        list.add(new RsNDArray(this, handles[0]));
+        list.add(new RsNDArray(this, handles[1]));
+        if (withTokenType) {
+            list.add(new RsNDArray(this, handles[2]));
+        } else {
+            RustLibrary.deleteTensor(handles[2]);
+        }
+        return list;
+    }
+
     /** {@inheritDoc} */
     @Override
     public RsNDArray from(NDArray array) {
@@ -144,30 +190,21 @@ public class RsNDManager extends BaseNDManager {
     /** {@inheritDoc} */
     @Override
     public NDArray eye(int rows, int cols, int k, DataType dataType) {
-        if (k != 0) {
-            throw new UnsupportedOperationException(
-                    "index of the diagonal is not supported in Rust");
-        }
-        if (rows != cols) {
-            throw new UnsupportedOperationException("rows must equals to columns in Rust");
-        }
         String deviceType = device.getDeviceType();
         int deviceId = device.getDeviceId();
         int dType = toRustDataType(dataType);
-        long handle = RustLibrary.eye(rows, cols, dType, deviceType, deviceId);
+        long handle = RustLibrary.eye(rows, cols, k, dType, deviceType, deviceId);
         return new RsNDArray(this, handle, dataType);
     }
 
     /** {@inheritDoc} */
     @Override
     public NDArray linspace(float start, float stop, int num, boolean endpoint) {
-        if (!endpoint) {
-            throw new UnsupportedOperationException("endpoint only support true");
-        }
         String deviceType = device.getDeviceType();
         int deviceId = device.getDeviceId();
         int dType = DataType.FLOAT32.ordinal();
-        long handle = RustLibrary.linspace(start, stop, num, dType, deviceType, deviceId);
+        long handle =
+                RustLibrary.linspace(start, stop, num, endpoint, dType, deviceType, deviceId);
         return new RsNDArray(this, handle, DataType.FLOAT32);
     }
 
@@ -194,23 +231,55 @@ public class RsNDManager extends BaseNDManager {
     /** {@inheritDoc} */
     @Override
     public NDArray randomUniform(float low, float high, Shape shape, DataType dataType) {
-        long[] sh = shape.getShape();
-        String deviceType = device.getDeviceType();
-        int deviceId = device.getDeviceId();
-        int dType = toRustDataType(dataType);
-        long handle = RustLibrary.uniform(low, high, sh, dType, deviceType, deviceId);
-        return new RsNDArray(this, handle, dataType);
+        return sampleUniform(low, high, shape, dataType, 0L);
     }
 
     /** {@inheritDoc} */
     @Override
     public NDArray randomNormal(float loc, float scale, Shape shape, DataType dataType) {
-        long[] sh = shape.getShape();
-        String deviceType = device.getDeviceType();
-        int deviceId = device.getDeviceId();
-        int dType = toRustDataType(dataType);
-        long handle = RustLibrary.randomNormal(loc, scale, sh, dType, deviceType, deviceId);
-        return new RsNDArray(this, handle, dataType);
+        return sampleNormal(loc, scale, shape, dataType, 0L);
+    }
+
+    /**
+     * Draws samples from a uniform distribution with a dedicated generator seeded by {@code seed}.
+     *
+     * <p>The same seed always produces the same samples, regardless of the engine random seed.
+     *
+     * @param low the lower boundary of the output interval
+     * @param high the upper boundary of the output interval
+     * @param shape the output {@link Shape}
+     * @param dataType the {@link DataType} of the {@link NDArray}
+     * @param seed the seed of the generator
+     * @return the drawn samples {@link NDArray}
+     */
+    public NDArray randomUniform(float low, float high, Shape shape, DataType dataType, long seed) {
+        long generator = RustLibrary.newGenerator(seed);
+        try {
+            return sampleUniform(low, high, shape, dataType, generator);
+        } finally {
+            RustLibrary.deleteGenerator(generator);
+        }
+    }
+
+    /**
+     * Draws samples from a normal distribution with a dedicated generator seeded by {@code seed}.
+     *
+     * <p>The same seed always produces the same samples, regardless of the engine random seed.
+     *
+     * @param loc the mean of the distribution
+     * @param scale the standard deviation of the distribution
+     * @param shape the output {@link Shape}
+     * @param dataType the {@link DataType} of the {@link NDArray}
+     * @param seed the seed of the generator
+     * @return the drawn samples {@link NDArray}
+     */
+    public NDArray randomNormal(float loc, float scale, Shape shape, DataType dataType, long seed) {
+        long generator = RustLibrary.newGenerator(seed);
+        try {
+            return sampleNormal(loc, scale, shape, dataType, generator);
+        } finally {
+            RustLibrary.deleteGenerator(generator);
+        }
     }
 
     /** {@inheritDoc} */
@@ -222,6 +291,130 @@ public class RsNDManager extends BaseNDManager {
         return new RsNDArray(this, handle);
     }
 
+    /**
+     * Evaluates the Einstein summation convention on the operands, for example {@code
+     * "bqd,bkd->bqk"}.
+     *
+     * @param equation the subscripts of the operands and the output
+     * @param operands the operands
+     * @return the result {@code NDArray}
+     */
+    public RsNDArray einsum(String equation, NDArray... operands) {
+        long[] handles = new long[operands.length];
+        try (NDScope ignore = new NDScope()) {
+            for (int i = 0; i < operands.length; ++i) {
+                handles[i] = from(operands[i]).getHandle();
+            }
+            RsNDArray array = new RsNDArray(this, RustLibrary.einsum(equation, handles));
+            NDScope.unregister(array);
+            return array;
+        }
+    }
+
+    /** {@inheritDoc} */
+    @Override
+    public NDList load(Path path) {
+        String fileName = Objects.requireNonNull(path.getFileName()).toString();
+        Map<String, NDArray> tensors;
+        if (fileName.endsWith(".safetensors")) {
+            tensors = loadTensors(path);
+        } else if (fileName.endsWith(".npz")) {
+            tensors = loadNpz(path);
+        } else if (fileName.endsWith(".npy")) {
+            return new NDList(loadNpy(path));
+        } else {
+            return super.load(path);
+        }
+        NDList list = new NDList(tensors.size());
+        for (Map.Entry<String, NDArray> entry : tensors.entrySet()) {
+            NDArray array = entry.getValue();
+            array.setName(entry.getKey());
+            list.add(array);
+        }
+        return list;
+    }
+
+    /**
+     * Saves the named {@code NDArray}s to a file in the safetensors format.
+     *
+     * @param tensors the {@code NDArray}s to save by name
+     * @param path the path to the file
+     */
+    public void saveTensors(Map<String, NDArray> tensors, Path path) {
+        String[] names = tensors.keySet().toArray(new String[0]);
+        try (NDScope ignore = new NDScope()) {
+            long[] handles = getHandles(tensors, names);
+            RustLibrary.saveTensors(names, handles, path.toAbsolutePath().toString());
+        }
+    }
+
+    /**
+     * Loads all the {@code NDArray}s of a safetensors file to the device of this manager.
+     *
+     * @param path the path to the file
+     * @return the {@code NDArray}s by name, sorted by name
+     */
+    public Map<String, NDArray> loadTensors(Path path) {
+        String file = path.toAbsolutePath().toString();
+        String[] names = RustLibrary.listTensors(file);
+        String deviceType = device.getDeviceType();
+        int deviceId = device.getDeviceId();
+        return toMap(names, RustLibrary.loadTensors(file, names, deviceType, deviceId));
+    }
+
+    /**
+     * Saves the {@code NDArray} to a NumPy {@code .npy} file.
+     *
+     * @param array the {@code NDArray} to save
+     * @param path the path to the file
+     */
+    public void saveNpy(NDArray array, Path path) {
+        try (NDScope ignore = new NDScope()) {
+            RustLibrary.writeNpy(from(array).getHandle(), path.toAbsolutePath().toString());
+        }
+    }
+
+    /**
+     * Loads an {@code NDArray} from a NumPy {@code .npy} file to the device of this manager.
+     *
+     * @param path the path to the file
+     * @return the loaded {@code NDArray}
+     */
+    public NDArray loadNpy(Path path) {
+        String file = path.toAbsolutePath().toString();
+        String deviceType = device.getDeviceType();
+        int deviceId = device.getDeviceId();
+        return new RsNDArray(this, RustLibrary.readNpy(file, deviceType, deviceId));
+    }
+
+    /**
+     * Saves the named {@code NDArray}s to a NumPy {@code .npz} file.
+     *
+     * @param tensors the {@code NDArray}s to save by name
+     * @param path the path to the file
+     */
+    public void saveNpz(Map<String, NDArray> tensors, Path path) {
+        String[] names = tensors.keySet().toArray(new String[0]);
+        try (NDScope ignore = new NDScope()) {
+            long[] handles = getHandles(tensors, names);
+            RustLibrary.writeNpz(names, handles, path.toAbsolutePath().toString());
+        }
+    }
+
+    /**
+     * Loads all the {@code NDArray}s of a NumPy {@code .npz} file to the device of this manager.
+     *
+     * @param path the path to the file
+     * @return the {@code NDArray}s by name, sorted by name
+     */
+    public Map<String, NDArray> loadNpz(Path path) {
+        String file = path.toAbsolutePath().toString();
+        String[] names = RustLibrary.listNpz(file);
+        String deviceType = device.getDeviceType();
+        int deviceId = device.getDeviceId();
+        return toMap(names, RustLibrary.readNpz(file, names, deviceType, deviceId));
+    }
+
     /** {@inheritDoc} */
     @Override
     public RsNDManager newSubManager(Device device) {
@@ -256,6 +449,43 @@ public class RsNDManager extends BaseNDManager {
         }
     }
 
+    private NDArray sampleUniform(
+            float low, float high, Shape shape, DataType dataType, long generator) {
+        long[] sh = shape.getShape();
+        String deviceType = device.getDeviceType();
+        int deviceId = device.getDeviceId();
+        int dType = toRustDataType(dataType);
+        long handle = RustLibrary.uniform(low, high, sh, dType, deviceType, deviceId, generator);
+        return new RsNDArray(this, handle, dataType);
+    }
+
+    private NDArray sampleNormal(
+            float loc, float scale, Shape shape, DataType dataType, long generator) {
+        long[] sh = shape.getShape();
+        String deviceType = device.getDeviceType();
+        int deviceId = device.getDeviceId();
+        int dType = toRustDataType(dataType);
+        long handle =
+                RustLibrary.randomNormal(loc, scale, sh, dType, deviceType, deviceId, generator);
+        return new RsNDArray(this, handle, dataType);
+    }
+
+    private long[] getHandles(Map<String, NDArray> tensors, String[] names) {
+        long[] handles = new long[names.length];
+        for (int i = 0; i < names.length; ++i) {
+            handles[i] = from(tensors.get(names[i])).getHandle();
+        }
+        return handles;
+    }
+
+    private Map<String, NDArray> toMap(String[] names, long[] handles) {
+        Map<String, NDArray> tensors = new LinkedHashMap<>();
+        for (int i = 0; i < names.length; ++i) {
+            tensors.put(names[i], new RsNDArray(this, handles[i]));
+        }
+        return tensors;
+    }
+
     /** The SystemManager is the root {@link RsNDManager} of which all others are children. */
     private static final class SystemManager extends RsNDManager implements SystemNDManager {
 
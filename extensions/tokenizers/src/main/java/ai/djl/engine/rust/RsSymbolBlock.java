@@ -13,21 +13,38 @@
 package ai.djl.engine.rust;
 
 import ai.djl.ndarray.NDList;
+import ai.djl.ndarray.NDManager;
+import ai.djl.ndarray.types.DataType;
 import ai.djl.nn.AbstractSymbolBlock;
 import ai.djl.nn.ParameterList;
 import ai.djl.nn.SymbolBlock;
 import ai.djl.training.ParameterStore;
+import ai.djl.util.JsonUtils;
 import ai.djl.util.PairList;
 
+import com.google.gson.JsonObject;
+
+import java.nio.file.Path;
 import java.util.Arrays;
+import java.util.List;
+import java.util.concurrent.CancellationException;
+import java.util.concurrent.CompletableFuture;
 import java.util.concurrent.atomic.AtomicReference;
 
-/** {@code RsSymbolBlock} is the Rust implementation of {@link SymbolBlock}. */
+/**
+ * {@code RsSymbolBlock} is the Rust implementation of {@link SymbolBlock}.
+ *
+ * <p>Forwards return all the {@link #getOutputNames() outputs} of the model. Set the {@code
+ * last_hidden_state_only} forward parameter to {@code true} to only compute the last hidden state,
+ * the heads such as the BERT pooler are then skipped.
+ */
 public class RsSymbolBlock extends AbstractSymbolBlock implements AutoCloseable {
 
     private AtomicReference<Long> handle;
     private String uid;
     private RsNDManager manager;
+    private List<String> outputNames;
+    private boolean owned;
 
     /**
      * Constructs a {@code RsSymbolBlock}.
@@ -43,10 +60,69 @@ public class RsSymbolBlock extends AbstractSymbolBlock implements AutoCloseable
         this.handle = new AtomicReference<>(handle);
         this.manager = manager;
         inputNames = Arrays.asList(RustLibrary.getInputNames(handle));
+        outputNames = Arrays.asList(RustLibrary.getOutputNames(handle));
         uid = String.valueOf(handle);
         manager.attachInternal(uid, this);
     }
 
+    private RsSymbolBlock(RsNDManager manager, long handle, boolean owned) {
+        this(manager, handle);
+        this.owned = owned;
+    }
+
+    /**
+     * Returns a new block that shares the native model with this one, so several predictors can
+     * use one copy of the weights. The weights are freed when the last block or model holding
+     * them is closed.
+     *
+     * @param manager the manager to use for the new block
+     * @return a new block holding its own reference to the model
+     */
+    public RsSymbolBlock retain(RsNDManager manager) {
+        return new RsSymbolBlock(manager, RustLibrary.retainModel(getHandle()), true);
+    }
+
+    /**
+     * Loads a PEFT LoRA adapter next to the model without merging it, so batches can run with
+     * different adapters. Loading an adapter with the name of a loaded one replaces it.
+     *
+     * @param name the name of the adapter
+     * @param adapterPath the adapter directory with {@code adapter_config.json}
+     */
+    public void loadAdapter(String name, Path adapterPath) {
+        RustLibrary.loadAdapter(getHandle(), name, adapterPath.toString());
+    }
+
+    /**
+     * Frees a LoRA adapter, blocks created for it fail to run afterwards.
+     *
+     * @param name the name of the adapter
+     */
+    public void unloadAdapter(String name) {
+        RustLibrary.unloadAdapter(getHandle(), name);
+    }
+
+    /**
+     * Returns a new block that shares the native model with this one and applies a loaded LoRA
+     * adapter in its forwards, for example one block per tenant.
+     *
+     * @param manager the manager to use for the new block
+     * @param name the name of the adapter
+     * @return a new block holding its own reference to the model
+     */
+    public RsSymbolBlock withAdapter(RsNDManager manager, String name) {
+        return new RsSymbolBlock(manager, RustLibrary.createAdaptedModel(getHandle(), name), true);
+    }
+
+    /**
+     * Returns the number of blocks and models that hold the native model.
+     *
+     * @return the number of references to the native model
+     */
+    public long getReferenceCount() {
+        return RustLibrary.getModelReferenceCount(getHandle());
+    }
+
     /** {@inheritDoc} */
     @Override
     protected NDList forwardInternal(
@@ -54,19 +130,179 @@ public class RsSymbolBlock extends AbstractSymbolBlock implements AutoCloseable
             NDList inputs,
             boolean training,
             PairList<String, Object> params) {
-        if (inputNames.size() != inputs.size()) {
-            throw new IllegalArgumentException("Input size mismatch, requires: " + inputNames);
+        checkInputs(inputs);
+        long token = 0;
+        if (params != null && params.get("cancellation_token") != null) {
+            token = ((RsCancellationToken) params.get("cancellation_token")).getHandle();
         }
+        boolean hiddenStateOnly =
+                params != null && Boolean.TRUE.equals(params.get("last_hidden_state_only"));
         try (RsNDManager sub = (RsNDManager) manager.newSubManager()) {
             long[] inputHandles = new long[inputs.size()];
             for (int i = 0; i < inputs.size(); i++) {
                 inputHandles[i] = sub.from(inputs.get(i)).getHandle();
             }
-            long outputHandle = RustLibrary.runInference(handle.get(), inputHandles);
-            RsNDArray output = new RsNDArray(manager, outputHandle, inputs.head().getDataType());
+            if (hiddenStateOnly) {
+                long output = RustLibrary.runInference(handle.get(), inputHandles, token);
+                return toNDList(new long[] {output}, inputs);
+            }
+            long[] outputHandles =
+                    RustLibrary.runInferenceMulti(handle.get(), inputHandles, token);
+            return toNDList(outputHandles, inputs);
+        }
+    }
+
+    /**
+     * Returns the names of the model outputs, the first one is the last hidden state.
+     *
+     * @return the names of the model outputs
+     */
+    public List<String> getOutputNames() {
+        return outputNames;
+    }
+
+    /**
+     * Returns the metadata of the model, for example {@code architecture}, {@code hidden_size},
+     * {@code max_position_embeddings}, {@code dtype}, {@code device}, {@code parameter_count},
+     * {@code quantization} and {@code id2label}.
+     *
+     * @return the metadata of the model
+     */
+    public JsonObject getMetadata() {
+        String json = RustLibrary.getModelMetadata(handle.get());
+        return JsonUtils.GSON.fromJson(json, JsonObject.class);
+    }
+
+    /**
+     * Returns the attention implementation chosen when the model was loaded, {@code eager} or
+     * {@code flash}. It's requested with the {@code attention_backend} load option.
+     *
+     * @return the attention implementation of the model
+     */
+    public String getAttentionBackend() {
+        return getMetadata().get("attention_backend").getAsString();
+    }
+
+    private void checkInputs(NDList inputs) {
+        // the inputs can be followed by position_ids, e.g. for left padded batches
+        int size = inputs.size();
+        if (size != inputNames.size() && size != inputNames.size() + 1) {
+            throw new IllegalArgumentException(
+                    "Input size mismatch, requires: " + inputNames + " and optional position_ids");
+        }
+    }
+
+    private NDList toNDList(long[] outputHandles, NDList inputs) {
+        NDList list = new NDList(outputHandles.length);
+        for (int i = 0; i < outputHandles.length; ++i) {
+            // the outputs keep the dtype of the model, e.g. bfloat16
+            RsNDArray output = new RsNDArray(manager, outputHandles[i]);
+            output.setName(outputNames.get(i));
             output.attach(inputs.head().getManager());
-            return new NDList(output);
+            list.add(output);
+        }
+        return list;
+    }
+
+    /**
+     * Runs the forward in the native inter-op thread pool, so the calling thread isn't blocked
+     * while the model runs. The inference holds a reference to the native model, so the block can
+     * be closed before the returned future completes. Cancelling the future stops the inference
+     * before its next layer.
+     *
+     * @param inputs the input NDList
+     * @return a future that completes with the output NDList
+     */
+    public CompletableFuture<NDList> forwardAsync(NDList inputs) {
+        checkInputs(inputs);
+        CompletableFuture<Void> done = new CompletableFuture<>();
+        RsCancellationToken token = new RsCancellationToken();
+        long future;
+        try (RsNDManager sub = (RsNDManager) manager.newSubManager()) {
+            long[] inputHandles = new long[inputs.size()];
+            for (int i = 0; i < inputs.size(); i++) {
+                inputHandles[i] = sub.from(inputs.get(i)).getHandle();
+            }
+            // the native inputs are kept alive by the worker
+            future =
+                    RustLibrary.runInferenceAsync(
+                            getHandle(),
+                            inputHandles,
+                            token.getHandle(),
+                            () -> done.complete(null));
+        }
+        CompletableFuture<NDList> result = new CompletableFuture<>();
+        result.whenComplete(
+                (output, e) -> {
+                    if (e instanceof CancellationException) {
+                        token.cancel();
+                    }
+                });
+        // the native callback always fires, cancelled or not, so the future and token are freed
+        done.whenComplete(
+                (v, e) -> {
+                    try {
+                        if (!result.isDone()) {
+                            long[] outputs = RustLibrary.getInferenceResult(future);
+                            result.complete(toNDList(outputs, inputs));
+                        }
+                    } catch (RuntimeException ex) {
+                        result.completeExceptionally(ex);
+                    } finally {
+                        RustLibrary.deleteInferenceFuture(future);
+                        token.close();
+                    }
+                });
+        return result;
+    }
+
+    /**
+     * Runs the forward on token ids of different lengths without padding them in Java. The batch
+     * is padded natively and the outputs are followed by the {@code attention_mask} that marks the
+     * padding.
+     *
+     * @param manager the manager to attach the outputs to
+     * @param inputIds the token ids of each sequence
+     * @param tokenTypeIds the token type ids of each sequence, or {@code null}
+     * @return the output NDList
+     */
+    public NDList forwardJagged(NDManager manager, long[][] inputIds, long[][] tokenTypeIds) {
+        long[] outputHandles =
+                RustLibrary.runInferenceJagged(handle.get(), inputIds, tokenTypeIds, 0);
+        NDList list = new NDList(outputHandles.length);
+        for (int i = 0; i < outputHandles.length; ++i) {
+            RsNDArray output = new RsNDArray(this.manager, outputHandles[i]);
+            boolean isMask = i == outputHandles.length - 1;
+            output.setName(isMask ? "attention_mask" : outputNames.get(i));
+            output.attach(manager);
+            list.add(output);
         }
+        return list;
+    }
+
+    /**
+     * Runs dummy inputs through the model, so the first requests don't pay for kernel
+     * compilation and memory allocation.
+     *
+     * @param maxBatch the largest batch size expected
+     * @param maxSeqLen the longest sequence length expected
+     */
+    public void warmup(int maxBatch, int maxSeqLen) {
+        RustLibrary.warmupModel(getHandle(), maxBatch, maxSeqLen);
+    }
+
+    /**
+     * Saves the weights of the model to a safetensors file. The weights are saved as the model
+     * loaded them, weights dequantized from AWQ or GPTQ checkpoints are saved dequantized and a
+     * merged adapter is saved merged. Adapters applied per batch aren't saved. Models quantized by
+     * the {@code quantize} load option, GGUF models and models split across tensor parallel ranks
+     * or CUDA streams can't be saved.
+     *
+     * @param path the safetensors file to write
+     * @param dataType the data type of the saved floating point weights
+     */
+    public void save(Path path, DataType dataType) {
+        RustLibrary.saveModel(getHandle(), path.toString(), dataType.ordinal());
     }
 
     /** {@inheritDoc} */
@@ -76,6 +312,9 @@ public class RsSymbolBlock extends AbstractSymbolBlock implements AutoCloseable
         if (pointer != null) {
             manager.detachInternal(uid);
             manager = null;
+            if (owned) {
+                RustLibrary.deleteModel(pointer);
+            }
         }
     }
 
@@ -22,13 +22,94 @@ public final class RustLibrary {
 
     public static native boolean isCudaAvailable();
 
-    public static native long loadModel(String modelPath, int dtype);
+    public static native boolean isMetalAvailable();
+
+    public static native String getBlasBackend();
+
+    public static native String getLiveHandles();
+
+    public static native long freeAllHandles(String kind);
+
+    public static native int getCudaDeviceCount();
+
+    public static native long[] getGpuMemory(int deviceId);
+
+    public static native void emptyCache(int deviceId);
+
+    public static native ByteBuffer allocatePinned(long size);
+
+    public static native void freePinned(ByteBuffer buffer);
+
+    public static native long[] getMemoryPoolStats(int deviceId);
+
+    public static native void setNumThreads(int numThreads);
+
+    public static native int getNumThreads();
+
+    public static native void setNumInteropThreads(int numThreads);
+
+    public static native int getNumInteropThreads();
+
+    public static native void setCheckNumerics(boolean enabled);
+
+    public static native boolean isCheckNumerics();
+
+    public static native void setDeterministic(boolean enabled);
+
+    public static native boolean isDeterministic();
+
+    public static native long loadModel(String modelPath, int dtype, String options);
+
+    public static native String downloadModel(String repoId, String options);
+
+    public static native long loadModelFromBytes(
+            String config, byte[][] shards, int dtype, String options);
 
     public static native long deleteModel(long handle);
 
+    public static native long retainModel(long handle);
+
+    public static native long getModelReferenceCount(long handle);
+
+    public static native void loadAdapter(long handle, String name, String adapterPath);
+
+    public static native void unloadAdapter(long handle, String name);
+
+    public static native long createAdaptedModel(long handle, String name);
+
     public static native String[] getInputNames(long handle);
 
-    public static native long runInference(long handle, long[] inputHandles);
+    public static native String[] getOutputNames(long handle);
+
+    public static native String getModelMetadata(long handle);
+
+    public static native void saveModel(long handle, String path, int dtype);
+
+    public static native void warmupModel(long handle, int maxBatch, int maxSeqLen);
+
+    public static native long runInference(
+            long handle, long[] inputHandles, long cancellationToken);
+
+    public static native long[] runInferenceMulti(
+            long handle, long[] inputHandles, long cancellationToken);
+
+    public static native long[] runInferenceJagged(
+            long handle, long[][] inputIds, long[][] tokenTypeIds, long cancellationToken);
+
+    public static native long runInferenceAsync(
+            long handle, long[] inputHandles, long cancellationToken, Runnable callback);
+
+    public static native boolean isInferenceDone(long future);
+
+    public static native long[] getInferenceResult(long future);
+
+    public static native void deleteInferenceFuture(long future);
+
+    public static native long createCancellationToken();
+
+    public static native void cancelInference(long cancellationToken);
+
+    public static native void deleteCancellationToken(long cancellationToken);
 
     public static native long tensorOf(
             ByteBuffer buf, long[] shape, int dataType, String deviceType, int deviceId);
@@ -44,12 +125,16 @@ public final class RustLibrary {
             float start, float stop, float step, int dataType, String deviceType, int deviceId);
 
     public static native long eye(
-            int rows, int cols, int dataType, String deviceType, int deviceId);
+            int rows, int cols, int k, int dataType, String deviceType, int deviceId);
 
-    public static long linspace(
-            float start, float stop, int num, int dataType, String deviceType, int deviceId) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long linspace(
+            float start,
+            float stop,
+            int num,
+            boolean endpoint,
+            int dataType,
+            String deviceType,
+            int deviceId);
 
     public static long randint(
             long low, long high, long[] shape, int dataType, String deviceType, int deviceId) {
@@ -61,10 +146,28 @@ public final class RustLibrary {
     }
 
     public static native long uniform(
-            float low, float high, long[] shape, int dataType, String deviceType, int deviceId);
+            float low,
+            float high,
+            long[] shape,
+            int dataType,
+            String deviceType,
+            int deviceId,
+            long generator);
 
     public static native long randomNormal(
-            float loc, float scale, long[] shape, int dataType, String deviceType, int deviceId);
+            float loc,
+            float scale,
+            long[] shape,
+            int dataType,
+            String deviceType,
+            int deviceId,
+            long generator);
+
+    public static native void manualSeed(long seed);
+
+    public static native long newGenerator(long seed);
+
+    public static native void deleteGenerator(long handle);
 
     public static long hannWindow(long numPoints, String deviceType, int deviceId) {
         throw new UnsupportedOperationException("Not implemented");
@@ -72,6 +175,24 @@ public final class RustLibrary {
 
     public static native void deleteTensor(long handle);
 
+    public static native void saveTensors(String[] names, long[] handles, String path);
+
+    public static native String[] listTensors(String path);
+
+    public static native long[] loadTensors(
+            String path, String[] names, String deviceType, int deviceId);
+
+    public static native long readNpy(String path, String deviceType, int deviceId);
+
+    public static native void writeNpy(long handle, String path);
+
+    public static native String[] listNpz(String path);
+
+    public static native long[] readNpz(
+            String path, String[] names, String deviceType, int deviceId);
+
+    public static native void writeNpz(String[] names, long[] handles, String path);
+
     public static native int getDataType(long handle);
 
     public static native int[] getDevice(long handle);
@@ -86,12 +207,20 @@ public final class RustLibrary {
 
     public static native long toDataType(long handle, int dataType);
 
-    public static native ByteBuffer getByteBuffer(long handle);
+    public static native long retainTensor(long handle);
+
+    public static native ByteBuffer getDirectByteBuffer(long handle);
+
+    public static native void copyToBuffer(long handle, ByteBuffer buffer);
 
     public static native long fullSlice(long handle, long[] min, long[] max, long[] step);
 
+    public static native long narrow(long handle, int axis, long start, long length);
+
     public static native long gather(long handle, long indexHandle, int axis);
 
+    public static native long indexSelect(long handle, int axis, long indexHandle);
+
     public static long take(long handle, long indexHandle) {
         throw new UnsupportedOperationException("Not implemented");
     }
@@ -102,9 +231,7 @@ public final class RustLibrary {
 
     public static native long scatter(long handle, long indexHandle, long valueHandle, int axis);
 
-    public static long booleanMask(long handle, long indexHandle, int axis) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long booleanMask(long handle, long indexHandle, int axis);
 
     // comparison ops
 
@@ -112,16 +239,28 @@ public final class RustLibrary {
 
     public static native long eq(long handle, long other);
 
+    public static native long eqScalar(long handle, double value);
+
     public static native long neq(long handle, long other);
 
+    public static native long neqScalar(long handle, double value);
+
     public static native long gt(long handle, long other);
 
+    public static native long gtScalar(long handle, double value);
+
     public static native long gte(long handle, long other);
 
+    public static native long gteScalar(long handle, double value);
+
     public static native long lt(long handle, long other);
 
+    public static native long ltScalar(long handle, double value);
+
     public static native long lte(long handle, long other);
 
+    public static native long lteScalar(long handle, double value);
+
     // binary  ops
 
     public static native long add(long handle, long other);
@@ -132,6 +271,16 @@ public final class RustLibrary {
 
     public static native long div(long handle, long other);
 
+    public static native void addInplace(long handle, long other);
+
+    public static native void subInplace(long handle, long other);
+
+    public static native void mulInplace(long handle, long other);
+
+    public static native void divInplace(long handle, long other);
+
+    public static native void addScaledInplace(long handle, long other, double alpha);
+
     public static native long minimum(long handle, long other);
 
     public static native long maximum(long handle, long other);
@@ -226,9 +375,8 @@ public final class RustLibrary {
 
     public static native long sumWithAxis(long handle, int[] axes, boolean keepDims);
 
-    public static long[] topK(long handle, int k, int axis, boolean largest, boolean sorted) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long[] topK(
+            long handle, int k, int axis, boolean largest, boolean sorted);
 
     public static native long max(long handle);
 
@@ -258,17 +406,17 @@ public final class RustLibrary {
 
     public static native long meanWithAxis(long handle, int[] axis, boolean keepDims);
 
+    public static native long var(long handle, int[] axes, boolean unbiased, boolean keepDims);
+
+    public static native long std(long handle, int[] axes, boolean unbiased, boolean keepDims);
+
     public static long[] median(long handle, int axis, boolean keepDims) {
         throw new UnsupportedOperationException("Not implemented");
     }
 
-    public static long cumProd(long handle, int axis) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long cumProd(long handle, int axis);
 
-    public static long cumProdWithType(long handle, int axis, int dataType) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long cumProdWithType(long handle, int axis, int dataType);
 
     public static long prod(long handle) {
         throw new UnsupportedOperationException("Not implemented");
@@ -288,6 +436,8 @@ public final class RustLibrary {
 
     public static native long[] split(long handle, long[] indices, int axis);
 
+    public static native long[] chunk(long handle, int chunks, int axis);
+
     public static native long flatten(long handle);
 
     public static native long flattenWithDims(long handle, int startDim, int endDim);
@@ -298,6 +448,8 @@ public final class RustLibrary {
 
     public static native long squeeze(long handle, int[] axes);
 
+    public static native long[] unique(long handle, int axis, boolean flatten, boolean sorted);
+
     public static long logicalAnd(long handle, long other) {
         throw new UnsupportedOperationException("Not implemented");
     }
@@ -314,21 +466,13 @@ public final class RustLibrary {
         throw new UnsupportedOperationException("Not implemented");
     }
 
-    public static long argSort(long handle, int axis, boolean ascending) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long argSort(long handle, int axis, boolean ascending);
 
-    public static long sort(long handle, int axis, boolean ascending) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long sort(long handle, int axis, boolean ascending);
 
-    public static long softmax(long handle, int axis) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long softmax(long handle, int axis);
 
-    public static long logSoftmax(long handle, int axis) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long logSoftmax(long handle, int axis);
 
     public static native long cumSum(long handle, int axis);
 
@@ -340,21 +484,13 @@ public final class RustLibrary {
         throw new UnsupportedOperationException("Not implemented");
     }
 
-    public static long tile(long handle, long[] repeats) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long tile(long handle, long[] repeats);
 
-    public static long tileWithAxis(long handle, int axis, long repeat) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long tileWithAxis(long handle, int axis, long repeat);
 
-    public static long tileWithShape(long handle, long[] shape) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long tileWithShape(long handle, long[] shape);
 
-    public static long repeat(long handle, long repeat, int axis) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long repeat(long handle, long repeat, int axis);
 
     public static long dot(long handle, long other) {
         throw new UnsupportedOperationException("Not implemented");
@@ -364,33 +500,40 @@ public final class RustLibrary {
 
     public static native long batchMatMul(long handle, long other);
 
+    public static native long cosineSimilarity(long handle, long other, int axis);
+
+    public static native long cosineSimilarityMatrix(long handle, long other);
+
+    public static native long cdist(long handle, long other, float p);
+
+    public static native long einsum(String equation, long[] handles);
+
     public static native long clip(long handle, double min, double max);
 
     public static native long transpose(long handle, int axis1, int axis2);
 
-    public static long flip(long handle, int[] axes) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long flip(long handle, int[] axes);
+
+    public static native long roll(long handle, long[] shifts, int[] axes);
+
+    public static native long tril(long handle, int k);
+
+    public static native long triu(long handle, int k);
 
     public static native long permute(long handle, int[] axes);
 
     public static native long broadcast(long handle, long[] shape);
 
-    public static long nonZero(long handle) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long nonZero(long handle);
 
     public static long inverse(long handle) {
         throw new UnsupportedOperationException("Not implemented");
     }
 
-    public static long norm(long handle, int i, int[] ints, boolean keepDims) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long norm(long handle, int order, int[] axes, boolean keepDims);
 
-    public static long oneHot(long handle, int depth, float onValue, float offValue, int dataType) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long oneHot(
+            long handle, int depth, float onValue, float offValue, int dataType);
 
     public static long complex(long handle) {
         throw new UnsupportedOperationException("Not implemented");
@@ -434,16 +577,35 @@ public final class RustLibrary {
         throw new UnsupportedOperationException("Not implemented");
     }
 
-    public static long maxPool(
-            long handle, long[] kernelShape, long[] stride, long[] padding, boolean ceilMode) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long maxPool(
+            long handle, long[] kernelShape, long[] stride, long[] padding, boolean ceilMode);
 
     public static long adaptiveMaxPool(long handle, long[] shape) {
         throw new UnsupportedOperationException("Not implemented");
     }
 
-    public static native long avgPool2d(long handle, long[] kernelShape, long[] stride);
+    public static native long convolution(
+            long handle,
+            long weightHandle,
+            long biasHandle,
+            long[] stride,
+            long[] padding,
+            long[] dilation,
+            int groups);
+
+    public static native long layerNorm(
+            long handle, long[] normalizedShape, long weightHandle, long biasHandle, float eps);
+
+    public static native long rmsNorm(
+            long handle, long[] normalizedShape, long weightHandle, float eps);
+
+    public static native long avgPool(
+            long handle,
+            long[] kernelShape,
+            long[] stride,
+            long[] padding,
+            boolean ceilMode,
+            boolean countIncludePad);
 
     public static long adaptiveAvgPool(long handle, long[] shape) {
         throw new UnsupportedOperationException("Not implemented");
@@ -454,9 +616,15 @@ public final class RustLibrary {
         throw new UnsupportedOperationException("Not implemented");
     }
 
-    public static long where(long conditionHandle, long handle, long otherHandle) {
-        throw new UnsupportedOperationException("Not implemented");
-    }
+    public static native long pad(long handle, long[] padding, String mode, float value);
+
+    public static native long where(long conditionHandle, long handle, long otherHandle);
+
+    public static native long maskedFill(long handle, long maskHandle, float value);
+
+    public static native long maskedSelect(long handle, long maskHandle);
+
+    public static native long maskedScatter(long handle, long maskHandle, long sourceHandle);
 
     public static native long stack(long[] srcArray, int axis);
 
@@ -14,8 +14,10 @@ package ai.djl.huggingface.tokenizers;
 
 import ai.djl.huggingface.tokenizers.jni.CharSpan;
 import ai.djl.huggingface.tokenizers.jni.LibUtils;
+import ai.djl.huggingface.tokenizers.jni.TensorEncoder;
 import ai.djl.huggingface.tokenizers.jni.TokenizersLibrary;
 import ai.djl.modality.nlp.preprocess.Tokenizer;
+import ai.djl.ndarray.NDList;
 import ai.djl.ndarray.NDManager;
 import ai.djl.translate.ArgumentsUtil;
 import ai.djl.util.Ec2Utils;
@@ -29,9 +31,11 @@ import org.slf4j.LoggerFactory;
 
 import java.io.IOException;
 import java.io.InputStream;
+import java.io.UncheckedIOException;
 import java.nio.file.Files;
 import java.nio.file.Path;
 import java.nio.file.Paths;
+import java.text.Normalizer;
 import java.util.Arrays;
 import java.util.List;
 import java.util.Locale;
@@ -50,16 +54,20 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
     private boolean withOverflowingTokens;
     private Locale doLowerCase;
     private TruncationStrategy truncation;
+    private Side truncationSide;
     private PaddingStrategy padding;
+    private Side paddingSide;
     private int maxLength;
     private int stride;
     private int padToMultipleOf;
     private int modelMaxLength;
 
-    private HuggingFaceTokenizer(long handle, Map<String, String> options) {
+    HuggingFaceTokenizer(long handle, Map<String, String> options) {
         super(handle);
         truncation = TruncationStrategy.LONGEST_FIRST;
+        truncationSide = Side.RIGHT;
         padding = PaddingStrategy.LONGEST;
+        paddingSide = Side.RIGHT;
         maxLength = TokenizersLibrary.LIB.getMaxLength(handle);
         stride = TokenizersLibrary.LIB.getStride(handle);
         padToMultipleOf = TokenizersLibrary.LIB.getPadToMultipleOf(handle);
@@ -73,9 +81,15 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
             if (options.containsKey("truncation")) {
                 truncation = TruncationStrategy.fromValue(options.get("truncation"));
             }
+            if (options.containsKey("truncationSide")) {
+                truncationSide = Side.fromValue(options.get("truncationSide"));
+            }
             if (options.containsKey("padding")) {
                 padding = PaddingStrategy.fromValue(options.get("padding"));
             }
+            if (options.containsKey("paddingSide")) {
+                paddingSide = Side.fromValue(options.get("paddingSide"));
+            }
             maxLength = ArgumentsUtil.intValue(options, "maxLength", maxLength);
             stride = ArgumentsUtil.intValue(options, "stride", stride);
             padToMultipleOf = ArgumentsUtil.intValue(options, "padToMultipleOf", padToMultipleOf);
@@ -85,6 +99,10 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
             } else if (!"false".equals(lowerCase)) {
                 this.doLowerCase = Locale.forLanguageTag(lowerCase);
             }
+            String preTokenizer = options.get("preTokenizer");
+            if (preTokenizer != null) {
+                TokenizersLibrary.LIB.setPreTokenizer(handle, preTokenizer);
+            }
         } else {
             addSpecialTokens = true;
             modelMaxLength = 512;
@@ -119,7 +137,26 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
         }
 
         long handle = TokenizersLibrary.LIB.createTokenizer(identifier, autoToken);
-        return new HuggingFaceTokenizer(handle, options);
+        try {
+            String configFile =
+                    TokenizersLibrary.LIB.downloadTokenizerConfig(identifier, autoToken);
+            TokenizerConfig config = null;
+            if (configFile != null) {
+                config = TokenizerConfig.load(Paths.get(configFile));
+            }
+            HuggingFaceTokenizer tokenizer =
+                    new HuggingFaceTokenizer(handle, withModelMaxLength(config, options));
+            if (config != null) {
+                config.apply(tokenizer);
+            }
+            return tokenizer;
+        } catch (IOException e) {
+            TokenizersLibrary.LIB.deleteTokenizer(handle);
+            throw new UncheckedIOException(e);
+        } catch (RuntimeException e) {
+            TokenizersLibrary.LIB.deleteTokenizer(handle);
+            throw e;
+        }
     }
 
     /**
@@ -143,12 +180,37 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
      */
     public static HuggingFaceTokenizer newInstance(Path modelPath, Map<String, String> options)
             throws IOException {
+        TokenizerConfig config = null;
         if (Files.isDirectory(modelPath)) {
+            config = TokenizerConfig.load(modelPath.resolve("tokenizer_config.json"));
             modelPath = modelPath.resolve("tokenizer.json");
+            options = withModelMaxLength(config, options);
+        } else if (modelPath.toString().endsWith(".model")) {
+            return fromSentencePiece(modelPath, options);
         }
+        HuggingFaceTokenizer tokenizer;
         try (InputStream is = Files.newInputStream(modelPath)) {
-            return newInstance(is, options);
+            tokenizer = newInstance(is, options);
+        }
+        if (config != null) {
+            config.apply(tokenizer);
         }
+        return tokenizer;
+    }
+
+    // the model_max_length of the config is the default, an explicit option takes precedence
+    private static Map<String, String> withModelMaxLength(
+            TokenizerConfig config, Map<String, String> options) {
+        int modelMaxLength = config == null ? -1 : config.getModelMaxLength();
+        if (modelMaxLength > 0 && (options == null || !options.containsKey("modelMaxLength"))) {
+            Map<String, String> map = new ConcurrentHashMap<>();
+            if (options != null) {
+                map.putAll(options);
+            }
+            map.put("modelMaxLength", String.valueOf(modelMaxLength));
+            return map;
+        }
+        return options;
     }
 
     /**
@@ -171,6 +233,70 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
         return new HuggingFaceTokenizer(handle, options);
     }
 
+    /**
+     * Create a {@code HuggingFaceTokenizer} instance from a SentencePiece {@code .model} file.
+     *
+     * <p>Both Unigram and BPE SentencePiece models are supported, the model is converted the same
+     * way as the transformers slow to fast tokenizer conversion.
+     *
+     * @param model the SentencePiece model file
+     * @param options tokenizer options
+     * @return a {@code HuggingFaceTokenizer} instance
+     * @throws IOException when IO operation fails in loading a resource
+     */
+    public static HuggingFaceTokenizer fromSentencePiece(Path model, Map<String, String> options)
+            throws IOException {
+        Ec2Utils.callHome("Huggingface");
+        LibUtils.checkStatus();
+
+        if (!Files.isRegularFile(model)) {
+            throw new IOException("SentencePiece model file not found: " + model);
+        }
+        String modelFile = model.toAbsolutePath().toString();
+        long handle = TokenizersLibrary.LIB.createSentencePieceTokenizer(modelFile);
+        return new HuggingFaceTokenizer(handle, options);
+    }
+
+    /**
+     * Create a {@code HuggingFaceTokenizer} instance from a tiktoken mergeable ranks file.
+     *
+     * @param ranks the tiktoken file, each line contains a base64 encoded token and its rank
+     * @param pattern the pre-tokenization regex, {@code null} to use the {@code cl100k_base}
+     *     pattern
+     * @param specialTokens the special tokens and their ids
+     * @param options tokenizer options
+     * @return a {@code HuggingFaceTokenizer} instance
+     * @throws IOException when IO operation fails in loading a resource
+     */
+    public static HuggingFaceTokenizer fromTiktoken(
+            Path ranks,
+            String pattern,
+            Map<String, Integer> specialTokens,
+            Map<String, String> options)
+            throws IOException {
+        Ec2Utils.callHome("Huggingface");
+        LibUtils.checkStatus();
+
+        if (!Files.isRegularFile(ranks)) {
+            throw new IOException("tiktoken file not found: " + ranks);
+        }
+        int size = specialTokens == null ? 0 : specialTokens.size();
+        String[] tokens = new String[size];
+        long[] ids = new long[size];
+        if (specialTokens != null) {
+            int i = 0;
+            for (Map.Entry<String, Integer> entry : specialTokens.entrySet()) {
+                tokens[i] = entry.getKey();
+                ids[i] = entry.getValue();
+                ++i;
+            }
+        }
+        String ranksFile = ranks.toAbsolutePath().toString();
+        long handle =
+                TokenizersLibrary.LIB.createTiktokenTokenizer(ranksFile, pattern, tokens, ids);
+        return new HuggingFaceTokenizer(handle, options);
+    }
+
     /**
      * Create a pre-trained {@code HuggingFaceTokenizer} instance from {@code InputStream}.
      *
@@ -181,9 +307,36 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
      */
     public static HuggingFaceTokenizer newInstance(InputStream is, Map<String, String> options)
             throws IOException {
+        return newInstance(Utils.toByteArray(is), options);
+    }
+
+    /**
+     * Create a pre-trained {@code HuggingFaceTokenizer} instance from the content of a {@code
+     * tokenizer.json} file.
+     *
+     * @param json the UTF-8 encoded {@code tokenizer.json} content
+     * @param options tokenizer options
+     * @return a {@code HuggingFaceTokenizer} instance
+     */
+    public static HuggingFaceTokenizer newInstance(byte[] json, Map<String, String> options) {
+        Ec2Utils.callHome("Huggingface");
+        LibUtils.checkStatus();
+
+        long handle = TokenizersLibrary.LIB.createTokenizerFromBytes(json);
+        return new HuggingFaceTokenizer(handle, options);
+    }
+
+    /**
+     * Create a pre-trained {@code HuggingFaceTokenizer} instance from the content of a {@code
+     * tokenizer.json} file.
+     *
+     * @param json the {@code tokenizer.json} content
+     * @param options tokenizer options
+     * @return a {@code HuggingFaceTokenizer} instance
+     */
+    public static HuggingFaceTokenizer fromJson(String json, Map<String, String> options) {
         Ec2Utils.callHome("Huggingface");
         LibUtils.checkStatus();
-        String json = Utils.toString(is);
 
         long handle = TokenizersLibrary.LIB.createTokenizerFromString(json);
         return new HuggingFaceTokenizer(handle, options);
@@ -213,6 +366,16 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
         return String.join(" ", tokens).replace(" ##", "").trim();
     }
 
+    /**
+     * Saves the tokenizer as {@code tokenizer.json} and {@code special_tokens_map.json} into
+     * the directory.
+     *
+     * @param directory the directory to save the tokenizer files
+     */
+    public void save(Path directory) {
+        TokenizersLibrary.LIB.saveTokenizer(getHandle(), directory.toAbsolutePath().toString());
+    }
+
     /** {@inheritDoc} */
     @Override
     public void close() {
@@ -249,6 +412,19 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
         return encode(text, addSpecialTokens, withOverflowingTokens);
     }
 
+    /**
+     * Returns the {@code Encoding} of the input sentence after applying the Unicode normalization.
+     *
+     * <p>The character spans of the {@code Encoding} refer to the normalized sentence.
+     *
+     * @param text the input sentence
+     * @param form the Unicode normalization form, for example {@link Normalizer.Form#NFKC}
+     * @return the {@code Encoding} of the normalized input sentence
+     */
+    public Encoding encode(String text, Normalizer.Form form) {
+        return encode(Normalizer.normalize(text, form));
+    }
+
     /**
      * Returns the {@code Encoding} of the input sentence.
      *
@@ -362,6 +538,24 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
         return batchEncode(inputs, addSpecialTokens, withOverflowingTokens);
     }
 
+    /**
+     * Returns the {@code Encoding} of the input sentence in batch after applying the Unicode
+     * normalization.
+     *
+     * <p>The character spans of the {@code Encoding} refer to the normalized sentences.
+     *
+     * @param inputs the batch of input sentence
+     * @param form the Unicode normalization form, for example {@link Normalizer.Form#NFKC}
+     * @return the {@code Encoding} of the normalized input sentence in batch
+     */
+    public Encoding[] batchEncode(List<String> inputs, Normalizer.Form form) {
+        String[] array = new String[inputs.size()];
+        for (int i = 0; i < array.length; ++i) {
+            array[i] = Normalizer.normalize(inputs.get(i), form);
+        }
+        return batchEncode(array, addSpecialTokens, withOverflowingTokens);
+    }
+
     /**
      * Returns the {@code Encoding} of the input sentence in batch.
      *
@@ -396,6 +590,48 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
         return batchEncode(inputs, addSpecialTokens, withOverflowingTokens);
     }
 
+    /**
+     * Encodes the input sentences in batch into {@code input_ids}, {@code attention_mask} and
+     * optionally {@code token_type_ids} arrays of shape (batch, sequence).
+     *
+     * <p>A {@link TensorEncoder} manager, like the Rust engine's, creates the tensors natively on
+     * its device without copying the token ids through Java.
+     *
+     * @param manager the {@link NDManager} to create the arrays
+     * @param inputs the batch of input sentence
+     * @param withTokenType whether to include the token type ids
+     * @return the encoded arrays
+     */
+    public NDList batchEncodeToNDList(
+            NDManager manager, List<String> inputs, boolean withTokenType) {
+        String[] array = inputs.toArray(Utils.EMPTY_ARRAY);
+        if (doLowerCase != null) {
+            for (int i = 0; i < array.length; ++i) {
+                array[i] = array[i].toLowerCase(doLowerCase);
+            }
+        }
+        if (manager instanceof TensorEncoder) {
+            return ((TensorEncoder) manager)
+                    .batchEncode(getHandle(), array, addSpecialTokens, withTokenType);
+        }
+        Encoding[] encodings = batchEncode(array, addSpecialTokens, false);
+        long[][] ids = new long[encodings.length][];
+        long[][] attentionMask = new long[encodings.length][];
+        long[][] typeIds = new long[encodings.length][];
+        for (int i = 0; i < encodings.length; i++) {
+            ids[i] = encodings[i].getIds();
+            attentionMask[i] = encodings[i].getAttentionMask();
+            typeIds[i] = encodings[i].getTypeIds();
+        }
+        NDList list = new NDList(withTokenType ? 3 : 2);
+        list.add(manager.create(ids));
+        list.add(manager.create(attentionMask));
+        if (withTokenType) {
+            list.add(manager.create(typeIds));
+        }
+        return list;
+    }
+
     /**
      * Returns the {@code Encoding} of the input text pair in batch.
      *
@@ -481,6 +717,62 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
         return batchDecode(batchIds, !addSpecialTokens);
     }
 
+    /**
+     * Returns the id of the token in the vocabulary.
+     *
+     * @param token the token to look up
+     * @return the token id, or -1 if the token is not in the vocabulary
+     */
+    public long tokenToId(String token) {
+        return TokenizersLibrary.LIB.tokenToId(getHandle(), token);
+    }
+
+    /**
+     * Returns the ids of the tokens in the vocabulary.
+     *
+     * @param tokens the tokens to look up
+     * @return the token ids, -1 for tokens not in the vocabulary
+     */
+    public long[] tokenToId(String[] tokens) {
+        return TokenizersLibrary.LIB.batchTokenToId(getHandle(), tokens);
+    }
+
+    /**
+     * Returns the token of the id in the vocabulary.
+     *
+     * @param id the token id to look up
+     * @return the token, or {@code null} if the id is not in the vocabulary
+     */
+    public String idToToken(long id) {
+        return TokenizersLibrary.LIB.idToToken(getHandle(), id);
+    }
+
+    /**
+     * Returns the tokens of the ids in the vocabulary.
+     *
+     * @param ids the token ids to look up
+     * @return the tokens, {@code null} for ids not in the vocabulary
+     */
+    public String[] idToToken(long[] ids) {
+        return TokenizersLibrary.LIB.batchIdToToken(getHandle(), ids);
+    }
+
+    /**
+     * Returns the vocabulary of the tokenizer ordered by token id.
+     *
+     * @param withAddedTokens whether to include the added tokens
+     * @return the tokens and their ids
+     */
+    public PairList<String, Long> getVocab(boolean withAddedTokens) {
+        String[] tokens = TokenizersLibrary.LIB.getVocabTokens(getHandle(), withAddedTokens);
+        long[] ids = TokenizersLibrary.LIB.getVocabIds(getHandle(), withAddedTokens);
+        PairList<String, Long> vocab = new PairList<>(tokens.length);
+        for (int i = 0; i < tokens.length; ++i) {
+            vocab.add(tokens[i], ids[i]);
+        }
+        return vocab;
+    }
+
     /**
      * Returns the truncation policy.
      *
@@ -490,6 +782,15 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
         return truncation.name();
     }
 
+    /**
+     * Returns the side from which the tokens are removed when truncating.
+     *
+     * @return the truncation side, {@code LEFT} or {@code RIGHT}
+     */
+    public String getTruncationSide() {
+        return truncationSide.name();
+    }
+
     /**
      * Returns the padding policy.
      *
@@ -499,6 +800,109 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
         return padding.name();
     }
 
+    /**
+     * Returns the side on which the padding tokens are added.
+     *
+     * @return the padding side, {@code LEFT} or {@code RIGHT}
+     */
+    public String getPaddingSide() {
+        return paddingSide.name();
+    }
+
+    /**
+     * Returns the normalizer configuration in {@code tokenizer.json} format.
+     *
+     * @return the normalizer configuration, or {@code null} if the tokenizer has no normalizer
+     */
+    public String getNormalizer() {
+        return TokenizersLibrary.LIB.getNormalizer(getHandle());
+    }
+
+    /**
+     * Overrides the normalizer with a configuration in {@code tokenizer.json} format.
+     *
+     * <p>For example, the following configuration applies NFKC, lowercase and replaces tabs:
+     *
+     * <pre>
+     * {"type": "Sequence", "normalizers": [
+     *   {"type": "NFKC"},
+     *   {"type": "Lowercase"},
+     *   {"type": "Replace", "pattern": {"String": "\t"}, "content": " "}
+     * ]}
+     * </pre>
+     *
+     * @param json the normalizer configuration
+     */
+    public void setNormalizer(String json) {
+        TokenizersLibrary.LIB.setNormalizer(getHandle(), json);
+    }
+
+    /**
+     * Returns the pre-tokenizer configuration in {@code tokenizer.json} format.
+     *
+     * @return the pre-tokenizer configuration, or {@code null} if the tokenizer has no
+     *     pre-tokenizer
+     */
+    public String getPreTokenizer() {
+        return TokenizersLibrary.LIB.getPreTokenizer(getHandle());
+    }
+
+    /**
+     * Overrides the pre-tokenizer with a configuration in {@code tokenizer.json} format.
+     *
+     * <p>For example, the following configuration splits on whitespace and punctuation, and
+     * splits numbers into individual digits:
+     *
+     * <pre>
+     * {"type": "Sequence", "pretokenizers": [
+     *   {"type": "WhitespaceSplit"},
+     *   {"type": "Punctuation", "behavior": "Isolated"},
+     *   {"type": "Digits", "individual_digits": true}
+     * ]}
+     * </pre>
+     *
+     * <p>Byte-level pre-tokenizers are configured with {@code {"type": "ByteLevel",
+     * "add_prefix_space": false, "trim_offsets": true, "use_regex": true}}.
+     *
+     * @param json the pre-tokenizer configuration
+     */
+    public void setPreTokenizer(String json) {
+        TokenizersLibrary.LIB.setPreTokenizer(getHandle(), json);
+    }
+
+    /**
+     * Returns the post-processor configuration in {@code tokenizer.json} format.
+     *
+     * @return the post-processor configuration, or {@code null} if the tokenizer has no
+     *     post-processor
+     */
+    public String getPostProcessor() {
+        return TokenizersLibrary.LIB.getPostProcessor(getHandle());
+    }
+
+    /**
+     * Overrides the post-processor with a configuration in {@code tokenizer.json} format.
+     *
+     * @param json the post-processor configuration
+     */
+    public void setPostProcessor(String json) {
+        TokenizersLibrary.LIB.setPostProcessor(getHandle(), json);
+    }
+
+    /**
+     * Overrides the post-processor with a {@code TemplateProcessing} for the single and pair
+     * sequence templates, for example {@code [CLS] $A [SEP]} and {@code [CLS] $A [SEP] $B:1
+     * [SEP]:1}.
+     *
+     * <p>The special tokens in the templates must exist in the vocabulary.
+     *
+     * @param single the template for single sequences
+     * @param pair the template for pair sequences, can be {@code null}
+     */
+    public void setTemplate(String single, String pair) {
+        TokenizersLibrary.LIB.setTemplateProcessing(getHandle(), single, pair);
+    }
+
     /**
      * Returns the max token length.
      *
@@ -508,6 +912,15 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
         return maxLength;
     }
 
+    /**
+     * Returns the maximum sequence length the model supports.
+     *
+     * @return the maximum sequence length the model supports
+     */
+    public int getModelMaxLength() {
+        return modelMaxLength;
+    }
+
     /**
      * Returns the stride to use in overflow overlap when truncating sequences longer than the model
      * supports.
@@ -586,7 +999,8 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
         }
 
         if (isTruncate) {
-            TokenizersLibrary.LIB.setTruncation(getHandle(), maxLength, truncation.name(), stride);
+            TokenizersLibrary.LIB.setTruncation(
+                    getHandle(), maxLength, truncation.name(), stride, truncationSide.name());
         } else {
             TokenizersLibrary.LIB.disableTruncation(getHandle());
         }
@@ -595,7 +1009,7 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
             TokenizersLibrary.LIB.disablePadding(getHandle());
         } else {
             TokenizersLibrary.LIB.setPadding(
-                    getHandle(), maxLength, padding.name(), padToMultipleOf);
+                    getHandle(), maxLength, padding.name(), padToMultipleOf, paddingSide.name());
         }
     }
 
@@ -670,6 +1084,28 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
         }
     }
 
+    /** An enum to represent the side of the sequence to remove or add tokens. */
+    private enum Side {
+        LEFT,
+        RIGHT;
+
+        /**
+         * Converts the String to the matching Side type.
+         *
+         * @param value the String to convert
+         * @return the matching Side type
+         * @throws IllegalArgumentException if the value does not match any Side type
+         */
+        static Side fromValue(String value) {
+            for (Side side : Side.values()) {
+                if (side.name().equalsIgnoreCase(value)) {
+                    return side;
+                }
+            }
+            throw new IllegalArgumentException("Invalid side: " + value);
+        }
+    }
+
     /** An enum to represent the different available padding strategies. */
     private enum PaddingStrategy {
         LONGEST,
@@ -701,6 +1137,10 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
     /** The builder for creating huggingface tokenizer. */
     public static final class Builder {
 
+        private static final String[] SENTENCE_PIECE_MODELS = {
+            "tokenizer.model", "sentencepiece.bpe.model", "spiece.model"
+        };
+
         private NDManager manager;
         private Map<String, String> options;
 
@@ -795,6 +1235,18 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
             return this;
         }
 
+        /**
+         * Sets the side from which the tokens are removed when truncating, {@code left} keeps the
+         * end of long inputs.
+         *
+         * @param truncationSide {@code left} or {@code right}, default {@code right}
+         * @return this builder
+         */
+        public Builder optTruncationSide(String truncationSide) {
+            options.put("truncationSide", truncationSide);
+            return this;
+        }
+
         /**
          * Enables or Disables default padding behavior for the tokenizer.
          *
@@ -806,6 +1258,18 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
             return this;
         }
 
+        /**
+         * Sets the side on which the padding tokens are added, batched decoder generation
+         * requires {@code left} padding.
+         *
+         * @param paddingSide {@code left} or {@code right}, default {@code right}
+         * @return this builder
+         */
+        public Builder optPaddingSide(String paddingSide) {
+            options.put("paddingSide", paddingSide);
+            return this;
+        }
+
         /**
          * Enables padding to pad sequences to previously specified maxLength, or modelMaxLength if
          * not specified.
@@ -828,6 +1292,18 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
             return this;
         }
 
+        /**
+         * Overrides the maximum sequence length the model supports, by default it's read from
+         * {@code model_max_length} in {@code tokenizer_config.json}, or 512 if not available.
+         *
+         * @param modelMaxLength the maximum sequence length of the model
+         * @return this builder
+         */
+        public Builder optModelMaxLength(int modelMaxLength) {
+            options.put("modelMaxLength", String.valueOf(modelMaxLength));
+            return this;
+        }
+
         /**
          * Sets padToMultipleOf for padding.
          *
@@ -873,6 +1349,18 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
             return this;
         }
 
+        /**
+         * Overrides the pre-tokenizer of the loaded tokenizer.
+         *
+         * @param json the pre-tokenizer configuration in {@code tokenizer.json} format
+         * @return this builder
+         * @see HuggingFaceTokenizer#setPreTokenizer(String)
+         */
+        public Builder optPreTokenizer(String json) {
+            options.put("preTokenizer", json);
+            return this;
+        }
+
         /**
          * Configures the builder with the arguments.
          *
@@ -923,6 +1411,12 @@ public final class HuggingFaceTokenizer extends NativeResource<Long> implements
                 if (Files.exists(vocab) && Files.exists(merges)) {
                     return managed(HuggingFaceTokenizer.newInstance(vocab, merges, options));
                 }
+                for (String name : SENTENCE_PIECE_MODELS) {
+                    Path model = tokenizerPath.resolve(name);
+                    if (Files.exists(model)) {
+                        return managed(HuggingFaceTokenizer.fromSentencePiece(model, options));
+                    }
+                }
                 throw new IOException("tokenizer.json file not found.");
             } else if (!Files.exists(tokenizerPath)) {
                 throw new IOException("Tokenizer file not exits: " + tokenizerPath);
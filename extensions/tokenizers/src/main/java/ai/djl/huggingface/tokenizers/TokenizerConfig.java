@@ -0,0 +1,176 @@
+/*
+ * Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License"). You may not use this file except in compliance
+ * with the License. A copy of the License is located at
+ *
+ * http://aws.amazon.com/apache2.0/
+ *
+ * or in the "license" file accompanying this file. This file is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES
+ * OR CONDITIONS OF ANY KIND, either express or implied. See the License for the specific language governing permissions
+ * and limitations under the License.
+ */
+package ai.djl.huggingface.tokenizers;
+
+import ai.djl.util.JsonUtils;
+
+import com.google.gson.JsonArray;
+import com.google.gson.JsonElement;
+import com.google.gson.JsonObject;
+import com.google.gson.JsonParser;
+import com.google.gson.annotations.SerializedName;
+
+import java.io.IOException;
+import java.io.Reader;
+import java.nio.file.Files;
+import java.nio.file.Path;
+
+/**
+ * The subset of the transformers {@code tokenizer_config.json} that is not always reflected in
+ * {@code tokenizer.json}.
+ */
+final class TokenizerConfig {
+
+    @SerializedName("do_lower_case")
+    Boolean doLowerCase;
+
+    @SerializedName("strip_accents")
+    Boolean stripAccents;
+
+    @SerializedName("add_prefix_space")
+    Boolean addPrefixSpace;
+
+    // transformers uses a very large number (1e30) when the limit is unknown
+    @SerializedName("model_max_length")
+    Double modelMaxLength;
+
+    private TokenizerConfig() {}
+
+    /**
+     * Returns the {@code model_max_length} if the model has a known limit.
+     *
+     * @return the {@code model_max_length}, or -1 if unknown
+     */
+    int getModelMaxLength() {
+        if (modelMaxLength == null || modelMaxLength <= 0 || modelMaxLength > Integer.MAX_VALUE) {
+            return -1;
+        }
+        return modelMaxLength.intValue();
+    }
+
+    /**
+     * Loads the {@code tokenizer_config.json} file.
+     *
+     * @param file the {@code tokenizer_config.json} file
+     * @return the {@code TokenizerConfig}, or {@code null} if the file doesn't exist
+     * @throws IOException if failed to read the file
+     */
+    static TokenizerConfig load(Path file) throws IOException {
+        if (!Files.isRegularFile(file)) {
+            return null;
+        }
+        try (Reader reader = Files.newBufferedReader(file)) {
+            return JsonUtils.GSON.fromJson(reader, TokenizerConfig.class);
+        }
+    }
+
+    /**
+     * Applies the flags to the normalizer and pre-tokenizer of the tokenizer.
+     *
+     * @param tokenizer the tokenizer to update
+     */
+    void apply(HuggingFaceTokenizer tokenizer) {
+        if (doLowerCase != null || stripAccents != null) {
+            String json = tokenizer.getNormalizer();
+            JsonObject normalizer = null;
+            if (json != null) {
+                normalizer = JsonParser.parseString(json).getAsJsonObject();
+            }
+            if (normalizer == null || !updateBertNormalizer(normalizer)) {
+                normalizer = appendNormalizers(normalizer);
+            }
+            if (normalizer != null) {
+                tokenizer.setNormalizer(normalizer.toString());
+            }
+        }
+
+        if (addPrefixSpace != null) {
+            String json = tokenizer.getPreTokenizer();
+            if (json != null) {
+                JsonObject preTokenizer = JsonParser.parseString(json).getAsJsonObject();
+                if (updatePrefixSpace(preTokenizer)) {
+                    tokenizer.setPreTokenizer(preTokenizer.toString());
+                }
+            }
+        }
+    }
+
+    private boolean updateBertNormalizer(JsonObject normalizer) {
+        String type = normalizer.get("type").getAsString();
+        if ("BertNormalizer".equals(type)) {
+            if (doLowerCase != null) {
+                normalizer.addProperty("lowercase", doLowerCase);
+            }
+            if (stripAccents != null) {
+                normalizer.addProperty("strip_accents", stripAccents);
+            }
+            return true;
+        } else if ("Sequence".equals(type)) {
+            boolean updated = false;
+            for (JsonElement element : normalizer.getAsJsonArray("normalizers")) {
+                updated |= updateBertNormalizer(element.getAsJsonObject());
+            }
+            return updated;
+        }
+        return false;
+    }
+
+    private JsonObject appendNormalizers(JsonObject normalizer) {
+        JsonArray normalizers = new JsonArray();
+        if (Boolean.TRUE.equals(stripAccents)) {
+            normalizers.add(newNormalizer("NFD"));
+            normalizers.add(newNormalizer("StripAccents"));
+        }
+        if (Boolean.TRUE.equals(doLowerCase)) {
+            normalizers.add(newNormalizer("Lowercase"));
+        }
+        if (normalizers.isEmpty()) {
+            return null;
+        }
+        if (normalizer != null) {
+            JsonArray sequence = new JsonArray();
+            sequence.add(normalizer);
+            sequence.addAll(normalizers);
+            normalizers = sequence;
+        }
+        JsonObject ret = newNormalizer("Sequence");
+        ret.add("normalizers", normalizers);
+        return ret;
+    }
+
+    private boolean updatePrefixSpace(JsonObject preTokenizer) {
+        String type = preTokenizer.get("type").getAsString();
+        switch (type) {
+            case "ByteLevel":
+                preTokenizer.addProperty("add_prefix_space", addPrefixSpace);
+                return true;
+            case "Metaspace":
+                preTokenizer.addProperty("prepend_scheme", addPrefixSpace ? "always" : "never");
+                return true;
+            case "Sequence":
+                boolean updated = false;
+                for (JsonElement element : preTokenizer.getAsJsonArray("pretokenizers")) {
+                    updated |= updatePrefixSpace(element.getAsJsonObject());
+                }
+                return updated;
+            default:
+                return false;
+        }
+    }
+
+    private static JsonObject newNormalizer(String type) {
+        JsonObject normalizer = new JsonObject();
+        normalizer.addProperty("type", type);
+        return normalizer;
+    }
+}
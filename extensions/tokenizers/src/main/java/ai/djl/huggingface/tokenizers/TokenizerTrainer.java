@@ -0,0 +1,279 @@
+/*
+ * Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License"). You may not use this file except in compliance
+ * with the License. A copy of the License is located at
+ *
+ * http://aws.amazon.com/apache2.0/
+ *
+ * or in the "license" file accompanying this file. This file is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES
+ * OR CONDITIONS OF ANY KIND, either express or implied. See the License for the specific language governing permissions
+ * and limitations under the License.
+ */
+package ai.djl.huggingface.tokenizers;
+
+import ai.djl.huggingface.tokenizers.jni.LibUtils;
+import ai.djl.huggingface.tokenizers.jni.TokenizersLibrary;
+import ai.djl.util.Ec2Utils;
+
+import java.io.IOException;
+import java.nio.file.Files;
+import java.nio.file.Path;
+import java.util.ArrayList;
+import java.util.Collections;
+import java.util.List;
+import java.util.Map;
+
+/**
+ * {@code TokenizerTrainer} trains a new {@link HuggingFaceTokenizer} from a text corpus.
+ *
+ * <p>Each line of the training files is used as a training sequence. The trained tokenizer can be
+ * persisted with {@link HuggingFaceTokenizer#save(Path)}.
+ */
+public final class TokenizerTrainer {
+
+    private List<String> files;
+    private List<String> texts;
+    private int vocabSize;
+    private int minFrequency;
+    private List<String> specialTokens;
+    private float characterCoverage;
+    private String unkToken;
+
+    TokenizerTrainer(Builder builder) {
+        files = builder.files;
+        texts = builder.texts;
+        vocabSize = builder.vocabSize;
+        minFrequency = builder.minFrequency;
+        specialTokens = builder.specialTokens;
+        characterCoverage = builder.characterCoverage;
+        unkToken = builder.unkToken;
+    }
+
+    /**
+     * Creates a builder to build a {@code TokenizerTrainer}.
+     *
+     * @return a new builder
+     */
+    public static Builder builder() {
+        return new Builder();
+    }
+
+    /**
+     * Trains a byte-level BPE tokenizer.
+     *
+     * @return the trained {@code HuggingFaceTokenizer}
+     */
+    public HuggingFaceTokenizer trainBpe() {
+        return trainBpe(null);
+    }
+
+    /**
+     * Trains a byte-level BPE tokenizer.
+     *
+     * @param options tokenizer options
+     * @return the trained {@code HuggingFaceTokenizer}
+     */
+    public HuggingFaceTokenizer trainBpe(Map<String, String> options) {
+        Ec2Utils.callHome("Huggingface");
+        LibUtils.checkStatus();
+
+        long handle =
+                TokenizersLibrary.LIB.trainBpeTokenizer(
+                        files.toArray(new String[0]),
+                        texts.toArray(new String[0]),
+                        vocabSize,
+                        minFrequency,
+                        specialTokens.toArray(new String[0]));
+        return new HuggingFaceTokenizer(handle, options);
+    }
+
+    /**
+     * Trains a BERT style WordPiece tokenizer.
+     *
+     * @return the trained {@code HuggingFaceTokenizer}
+     */
+    public HuggingFaceTokenizer trainWordPiece() {
+        return trainWordPiece(null);
+    }
+
+    /**
+     * Trains a BERT style WordPiece tokenizer.
+     *
+     * @param options tokenizer options
+     * @return the trained {@code HuggingFaceTokenizer}
+     */
+    public HuggingFaceTokenizer trainWordPiece(Map<String, String> options) {
+        Ec2Utils.callHome("Huggingface");
+        LibUtils.checkStatus();
+
+        long handle =
+                TokenizersLibrary.LIB.trainWordPieceTokenizer(
+                        files.toArray(new String[0]),
+                        texts.toArray(new String[0]),
+                        vocabSize,
+                        minFrequency,
+                        specialTokens.toArray(new String[0]),
+                        characterCoverage,
+                        unkToken == null ? "[UNK]" : unkToken);
+        return new HuggingFaceTokenizer(handle, options);
+    }
+
+    /**
+     * Trains a SentencePiece style Unigram tokenizer.
+     *
+     * @return the trained {@code HuggingFaceTokenizer}
+     */
+    public HuggingFaceTokenizer trainUnigram() {
+        return trainUnigram(null);
+    }
+
+    /**
+     * Trains a SentencePiece style Unigram tokenizer.
+     *
+     * <p>The minimum frequency doesn't apply to Unigram training.
+     *
+     * @param options tokenizer options
+     * @return the trained {@code HuggingFaceTokenizer}
+     */
+    public HuggingFaceTokenizer trainUnigram(Map<String, String> options) {
+        Ec2Utils.callHome("Huggingface");
+        LibUtils.checkStatus();
+
+        long handle =
+                TokenizersLibrary.LIB.trainUnigramTokenizer(
+                        files.toArray(new String[0]),
+                        texts.toArray(new String[0]),
+                        vocabSize,
+                        specialTokens.toArray(new String[0]),
+                        characterCoverage,
+                        unkToken == null ? "<unk>" : unkToken);
+        return new HuggingFaceTokenizer(handle, options);
+    }
+
+    /** The builder for creating {@code TokenizerTrainer}. */
+    public static final class Builder {
+
+        List<String> files = new ArrayList<>();
+        List<String> texts = new ArrayList<>();
+        int vocabSize = 30000;
+        int minFrequency;
+        List<String> specialTokens = new ArrayList<>();
+        float characterCoverage = 1f;
+        String unkToken;
+
+        Builder() {}
+
+        /**
+         * Adds a training file, each line of the file is used as a training sequence.
+         *
+         * @param file the training file
+         * @return this builder
+         * @throws IOException if the file does not exist
+         */
+        public Builder addFile(Path file) throws IOException {
+            if (!Files.isRegularFile(file)) {
+                throw new IOException("Training file not found: " + file);
+            }
+            files.add(file.toAbsolutePath().toString());
+            return this;
+        }
+
+        /**
+         * Adds training texts.
+         *
+         * @param texts the training sequences
+         * @return this builder
+         */
+        public Builder addTexts(Iterable<String> texts) {
+            for (String text : texts) {
+                this.texts.add(text);
+            }
+            return this;
+        }
+
+        /**
+         * Sets the target vocabulary size, including the special tokens.
+         *
+         * @param vocabSize the target vocabulary size
+         * @return this builder
+         */
+        public Builder optVocabSize(int vocabSize) {
+            this.vocabSize = vocabSize;
+            return this;
+        }
+
+        /**
+         * Sets the minimum frequency a pair should have in order to be merged.
+         *
+         * @param minFrequency the minimum frequency
+         * @return this builder
+         */
+        public Builder optMinFrequency(int minFrequency) {
+            this.minFrequency = minFrequency;
+            return this;
+        }
+
+        /**
+         * Sets the special tokens, special tokens are added to the vocabulary first.
+         *
+         * @param specialTokens the special tokens
+         * @return this builder
+         */
+        public Builder optSpecialTokens(List<String> specialTokens) {
+            this.specialTokens = specialTokens;
+            return this;
+        }
+
+        /**
+         * Sets the special tokens, special tokens are added to the vocabulary first.
+         *
+         * @param specialTokens the special tokens
+         * @return this builder
+         */
+        public Builder optSpecialTokens(String... specialTokens) {
+            this.specialTokens = new ArrayList<>();
+            Collections.addAll(this.specialTokens, specialTokens);
+            return this;
+        }
+
+        /**
+         * Sets the fraction of characters in the corpus covered by the alphabet, the rest of
+         * characters are encoded as the unknown token. Doesn't apply to byte-level BPE.
+         *
+         * @param characterCoverage the character coverage between 0 and 1, default 1
+         * @return this builder
+         */
+        public Builder optCharacterCoverage(float characterCoverage) {
+            if (characterCoverage <= 0 || characterCoverage > 1) {
+                throw new IllegalArgumentException("characterCoverage must be in (0, 1].");
+            }
+            this.characterCoverage = characterCoverage;
+            return this;
+        }
+
+        /**
+         * Sets the unknown token, defaults to {@code [UNK]} for WordPiece and {@code <unk>} for
+         * Unigram.
+         *
+         * @param unkToken the unknown token
+         * @return this builder
+         */
+        public Builder optUnkToken(String unkToken) {
+            this.unkToken = unkToken;
+            return this;
+        }
+
+        /**
+         * Builds the {@code TokenizerTrainer}.
+         *
+         * @return the {@code TokenizerTrainer}
+         */
+        public TokenizerTrainer build() {
+            if (files.isEmpty() && texts.isEmpty()) {
+                throw new IllegalArgumentException("Missing training files or texts.");
+            }
+            return new TokenizerTrainer(this);
+        }
+    }
+}
@@ -0,0 +1,35 @@
+/*
+ * Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License"). You may not use this file except in compliance
+ * with the License. A copy of the License is located at
+ *
+ * http://aws.amazon.com/apache2.0/
+ *
+ * or in the "license" file accompanying this file. This file is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES
+ * OR CONDITIONS OF ANY KIND, either express or implied. See the License for the specific language governing permissions
+ * and limitations under the License.
+ */
+package ai.djl.huggingface.tokenizers.jni;
+
+import ai.djl.ndarray.NDList;
+
+/**
+ * An {@link ai.djl.ndarray.NDManager} that creates the arrays of a batch encoding natively, without
+ * copying the token ids through Java.
+ */
+public interface TensorEncoder {
+
+    /**
+     * Encodes the input sentences in batch into {@code input_ids}, {@code attention_mask} and
+     * optionally {@code token_type_ids} arrays of shape (batch, sequence).
+     *
+     * @param tokenizer the native tokenizer handle
+     * @param inputs the batch of input sentence
+     * @param addSpecialTokens whether to encode the sequence with special tokens
+     * @param withTokenType whether to include the token type ids
+     * @return the encoded arrays
+     */
+    NDList batchEncode(
+            long tokenizer, String[] inputs, boolean addSpecialTokens, boolean withTokenType);
+}
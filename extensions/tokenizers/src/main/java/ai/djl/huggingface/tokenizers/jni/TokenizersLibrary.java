@@ -22,12 +22,47 @@ public final class TokenizersLibrary {
 
     public native long createTokenizer(String identifier, String authToken);
 
+    public native String downloadTokenizerConfig(String identifier, String authToken);
+
     public native long createTokenizerFromString(String json);
 
+    public native long createTokenizerFromBytes(byte[] json);
+
     public native long createBpeTokenizer(String vocabulary, String merges);
 
+    public native long createSentencePieceTokenizer(String modelFile);
+
+    public native long createTiktokenTokenizer(
+            String ranksFile, String pattern, String[] specialTokens, long[] specialTokenIds);
+
+    public native long trainBpeTokenizer(
+            String[] files,
+            String[] texts,
+            int vocabSize,
+            int minFrequency,
+            String[] specialTokens);
+
+    public native long trainWordPieceTokenizer(
+            String[] files,
+            String[] texts,
+            int vocabSize,
+            int minFrequency,
+            String[] specialTokens,
+            float characterCoverage,
+            String unkToken);
+
+    public native long trainUnigramTokenizer(
+            String[] files,
+            String[] texts,
+            int vocabSize,
+            String[] specialTokens,
+            float characterCoverage,
+            String unkToken);
+
     public native void deleteTokenizer(long handle);
 
+    public native void saveTokenizer(long handle, String path);
+
     public native long encode(long tokenizer, String text, boolean addSpecialTokens);
 
     public native long encodeDual(
@@ -37,6 +72,13 @@ public final class TokenizersLibrary {
 
     public native long[] batchEncode(long tokenizer, String[] inputs, boolean addSpecialTokens);
 
+    public native long[] batchEncodeToTensors(
+            long tokenizer,
+            String[] inputs,
+            boolean addSpecialTokens,
+            String deviceType,
+            int deviceId);
+
     public native long[] batchEncodePair(
             long tokenizer, String[] text, String[] textPair, boolean addSpecialTokens);
 
@@ -62,6 +104,18 @@ public final class TokenizersLibrary {
 
     public native String decode(long tokenizer, long[] ids, boolean addSpecialTokens);
 
+    public native long tokenToId(long tokenizer, String token);
+
+    public native String idToToken(long tokenizer, long id);
+
+    public native long[] batchTokenToId(long tokenizer, String[] tokens);
+
+    public native String[] batchIdToToken(long tokenizer, long[] ids);
+
+    public native String[] getVocabTokens(long tokenizer, boolean withAddedTokens);
+
+    public native long[] getVocabIds(long tokenizer, boolean withAddedTokens);
+
     public native String getTruncationStrategy(long tokenizer);
 
     public native String getPaddingStrategy(long tokenizer);
@@ -75,10 +129,32 @@ public final class TokenizersLibrary {
     public native void disablePadding(long tokenizer);
 
     public native void setPadding(
-            long tokenizer, int maxLength, String paddingStrategy, int padToMultipleOf);
+            long tokenizer,
+            int maxLength,
+            String paddingStrategy,
+            int padToMultipleOf,
+            String paddingSide);
 
     public native void disableTruncation(long tokenizer);
 
     public native void setTruncation(
-            long tokenizer, int maxLength, String truncationStrategy, int stride);
+            long tokenizer,
+            int maxLength,
+            String truncationStrategy,
+            int stride,
+            String truncationSide);
+
+    public native String getNormalizer(long tokenizer);
+
+    public native void setNormalizer(long tokenizer, String json);
+
+    public native String getPreTokenizer(long tokenizer);
+
+    public native void setPreTokenizer(long tokenizer, String json);
+
+    public native String getPostProcessor(long tokenizer);
+
+    public native void setPostProcessor(long tokenizer, String json);
+
+    public native void setTemplateProcessing(long tokenizer, String single, String pair);
 }
@@ -122,25 +122,8 @@ public class TextEmbeddingTranslator implements Translator<String, float[]> {
     @Override
     public NDList batchProcessInput(TranslatorContext ctx, List<String> inputs) {
         NDManager manager = ctx.getNDManager();
-        Encoding[] encodings = tokenizer.batchEncode(inputs);
-        long[][] ids = new long[encodings.length][];
-        long[][] attentionMask = new long[encodings.length][];
-        long[][] typeIds = new long[encodings.length][];
-        for (int i = 0; i < encodings.length; i++) {
-            ids[i] = encodings[i].getIds();
-            attentionMask[i] = encodings[i].getAttentionMask();
-            if (includeTokenTypes) {
-                typeIds[i] = encodings[i].getTypeIds();
-            }
-        }
-        NDList list = new NDList();
-        list.add(manager.create(ids));
-        NDArray inputAttentionMask = manager.create(attentionMask);
-        list.add(inputAttentionMask);
-        ctx.setAttachment("attentionMask", inputAttentionMask);
-        if (includeTokenTypes) {
-            list.add(manager.create(typeIds));
-        }
+        NDList list = tokenizer.batchEncodeToNDList(manager, inputs, includeTokenTypes);
+        ctx.setAttachment("attentionMask", list.get(1));
         return list;
     }
 
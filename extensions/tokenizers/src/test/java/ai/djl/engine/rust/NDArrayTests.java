@@ -13,17 +13,30 @@
 package ai.djl.engine.rust;
 
 import ai.djl.Device;
+import ai.djl.engine.Engine;
+import ai.djl.engine.EngineException;
 import ai.djl.ndarray.NDArray;
+import ai.djl.ndarray.NDArrays;
 import ai.djl.ndarray.NDList;
 import ai.djl.ndarray.NDManager;
 import ai.djl.ndarray.types.DataType;
 import ai.djl.ndarray.types.Shape;
+import ai.djl.nn.convolutional.Conv1d;
+import ai.djl.nn.convolutional.Conv2d;
+import ai.djl.nn.pooling.Pool;
 import ai.djl.testing.Assertions;
+import ai.djl.util.Utils;
 
 import org.testng.Assert;
 import org.testng.annotations.Test;
 
+import java.io.IOException;
 import java.nio.ByteBuffer;
+import java.nio.file.Files;
+import java.nio.file.Path;
+import java.nio.file.Paths;
+import java.util.Map;
+import java.util.concurrent.ConcurrentHashMap;
 
 public class NDArrayTests {
 
@@ -249,4 +262,804 @@ public class NDArrayTests {
             System.out.println(v);
         }
     }
+
+    @Test
+    public void testTopK() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.create(new float[] {3f, 1f, 4f, 1f, 5f, 9f}, new Shape(2, 3));
+            NDList result = array.topK(2, -1, true, true);
+            Assert.assertEquals(result.get(0), manager.create(new float[][] {{4f, 3f}, {9f, 5f}}));
+            Assert.assertEquals(result.get(1), manager.create(new long[][] {{2, 0}, {2, 1}}));
+
+            result = array.topK(1, 0, false, true);
+            Assert.assertEquals(result.get(0), manager.create(new float[][] {{1f, 1f, 4f}}));
+            Assert.assertEquals(result.get(1), manager.create(new long[][] {{1, 0, 0}}));
+
+            // unsorted results keep the order of the elements
+            result = array.topK(2, -1, true, false);
+            Assert.assertEquals(result.get(0), manager.create(new float[][] {{3f, 4f}, {5f, 9f}}));
+            Assert.assertEquals(result.get(1), manager.create(new long[][] {{0, 2}, {1, 2}}));
+        }
+    }
+
+    @Test
+    public void testSort() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.create(new float[] {3f, 1f, 2f, 6f, 5f, 4f}, new Shape(2, 3));
+            NDArray expected = manager.create(new float[][] {{1f, 2f, 3f}, {4f, 5f, 6f}});
+            Assert.assertEquals(array.sort(), expected);
+            expected = manager.create(new float[][] {{3f, 1f, 2f}, {6f, 5f, 4f}});
+            Assert.assertEquals(array.sort(0), expected);
+
+            expected = manager.create(new long[][] {{1, 2, 0}, {2, 1, 0}});
+            Assert.assertEquals(array.argSort(), expected);
+            expected = manager.create(new long[][] {{0, 2, 1}, {0, 1, 2}});
+            Assert.assertEquals(array.argSort(-1, false), expected);
+        }
+    }
+
+    @Test
+    public void testGatherScatter() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.create(new float[] {1f, 2f, 3f, 4f, 5f, 6f}, new Shape(2, 3));
+            NDArray index = manager.create(new long[][] {{2, 0}, {1, 1}});
+            NDArray expected = manager.create(new float[][] {{3f, 1f}, {5f, 5f}});
+            Assert.assertEquals(array.gather(index, 1), expected);
+
+            NDArray zeros = manager.zeros(new Shape(2, 3));
+            NDArray value = manager.create(new float[][] {{1f, 2f}, {3f, 4f}});
+            expected = manager.create(new float[][] {{2f, 0f, 1f}, {0f, 7f, 0f}});
+            Assert.assertEquals(zeros.scatter(index, value, 1), expected);
+        }
+    }
+
+    @Test
+    public void testIndexSelect() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            float[][] data = {{1f, 2f}, {3f, 4f}, {5f, 6f}};
+            RsNDArray array = (RsNDArray) manager.create(data);
+            NDArray index = manager.create(new long[] {2, 0});
+            NDArray expected = manager.create(new float[][] {{5f, 6f}, {1f, 2f}});
+            Assert.assertEquals(array.indexSelect(index, 0), expected);
+
+            expected = manager.create(new float[][] {{2f}, {4f}, {6f}});
+            Assert.assertEquals(array.indexSelect(manager.create(new long[] {1}), -1), expected);
+        }
+    }
+
+    @Test
+    public void testEinsum() {
+        try (RsNDManager manager = (RsNDManager) NDManager.newBaseManager("Rust")) {
+            NDArray a = manager.arange(6f).reshape(2, 3);
+            NDArray b = manager.arange(12f).reshape(3, 4);
+            Assert.assertEquals(manager.einsum("ij,jk->ik", a, b), a.matMul(b));
+            Assert.assertEquals(manager.einsum("ij->ji", a), a.transpose());
+            Assert.assertEquals(manager.einsum("ij->", a), a.sum());
+            Assert.assertEquals(manager.einsum("ij,jk", a, b), a.matMul(b));
+
+            NDArray q = manager.arange(24f).reshape(2, 3, 4);
+            NDArray k = manager.arange(16f).reshape(2, 2, 4);
+            NDArray expected = q.batchMatMul(k.transpose(0, 2, 1));
+            Assert.assertEquals(manager.einsum("bqd,bkd->bqk", q, k), expected);
+        }
+    }
+
+    @Test
+    public void testWhere() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.create(new float[][] {{1f, 2f}, {3f, 4f}});
+            NDArray other = manager.zeros(new Shape(2, 2));
+            NDArray condition = manager.create(new boolean[] {true, false});
+            NDArray expected = manager.create(new float[][] {{1f, 0f}, {3f, 0f}});
+            Assert.assertEquals(NDArrays.where(condition, array, other), expected);
+
+            RsNDArray scores = (RsNDArray) array;
+            NDArray mask = manager.create(new boolean[][] {{false, true}, {true, false}});
+            expected = manager.create(new float[][] {{1f, -1f}, {-1f, 4f}});
+            Assert.assertEquals(scores.maskedFill(mask, -1f), expected);
+        }
+    }
+
+    @Test
+    public void testCumulativeOp() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray mask = manager.create(new long[][] {{0, 0, 1, 1}, {1, 1, 1, 1}});
+            NDArray expected = manager.create(new long[][] {{0, 0, 1, 2}, {1, 2, 3, 4}});
+            Assert.assertEquals(mask.cumSum(-1), expected);
+
+            NDArray array = manager.create(new float[][] {{1f, 2f, 3f}, {4f, 5f, 6f}});
+            expected = manager.create(new float[][] {{1f, 2f, 6f}, {4f, 20f, 120f}});
+            Assert.assertEquals(array.cumProd(1), expected);
+            expected = manager.create(new double[][] {{1d, 2d, 3d}, {4d, 10d, 18d}});
+            Assert.assertEquals(array.cumProd(0, DataType.FLOAT64), expected);
+        }
+    }
+
+    @Test
+    public void testNorm() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.create(new float[][] {{3f, -4f}, {-6f, 8f}});
+            Assertions.assertAlmostEquals(array.norm(), manager.create((float) Math.sqrt(125)));
+            NDArray expected = manager.create(new float[] {7f, 14f});
+            Assert.assertEquals(array.norm(1, new int[] {1}, false), expected);
+            expected = manager.create(new float[][] {{5f}, {10f}});
+            Assert.assertEquals(array.norm(2, new int[] {-1}, true), expected);
+            expected = manager.create(new float[] {4f, 8f});
+            Assert.assertEquals(array.norm(Integer.MAX_VALUE, new int[] {1}, false), expected);
+
+            RsNDArray embeddings = (RsNDArray) array.duplicate();
+            embeddings.normalizei(1, 1, 1e-12);
+            expected = manager.create(new float[][] {{3f / 7, -4f / 7}, {-6f / 14, 8f / 14}});
+            Assertions.assertAlmostEquals(embeddings, expected);
+        }
+    }
+
+    @Test
+    public void testSoftmax() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray logits = manager.create(new float[][] {{1f, 2f, 3f}, {1000f, 1000f, 1000f}});
+            NDArray expected =
+                    manager.create(
+                            new float[][] {
+                                {0.09003057f, 0.24472847f, 0.66524096f},
+                                {0.33333334f, 0.33333334f, 0.33333334f}
+                            });
+            Assertions.assertAlmostEquals(logits.softmax(-1), expected);
+            Assertions.assertAlmostEquals(logits.logSoftmax(1), expected.log());
+            NDArray sum = logits.softmax(0).sum(new int[] {0});
+            Assertions.assertAlmostEquals(sum, manager.ones(new Shape(3)));
+        }
+    }
+
+    @Test
+    public void testNormalization() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray input = manager.create(new float[][] {{1f, 2f, 3f}, {2f, 4f, 6f}});
+            NDArray gamma = manager.create(new float[] {1f, 2f, 1f});
+            NDArray beta = manager.create(new float[] {0f, 0f, 1f});
+            RsNDArrayEx ex = (RsNDArrayEx) input.getNDArrayInternal();
+            NDArray ret = ex.layerNorm(input, new Shape(3), gamma, beta, 0f).singletonOrThrow();
+            float[] row = {-1.2247f, 0f, 2.2247f};
+            Assertions.assertAlmostEquals(ret, manager.create(new float[][] {row, row}));
+
+            ret = ex.rmsNorm(input, new Shape(3), gamma, 0f).singletonOrThrow();
+            row = new float[] {0.4629f, 1.8516f, 1.3887f};
+            Assertions.assertAlmostEquals(ret, manager.create(new float[][] {row, row}));
+        }
+    }
+
+    @Test
+    public void testMatMul() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray queries = manager.arange(12f).reshape(2, 3, 2);
+            NDArray keys = manager.arange(8f).reshape(2, 4);
+            NDArray ret = queries.matMul(keys);
+            Assert.assertEquals(ret.getShape(), new Shape(2, 3, 4));
+            Assert.assertEquals(ret.get(1), queries.get(1).matMul(keys));
+
+            NDArray vector = manager.create(new float[] {1f, 1f, 1f, 1f});
+            NDArray expected = manager.create(new float[] {6f, 22f});
+            Assert.assertEquals(keys.matMul(vector), expected);
+            expected = manager.create(new float[] {4f, 6f, 8f, 10f});
+            Assert.assertEquals(manager.ones(new Shape(2)).matMul(keys), expected);
+        }
+    }
+
+    @Test
+    public void testConvolution() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray input = manager.arange(5f).reshape(1, 1, 5);
+            NDArray weight = manager.ones(new Shape(1, 1, 2));
+            NDArray bias = manager.create(new float[] {1f});
+            NDArray ret =
+                    Conv1d.conv1d(input, weight, bias, new Shape(1), new Shape(1))
+                            .singletonOrThrow();
+            NDArray expected = manager.create(new float[] {1f, 2f, 4f, 6f, 8f, 5f});
+            Assert.assertEquals(ret, expected.reshape(1, 1, 6));
+
+            input = manager.arange(16f).reshape(1, 1, 4, 4);
+            weight = manager.ones(new Shape(2, 1, 2, 2));
+            ret = Conv2d.conv2d(input, weight, null, new Shape(2, 2)).singletonOrThrow();
+            expected = manager.create(new float[] {10f, 18f, 42f, 50f}, new Shape(1, 1, 2, 2));
+            Assert.assertEquals(ret.getShape(), new Shape(1, 2, 2, 2));
+            Assert.assertEquals(ret.get(":, 1:"), expected);
+        }
+    }
+
+    @Test
+    public void testPooling() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray input = manager.create(new float[] {1f, 3f, 2f, 5f, 4f}, new Shape(1, 1, 5));
+            NDArray ret = Pool.maxPool1d(input, new Shape(2), new Shape(2), new Shape(0), false);
+            Assert.assertEquals(ret, manager.create(new float[] {3f, 5f}, new Shape(1, 1, 2)));
+            ret = Pool.maxPool1d(input, new Shape(2), new Shape(2), new Shape(0), true);
+            Assert.assertEquals(ret, manager.create(new float[] {3f, 5f, 4f}, new Shape(1, 1, 3)));
+            ret = Pool.avgPool1d(input, new Shape(2), new Shape(2), new Shape(1), false, false);
+            NDArray expected = manager.create(new float[] {1f, 2.5f, 4.5f}, new Shape(1, 1, 3));
+            Assert.assertEquals(ret, expected);
+
+            input = manager.arange(16f).reshape(1, 1, 4, 4);
+            ret = Pool.maxPool2d(input, new Shape(2, 2), new Shape(2, 2), new Shape(0, 0), false);
+            expected = manager.create(new float[] {5f, 7f, 13f, 15f}, new Shape(1, 1, 2, 2));
+            Assert.assertEquals(ret, expected);
+            ret =
+                    Pool.avgPool2d(
+                            input, new Shape(2, 2), new Shape(2, 2), new Shape(1, 1), false, true);
+            Assert.assertEquals(ret.getShape(), new Shape(1, 1, 3, 3));
+            Assert.assertEquals(ret.getFloat(0, 0, 0, 0), 0f);
+            Assert.assertEquals(ret.getFloat(0, 0, 1, 1), 7.5f);
+        }
+    }
+
+    @Test
+    public void testPad() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            RsNDArray array = (RsNDArray) manager.create(new float[][] {{1f, 2f, 3f}});
+            NDArray expected = manager.create(new float[][] {{1f, 2f, 3f, 0f, 0f}});
+            Assert.assertEquals(array.pad(new long[] {0, 2}, "constant", 0f), expected);
+            expected = manager.create(new float[][] {{3f, 2f, 1f, 2f, 3f, 2f}});
+            Assert.assertEquals(array.pad(new long[] {2, 1}, "reflect", 0f), expected);
+            expected = manager.create(new float[][] {{-1f, 1f, 2f, 3f}, {-1f, -1f, -1f, -1f}});
+            Assert.assertEquals(array.pad(new long[] {1, 0, 0, 1}, "constant", -1f), expected);
+            expected = manager.create(new float[][] {{1f, 2f, 3f, 3f}, {1f, 2f, 3f, 3f}});
+            Assert.assertEquals(array.pad(new long[] {0, 1, 1, 0}, "replicate", 0f), expected);
+        }
+    }
+
+    @Test
+    public void testTileRepeat() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.create(new float[][] {{1f, 2f}, {3f, 4f}});
+            NDArray expected = manager.create(new float[][] {{1f, 2f, 1f, 2f}, {3f, 4f, 3f, 4f}});
+            Assert.assertEquals(array.tile(1, 2), expected);
+            Assert.assertEquals(array.tile(new Shape(2, 4)), expected);
+            Assert.assertEquals(array.tile(2).getShape(), new Shape(4, 4));
+
+            expected = manager.create(new float[][] {{1f, 1f, 2f, 2f}, {3f, 3f, 4f, 4f}});
+            Assert.assertEquals(array.repeat(1, 2), expected);
+            expected = manager.create(new float[][] {{1f, 2f}, {1f, 2f}, {3f, 4f}, {3f, 4f}});
+            Assert.assertEquals(array.repeat(0, 2), expected);
+        }
+    }
+
+    @Test
+    public void testSplit() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.arange(10f).reshape(2, 5);
+            NDList result = array.split(new long[] {1, 3}, -1);
+            Assert.assertEquals(result.size(), 3);
+            Assert.assertEquals(result.get(2), manager.create(new float[][] {{3f, 4f}, {8f, 9f}}));
+
+            result = ((RsNDArray) array).chunk(2, 1);
+            Assert.assertEquals(result.size(), 2);
+            Assert.assertEquals(result.get(0).getShape(), new Shape(2, 3));
+            Assert.assertEquals(result.get(1), manager.create(new float[][] {{3f, 4f}, {8f, 9f}}));
+        }
+    }
+
+    @Test
+    public void testStackConcat() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray a = manager.create(new float[] {1f, 2f});
+            NDArray b = manager.create(new float[] {3f, 4f});
+            NDArray expected = manager.create(new float[][] {{1f, 3f}, {2f, 4f}});
+            Assert.assertEquals(NDArrays.stack(new NDList(a, b), -1), expected);
+            expected = manager.create(new float[][] {{1f, 2f}, {3f, 4f}});
+            Assert.assertEquals(NDArrays.stack(new NDList(a, b)), expected);
+
+            expected = manager.create(new float[] {1f, 2f, 3f, 4f});
+            Assert.assertEquals(NDArrays.concat(new NDList(a, b), -1), expected);
+            NDArray c = manager.create(new float[][] {{5f, 6f}});
+            expected = manager.create(new float[][] {{1f, 2f}, {3f, 4f}, {5f, 6f}});
+            Assert.assertEquals(NDArrays.concat(new NDList(expected.get(":2"), c)), expected);
+        }
+    }
+
+    @Test
+    public void testShapeManipulation() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.ones(new Shape(1, 3, 1, 2));
+            Assert.assertEquals(array.squeeze(new int[] {2, 0}).getShape(), new Shape(3, 2));
+            Assert.assertEquals(array.squeeze(-2).getShape(), new Shape(1, 3, 2));
+            Assert.assertEquals(array.squeeze().getShape(), new Shape(3, 2));
+
+            array = manager.create(new float[] {1f, 2f});
+            Assert.assertEquals(array.expandDims(-1).getShape(), new Shape(2, 1));
+            Assert.assertEquals(array.expandDims(-2).getShape(), new Shape(1, 2));
+
+            array = array.expandDims(0);
+            NDArray expected = manager.create(new float[][] {{1f, 2f}, {1f, 2f}, {1f, 2f}});
+            Assert.assertEquals(array.broadcast(new Shape(3, 2)), expected);
+            Assert.assertEquals(array.broadcast(3, -1), expected);
+            Assert.assertEquals(array.broadcast(4, 3, 2).getShape(), new Shape(4, 3, 2));
+        }
+    }
+
+    @Test
+    public void testMixedPrecision() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.create(new float[] {0.5f, -1.5f, 0f, 3f});
+            for (DataType type :
+                    new DataType[] {DataType.FLOAT16, DataType.BFLOAT16, DataType.FLOAT64}) {
+                NDArray converted = array.toType(type, false);
+                Assert.assertEquals(converted.getDataType(), type);
+                Assert.assertEquals(converted.getDevice(), array.getDevice());
+                Assert.assertEquals(converted.toType(DataType.FLOAT32, false), array);
+            }
+            NDArray int64 = array.toType(DataType.INT64, false);
+            Assert.assertEquals(int64, manager.create(new long[] {0, -1, 0, 3}));
+            NDArray bool = array.toType(DataType.BOOLEAN, false);
+            Assert.assertEquals(bool, manager.create(new boolean[] {true, true, false, true}));
+        }
+    }
+
+    @Test
+    public void testToDevice() {
+        try (NDManager manager = NDManager.newBaseManager(Device.cpu(), "Rust")) {
+            NDArray array = manager.create(new float[] {1f, 2f});
+            NDArray copy = array.toDevice(Device.cpu(), true);
+            Assert.assertEquals(copy.getDevice(), Device.cpu());
+            Assert.assertEquals(copy, array);
+
+            int gpuCount = manager.getEngine().getGpuCount();
+            for (int i = 0; i < gpuCount; ++i) {
+                NDArray gpu = array.toDevice(Device.gpu(i), false);
+                Assert.assertEquals(gpu.getDevice(), Device.gpu(i));
+                // GPU to GPU transfer goes through the host
+                NDArray next = gpu.toDevice(Device.gpu((i + 1) % gpuCount), false);
+                Assert.assertEquals(next.toDevice(Device.cpu(), false), array);
+            }
+        }
+    }
+
+    @Test
+    public void testSeededRandom() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            RsNDManager rsManager = (RsNDManager) manager;
+            Shape shape = new Shape(2, 3);
+            NDArray a = rsManager.randomUniform(-1f, 1f, shape, DataType.FLOAT32, 42);
+            NDArray b = rsManager.randomUniform(-1f, 1f, shape, DataType.FLOAT32, 42);
+            NDArray c = rsManager.randomUniform(-1f, 1f, shape, DataType.FLOAT32, 7);
+            Assert.assertEquals(a.toFloatArray(), b.toFloatArray());
+            Assert.assertNotEquals(a.toFloatArray(), c.toFloatArray());
+            Assert.assertTrue(a.gte(-1f).all().getBoolean());
+            Assert.assertTrue(a.lt(1f).all().getBoolean());
+
+            a = rsManager.randomNormal(0f, 1f, shape, DataType.FLOAT64, 42);
+            b = rsManager.randomNormal(0f, 1f, shape, DataType.FLOAT64, 42);
+            Assert.assertEquals(a.toDoubleArray(), b.toDoubleArray());
+
+            a = manager.randomUniform(0f, 1f, shape, DataType.FLOAT16);
+            Assert.assertEquals(a.getDataType(), DataType.FLOAT16);
+
+            Engine engine = manager.getEngine();
+            engine.setRandomSeed(1234);
+            a = manager.randomNormal(shape);
+            engine.setRandomSeed(1234);
+            b = manager.randomNormal(shape);
+            Assert.assertEquals(a.toFloatArray(), b.toFloatArray());
+            a = manager.randomUniform(0f, 1f, shape, DataType.FLOAT16);
+            Assert.assertEquals(a.getDataType(), DataType.FLOAT16);
+        }
+    }
+
+    @Test
+    public void testCreationOps() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.arange(0f, 1f, 0.1f);
+            Assert.assertEquals(array.getShape(), new Shape(10));
+            Assertions.assertAlmostEquals(array.get(9), manager.create(0.9f));
+
+            array = manager.arange(5, 0, -2, DataType.INT64);
+            Assert.assertEquals(array.toLongArray(), new long[] {5, 3, 1});
+
+            array = manager.linspace(0f, 1f, 5);
+            Assert.assertEquals(array.toFloatArray(), new float[] {0f, 0.25f, 0.5f, 0.75f, 1f});
+            array = manager.linspace(0f, 1f, 4, false);
+            Assert.assertEquals(array.toFloatArray(), new float[] {0f, 0.25f, 0.5f, 0.75f});
+
+            array = manager.eye(2, 3, 1, DataType.INT64);
+            Assert.assertEquals(array.toLongArray(), new long[] {0, 1, 0, 0, 0, 1});
+            array = manager.eye(3, 2, -1, DataType.FLOAT32);
+            Assert.assertEquals(array.toFloatArray(), new float[] {0f, 0f, 1f, 0f, 0f, 1f});
+        }
+    }
+
+    @Test
+    public void testClip() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.create(new float[] {-2f, -0.5f, 0.5f, 2f});
+            NDArray clipped = array.clip(-1, 1);
+            Assert.assertEquals(clipped.toFloatArray(), new float[] {-1f, -0.5f, 0.5f, 1f});
+            clipped = array.clip(0, null);
+            Assert.assertEquals(clipped.toFloatArray(), new float[] {0f, 0f, 0.5f, 2f});
+            clipped = array.clip(null, 0);
+            Assert.assertEquals(clipped.toFloatArray(), new float[] {-2f, -0.5f, 0f, 0f});
+
+            array = manager.create(new long[] {-5, 3, 10});
+            clipped = array.clip(0, 5);
+            Assert.assertEquals(clipped.getDataType(), DataType.INT64);
+            Assert.assertEquals(clipped.toLongArray(), new long[] {0, 3, 5});
+        }
+    }
+
+    @Test
+    public void testUnique() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.create(new float[] {3f, 1f, 2f, 1f, 3f}, new Shape(5));
+            NDList list = array.unique(true, true, true);
+            Assert.assertEquals(list.get(0).toFloatArray(), new float[] {1f, 2f, 3f});
+            Assert.assertEquals(list.get(1).toLongArray(), new long[] {2, 0, 1, 0, 2});
+            Assert.assertEquals(list.get(2).toLongArray(), new long[] {2, 1, 2});
+
+            list = array.unique(false, false, true);
+            Assert.assertEquals(list.size(), 2);
+            Assert.assertEquals(list.get(0).toFloatArray(), new float[] {3f, 1f, 2f});
+            Assert.assertEquals(list.get(1).toLongArray(), new long[] {2, 2, 1});
+
+            array = manager.create(new long[] {1, 2, 1, 2, 3, 4}, new Shape(3, 2));
+            list = array.unique(0, true, true, false);
+            Assert.assertEquals(list.get(0).getShape(), new Shape(2, 2));
+            Assert.assertEquals(list.get(0).toLongArray(), new long[] {1, 2, 3, 4});
+            Assert.assertEquals(list.get(1).toLongArray(), new long[] {0, 0, 1});
+
+            array = manager.create(new int[] {2, 1, 2, 1}, new Shape(2, 2));
+            list = array.unique(true, true, false);
+            Assert.assertEquals(list.get(1).getShape(), new Shape(2, 2));
+        }
+    }
+
+    @Test
+    public void testNonzero() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.create(new float[] {0f, 1f, 2f, 0f, 0f, 3f}, new Shape(2, 3));
+            NDArray indices = array.nonzero();
+            Assert.assertEquals(indices.getShape(), new Shape(3, 2));
+            Assert.assertEquals(indices.getDataType(), DataType.INT64);
+            Assert.assertEquals(indices.toLongArray(), new long[] {0, 1, 0, 2, 1, 2});
+
+            NDArray mask = manager.create(new boolean[] {false, true, true});
+            Assert.assertEquals(mask.nonzero().toLongArray(), new long[] {1, 2});
+
+            indices = manager.zeros(new Shape(2, 2)).nonzero();
+            Assert.assertEquals(indices.getShape(), new Shape(0, 2));
+        }
+    }
+
+    @Test
+    public void testOneHot() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.create(new long[] {0, 2, 1});
+            NDArray oneHot = array.oneHot(3);
+            Assert.assertEquals(oneHot.getShape(), new Shape(3, 3));
+            Assert.assertEquals(oneHot.getDataType(), DataType.FLOAT32);
+            float[] expected = {1f, 0f, 0f, 0f, 0f, 1f, 0f, 1f, 0f};
+            Assert.assertEquals(oneHot.toFloatArray(), expected);
+
+            array = manager.create(new int[] {1, 5}, new Shape(2, 1));
+            oneHot = array.oneHot(2, 5f, -1f, DataType.INT64);
+            Assert.assertEquals(oneHot.getShape(), new Shape(2, 1, 2));
+            Assert.assertEquals(oneHot.toLongArray(), new long[] {-1, 5, -1, -1});
+        }
+    }
+
+    @Test
+    public void testArgMaxMin() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.create(new float[] {1f, 5f, 3f, 4f, 2f, 6f}, new Shape(2, 3));
+            Assert.assertEquals(array.argMax().getLong(), 5L);
+            Assert.assertEquals(array.argMin().getLong(), 0L);
+            Assert.assertEquals(array.argMax(1).toLongArray(), new long[] {1, 2});
+            Assert.assertEquals(array.argMax(-1).toLongArray(), new long[] {1, 2});
+            Assert.assertEquals(array.argMin(0).toLongArray(), new long[] {0, 1, 0});
+
+            RsNDArray indices = ((RsNDArray) array).argMax(-1, true);
+            Assert.assertEquals(indices.getShape(), new Shape(2, 1));
+            Assert.assertEquals(indices.getDataType(), DataType.INT64);
+            indices = ((RsNDArray) array).argMin(0, true);
+            Assert.assertEquals(indices.getShape(), new Shape(1, 3));
+        }
+    }
+
+    @Test
+    public void testStatistics() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.create(new float[] {1f, 2f, 3f, 4f, 6f, 8f}, new Shape(2, 3));
+            NDArray mean = array.mean(new int[] {-1}, true);
+            Assert.assertEquals(mean.getShape(), new Shape(2, 1));
+            Assert.assertEquals(mean.toFloatArray(), new float[] {2f, 6f});
+
+            RsNDArray rs = (RsNDArray) array;
+            NDArray var = rs.var(new int[] {1}, false, false);
+            Assertions.assertAlmostEquals(var, manager.create(new float[] {2f / 3, 8f / 3}));
+            var = rs.var(new int[] {1}, true, true);
+            Assert.assertEquals(var.getShape(), new Shape(2, 1));
+            Assert.assertEquals(var.toFloatArray(), new float[] {1f, 4f});
+            NDArray std = rs.std(new int[] {0, 1}, true, false);
+            Assert.assertEquals(std.getShape(), new Shape());
+            Assertions.assertAlmostEquals(std, manager.create((float) Math.sqrt(6.8)));
+
+            RsNDArray ints = (RsNDArray) manager.create(new long[] {1, 2});
+            Assert.assertEquals(ints.mean().getFloat(), 1.5f);
+            Assert.assertEquals(ints.var(new int[] {0}, true, false).getFloat(), 0.5f);
+
+            NDArray all = rs.var(new int[0], true, false);
+            Assertions.assertAlmostEquals(all, manager.create(6.8f));
+            RsNDArray single = (RsNDArray) manager.create(new float[] {1f});
+            Assert.assertTrue(Float.isNaN(single.var(new int[] {0}, true, false).getFloat()));
+        }
+    }
+
+    @Test
+    public void testComparison() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.create(new float[] {1f, 2f, 3f, 4f}, new Shape(2, 2));
+            NDArray other = manager.create(new long[] {2, 3});
+            NDArray result = array.gt(other);
+            Assert.assertEquals(result.getDataType(), DataType.BOOLEAN);
+            Assert.assertEquals(result.toBooleanArray(), new boolean[] {false, false, true, true});
+            result = array.eq(other);
+            Assert.assertFalse(result.any().getBoolean());
+            result = array.lte(manager.create(new double[] {1.5, 4}));
+            Assert.assertEquals(result.toBooleanArray(), new boolean[] {true, true, false, true});
+
+            NDArray ints = manager.create(new long[] {1, 2, 3});
+            Assert.assertEquals(ints.gt(1.5).toBooleanArray(), new boolean[] {false, true, true});
+            Assert.assertEquals(ints.neq(2).toBooleanArray(), new boolean[] {true, false, true});
+            Assert.assertEquals(ints.gte(3).toBooleanArray(), new boolean[] {false, false, true});
+
+            NDArray bytes = manager.create(new byte[] {0, 1}).toType(DataType.UINT8, false);
+            Assert.assertEquals(bytes.gt(-1).toBooleanArray(), new boolean[] {true, true});
+            Assert.assertEquals(bytes.lt(300).toBooleanArray(), new boolean[] {true, true});
+        }
+    }
+
+    @Test
+    public void testMaskedSelect() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.arange(6f).reshape(2, 3);
+            NDArray mask = manager.create(new boolean[] {true, false, true});
+            RsNDArray rs = (RsNDArray) array;
+            Assert.assertEquals(rs.maskedSelect(mask).toFloatArray(), new float[] {0f, 2f, 3f, 5f});
+
+            NDArray source = manager.create(new float[] {10f, 20f, 30f, 40f, 50f});
+            NDArray scattered = rs.maskedScatter(mask, source);
+            Assert.assertEquals(scattered.getShape(), new Shape(2, 3));
+            float[] expected = {10f, 1f, 20f, 30f, 4f, 40f};
+            Assert.assertEquals(scattered.toFloatArray(), expected);
+
+            NDArray rows = manager.create(new boolean[] {false, true});
+            NDArray selected = array.booleanMask(rows);
+            Assert.assertEquals(selected.getShape(), new Shape(1, 3));
+            Assert.assertEquals(selected.toFloatArray(), new float[] {3f, 4f, 5f});
+            selected = array.booleanMask(mask, 1);
+            Assert.assertEquals(selected.getShape(), new Shape(2, 2));
+        }
+    }
+
+    @Test
+    public void testRollFlip() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            RsNDArray array = (RsNDArray) manager.arange(6f).reshape(2, 3);
+            NDArray flipped = array.flip(1);
+            Assert.assertEquals(flipped.toFloatArray(), new float[] {2f, 1f, 0f, 5f, 4f, 3f});
+            flipped = array.flip(0, -1);
+            Assert.assertEquals(flipped.toFloatArray(), new float[] {5f, 4f, 3f, 2f, 1f, 0f});
+
+            NDArray rolled = array.roll(new long[] {1}, 1);
+            Assert.assertEquals(rolled.toFloatArray(), new float[] {2f, 0f, 1f, 5f, 3f, 4f});
+            rolled = array.roll(new long[] {-1, 4}, 0, -1);
+            Assert.assertEquals(rolled.toFloatArray(), new float[] {5f, 3f, 4f, 2f, 0f, 1f});
+            rolled = array.roll(new long[] {2});
+            Assert.assertEquals(rolled.getShape(), new Shape(2, 3));
+            Assert.assertEquals(rolled.toFloatArray(), new float[] {4f, 5f, 0f, 1f, 2f, 3f});
+        }
+    }
+
+    @Test
+    public void testTriangle() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            RsNDArray ones = (RsNDArray) manager.ones(new Shape(3, 3));
+            float[] expected = {1f, 0f, 0f, 1f, 1f, 0f, 1f, 1f, 1f};
+            Assert.assertEquals(ones.tril(0).toFloatArray(), expected);
+            expected = new float[] {0f, 1f, 1f, 0f, 0f, 1f, 0f, 0f, 0f};
+            Assert.assertEquals(ones.triu(1).toFloatArray(), expected);
+
+            RsNDArray array = (RsNDArray) manager.arange(1, 13, 1, DataType.INT64).reshape(2, 2, 3);
+            NDArray lower = array.tril(-1);
+            Assert.assertEquals(lower.getShape(), new Shape(2, 2, 3));
+            long[] values = {0, 0, 0, 4, 0, 0, 0, 0, 0, 10, 0, 0};
+            Assert.assertEquals(lower.toLongArray(), values);
+        }
+    }
+
+    @Test
+    public void testCosineSimilarity() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            RsNDArray queries = (RsNDArray) manager.create(new float[][] {{1f, 0f}, {1f, 1f}});
+            NDArray docs = manager.create(new float[][] {{2f, 0f}, {0f, 3f}, {0f, 0f}});
+
+            NDArray scores = queries.cosineSimilarity(manager.create(new float[] {0f, 1f}), -1);
+            Assert.assertEquals(scores.getShape(), new Shape(2));
+            float sqrt = (float) Math.sqrt(0.5);
+            Assertions.assertAlmostEquals(scores, manager.create(new float[] {0f, sqrt}));
+
+            scores = queries.cosineSimilarityMatrix(docs);
+            Assert.assertEquals(scores.getShape(), new Shape(2, 3));
+            float[] expected = {1f, 0f, 0f, sqrt, sqrt, 0f};
+            Assertions.assertAlmostEquals(scores, manager.create(expected, new Shape(2, 3)));
+        }
+    }
+
+    @Test
+    public void testCdist() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            RsNDArray a = (RsNDArray) manager.create(new float[][] {{0f, 0f}, {1f, 1f}});
+            NDArray b = manager.create(new float[][] {{3f, 4f}, {1f, 1f}, {0f, 1f}});
+
+            NDArray distances = a.cdist(b, 2);
+            Assert.assertEquals(distances.getShape(), new Shape(2, 3));
+            float[] expected = {5f, (float) Math.sqrt(2), 1f, (float) Math.sqrt(13), 0f, 1f};
+            Assertions.assertAlmostEquals(distances, manager.create(expected, new Shape(2, 3)));
+
+            distances = a.cdist(b, 1);
+            expected = new float[] {7f, 2f, 1f, 5f, 0f, 1f};
+            Assert.assertEquals(distances.toFloatArray(), expected);
+            distances = a.cdist(b, Float.POSITIVE_INFINITY);
+            expected = new float[] {4f, 1f, 1f, 3f, 0f, 1f};
+            Assert.assertEquals(distances.toFloatArray(), expected);
+
+            NDArray batch = a.expandDims(0).repeat(0, 3);
+            Assert.assertEquals(((RsNDArray) batch).cdist(b, 2).getShape(), new Shape(3, 2, 3));
+        }
+    }
+
+    @Test
+    public void testSafetensors() throws IOException {
+        Path dir = Paths.get("build/safetensors");
+        Files.createDirectories(dir);
+        Path file = dir.resolve("tensors.safetensors");
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            RsNDManager rsManager = (RsNDManager) manager;
+            Map<String, NDArray> tensors = new ConcurrentHashMap<>();
+            tensors.put("weight", manager.arange(6f).reshape(2, 3));
+            tensors.put("bias", manager.create(new long[] {1, 2}));
+            tensors.put("half", manager.ones(new Shape(2)).toType(DataType.FLOAT16, false));
+            rsManager.saveTensors(tensors, file);
+
+            Map<String, NDArray> loaded = rsManager.loadTensors(file);
+            Assert.assertEquals(loaded.keySet().toArray(), new String[] {"bias", "half", "weight"});
+            Assert.assertEquals(loaded.get("weight").getShape(), new Shape(2, 3));
+            float[] expected = {0f, 1f, 2f, 3f, 4f, 5f};
+            Assert.assertEquals(loaded.get("weight").toFloatArray(), expected);
+            Assert.assertEquals(loaded.get("bias").toLongArray(), new long[] {1, 2});
+            Assert.assertEquals(loaded.get("half").getDataType(), DataType.FLOAT16);
+
+            NDList list = manager.load(file);
+            Assert.assertEquals(list.size(), 3);
+            Assert.assertEquals(list.get(2).getName(), "weight");
+        } finally {
+            Utils.deleteQuietly(dir);
+        }
+    }
+
+    @Test
+    public void testNumpyFormat() throws IOException {
+        Path dir = Paths.get("build/numpy");
+        Files.createDirectories(dir);
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            RsNDManager rsManager = (RsNDManager) manager;
+            NDArray array = manager.arange(6f).reshape(3, 2);
+            Path npy = dir.resolve("array.npy");
+            rsManager.saveNpy(array, npy);
+            NDArray loaded = rsManager.loadNpy(npy);
+            Assert.assertEquals(loaded.getShape(), new Shape(3, 2));
+            Assert.assertEquals(loaded.toFloatArray(), array.toFloatArray());
+
+            Map<String, NDArray> tensors = new ConcurrentHashMap<>();
+            tensors.put("x", array.transpose());
+            tensors.put("ids", manager.create(new long[] {7, 8, 9}));
+            Path npz = dir.resolve("arrays.npz");
+            rsManager.saveNpz(tensors, npz);
+            Map<String, NDArray> map = rsManager.loadNpz(npz);
+            Assert.assertEquals(map.keySet().toArray(), new String[] {"ids", "x"});
+            Assert.assertEquals(map.get("x").getShape(), new Shape(2, 3));
+            float[] expected = {0f, 2f, 4f, 1f, 3f, 5f};
+            Assert.assertEquals(map.get("x").toFloatArray(), expected);
+            Assert.assertEquals(map.get("ids").toLongArray(), new long[] {7, 8, 9});
+
+            NDList list = manager.load(npz);
+            Assert.assertEquals(list.get(0).getName(), "ids");
+            Assert.assertEquals(manager.load(npy).singletonOrThrow().getShape(), new Shape(3, 2));
+        } finally {
+            Utils.deleteQuietly(dir);
+        }
+    }
+
+    @Test
+    public void testByteBuffer() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.arange(6f).reshape(2, 3);
+            ByteBuffer view = array.toByteBuffer(true);
+            Assert.assertTrue(view.isDirect());
+            Assert.assertEquals(view.asFloatBuffer().get(4), 4f);
+
+            ByteBuffer copy = array.transpose().toByteBuffer(true);
+            Assert.assertEquals(copy.remaining(), 24);
+            Assert.assertEquals(copy.asFloatBuffer().get(1), 3f);
+
+            ByteBuffer bb = array.toByteBuffer();
+            NDArray other = manager.create(bb, array.getShape(), DataType.FLOAT32);
+            Assert.assertEquals(other.toFloatArray(), array.toFloatArray());
+
+            // the view keeps the storage alive after the array is closed
+            array.close();
+            Assert.assertEquals(view.asFloatBuffer().get(5), 5f);
+        }
+    }
+
+    @Test
+    public void testInplaceArithmetic() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            RsNDArray array = (RsNDArray) manager.arange(6f).reshape(2, 3);
+            long handle = array.getHandle();
+            NDArray row = manager.create(new float[] {1f, 2f, 3f});
+            array.addi(row).muli(2).subi(1);
+            Assert.assertEquals(array.getHandle().longValue(), handle);
+            float[] expected = {1f, 5f, 9f, 7f, 11f, 15f};
+            Assert.assertEquals(array.toFloatArray(), expected);
+
+            array.addScaledi(row, -0.5).divi(manager.create(2f));
+            expected = new float[] {0.25f, 2f, 3.75f, 3.25f, 5f, 6.75f};
+            Assert.assertEquals(array.toFloatArray(), expected);
+            Assert.assertThrows(() -> row.addi(array));
+        }
+    }
+
+    @Test
+    public void testStridedSlice() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.arange(12f).reshape(3, 4);
+            NDArray expected = manager.create(new float[] {1f, 3f, 9f, 11f}, new Shape(2, 2));
+            Assert.assertEquals(array.get("::2, 1::2"), expected);
+
+            RsNDArray rs = (RsNDArray) array;
+            long[] start = {-2, 0};
+            RsNDArray slice = rs.slice(start, new long[] {100, 4}, new long[] {1, 3});
+            expected = manager.create(new float[] {4f, 7f, 8f, 11f}, new Shape(2, 2));
+            Assert.assertEquals(slice, expected);
+
+            Assert.assertEquals(rs.narrow(1, -2, 2), array.get(":, 2:"));
+            Assert.assertThrows(() -> rs.narrow(0, 2, 5));
+        }
+    }
+
+    @Test
+    public void testPinnedBuffer() {
+        try (RsNDManager manager = (RsNDManager) NDManager.newBaseManager("Rust")) {
+            ByteBuffer bb = manager.allocatePinned(24);
+            Assert.assertTrue(bb.isDirect());
+            bb.asFloatBuffer().put(new float[] {0f, 1f, 2f, 3f, 4f, 5f});
+            NDArray array = manager.create(bb, new Shape(2, 3), DataType.FLOAT32);
+            Assert.assertEquals(array.toFloatArray(), new float[] {0f, 1f, 2f, 3f, 4f, 5f});
+
+            ByteBuffer out = manager.allocatePinned(24);
+            ((RsNDArray) array.add(1)).copyTo(out);
+            Assert.assertEquals(out.asFloatBuffer().get(5), 6f);
+
+            // only the buffers of allocatePinned can be freed
+            ByteBuffer direct = ByteBuffer.allocateDirect(24);
+            Assert.assertThrows(EngineException.class, () -> RustLibrary.freePinned(direct));
+        }
+    }
+
+    @Test
+    public void testBFloat16ToFloatArray() {
+        try (NDManager manager = NDManager.newBaseManager("Rust")) {
+            NDArray array = manager.create(new float[] {1f, -2.5f, 3f});
+            NDArray bf16 = array.toType(DataType.BFLOAT16, false);
+            Assert.assertEquals(bf16.getDataType(), DataType.BFLOAT16);
+            Assert.assertEquals(bf16.toFloatArray(), new float[] {1f, -2.5f, 3f});
+        }
+    }
 }
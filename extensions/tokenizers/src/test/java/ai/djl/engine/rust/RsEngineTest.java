@@ -12,16 +12,115 @@
  */
 package ai.djl.engine.rust;
 
+import ai.djl.Device;
 import ai.djl.engine.Engine;
+import ai.djl.engine.EngineException;
+import ai.djl.engine.StandardCapabilities;
+import ai.djl.ndarray.NDManager;
+import ai.djl.ndarray.types.Shape;
 
 import org.testng.Assert;
 import org.testng.annotations.Test;
 
+import java.lang.management.MemoryUsage;
+import java.util.Map;
+
 public class RsEngineTest {
 
     @Test
     public void testVersion() {
         Engine engine = Engine.getEngine("Rust");
         Assert.assertEquals(engine.getVersion(), Engine.getDjlVersion());
+        String blas = RustLibrary.getBlasBackend();
+        Assert.assertTrue(engine.toString().contains("BLAS: " + blas));
+        Assert.assertEquals(engine.hasCapability(StandardCapabilities.MKL), "mkl".equals(blas));
+    }
+
+    @Test
+    public void testDevices() {
+        RsEngine engine = (RsEngine) Engine.getEngine("Rust");
+        int gpuCount = engine.getGpuCount();
+        Assert.assertTrue(gpuCount >= 0);
+        if (gpuCount == 0) {
+            Assert.assertFalse(engine.defaultDevice().isGpu());
+            Assert.assertThrows(() -> engine.getGpuMemory(Device.gpu()));
+            Assert.assertThrows(() -> engine.emptyCache(Device.gpu()));
+            return;
+        }
+        Assert.assertEquals(engine.defaultDevice(), Device.gpu());
+        MemoryUsage memory = engine.getGpuMemory(Device.gpu(gpuCount - 1));
+        Assert.assertTrue(memory.getMax() > 0);
+        Assert.assertTrue(memory.getUsed() <= memory.getMax());
+
+        engine.emptyCache(Device.gpu());
+        Map<String, Long> stats = engine.getMemoryPoolStats(Device.gpu());
+        Assert.assertTrue(stats.get("used") <= stats.get("reserved"));
+        Assert.assertTrue(stats.get("reserved") <= stats.get("reserved_peak"));
+    }
+
+    @Test
+    public void testNumThreads() {
+        RsEngine engine = (RsEngine) Engine.getEngine("Rust");
+        int numThreads = engine.getNumThreads();
+        engine.setNumThreads(numThreads + 1);
+        Assert.assertEquals(engine.getNumThreads(), numThreads + 1);
+        engine.setNumThreads(numThreads);
+        Assert.assertEquals(engine.getNumThreads(), numThreads);
+        engine.setNumInteropThreads(3);
+        Assert.assertEquals(engine.getNumInteropThreads(), 3);
+        engine.setNumInteropThreads(0);
+        Assert.assertEquals(engine.getNumInteropThreads(), 1);
+    }
+
+    @Test
+    public void testLiveHandles() {
+        RsEngine engine = (RsEngine) Engine.getEngine("Rust");
+        long before = engine.getLiveHandles().getOrDefault("Tensor", 0L);
+        try (NDManager manager = engine.newBaseManager()) {
+            manager.ones(new Shape(2, 3));
+            long live = engine.getLiveHandles().getOrDefault("Tensor", 0L);
+            Assert.assertTrue(live > before);
+        }
+        Assert.assertEquals(engine.getLiveHandles().getOrDefault("Tensor", 0L), before);
+        Assert.assertEquals(engine.freeAll("NoSuchKind"), 0);
+    }
+
+    @Test
+    public void testFreedHandle() {
+        RsEngine engine = (RsEngine) Engine.getEngine("Rust");
+        try (RsCancellationToken token = new RsCancellationToken()) {
+            // cancellation tokens are registered as the flag they share
+            Assert.assertTrue(engine.freeAll("AtomicBool") > 0);
+            Assert.assertThrows(EngineException.class, token::cancel);
+        }
+    }
+
+    @Test
+    public void testCheckNumerics() {
+        RsEngine engine = (RsEngine) Engine.getEngine("Rust");
+        boolean enabled = engine.isCheckNumerics();
+        try {
+            engine.setCheckNumerics(true);
+            Assert.assertTrue(engine.isCheckNumerics());
+            engine.setCheckNumerics(false);
+            Assert.assertFalse(engine.isCheckNumerics());
+        } finally {
+            engine.setCheckNumerics(enabled);
+        }
+    }
+
+    @Test
+    public void testDeterministic() {
+        RsEngine engine = (RsEngine) Engine.getEngine("Rust");
+        boolean enabled = engine.isDeterministic();
+        engine.setDeterministic(enabled);
+        try {
+            engine.setDeterministic(!enabled);
+            Assert.assertEquals(engine.isDeterministic(), !enabled);
+            engine.setDeterministic(enabled);
+        } catch (EngineException e) {
+            // other tests already ran an inference, the mode is fixed
+            Assert.assertEquals(engine.isDeterministic(), enabled);
+        }
     }
 }
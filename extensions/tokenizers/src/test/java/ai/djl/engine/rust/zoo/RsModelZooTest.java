@@ -12,26 +12,60 @@
  */
 package ai.djl.engine.rust.zoo;
 
+import ai.djl.Device;
+import ai.djl.Model;
 import ai.djl.ModelException;
+import ai.djl.engine.Engine;
+import ai.djl.engine.EngineException;
+import ai.djl.engine.rust.RsCancellationToken;
+import ai.djl.engine.rust.RsEngine;
+import ai.djl.engine.rust.RsModel;
+import ai.djl.engine.rust.RsNDManager;
+import ai.djl.engine.rust.RsSymbolBlock;
 import ai.djl.inference.Predictor;
+import ai.djl.ndarray.NDArray;
+import ai.djl.ndarray.NDList;
+import ai.djl.ndarray.NDManager;
+import ai.djl.ndarray.types.DataType;
+import ai.djl.ndarray.types.Shape;
+import ai.djl.nn.Block;
 import ai.djl.repository.zoo.Criteria;
 import ai.djl.repository.zoo.ModelZoo;
 import ai.djl.repository.zoo.ZooModel;
 import ai.djl.testing.TestRequirements;
+import ai.djl.training.ParameterStore;
 import ai.djl.translate.TranslateException;
+import ai.djl.util.PairList;
 import ai.djl.util.Utils;
 
+import com.google.gson.JsonObject;
+
 import org.testng.Assert;
 import org.testng.annotations.Test;
 
 import java.io.IOException;
+import java.nio.ByteBuffer;
+import java.nio.ByteOrder;
+import java.nio.charset.StandardCharsets;
+import java.nio.file.Files;
+import java.nio.file.Path;
 import java.nio.file.Paths;
+import java.util.ArrayList;
+import java.util.Arrays;
+import java.util.List;
+import java.util.Random;
 import java.util.Set;
+import java.util.concurrent.ExecutionException;
+import java.util.concurrent.ExecutorService;
+import java.util.concurrent.Executors;
+import java.util.concurrent.Future;
 
 public class RsModelZooTest {
 
     @Test
-    public void testRsModelZoo() throws ModelException, IOException, TranslateException {
+    public void testRsModelZoo()
+            throws ModelException, IOException, TranslateException, ExecutionException,
+                    InterruptedException {
         TestRequirements.nightly();
 
         String text = "What is deep learning?";
@@ -41,8 +75,152 @@ public class RsModelZooTest {
 
         try (ZooModel<String, float[]> model = criteria.loadModel();
                 Predictor<String, float[]> predictor = model.newPredictor()) {
+            ((RsSymbolBlock) model.getBlock()).warmup(2, 16);
             float[] res = predictor.predict(text);
             Assert.assertEquals(res.length, 384);
+
+            RsSymbolBlock block = (RsSymbolBlock) model.getBlock();
+            NDManager manager = model.getNDManager();
+            NDList inputs = sampleInputs(manager);
+            NDList expected = block.forward(new ParameterStore(), inputs, false);
+            NDList output = block.forwardAsync(inputs).get();
+            Assert.assertEquals(output.head().getShape(), expected.head().getShape());
+
+            // a cancelled request still frees its native future once the callback fires
+            RsEngine engine = (RsEngine) Engine.getEngine("Rust");
+            String kind = "InferenceFuture";
+            long before = engine.getLiveHandles().getOrDefault(kind, 0L);
+            block.forwardAsync(inputs).cancel(true);
+            long deadline = System.currentTimeMillis() + 10000;
+            while (engine.getLiveHandles().getOrDefault(kind, 0L) > before
+                    && System.currentTimeMillis() < deadline) {
+                Thread.sleep(10);
+            }
+            Assert.assertEquals(engine.getLiveHandles().getOrDefault(kind, 0L), before);
+            Assert.assertEquals(expected.size(), block.getOutputNames().size());
+            Assert.assertEquals(expected.head().getName(), "last_hidden_state");
+            PairList<String, Object> hiddenParams = new PairList<>();
+            hiddenParams.add("last_hidden_state_only", true);
+            NDList hidden = block.forward(new ParameterStore(), inputs, false, hiddenParams);
+            Assert.assertEquals(hidden.size(), 1);
+            Assert.assertTrue(hidden.head().allClose(expected.head(), 1e-3, 1e-3, false));
+
+            JsonObject metadata = block.getMetadata();
+            Assert.assertEquals(metadata.get("model_type").getAsString(), "bert");
+            Assert.assertEquals(metadata.get("hidden_size").getAsInt(), 384);
+            Assert.assertTrue(metadata.get("parameter_count").getAsLong() > 0);
+            Assert.assertEquals(model.getProperty("hidden_size"), "384");
+            String backend = block.getAttentionBackend();
+            Assert.assertTrue("eager".equals(backend) || "flash".equals(backend), backend);
+
+            NDList withPositions = new NDList(inputs);
+            withPositions.add(manager.arange(0, 4, 1, DataType.INT64).expandDims(0));
+            NDList positioned = block.forward(new ParameterStore(), withPositions, false);
+            Assert.assertTrue(positioned.head().allClose(expected.head(), 1e-3, 1e-3, false));
+
+            long[][] jaggedIds = {{101, 2054, 2003, 102}, {101, 102}};
+            NDList jagged = block.forwardJagged(manager, jaggedIds, null);
+            Assert.assertEquals(jagged.head().getShape().get(1), 4);
+            NDArray mask = jagged.get("attention_mask").toType(DataType.INT64, false);
+            Assert.assertEquals(mask.sum().getLong(), 6);
+            NDArray first = jagged.head().get(0).expandDims(0);
+            Assert.assertTrue(first.allClose(expected.head(), 1e-3, 1e-3, false));
+
+            NDArray longIds = manager.zeros(new Shape(1, 600), DataType.INT64);
+            NDList longInputs =
+                    new NDList(longIds, longIds.onesLike(), longIds.zerosLike());
+            Exception e =
+                    Assert.expectThrows(
+                            Exception.class,
+                            () -> block.forward(new ParameterStore(), longInputs, false));
+            Assert.assertTrue(e.getMessage().contains("input 600 > max 512"), e.getMessage());
+
+            PairList<String, Object> params = new PairList<>();
+            try (RsCancellationToken token = new RsCancellationToken()) {
+                params.add("cancellation_token", token);
+                token.cancel();
+                Assert.assertThrows(
+                        () -> block.forward(new ParameterStore(), inputs, false, params));
+            }
+        }
+    }
+
+    @Test
+    public void testCudaGraphsOption() {
+        TestRequirements.nightly();
+
+        String url = "djl://ai.djl.huggingface.rust/TaylorAI/bge-micro-v2";
+        Criteria<String, float[]> criteria =
+                Criteria.builder()
+                        .setTypes(String.class, float[].class)
+                        .optModelUrls(url)
+                        .optOption("cuda_graphs", "true")
+                        .build();
+        // the encoders have no decode steps to capture
+        Assert.assertThrows(criteria::loadModel);
+    }
+
+    @Test
+    public void testConcurrentForward()
+            throws ModelException, IOException, ExecutionException, InterruptedException {
+        TestRequirements.nightly();
+
+        String url = "djl://ai.djl.huggingface.rust/TaylorAI/bge-micro-v2";
+        Criteria<NDList, NDList> criteria =
+                Criteria.builder().setTypes(NDList.class, NDList.class).optModelUrls(url).build();
+
+        ExecutorService executor = Executors.newFixedThreadPool(8);
+        try (ZooModel<NDList, NDList> model = criteria.loadModel()) {
+            RsSymbolBlock block = (RsSymbolBlock) model.getBlock();
+            NDManager manager = model.getNDManager();
+            NDList inputs = sampleInputs(manager);
+            float[] expected = forwardIds(block, manager);
+
+            // all threads share the same native model handle
+            List<Future<float[]>> futures = new ArrayList<>();
+            for (int i = 0; i < 64; ++i) {
+                futures.add(
+                        executor.submit(
+                                () -> {
+                                    try (NDManager sub = manager.newSubManager()) {
+                                        NDList copy = new NDList();
+                                        for (NDArray array : inputs) {
+                                            copy.add(array.duplicate());
+                                        }
+                                        copy.attach(sub);
+                                        NDList output =
+                                                block.forward(new ParameterStore(), copy, false);
+                                        NDArray head = output.head();
+                                        return head.toType(DataType.FLOAT32, false).toFloatArray();
+                                    }
+                                }));
+            }
+            for (Future<float[]> future : futures) {
+                Assert.assertEquals(future.get(), expected);
+            }
+        } finally {
+            executor.shutdown();
+        }
+    }
+
+    @Test
+    public void testLoadFromBytes() throws ModelException, IOException {
+        TestRequirements.nightly();
+
+        String url = "djl://ai.djl.huggingface.rust/TaylorAI/bge-micro-v2";
+        Criteria<NDList, NDList> criteria =
+                Criteria.builder().setTypes(NDList.class, NDList.class).optModelUrls(url).build();
+
+        try (ZooModel<NDList, NDList> model = criteria.loadModel();
+                RsModel copy = (RsModel) Model.newInstance("copy", Device.cpu(), "Rust")) {
+            Path dir = model.getModelPath();
+            byte[] config = Files.readAllBytes(dir.resolve("config.json"));
+            byte[][] shards = {Files.readAllBytes(dir.resolve("model.safetensors"))};
+            copy.load(new String(config, StandardCharsets.UTF_8), shards, null);
+
+            NDManager manager = model.getNDManager();
+            float[] expected = forwardIds(model.getBlock(), manager);
+            Assert.assertEquals(forwardIds(copy.getBlock(), manager), expected);
         }
     }
 
@@ -66,4 +244,242 @@ public class RsModelZooTest {
             System.clearProperty("ai.djl.offline");
         }
     }
+
+    @Test
+    public void testAutoDataType() throws ModelException, IOException {
+        TestRequirements.nightly();
+
+        String url = "djl://ai.djl.huggingface.rust/TaylorAI/bge-micro-v2";
+        Criteria<NDList, NDList> criteria =
+                Criteria.builder()
+                        .setTypes(NDList.class, NDList.class)
+                        .optModelUrls(url)
+                        .optOption("dtype", "auto")
+                        .build();
+
+        try (ZooModel<NDList, NDList> model = criteria.loadModel()) {
+            RsSymbolBlock block = (RsSymbolBlock) model.getBlock();
+            String dtype = block.getMetadata().get("dtype").getAsString();
+            Assert.assertEquals(model.getDataType() == DataType.FLOAT16, "f16".equals(dtype));
+            if (Engine.getEngine("Rust").getGpuCount() == 0) {
+                // f16 checkpoints run in f32 on CPU
+                Assert.assertNotEquals(model.getDataType(), DataType.FLOAT16);
+            }
+        }
+    }
+
+    @Test
+    public void testF32Heads() throws ModelException, IOException {
+        TestRequirements.nightly();
+
+        String url = "djl://ai.djl.huggingface.rust/TaylorAI/bge-micro-v2";
+        Criteria<NDList, NDList> criteria =
+                Criteria.builder()
+                        .setTypes(NDList.class, NDList.class)
+                        .optModelUrls(url)
+                        .optOption("f32_heads", "true")
+                        .build();
+
+        try (ZooModel<NDList, NDList> model = criteria.loadModel()) {
+            RsSymbolBlock block = (RsSymbolBlock) model.getBlock();
+            NDManager manager = model.getNDManager();
+            NDList output = block.forward(new ParameterStore(), sampleInputs(manager), false);
+            NDArray pooled = output.get("pooler_output");
+            if (pooled != null) {
+                Assert.assertEquals(pooled.getDataType(), DataType.FLOAT32);
+            }
+        }
+    }
+
+    @Test
+    public void testFlashAttentionOption() throws ModelException, IOException {
+        TestRequirements.nightly();
+
+        String url = "djl://ai.djl.huggingface.rust/TaylorAI/bge-micro-v2";
+        Criteria<NDList, NDList> criteria =
+                Criteria.builder()
+                        .setTypes(NDList.class, NDList.class)
+                        .optModelUrls(url)
+                        .optOption("use_flash_attention", "false")
+                        .build();
+
+        try (ZooModel<NDList, NDList> model = criteria.loadModel()) {
+            RsSymbolBlock block = (RsSymbolBlock) model.getBlock();
+            Assert.assertEquals(block.getAttentionBackend(), "eager");
+        }
+    }
+
+    @Test
+    public void testRetainModel() throws ModelException, IOException {
+        TestRequirements.nightly();
+
+        String url = "djl://ai.djl.huggingface.rust/TaylorAI/bge-micro-v2";
+        Criteria<NDList, NDList> criteria =
+                Criteria.builder().setTypes(NDList.class, NDList.class).optModelUrls(url).build();
+
+        try (RsNDManager manager = (RsNDManager) NDManager.newBaseManager("Rust")) {
+            RsSymbolBlock retained;
+            try (ZooModel<NDList, NDList> model = criteria.loadModel()) {
+                RsSymbolBlock block = (RsSymbolBlock) model.getBlock();
+                retained = block.retain(manager);
+                Assert.assertEquals(retained.getReferenceCount(), 2);
+            }
+            // the weights are kept alive by the retained block
+            Assert.assertEquals(retained.getReferenceCount(), 1);
+            NDList output = retained.forward(new ParameterStore(), sampleInputs(manager), false);
+            Assert.assertEquals(output.head().getShape().get(1), 4);
+            retained.close();
+        }
+    }
+
+    @Test
+    public void testInt8Quantization() throws ModelException, IOException {
+        TestRequirements.nightly();
+
+        String url = "djl://ai.djl.huggingface.rust/TaylorAI/bge-micro-v2";
+        Criteria<NDList, NDList> criteria =
+                Criteria.builder()
+                        .setTypes(NDList.class, NDList.class)
+                        .optModelUrls(url)
+                        .optOption("quantize", "int8")
+                        .build();
+
+        try (ZooModel<NDList, NDList> model = criteria.loadModel()) {
+            RsSymbolBlock block = (RsSymbolBlock) model.getBlock();
+            Assert.assertEquals(block.getMetadata().get("quantization").getAsString(), "q8_0");
+            NDManager manager = model.getNDManager();
+            NDList output = block.forward(new ParameterStore(), sampleInputs(manager), false);
+            Assert.assertEquals(output.head().getShape(), new Shape(1, 4, 384));
+            // the quantized weights can't be saved as safetensors
+            Path file = Paths.get("build/saved/quantized.safetensors");
+            Assert.assertThrows(EngineException.class, () -> block.save(file, DataType.FLOAT32));
+        }
+    }
+
+    @Test
+    public void testSaveModel() throws ModelException, IOException {
+        TestRequirements.nightly();
+
+        String url = "djl://ai.djl.huggingface.rust/TaylorAI/bge-micro-v2";
+        Criteria<NDList, NDList> criteria =
+                Criteria.builder().setTypes(NDList.class, NDList.class).optModelUrls(url).build();
+        Path dir = Paths.get("build/saved/bge-micro-v2");
+        float[] expected;
+        try (ZooModel<NDList, NDList> model = criteria.loadModel()) {
+            model.save(dir, null);
+            Assert.assertTrue(Files.exists(dir.resolve("model.safetensors")));
+            expected = forwardIds(model.getBlock(), model.getNDManager());
+        }
+
+        Criteria<NDList, NDList> saved =
+                Criteria.builder()
+                        .setTypes(NDList.class, NDList.class)
+                        .optModelPath(dir)
+                        .optEngine("Rust")
+                        .build();
+        try (ZooModel<NDList, NDList> model = saved.loadModel()) {
+            float[] actual = forwardIds(model.getBlock(), model.getNDManager());
+            Assert.assertEquals(actual, expected, 1e-5f);
+        } finally {
+            Utils.deleteQuietly(dir);
+        }
+    }
+
+    @Test
+    public void testLoadFromHub() throws ModelException, IOException {
+        TestRequirements.nightly();
+
+        String url = "djl://ai.djl.huggingface.rust/TaylorAI/bge-micro-v2";
+        Criteria<NDList, NDList> criteria =
+                Criteria.builder().setTypes(NDList.class, NDList.class).optModelUrls(url).build();
+        try (ZooModel<NDList, NDList> model = criteria.loadModel();
+                RsModel hub = (RsModel) Model.newInstance("hub", Device.cpu(), "Rust")) {
+            hub.loadFromHub("TaylorAI/bge-micro-v2", null);
+            Assert.assertTrue(Files.exists(hub.getModelPath().resolve("tokenizer.json")));
+
+            NDManager manager = model.getNDManager();
+            float[] expected = forwardIds(model.getBlock(), manager);
+            Assert.assertEquals(forwardIds(hub.getBlock(), manager), expected);
+        }
+    }
+
+    @Test
+    public void testAdapter() throws ModelException, IOException {
+        TestRequirements.nightly();
+
+        Path dir = Paths.get("build/adapters/bge-micro-v2").toAbsolutePath();
+        writeAdapter(dir);
+        String url = "djl://ai.djl.huggingface.rust/TaylorAI/bge-micro-v2";
+        Criteria<NDList, NDList> criteria =
+                Criteria.builder().setTypes(NDList.class, NDList.class).optModelUrls(url).build();
+        Criteria<NDList, NDList> merged =
+                Criteria.builder()
+                        .setTypes(NDList.class, NDList.class)
+                        .optModelUrls(url)
+                        .optOption("adapter", dir.toString())
+                        .build();
+        try (ZooModel<NDList, NDList> model = criteria.loadModel();
+                ZooModel<NDList, NDList> mergedModel = merged.loadModel()) {
+            RsSymbolBlock block = (RsSymbolBlock) model.getBlock();
+            RsNDManager manager = (RsNDManager) model.getNDManager();
+            float[] base = forwardIds(block, manager);
+            float[] expected = forwardIds(mergedModel.getBlock(), manager);
+            Assert.assertFalse(Arrays.equals(base, expected));
+
+            block.loadAdapter("lora", dir);
+            RsSymbolBlock adapted = block.withAdapter(manager, "lora");
+            Assert.assertEquals(forwardIds(adapted, manager), expected, 1e-2f);
+            // the adapter only applies to the forwards of the adapted block
+            Assert.assertEquals(forwardIds(block, manager), base);
+
+            block.unloadAdapter("lora");
+            Assert.assertThrows(() -> forwardIds(adapted, manager));
+            adapted.close();
+        } finally {
+            Utils.deleteQuietly(dir);
+        }
+    }
+
+    /** Returns the inputs of {@code [CLS] what is [SEP]} for a batch of one. */
+    private static NDList sampleInputs(NDManager manager) {
+        NDArray ids = manager.create(new long[] {101, 2054, 2003, 102}).expandDims(0);
+        return new NDList(ids, ids.onesLike(), ids.zerosLike());
+    }
+
+    /** Returns the first output of a forward of {@link #sampleInputs}, as floats. */
+    private static float[] forwardIds(Block block, NDManager manager) {
+        NDList output = block.forward(new ParameterStore(), sampleInputs(manager), false);
+        return output.head().toType(DataType.FLOAT32, false).toFloatArray();
+    }
+
+    /** Writes a rank 2 LoRA adapter of the first query projection of bge-micro-v2. */
+    private static void writeAdapter(Path dir) throws IOException {
+        Files.createDirectories(dir);
+        String config = "{\"peft_type\": \"LORA\", \"r\": 2, \"lora_alpha\": 4}";
+        Files.write(dir.resolve("adapter_config.json"), config.getBytes(StandardCharsets.UTF_8));
+
+        String module = "base_model.model.encoder.layer.0.attention.self.query";
+        int hiddenSize = 384;
+        int size = 2 * hiddenSize * 4;
+        String header =
+                String.format(
+                        "{\"%1$s.lora_A.weight\":{\"dtype\":\"F32\",\"shape\":[2,%2$d],"
+                                + "\"data_offsets\":[0,%3$d]},"
+                                + "\"%1$s.lora_B.weight\":{\"dtype\":\"F32\",\"shape\":[%2$d,2],"
+                                + "\"data_offsets\":[%3$d,%4$d]}}",
+                        module,
+                        hiddenSize,
+                        size,
+                        2 * size);
+        byte[] json = header.getBytes(StandardCharsets.UTF_8);
+        ByteBuffer buf = ByteBuffer.allocate(8 + json.length + 2 * size);
+        buf.order(ByteOrder.LITTLE_ENDIAN);
+        buf.putLong(json.length);
+        buf.put(json);
+        Random random = new Random(0);
+        for (int i = 0; i < 4 * hiddenSize; ++i) {
+            buf.putFloat((float) random.nextGaussian() * 0.1f);
+        }
+        Files.write(dir.resolve("adapter_model.safetensors"), buf.array());
+    }
 }
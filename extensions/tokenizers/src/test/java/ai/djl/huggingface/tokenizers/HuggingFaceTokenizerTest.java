@@ -15,6 +15,11 @@ package ai.djl.huggingface.tokenizers;
 
 import ai.djl.engine.Engine;
 import ai.djl.huggingface.tokenizers.jni.CharSpan;
+import ai.djl.ndarray.NDArray;
+import ai.djl.ndarray.NDList;
+import ai.djl.ndarray.NDManager;
+import ai.djl.ndarray.types.DataType;
+import ai.djl.ndarray.types.Shape;
 import ai.djl.testing.TestRequirements;
 import ai.djl.training.util.DownloadUtils;
 import ai.djl.util.PairList;
@@ -24,8 +29,11 @@ import org.testng.Assert;
 import org.testng.annotations.Test;
 
 import java.io.IOException;
+import java.nio.charset.StandardCharsets;
+import java.nio.file.Files;
 import java.nio.file.Path;
 import java.nio.file.Paths;
+import java.text.Normalizer;
 import java.util.Arrays;
 import java.util.List;
 import java.util.Locale;
@@ -552,6 +560,199 @@ public class HuggingFaceTokenizerTest {
         }
     }
 
+    @Test
+    public void testBatchEncodeToNDList() {
+        List<String> inputs = Arrays.asList("Hello there friend", "How are you today?");
+        try (HuggingFaceTokenizer tokenizer = HuggingFaceTokenizer.newInstance("bert-base-cased");
+                NDManager manager = NDManager.newBaseManager("Rust")) {
+            Encoding[] encodings = tokenizer.batchEncode(inputs);
+            NDList list = tokenizer.batchEncodeToNDList(manager, inputs, true);
+            Assert.assertEquals(list.size(), 3);
+            NDArray ids = list.get(0);
+            Assert.assertEquals(ids.getDataType(), DataType.INT64);
+            Assert.assertEquals(ids.getShape(), new Shape(2, encodings[0].getIds().length));
+            Assert.assertEquals(ids.get(1).toLongArray(), encodings[1].getIds());
+            Assert.assertEquals(list.get(1).get(0).toLongArray(), encodings[0].getAttentionMask());
+
+            list = tokenizer.batchEncodeToNDList(manager, inputs, false);
+            Assert.assertEquals(list.size(), 2);
+        }
+    }
+
+    @Test
+    public void testUnicodeNormalization() {
+        try (HuggingFaceTokenizer tokenizer = HuggingFaceTokenizer.newInstance("gpt2")) {
+            // "ﬁ" ligature and full width digits are compatibility characters
+            String text = "ﬁne １２";
+            Encoding expected = tokenizer.encode("fine 12");
+            Encoding encoding = tokenizer.encode(text, Normalizer.Form.NFKC);
+            Assert.assertEquals(encoding.getIds(), expected.getIds());
+            Assert.assertNotEquals(tokenizer.encode(text).getIds(), expected.getIds());
+
+            Encoding[] encodings =
+                    tokenizer.batchEncode(Arrays.asList(text, "fine"), Normalizer.Form.NFKC);
+            Assert.assertEquals(encodings[0].getIds(), expected.getIds());
+        }
+    }
+
+    @Test
+    public void testTruncationSide() throws IOException {
+        try (HuggingFaceTokenizer tokenizer =
+                HuggingFaceTokenizer.builder()
+                        .optTokenizerName("bert-base-cased")
+                        .optMaxLength(4)
+                        .optTruncationSide("left")
+                        .build()) {
+            Assert.assertEquals(tokenizer.getTruncationSide(), "LEFT");
+            String[] tokens = tokenizer.encode("one two three four").getTokens();
+            Assert.assertEquals(tokens, new String[] {"[CLS]", "three", "four", "[SEP]"});
+        }
+    }
+
+    @Test
+    public void testPaddingSide() throws IOException {
+        try (HuggingFaceTokenizer tokenizer =
+                HuggingFaceTokenizer.builder()
+                        .optTokenizerName("bert-base-cased")
+                        .optPaddingSide("left")
+                        .build()) {
+            Assert.assertEquals(tokenizer.getPaddingSide(), "LEFT");
+            Encoding[] encodings = tokenizer.batchEncode(Arrays.asList("Hello", "Hello world"));
+            String[] tokens = encodings[0].getTokens();
+            Assert.assertEquals(tokens, new String[] {"[PAD]", "[CLS]", "Hello", "[SEP]"});
+            Assert.assertEquals(encodings[0].getAttentionMask(), new long[] {0, 1, 1, 1});
+        }
+    }
+
+    @Test
+    public void testVocabularyLookup() {
+        try (HuggingFaceTokenizer tokenizer = HuggingFaceTokenizer.newInstance("bert-base-cased")) {
+            Assert.assertEquals(tokenizer.tokenToId("[PAD]"), 0);
+            Assert.assertEquals(tokenizer.tokenToId("[MASK]"), 103);
+            Assert.assertEquals(tokenizer.tokenToId("not-a-token-in-vocab"), -1);
+            Assert.assertEquals(tokenizer.idToToken(101), "[CLS]");
+            Assert.assertNull(tokenizer.idToToken(Integer.MAX_VALUE));
+
+            long[] ids = tokenizer.tokenToId(new String[] {"[CLS]", "[SEP]", "[UNK]"});
+            Assert.assertEquals(ids, new long[] {101, 102, 100});
+            String[] tokens = tokenizer.idToToken(ids);
+            Assert.assertEquals(tokens, new String[] {"[CLS]", "[SEP]", "[UNK]"});
+        }
+    }
+
+    @Test
+    public void testGetVocab() {
+        try (HuggingFaceTokenizer tokenizer = HuggingFaceTokenizer.newInstance("bert-base-cased")) {
+            PairList<String, Long> vocab = tokenizer.getVocab(true);
+            Assert.assertEquals(vocab.size(), 28996);
+            Assert.assertEquals(vocab.get(0).getKey(), "[PAD]");
+            Assert.assertEquals(vocab.get(101).getKey(), "[CLS]");
+            Assert.assertEquals(vocab.get(101).getValue().longValue(), 101L);
+        }
+    }
+
+    @Test
+    public void testSaveTokenizer() throws IOException {
+        Path dir = Paths.get("build/tokenizer/saved");
+        try (HuggingFaceTokenizer tokenizer = HuggingFaceTokenizer.newInstance("bert-base-cased")) {
+            tokenizer.save(dir);
+            String map =
+                    new String(
+                            Files.readAllBytes(dir.resolve("special_tokens_map.json")),
+                            StandardCharsets.UTF_8);
+            Assert.assertTrue(map.contains("\"cls_token\": \"[CLS]\""));
+            Assert.assertTrue(map.contains("\"sep_token\": \"[SEP]\""));
+            Assert.assertTrue(map.contains("\"unk_token\": \"[UNK]\""));
+            Assert.assertTrue(map.contains("\"mask_token\": \"[MASK]\""));
+            Encoding expected = tokenizer.encode("Hello, y'all!");
+            try (HuggingFaceTokenizer saved = HuggingFaceTokenizer.newInstance(dir)) {
+                Assert.assertEquals(saved.encode("Hello, y'all!").getIds(), expected.getIds());
+            }
+
+            byte[] json = Files.readAllBytes(dir.resolve("tokenizer.json"));
+            try (HuggingFaceTokenizer fromBytes = HuggingFaceTokenizer.newInstance(json, null)) {
+                Assert.assertEquals(fromBytes.encode("Hello, y'all!").getIds(), expected.getIds());
+            }
+            String str = new String(json, StandardCharsets.UTF_8);
+            try (HuggingFaceTokenizer fromJson = HuggingFaceTokenizer.fromJson(str, null)) {
+                Assert.assertEquals(fromJson.encode("Hello, y'all!").getIds(), expected.getIds());
+            }
+        }
+    }
+
+    @Test
+    public void testTokenizerConfig() throws IOException {
+        Path dir = Paths.get("build/tokenizer/uncased");
+        try (HuggingFaceTokenizer tokenizer = HuggingFaceTokenizer.newInstance("bert-base-cased")) {
+            tokenizer.save(dir);
+        }
+        String config =
+                "{\"do_lower_case\": true, \"strip_accents\": true, \"model_max_length\": 8}";
+        Files.write(dir.resolve("tokenizer_config.json"), config.getBytes(StandardCharsets.UTF_8));
+        try (HuggingFaceTokenizer tokenizer = HuggingFaceTokenizer.newInstance(dir)) {
+            Assert.assertTrue(tokenizer.getNormalizer().contains("\"lowercase\":true"));
+            Assert.assertEquals(tokenizer.encode("Café").getTokens()[1], "cafe");
+            Assert.assertEquals(tokenizer.getModelMaxLength(), 8);
+            Assert.assertEquals(tokenizer.getMaxLength(), 8);
+            String text = "one two three four five six seven eight nine ten";
+            Assert.assertEquals(tokenizer.encode(text).getIds().length, 8);
+        }
+
+        try (HuggingFaceTokenizer tokenizer =
+                HuggingFaceTokenizer.builder()
+                        .optTokenizerPath(dir)
+                        .optModelMaxLength(16)
+                        .build()) {
+            Assert.assertEquals(tokenizer.getModelMaxLength(), 16);
+        }
+
+        config = "{\"model_max_length\": 1000000000000000019884624838656}";
+        Files.write(dir.resolve("tokenizer_config.json"), config.getBytes(StandardCharsets.UTF_8));
+        try (HuggingFaceTokenizer tokenizer = HuggingFaceTokenizer.newInstance(dir)) {
+            Assert.assertEquals(tokenizer.getModelMaxLength(), 512);
+        }
+
+        // gpt2 has no model_max_length in tokenizer.json, only in tokenizer_config.json
+        try (HuggingFaceTokenizer tokenizer = HuggingFaceTokenizer.newInstance("gpt2")) {
+            Assert.assertEquals(tokenizer.getModelMaxLength(), 1024);
+        }
+    }
+
+    @Test
+    public void testCustomizePipeline() throws IOException {
+        try (HuggingFaceTokenizer tokenizer = HuggingFaceTokenizer.newInstance("bert-base-cased")) {
+            Assert.assertTrue(tokenizer.getNormalizer().contains("BertNormalizer"));
+            Assert.assertEquals(tokenizer.encode("HELLO").getTokens()[1], "H");
+
+            tokenizer.setNormalizer(
+                    "{\"type\":\"Sequence\",\"normalizers\":[{\"type\":\"NFKC\"},"
+                            + "{\"type\":\"Lowercase\"}]}");
+            Assert.assertEquals(tokenizer.encode("HELLO").getTokens()[1], "hello");
+            Assert.assertThrows(() -> tokenizer.setNormalizer("{\"type\":\"Unknown\"}"));
+
+            Assert.assertTrue(tokenizer.getPreTokenizer().contains("BertPreTokenizer"));
+            tokenizer.setPreTokenizer("{\"type\":\"Digits\",\"individual_digits\":true}");
+            Assert.assertEquals(tokenizer.encode("2024").getTokens().length, 6);
+
+            Assert.assertTrue(tokenizer.getPostProcessor().contains("TemplateProcessing"));
+            tokenizer.setTemplate("[CLS] $A [SEP] [SEP]", "[CLS] $A [SEP] $B:1 [SEP]:1");
+            String[] tokens = tokenizer.encode("hello").getTokens();
+            Assert.assertEquals(tokens, new String[] {"[CLS]", "hello", "[SEP]", "[SEP]"});
+            Encoding pair = tokenizer.encode("hello", "world");
+            Assert.assertEquals(pair.getTypeIds(), new long[] {0, 0, 0, 1, 1});
+            Assert.assertThrows(() -> tokenizer.setTemplate("<s> $A </s>", null));
+        }
+
+        String preTokenizer = "{\"type\":\"WhitespaceSplit\"}";
+        try (HuggingFaceTokenizer tokenizer =
+                HuggingFaceTokenizer.builder()
+                        .optTokenizerName("bert-base-cased")
+                        .optPreTokenizer(preTokenizer)
+                        .build()) {
+            Assert.assertEquals(tokenizer.getPreTokenizer(), preTokenizer);
+        }
+    }
+
     @Test
     public void testAuthToken() {
         TestRequirements.notOffline();
@@ -0,0 +1,83 @@
+/*
+ * Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License"). You may not use this file except in compliance
+ * with the License. A copy of the License is located at
+ *
+ * http://aws.amazon.com/apache2.0/
+ *
+ * or in the "license" file accompanying this file. This file is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES
+ * OR CONDITIONS OF ANY KIND, either express or implied. See the License for the specific language governing permissions
+ * and limitations under the License.
+ */
+package ai.djl.huggingface.tokenizers;
+
+import org.testng.Assert;
+import org.testng.annotations.Test;
+
+import java.io.IOException;
+import java.nio.file.Files;
+import java.nio.file.Path;
+import java.nio.file.Paths;
+import java.util.Arrays;
+import java.util.List;
+
+public class TokenizerTrainerTest {
+
+    private static final List<String> CORPUS =
+            Arrays.asList(
+                    "Hello world, this is a tokenizer training corpus.",
+                    "The quick brown fox jumps over the lazy dog.",
+                    "Deep Java Library makes deep learning easy for Java developers.",
+                    "Hello again, the tokenizer should learn frequent words.");
+
+    @Test
+    public void testTrainBpe() throws IOException {
+        Path dir = Paths.get("build/tokenizer/trained");
+        Files.createDirectories(dir);
+        Path file = dir.resolve("corpus.txt");
+        Files.write(file, CORPUS);
+
+        TokenizerTrainer trainer =
+                TokenizerTrainer.builder()
+                        .addFile(file)
+                        .addTexts(CORPUS)
+                        .optVocabSize(300)
+                        .optSpecialTokens("<|endoftext|>")
+                        .build();
+        try (HuggingFaceTokenizer tokenizer = trainer.trainBpe()) {
+            Assert.assertEquals(tokenizer.tokenToId("<|endoftext|>"), 0);
+            Assert.assertTrue(tokenizer.getVocab(true).size() <= 300);
+
+            Encoding encoding = tokenizer.encode("Hello world");
+            Assert.assertEquals(tokenizer.decode(encoding.getIds()), "Hello world");
+
+            tokenizer.save(dir);
+            Assert.assertTrue(Files.exists(dir.resolve("tokenizer.json")));
+        }
+    }
+
+    @Test
+    public void testTrainWordPieceAndUnigram() {
+        TokenizerTrainer trainer =
+                TokenizerTrainer.builder()
+                        .addTexts(CORPUS)
+                        .optVocabSize(100)
+                        .optMinFrequency(1)
+                        .optCharacterCoverage(0.99f)
+                        .optSpecialTokens("[PAD]", "[CLS]", "[SEP]")
+                        .build();
+        try (HuggingFaceTokenizer tokenizer = trainer.trainWordPiece()) {
+            Assert.assertEquals(tokenizer.tokenToId("[UNK]"), 0);
+            Assert.assertEquals(tokenizer.tokenToId("[PAD]"), 1);
+            Encoding encoding = tokenizer.encode("hello world");
+            Assert.assertTrue(encoding.getIds().length > 0);
+        }
+
+        try (HuggingFaceTokenizer tokenizer = trainer.trainUnigram()) {
+            Assert.assertEquals(tokenizer.tokenToId("<unk>"), 0);
+            Encoding encoding = tokenizer.encode("hello world");
+            Assert.assertEquals(tokenizer.decode(encoding.getIds()), "hello world");
+        }
+    }
+}